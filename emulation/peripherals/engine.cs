@@ -0,0 +1,124 @@
+//
+// This file is licensed under the MIT License.
+//
+using Antmicro.Renode.Core;
+using Antmicro.Renode.Core.Structure.Registers;
+using Antmicro.Renode.Logging;
+using Antmicro.Renode.Peripherals.Bus;
+
+namespace Antmicro.Renode.Peripherals.Miscellaneous
+{
+    // Stub of the Betrusted Curve25519 engine's CSRs.
+    //
+    // The real engine runs microcode out of a memory window that also holds
+    // its register file.  That window is plain memory in the platform file,
+    // so software can load programs and operands without faulting, but the
+    // microcode is never executed.
+    //
+    // Writing CONTROL.go finishes at once: STATUS.running stays clear,
+    // STATUS.mpc moves to the end of the program, and the finished event
+    // fires.  The register file is left as it was, so any result read back
+    // is wrong.  This is enough for drivers to probe and initialise the
+    // block.
+    [AllowedTranslations(AllowedTranslation.ByteToDoubleWord)]
+    public class BetrustedEngine : BasicDoubleWordPeripheral, IKnownSize
+    {
+        public BetrustedEngine(Machine machine) : base(machine)
+        {
+            IRQ = new GPIO();
+            DefineRegisters();
+        }
+
+        public override void Reset()
+        {
+            base.Reset();
+            mpc = 0;
+            IRQ.Unset();
+        }
+
+        public long Size { get { return 0x24; } }
+
+        public GPIO IRQ { get; private set; }
+
+        private void DefineRegisters()
+        {
+            Registers.Window.Define32(this)
+                .WithValueField(0, 4, name: "window")
+            ;
+
+            Registers.MpStart.Define32(this)
+                .WithValueField(0, 10, out mpStart, name: "mpstart")
+            ;
+
+            Registers.MpLen.Define32(this)
+                .WithValueField(0, 10, out mpLen, name: "mplen")
+            ;
+
+            Registers.Control.Define32(this)
+                .WithFlag(0, FieldMode.Write, name: "go", writeCallback: (_, val) =>
+                {
+                    if (val)
+                    {
+                        Run();
+                    }
+                })
+            ;
+
+            Registers.Status.Define32(this)
+                .WithFlag(0, FieldMode.Read, name: "running", valueProviderCallback: _ => false)
+                .WithValueField(1, 10, FieldMode.Read, name: "mpc", valueProviderCallback: _ => mpc)
+            ;
+
+            Registers.EventStatus.Define32(this)
+                .WithFlag(0, FieldMode.Read, name: "finished", valueProviderCallback: _ => finishedPending.Value)
+                .WithFlag(1, FieldMode.Read, name: "illegal_opcode", valueProviderCallback: _ => false)
+            ;
+
+            Registers.EventPending.Define32(this)
+                .WithFlag(0, out finishedPending, FieldMode.Read | FieldMode.WriteOneToClear, name: "finished", changeCallback: (_, __) => UpdateInterrupts())
+                .WithFlag(1, FieldMode.Read | FieldMode.WriteOneToClear, name: "illegal_opcode")
+            ;
+
+            Registers.EventEnable.Define32(this)
+                .WithFlag(0, out finishedEnabled, name: "finished", changeCallback: (_, __) => UpdateInterrupts())
+                .WithFlag(1, name: "illegal_opcode")
+            ;
+
+            Registers.Instruction.Define32(this)
+                .WithValueField(0, 32, FieldMode.Read, name: "instruction", valueProviderCallback: _ => 0)
+            ;
+        }
+
+        private void Run()
+        {
+            this.Log(LogLevel.Warning, "Microcode at 0x{0:X} ({1} instructions) is not emulated", mpStart.Value, mpLen.Value);
+            mpc = (uint)((mpStart.Value + mpLen.Value) & 0x3ff);
+            finishedPending.Value = true;
+            UpdateInterrupts();
+        }
+
+        private void UpdateInterrupts()
+        {
+            IRQ.Set(finishedPending.Value && finishedEnabled.Value);
+        }
+
+        private uint mpc;
+        private IValueRegisterField mpStart;
+        private IValueRegisterField mpLen;
+        private IFlagRegisterField finishedPending;
+        private IFlagRegisterField finishedEnabled;
+
+        private enum Registers
+        {
+            Window = 0x00,
+            MpStart = 0x04,
+            MpLen = 0x08,
+            Control = 0x0c,
+            Status = 0x10,
+            EventStatus = 0x14,
+            EventPending = 0x18,
+            EventEnable = 0x1c,
+            Instruction = 0x20,
+        }
+    }
+}
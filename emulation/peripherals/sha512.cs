@@ -0,0 +1,289 @@
+//
+// This file is licensed under the MIT License.
+//
+using System;
+using System.Collections.Generic;
+using Antmicro.Renode.Core;
+using Antmicro.Renode.Core.Structure.Registers;
+using Antmicro.Renode.Logging;
+using Antmicro.Renode.Peripherals.Bus;
+
+namespace Antmicro.Renode.Peripherals.Miscellaneous
+{
+    // Model of the Betrusted SHA-512 engine.
+    //
+    // The engine has two windows: the CSRs, and a small "fifo" region that
+    // message data is written to.  The interface this model implements is:
+    //
+    //  1. Set CONFIG.sha_en, and optionally endian_swap, digest_swap and
+    //     select_256 (for SHA-512/256).
+    //  2. Write COMMAND.hash_start.  This discards any previous message.
+    //  3. Write the message to the fifo region, 32 bits at a time.  Bytes
+    //     are hashed in memory order, or reversed within each word when
+    //     endian_swap is set.
+    //  4. Write COMMAND.hash_process.  The digest is computed immediately,
+    //     MSG_LENGTH holds the message length in bits, and the sha512_done
+    //     event fires.
+    //  5. Read DIGESTn1 (upper half) and DIGESTn0 (lower half) of each
+    //     64-bit digest word.  With digest_swap, each half is byte-swapped.
+    //
+    // Because hashing is instant the FIFO never fills, so fifo_full and
+    // err_valid are never raised.
+    [AllowedTranslations(AllowedTranslation.ByteToDoubleWord)]
+    public class BetrustedSha512 : BasicDoubleWordPeripheral, IKnownSize
+    {
+        public BetrustedSha512(Machine machine) : base(machine)
+        {
+            IRQ = new GPIO();
+            message = new List<byte>();
+            digest = new ulong[8];
+            DefineRegisters();
+        }
+
+        public override void Reset()
+        {
+            base.Reset();
+            message.Clear();
+            Array.Clear(digest, 0, digest.Length);
+            messageBits = 0;
+            IRQ.Unset();
+        }
+
+        public long Size { get { return 0x60; } }
+
+        public GPIO IRQ { get; private set; }
+
+        [ConnectionRegion("fifo")]
+        public void WriteDoubleWordToFifo(long offset, uint value)
+        {
+            if (!shaEnabled.Value)
+            {
+                this.Log(LogLevel.Warning, "Data written to the FIFO while the engine is disabled");
+                return;
+            }
+            var bytes = BitConverter.GetBytes(value);
+            if (endianSwap.Value)
+            {
+                Array.Reverse(bytes);
+            }
+            message.AddRange(bytes);
+        }
+
+        [ConnectionRegion("fifo")]
+        public uint ReadDoubleWordFromFifo(long offset)
+        {
+            this.Log(LogLevel.Warning, "The FIFO is write-only");
+            return 0;
+        }
+
+        private void DefineRegisters()
+        {
+            Registers.Config.Define32(this)
+                .WithFlag(0, out shaEnabled, name: "sha_en")
+                .WithFlag(1, out endianSwap, name: "endian_swap")
+                .WithFlag(2, out digestSwap, name: "digest_swap")
+                .WithFlag(3, out select256, name: "select_256")
+            ;
+
+            Registers.Command.Define32(this)
+                .WithFlag(0, FieldMode.Write, name: "hash_start", writeCallback: (_, val) =>
+                {
+                    if (val)
+                    {
+                        message.Clear();
+                    }
+                })
+                .WithFlag(1, FieldMode.Write, name: "hash_process", writeCallback: (_, val) =>
+                {
+                    if (val)
+                    {
+                        Process();
+                    }
+                })
+            ;
+
+            for (var i = 0; i < 8; i++)
+            {
+                var index = i;
+                ((Registers)((long)Registers.Digest01 + i * 8)).Define32(this)
+                    .WithValueField(0, 32, FieldMode.Read, name: $"digest{i}", valueProviderCallback: _ => DigestHalf(index, true))
+                ;
+                ((Registers)((long)Registers.Digest00 + i * 8)).Define32(this)
+                    .WithValueField(0, 32, FieldMode.Read, name: $"digest{i}", valueProviderCallback: _ => DigestHalf(index, false))
+                ;
+            }
+
+            Registers.MsgLength1.Define32(this)
+                .WithValueField(0, 32, FieldMode.Read, name: "msg_length", valueProviderCallback: _ => (uint)(messageBits >> 32))
+            ;
+            Registers.MsgLength0.Define32(this)
+                .WithValueField(0, 32, FieldMode.Read, name: "msg_length", valueProviderCallback: _ => (uint)messageBits)
+            ;
+
+            Registers.EventStatus.Define32(this)
+                .WithFlag(0, FieldMode.Read, name: "err_valid", valueProviderCallback: _ => false)
+                .WithFlag(1, FieldMode.Read, name: "fifo_full", valueProviderCallback: _ => false)
+                .WithFlag(2, FieldMode.Read, name: "sha512_done", valueProviderCallback: _ => donePending.Value)
+            ;
+            Registers.EventPending.Define32(this)
+                .WithFlag(0, FieldMode.Read | FieldMode.WriteOneToClear, name: "err_valid")
+                .WithFlag(1, FieldMode.Read | FieldMode.WriteOneToClear, name: "fifo_full")
+                .WithFlag(2, out donePending, FieldMode.Read | FieldMode.WriteOneToClear, name: "sha512_done", changeCallback: (_, __) => UpdateInterrupts())
+            ;
+            Registers.EventEnable.Define32(this)
+                .WithFlag(0, name: "err_valid")
+                .WithFlag(1, name: "fifo_full")
+                .WithFlag(2, out doneEnabled, name: "sha512_done", changeCallback: (_, __) => UpdateInterrupts())
+            ;
+
+            Registers.Fifo.Define32(this)
+                .WithValueField(0, 9, FieldMode.Read, name: "read_count", valueProviderCallback: _ => 0)
+                .WithValueField(9, 9, FieldMode.Read, name: "write_count", valueProviderCallback: _ => 0)
+                .WithFlag(18, FieldMode.Read, name: "read_error", valueProviderCallback: _ => false)
+                .WithFlag(19, FieldMode.Read, name: "write_error", valueProviderCallback: _ => false)
+                .WithFlag(20, FieldMode.Read, name: "almost_full", valueProviderCallback: _ => false)
+                .WithFlag(21, FieldMode.Read, name: "almost_empty", valueProviderCallback: _ => true)
+                .WithFlag(22, FieldMode.Read, name: "running", valueProviderCallback: _ => false)
+            ;
+        }
+
+        private uint DigestHalf(int index, bool upper)
+        {
+            var half = upper ? (uint)(digest[index] >> 32) : (uint)digest[index];
+            if (digestSwap.Value)
+            {
+                half = (half >> 24) | ((half >> 8) & 0xff00) | ((half << 8) & 0xff0000) | (half << 24);
+            }
+            return half;
+        }
+
+        private void UpdateInterrupts()
+        {
+            IRQ.Set(donePending.Value && doneEnabled.Value);
+        }
+
+        private void Process()
+        {
+            messageBits = (ulong)message.Count * 8;
+            var h = (ulong[])(select256.Value ? Iv256 : Iv512).Clone();
+
+            // Pad to a multiple of 128 bytes: 0x80, zeros, then the 128-bit length.
+            var padded = new List<byte>(message);
+            padded.Add(0x80);
+            while (padded.Count % 128 != 112)
+            {
+                padded.Add(0);
+            }
+            for (var i = 0; i < 8; i++)
+            {
+                padded.Add(0);
+            }
+            for (var i = 7; i >= 0; i--)
+            {
+                padded.Add((byte)(messageBits >> (i * 8)));
+            }
+
+            var w = new ulong[80];
+            for (var block = 0; block < padded.Count; block += 128)
+            {
+                for (var t = 0; t < 16; t++)
+                {
+                    ulong word = 0;
+                    for (var b = 0; b < 8; b++)
+                    {
+                        word = (word << 8) | padded[block + t * 8 + b];
+                    }
+                    w[t] = word;
+                }
+                for (var t = 16; t < 80; t++)
+                {
+                    var s0 = Rotr(w[t - 15], 1) ^ Rotr(w[t - 15], 8) ^ (w[t - 15] >> 7);
+                    var s1 = Rotr(w[t - 2], 19) ^ Rotr(w[t - 2], 61) ^ (w[t - 2] >> 6);
+                    w[t] = w[t - 16] + s0 + w[t - 7] + s1;
+                }
+
+                ulong a = h[0], b2 = h[1], c = h[2], d = h[3], e = h[4], f = h[5], g = h[6], hh = h[7];
+                for (var t = 0; t < 80; t++)
+                {
+                    var t1 = hh + (Rotr(e, 14) ^ Rotr(e, 18) ^ Rotr(e, 41)) + ((e & f) ^ (~e & g)) + K[t] + w[t];
+                    var t2 = (Rotr(a, 28) ^ Rotr(a, 34) ^ Rotr(a, 39)) + ((a & b2) ^ (a & c) ^ (b2 & c));
+                    hh = g; g = f; f = e; e = d + t1;
+                    d = c; c = b2; b2 = a; a = t1 + t2;
+                }
+                h[0] += a; h[1] += b2; h[2] += c; h[3] += d;
+                h[4] += e; h[5] += f; h[6] += g; h[7] += hh;
+            }
+
+            Array.Copy(h, digest, 8);
+            message.Clear();
+            donePending.Value = true;
+            UpdateInterrupts();
+        }
+
+        private static ulong Rotr(ulong x, int n)
+        {
+            return (x >> n) | (x << (64 - n));
+        }
+
+        private static readonly ulong[] Iv512 =
+        {
+            0x6a09e667f3bcc908, 0xbb67ae8584caa73b, 0x3c6ef372fe94f82b, 0xa54ff53a5f1d36f1,
+            0x510e527fade682d1, 0x9b05688c2b3e6c1f, 0x1f83d9abfb41bd6b, 0x5be0cd19137e2179,
+        };
+
+        private static readonly ulong[] Iv256 =
+        {
+            0x22312194fc2bf72c, 0x9f555fa3c84c64c2, 0x2393b86b6f53b151, 0x963877195940eabd,
+            0x96283ee2a88effe3, 0xbe5e1e2553863992, 0x2b0199fc2c85b8aa, 0x0eb72ddc81c52ca2,
+        };
+
+        private static readonly ulong[] K =
+        {
+            0x428a2f98d728ae22, 0x7137449123ef65cd, 0xb5c0fbcfec4d3b2f, 0xe9b5dba58189dbbc,
+            0x3956c25bf348b538, 0x59f111f1b605d019, 0x923f82a4af194f9b, 0xab1c5ed5da6d8118,
+            0xd807aa98a3030242, 0x12835b0145706fbe, 0x243185be4ee4b28c, 0x550c7dc3d5ffb4e2,
+            0x72be5d74f27b896f, 0x80deb1fe3b1696b1, 0x9bdc06a725c71235, 0xc19bf174cf692694,
+            0xe49b69c19ef14ad2, 0xefbe4786384f25e3, 0x0fc19dc68b8cd5b5, 0x240ca1cc77ac9c65,
+            0x2de92c6f592b0275, 0x4a7484aa6ea6e483, 0x5cb0a9dcbd41fbd4, 0x76f988da831153b5,
+            0x983e5152ee66dfab, 0xa831c66d2db43210, 0xb00327c898fb213f, 0xbf597fc7beef0ee4,
+            0xc6e00bf33da88fc2, 0xd5a79147930aa725, 0x06ca6351e003826f, 0x142929670a0e6e70,
+            0x27b70a8546d22ffc, 0x2e1b21385c26c926, 0x4d2c6dfc5ac42aed, 0x53380d139d95b3df,
+            0x650a73548baf63de, 0x766a0abb3c77b2a8, 0x81c2c92e47edaee6, 0x92722c851482353b,
+            0xa2bfe8a14cf10364, 0xa81a664bbc423001, 0xc24b8b70d0f89791, 0xc76c51a30654be30,
+            0xd192e819d6ef5218, 0xd69906245565a910, 0xf40e35855771202a, 0x106aa07032bbd1b8,
+            0x19a4c116b8d2d0c8, 0x1e376c085141ab53, 0x2748774cdf8eeb99, 0x34b0bcb5e19b48a8,
+            0x391c0cb3c5c95a63, 0x4ed8aa4ae3418acb, 0x5b9cca4f7763e373, 0x682e6ff3d6b2b8a3,
+            0x748f82ee5defb2fc, 0x78a5636f43172f60, 0x84c87814a1f0ab72, 0x8cc702081a6439ec,
+            0x90befffa23631e28, 0xa4506cebde82bde9, 0xbef9a3f7b2c67915, 0xc67178f2e372532b,
+            0xca273eceea26619c, 0xd186b8c721c0c207, 0xeada7dd6cde0eb1e, 0xf57d4f7fee6ed178,
+            0x06f067aa72176fba, 0x0a637dc5a2c898a6, 0x113f9804bef90dae, 0x1b710b35131c471b,
+            0x28db77f523047d84, 0x32caab7b40c72493, 0x3c9ebe0a15c9bebc, 0x431d67c49c100d4c,
+            0x4cc5d4becb3e42b6, 0x597f299cfc657e2a, 0x5fcb6fab3ad6faec, 0x6c44198c4a475817,
+        };
+
+        private readonly List<byte> message;
+        private readonly ulong[] digest;
+        private ulong messageBits;
+
+        private IFlagRegisterField shaEnabled;
+        private IFlagRegisterField endianSwap;
+        private IFlagRegisterField digestSwap;
+        private IFlagRegisterField select256;
+        private IFlagRegisterField donePending;
+        private IFlagRegisterField doneEnabled;
+
+        private enum Registers
+        {
+            Config = 0x00,
+            Command = 0x04,
+            Digest01 = 0x08,
+            Digest00 = 0x0c,
+            MsgLength1 = 0x48,
+            MsgLength0 = 0x4c,
+            EventStatus = 0x50,
+            EventPending = 0x54,
+            EventEnable = 0x58,
+            Fifo = 0x5c,
+        }
+    }
+}
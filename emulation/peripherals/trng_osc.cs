@@ -0,0 +1,78 @@
+//
+// This file is licensed under the MIT License.
+//
+using System;
+using Antmicro.Renode.Core;
+using Antmicro.Renode.Core.Structure.Registers;
+using Antmicro.Renode.Logging;
+using Antmicro.Renode.Peripherals.Bus;
+
+namespace Antmicro.Renode.Peripherals.Miscellaneous
+{
+    // Model of the Betrusted ring-oscillator TRNG (TRNG_OSC).
+    //
+    // Software sets CTL.ena, waits for STATUS.fresh, then reads RAND.
+    // Reading RAND clears STATUS.fresh until the next word is ready, which
+    // in this model is immediately.  The dwell and delay fields are stored
+    // but have no effect.
+    //
+    // The output comes from a seeded PRNG rather than the host's entropy,
+    // so that a run can be reproduced by reusing the seed.
+    [AllowedTranslations(AllowedTranslation.ByteToDoubleWord)]
+    public class BetrustedTrngOsc : BasicDoubleWordPeripheral, IKnownSize
+    {
+        public BetrustedTrngOsc(Machine machine, int seed = 0) : base(machine)
+        {
+            this.seed = seed;
+            DefineRegisters();
+            Reset();
+        }
+
+        public override void Reset()
+        {
+            base.Reset();
+            random = new Random(seed);
+        }
+
+        public long Size { get { return 0x0c; } }
+
+        private void DefineRegisters()
+        {
+            Registers.Control.Define32(this)
+                .WithFlag(0, out enabled, name: "ena")
+                .WithFlag(1, name: "gang")
+                .WithValueField(2, 20, name: "dwell")
+                .WithValueField(22, 10, name: "delay")
+            ;
+
+            Registers.Rand.Define32(this)
+                .WithValueField(0, 32, FieldMode.Read, name: "rand", valueProviderCallback: _ =>
+                {
+                    if (!enabled.Value)
+                    {
+                        this.Log(LogLevel.Warning, "RAND read while the TRNG is disabled");
+                        return 0;
+                    }
+                    var bytes = new byte[4];
+                    random.NextBytes(bytes);
+                    return BitConverter.ToUInt32(bytes, 0);
+                })
+            ;
+
+            Registers.Status.Define32(this)
+                .WithFlag(0, FieldMode.Read, name: "fresh", valueProviderCallback: _ => enabled.Value)
+            ;
+        }
+
+        private readonly int seed;
+        private Random random;
+        private IFlagRegisterField enabled;
+
+        private enum Registers
+        {
+            Control = 0x00,
+            Rand = 0x04,
+            Status = 0x08,
+        }
+    }
+}
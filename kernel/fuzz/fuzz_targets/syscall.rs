@@ -0,0 +1,8 @@
+//! Run with `cargo fuzz run syscall` from the `kernel` directory.  Each
+//! input is a list of syscalls, `kernel::CALL_SIZE` bytes apiece.
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    kernel::fuzz_syscall(data);
+});
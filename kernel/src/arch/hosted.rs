@@ -529,7 +529,12 @@ pub fn idle() -> bool {
                 // If the call being made is to terminate the current process, we need to know
                 // because we won't be able to send a response.
                 let is_terminate = call == SysCall::TerminateProcess;
-                let is_shutdown = call == SysCall::Shutdown;
+                // A sandboxed process isn't allowed to shut down, and gets an
+                // ordinary error response.
+                let is_shutdown = call == SysCall::Shutdown
+                    && SystemServices::with(|ss| {
+                        ss.get_process(pid).map(|p| p.sandbox.is_none()).unwrap_or(true)
+                    });
 
                 // For a "Shutdown" command, send the response before we issue the shutdown.
                 // This is because the "process" will be "terminated" (the network socket will be closed),
@@ -607,3 +612,20 @@ pub fn idle() -> bool {
     // println!("Thank you for using Xous!");
     false
 }
+
+/// In hosted mode the host OS does the idling, so there is nothing to
+/// report.
+pub fn idle_stats() -> (u64, u64, usize) {
+    (0, 0, 0)
+}
+
+/// Hosted processes are threads that the host OS schedules, so the kernel
+/// never measures how long they run.
+pub fn timestamp() -> u64 {
+    0
+}
+
+/// There is no cycle counter to read on a hosted kernel
+pub fn cycles() -> u64 {
+    0
+}
@@ -362,12 +362,11 @@ impl Process {
                 panic!("attempted to destroy PID that exceeds table index: {}", pid);
             }
             let process = process_table.table[pid_idx].as_mut().unwrap();
-            process
-                .conn
-                .as_mut()
-                .unwrap()
-                .shutdown(std::net::Shutdown::Both)
-                .unwrap();
+            // PID 1 never connects, and a process may be destroyed before
+            // its client has connected.
+            if let Some(conn) = process.conn.as_mut() {
+                conn.shutdown(std::net::Shutdown::Both).ok();
+            }
             process_table.table[pid_idx] = None;
             process_table.total -= 1;
             Ok(())
@@ -1,20 +1,89 @@
 use riscv::register::{satp, sie, sstatus};
+use utralib::generated::*;
 use xous_kernel::PID;
 
 pub mod exception;
 pub mod irq;
 pub mod mem;
 pub mod process;
+#[cfg(feature = "stack-watermark")]
+pub mod stack;
 pub mod syscall;
 
 pub use process::Thread;
 
+/// The number of harts that the kernel keeps per-hart state for.
+#[cfg(feature = "multicore")]
+pub const MAX_HARTS: usize = 2;
+#[cfg(not(feature = "multicore"))]
+pub const MAX_HARTS: usize = 1;
+
+/// Return the index of the hart that is currently executing.  Only the
+/// boot hart is started at the moment, so this is always `0`.
+pub fn hart_id() -> usize {
+    0
+}
+
 pub fn current_pid() -> PID {
     PID::new(satp::read().asid() as _).unwrap()
 }
 
+/// Where the loader maps the ticktimer's registers for the kernel.  The
+/// VexRiscv core doesn't implement the `time` CSR, so reading that would
+/// trap, and the ticktimer is the only clock every SoC has.
+const TICKTIMER_ADDRESS: usize = 0xffce_0000;
+
+/// Idle accounting, in ticktimer ticks
+static mut BOOT_TIME: u64 = 0;
+static mut IDLE_TIME: u64 = 0;
+static mut WAKEUPS: usize = 0;
+
+/// The ticktimer server resets the count when it starts, so the raw count
+/// goes backwards once after boot.  `CLOCK_BASE` is added to every reading
+/// so the kernel's clock carries on from the last value it saw.
+static mut CLOCK_BASE: u64 = 0;
+static mut CLOCK_LAST: u64 = 0;
+
+/// Read the ticktimer's 64-bit count, taking care that the upper half
+/// does not roll over while the lower half is being read.
+pub fn timestamp() -> u64 {
+    let ticktimer = CSR::new(
+        (TICKTIMER_ADDRESS | (utra::ticktimer::HW_TICKTIMER_BASE & 0xfff)) as *mut u32,
+    );
+    let raw = loop {
+        let hi = ticktimer.r(utra::ticktimer::TIME1);
+        let lo = ticktimer.r(utra::ticktimer::TIME0);
+        if hi == ticktimer.r(utra::ticktimer::TIME1) {
+            break ((hi as u64) << 32) | (lo as u64);
+        }
+    };
+    unsafe {
+        if raw < CLOCK_LAST {
+            CLOCK_BASE += CLOCK_LAST;
+        }
+        CLOCK_LAST = raw;
+        CLOCK_BASE + raw
+    }
+}
+
+/// Read the 64-bit cycle counter, taking care that the upper half does not
+/// roll over while the lower half is being read.  Unlike `timestamp()`,
+/// this counts CPU cycles, which is fine enough to time interrupt dispatch
+/// and tracepoints.  The SoC must let supervisor mode read `cycle`.
+pub fn cycles() -> u64 {
+    use riscv::register::{cycle, cycleh};
+    loop {
+        let hi = cycleh::read();
+        let lo = cycle::read();
+        if hi == cycleh::read() {
+            break ((hi as u64) << 32) | (lo as u64);
+        }
+    }
+}
+
 pub fn init() {
     unsafe {
+        BOOT_TIME = timestamp();
         sstatus::set_sie();
         sie::set_ssoft();
         sie::set_sext();
@@ -23,7 +92,22 @@ pub fn init() {
 
 /// Put the core to sleep until an interrupt hits. Returns `true`
 /// to indicate the kernel should not exit.
+///
+/// The kernel has no periodic tick of its own, so the core stays in WFI
+/// until the next hardware interrupt -- usually the ticktimer firing for
+/// the nearest deadline that a userspace process is waiting on.
 pub fn idle() -> bool {
-    unsafe { riscv::asm::wfi() };
+    let start = timestamp();
+    unsafe {
+        riscv::asm::wfi();
+        IDLE_TIME += timestamp().wrapping_sub(start);
+        WAKEUPS = WAKEUPS.wrapping_add(1);
+    }
     true
 }
+
+/// Return the time spent idle, the time elapsed since boot, and the number
+/// of times the core has woken from idle.
+pub fn idle_stats() -> (u64, u64, usize) {
+    unsafe { (IDLE_TIME, timestamp().wrapping_sub(BOOT_TIME), WAKEUPS) }
+}
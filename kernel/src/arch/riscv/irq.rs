@@ -122,10 +122,15 @@ pub extern "C" fn trap_handler(
         match ex {
             RiscvException::StorePageFault(pc, addr) | RiscvException::LoadPageFault(pc, addr) => {
                 #[cfg(any(feature = "debug-print", feature = "print-panics"))]
-                println!(
-                    "KERNEL({}): RISC-V fault: {} @ {:08x}, addr {:08x}",
-                    pid, ex, pc, addr
-                );
+                {
+                    println!(
+                        "KERNEL({}): RISC-V fault: {} @ {:08x}, addr {:08x}",
+                        pid, ex, pc, addr
+                    );
+                    if let Some(symbol) = crate::arch::process::symbolize(pid, pc) {
+                        println!("KERNEL({}): fault is in {}", pid, symbol);
+                    }
+                }
                 let entry = crate::arch::mem::pagetable_entry(addr).unwrap_or_else(|x| {
                     // MemoryManagerHandle::get().print_ownership();
                     MemoryMapping::current().print_map();
@@ -136,10 +141,21 @@ pub extern "C" fn trap_handler(
                 });
                 let flags = *entry & 0x1ff;
 
+                if crate::arch::mem::is_guard_page(addr) {
+                    println!(
+                        "KERNEL({}): guard page hit @ {:08x} -- likely a stack overflow",
+                        pid, addr
+                    );
+                }
+
                 // If the flags are nonzero, but the "Valid" bit is not 1 and
                 // the page isn't shared, then this is a reserved page. Allocate
                 // a real page to back it and resume execution.
-                if flags & 1 == 0 && flags != 0 && flags & (1 << 8) == 0 {
+                if flags & 1 == 0
+                    && flags != 0
+                    && flags & (1 << 8) == 0
+                    && !crate::arch::mem::is_guard_page(addr)
+                {
                     let new_page = MemoryManager::with_mut(|mm| {
                         mm.alloc_page(pid).expect("Couldn't allocate new page")
                     });
@@ -156,6 +172,8 @@ pub extern "C" fn trap_handler(
                         let virt = addr & !0xfff;
                         (virt as *mut usize)
                             .write_bytes(0, PAGE_SIZE / core::mem::size_of::<usize>());
+                        #[cfg(feature = "stack-watermark")]
+                        crate::arch::stack::fill_if_stack(pid, virt);
 
                         // Move the page into userspace
                         *entry = (ppn1 << 20)
@@ -176,6 +194,8 @@ pub extern "C" fn trap_handler(
                 // If we hit this address, then an ISR has just returned.  Since
                 // we're in an interrupt context, it is safe to access this
                 // global variable.
+                #[cfg(feature = "irq-latency")]
+                crate::irq::latency::complete();
                 let (previous_pid, previous_context) = unsafe {
                     PREVIOUS_PAIR
                         .take()
@@ -191,8 +211,12 @@ pub extern "C" fn trap_handler(
                         .expect("unable to resume previous PID")
                 });
 
-                // Re-enable interrupts now that they're handled
-                enable_all_irqs();
+                // Any other processes sharing this interrupt get called
+                // before interrupts are re-enabled.
+                if !crate::irq::dispatch_next(previous_pid, previous_context) {
+                    // Re-enable interrupts now that they're handled
+                    enable_all_irqs();
+                }
 
                 ArchProcess::with_current_mut(|process| {
                     crate::arch::syscall::resume(current_pid().get() == 1, process.current_thread())
@@ -201,6 +225,10 @@ pub extern "C" fn trap_handler(
             _ => (),
         }
         println!("SYSTEM HALT: CPU Exception on PID {}: {}", pid, ex);
+        #[cfg(any(feature = "debug-print", feature = "print-panics"))]
+        if let Some(symbol) = crate::arch::process::symbolize(pid, sepc::read()) {
+            println!("Exception occurred in {}", symbol);
+        }
         ArchProcess::with_current(|process| {
             println!("Current thread {}:", process.current_tid());
             process.print_thread();
@@ -208,6 +236,8 @@ pub extern "C" fn trap_handler(
         MemoryMapping::current().print_map();
         loop {}
     } else {
+        #[cfg(feature = "irq-latency")]
+        crate::irq::latency::entry();
         let irqs_pending = sip::read();
         // Safe to access globals since interrupts are disabled
         // when this function runs.
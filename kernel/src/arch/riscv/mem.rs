@@ -33,6 +33,17 @@ bitflags! {
     }
 }
 
+/// A page table entry that is not valid and has only the global bit set
+/// marks a guard page. User mappings never set the global bit, so this can
+/// not be mistaken for a reserved page and will never be demand-paged.
+const GUARD_PAGE: usize = MMUFlags::GLOBAL.bits();
+
+/// The state of a page, as reported to userspace by `GetMemoryFlags`
+pub const PAGE_STATE_UNMAPPED: usize = 0;
+pub const PAGE_STATE_RESERVED: usize = 1;
+pub const PAGE_STATE_MAPPED: usize = 2;
+pub const PAGE_STATE_GUARD: usize = 3;
+
 #[derive(Copy, Clone, Default, PartialEq)]
 pub struct MemoryMapping {
     satp: usize,
@@ -606,5 +617,94 @@ pub fn virt_to_phys(virt: usize) -> Result<usize, xous_kernel::Error> {
 
 /// Determine whether a virtual address has been mapped
 pub fn address_available(virt: usize) -> bool {
-    virt_to_phys(virt).is_err()
+    virt_to_phys(virt).is_err() && !is_guard_page(virt)
+}
+
+/// Copy `words.len()` words starting at `virt` in the current process into
+/// `words`.  Every page touched must be mapped and readable by the process,
+/// so a bad pointer from userspace can't fault the kernel.
+pub fn read_user_words(virt: usize, words: &mut [usize]) -> Result<(), xous_kernel::Error> {
+    let word_size = core::mem::size_of::<usize>();
+    if virt & (word_size - 1) != 0 {
+        return Err(xous_kernel::Error::BadAlignment);
+    }
+    let end = virt
+        .checked_add(words.len() * word_size)
+        .ok_or(xous_kernel::Error::BadAddress)?;
+    if end > USER_AREA_END {
+        return Err(xous_kernel::Error::BadAddress);
+    }
+    let wanted = (MMUFlags::VALID | MMUFlags::R | MMUFlags::USER).bits();
+    for page in ((virt & !(PAGE_SIZE - 1))..end).step_by(PAGE_SIZE) {
+        if *pagetable_entry(page)? & wanted != wanted {
+            return Err(xous_kernel::Error::BadAddress);
+        }
+    }
+
+    // Allow access to pages that belong to userspace
+    unsafe { riscv::register::sstatus::set_sum() };
+    for (index, word) in words.iter_mut().enumerate() {
+        *word = unsafe { ((virt + index * word_size) as *const usize).read_volatile() };
+    }
+    unsafe { riscv::register::sstatus::clear_sum() };
+    Ok(())
+}
+
+/// Turn the page at `virt` into a guard page, releasing any memory behind
+/// it. Any access to the page will fault, and it will not be handed out
+/// again by the allocator.
+pub fn make_guard_page(mm: &mut MemoryManager, virt: usize) -> Result<(), xous_kernel::Error> {
+    let virt = virt & !(PAGE_SIZE - 1);
+    if virt_to_phys(virt).is_ok() {
+        mm.unmap_page(virt as *mut usize)?;
+    }
+    let entry = pagetable_entry(virt)?;
+    *entry = GUARD_PAGE;
+    unsafe { flush_mmu() };
+    Ok(())
+}
+
+/// Determine whether a virtual address lies in a guard page
+pub fn is_guard_page(virt: usize) -> bool {
+    pagetable_entry(virt & !(PAGE_SIZE - 1))
+        .map(|entry| *entry == GUARD_PAGE)
+        .unwrap_or(false)
+}
+
+/// Return the flags of the page containing `virt` in the current process,
+/// along with one of the `PAGE_STATE_*` values.
+pub fn page_flags(virt: usize) -> (MemoryFlags, usize) {
+    let entry = match pagetable_entry(virt & !(PAGE_SIZE - 1)) {
+        Ok(entry) => *entry,
+        Err(_) => return (MemoryFlags::FREE, PAGE_STATE_UNMAPPED),
+    };
+    if entry == 0 {
+        (MemoryFlags::FREE, PAGE_STATE_UNMAPPED)
+    } else if entry == GUARD_PAGE {
+        (MemoryFlags::FREE, PAGE_STATE_GUARD)
+    } else if entry & (MMUFlags::VALID | MMUFlags::S).bits() == 0 {
+        (untranslate_flags(entry), PAGE_STATE_RESERVED)
+    } else {
+        (untranslate_flags(entry), PAGE_STATE_MAPPED)
+    }
+}
+
+/// Return one of the address ranges that the loader protected with the PMP,
+/// as recorded in the `PMPr` tag.  The tag holds `(start, end, flags)`
+/// triples, where `flags` uses the PMP's R/W/X bit order.
+pub fn pmp_range(index: usize) -> Result<xous_kernel::Result, xous_kernel::Error> {
+    let args = crate::args::KernelArguments::get();
+    let record = args
+        .iter()
+        .find(|tag| tag.name == crate::make_type!("PMPr"))
+        .and_then(|tag| tag.data.chunks_exact(3).nth(index))
+        .ok_or(xous_kernel::Error::InvalidSyscall)?;
+    let flags = MemoryFlags::from_bits_truncate((record[2] as usize) << 1);
+    Ok(xous_kernel::Result::Scalar5(
+        record[0] as usize,
+        record[1] as usize,
+        flags.bits(),
+        0,
+        0,
+    ))
 }
@@ -64,26 +64,50 @@ struct ProcessImpl {
 
 /// Singleton process table. Each process in the system gets allocated from this table.
 struct ProcessTable {
-    /// The process upon which the current syscall is operating
-    current: PID,
+    /// The process upon which the current syscall is operating,
+    /// for each hart
+    current: [PID; crate::arch::MAX_HARTS],
 
     /// The number of processes that exist
     // total: usize,
 
     /// The actual table contents
     table: [bool; MAX_PROCESS_COUNT],
+
+    /// Layout seeds that have not yet been consumed by `setup_process()`.
+    /// The kernel's own slot is used as the state for deriving seeds of
+    /// processes created after boot.
+    aslr: [u32; MAX_PROCESS_COUNT],
 }
 
 static mut PROCESS_TABLE: ProcessTable = ProcessTable {
-    current: unsafe { PID::new_unchecked(1) },
+    current: [unsafe { PID::new_unchecked(1) }; crate::arch::MAX_HARTS],
     // total: 0,
     table: [false; MAX_PROCESS_COUNT],
+    aslr: [0; MAX_PROCESS_COUNT],
 };
 
+/// Record the layout seed the loader drew for `pid`.
+pub fn set_aslr_seed(pid: PID, seed: usize) {
+    unsafe { PROCESS_TABLE.aslr[pid.get() as usize - 1] = seed as u32 };
+}
+
+/// Derive a layout seed for a process that wasn't set up by the loader.
+/// This is a xorshift32 stepped from the kernel's seed, so a kernel seed of
+/// `0` (randomization disabled) keeps returning `0`.
+fn next_aslr_seed() -> u32 {
+    let mut x = unsafe { PROCESS_TABLE.aslr[0] };
+    x ^= x << 13;
+    x ^= x >> 17;
+    x ^= x << 5;
+    unsafe { PROCESS_TABLE.aslr[0] = x };
+    x
+}
+
 #[repr(C)]
 #[cfg(baremetal)]
 /// The stage1 bootloader sets up some initial processes.  These are reported
-/// to us as (satp, entrypoint, sp) tuples, which can be turned into a structure.
+/// to us as (satp, entrypoint, sp, aslr_seed) tuples, which can be turned into a structure.
 /// The first element is always the kernel.
 pub struct InitialProcess {
     /// The RISC-V SATP value, which includes the offset of the root page
@@ -95,6 +119,10 @@ pub struct InitialProcess {
 
     /// Address of the top of the stack
     pub sp: usize,
+
+    /// Random bits drawn by the loader for laying out this process, or `0`
+    /// if address space randomization is disabled.
+    pub aslr_seed: usize,
 }
 
 #[repr(C)]
@@ -119,7 +147,7 @@ pub struct Thread {
 
 impl Process {
     pub fn current() -> Process {
-        let pid = unsafe { PROCESS_TABLE.current };
+        let pid = current_pid();
         let hardware_pid = (riscv::register::satp::read().bits() >> 22) & ((1 << 9) - 1);
         assert!((pid.get() as usize) == hardware_pid);
         Process {
@@ -311,6 +339,27 @@ impl Process {
 
         process.inner = Default::default();
 
+        // Slide the heap, the default mapping area, and the message area by
+        // up to 255 pages each.  Bits 24-31 of the seed were used by the
+        // loader to place the initial stack.
+        if pid.get() != 1 {
+            let pid_idx = (pid.get() as usize) - 1;
+            let seed = match unsafe { mem::replace(&mut PROCESS_TABLE.aslr[pid_idx], 0) } {
+                0 => next_aslr_seed(),
+                seed => seed,
+            } as usize;
+            let inner = &mut process.inner;
+            inner.mem_heap_base += (seed & 0xff) * PAGE_SIZE;
+            inner.mem_default_base += ((seed >> 8) & 0xff) * PAGE_SIZE;
+            inner.mem_default_last = inner.mem_default_base;
+            inner.mem_message_base += ((seed >> 16) & 0xff) * PAGE_SIZE;
+            inner.mem_message_last = inner.mem_message_base;
+        }
+
+        // Nothing is known about the stacks of whatever had this PID before
+        #[cfg(feature = "stack-watermark")]
+        crate::arch::stack::forget(pid);
+
         // Mark the stack as "unallocated-but-free"
         let init_sp = (thread_init.stack.as_ptr() as usize) & !0xfff;
         if init_sp != 0 {
@@ -324,13 +373,34 @@ impl Process {
                     )
                     .expect("couldn't reserve stack")
             });
+            #[cfg(feature = "stack-watermark")]
+            crate::arch::stack::track(pid, tid, init_sp, stack_size + 4096);
         }
         Ok(())
     }
 
     pub fn setup_thread(&mut self, new_tid: TID, setup: ThreadInit) -> Result<(), xous_kernel::Error> {
         let entrypoint = unsafe { core::mem::transmute::<_, usize>(setup.call) };
+
+        // Turn the lowest page of the stack into a guard page, so that
+        // overflowing the stack faults rather than corrupting whatever is
+        // mapped below it.  This is the only step that can fail, so it is
+        // done first and nothing has to be undone.
+        let guard = setup.stack.as_ptr() as usize & !(PAGE_SIZE - 1);
+        if setup.stack.len() > PAGE_SIZE {
+            crate::mem::MemoryManager::with_mut(|mm| {
+                crate::arch::mem::make_guard_page(mm, guard)
+            })?;
+        }
+
         // Create the new context and set it to run in the new address space.
+        #[cfg(feature = "stack-watermark")]
+        crate::arch::stack::track(
+            self.pid,
+            new_tid,
+            setup.stack.as_ptr() as usize,
+            setup.stack.len(),
+        );
         let pid = self.pid.get();
         let thread = self.thread_mut(new_tid);
         // println!("Setting up thread {}, pid {}", new_tid, pid);
@@ -345,6 +415,21 @@ impl Process {
         Ok(())
     }
 
+    /// Return saved register `index` of thread `tid`, where 0 is the
+    /// program counter and 1 through 31 are `x1` through `x31`.  Returns
+    /// `None` if the thread is not in use.
+    pub fn thread_register(&self, tid: TID, index: usize) -> Option<usize> {
+        let process = unsafe { &*PROCESS };
+        let thread = process.threads.get(tid)?;
+        if thread.sepc == 0 {
+            return None;
+        }
+        match index {
+            0 => Some(thread.sepc),
+            n => thread.registers.get(n - 1).copied(),
+        }
+    }
+
     pub fn print_thread(&self) {
         let _thread = self.current_thread();
         println!(
@@ -383,6 +468,12 @@ impl Process {
             "A4:{:08x}   A5:{:08x}   A6:{:08x}   A7:{:08x}",
             _thread.registers[13], _thread.registers[14], _thread.registers[15], _thread.registers[16]
         );
+        #[cfg(any(feature = "debug-print", feature = "print-panics"))]
+        for (name, addr) in &[("PC", _thread.sepc), ("RA", _thread.registers[0])] {
+            if let Some(symbol) = symbolize(self.pid, *addr) {
+                println!("{} is in {}", name, symbol);
+            }
+        }
     }
 
     pub fn create(_pid: PID, _init_data: ProcessInit) -> PID {
@@ -390,6 +481,8 @@ impl Process {
     }
 
     pub fn destroy(_pid: PID) -> Result<(), xous_kernel::Error> {
+        #[cfg(feature = "stack-watermark")]
+        crate::arch::stack::forget(_pid);
         todo!();
         // let mut process_table = unsafe { &mut *PROCESS };
         // let pid_idx = pid.get() as usize - 1;
@@ -410,6 +503,18 @@ impl Thread {
     }
 }
 
+/// Resolve an address in the given process to a function name. Addresses
+/// in the kernel's region are looked up in the kernel's own symbols.
+#[cfg(any(feature = "debug-print", feature = "print-panics"))]
+pub fn symbolize(pid: PID, addr: usize) -> Option<crate::symbols::Symbol> {
+    let pid = if addr >= crate::arch::mem::USER_AREA_END {
+        unsafe { PID::new_unchecked(1) }
+    } else {
+        pid
+    };
+    crate::symbols::lookup(pid, addr)
+}
+
 pub fn set_current_pid(pid: PID) {
     let pid_idx = (pid.get() - 1) as usize;
     unsafe {
@@ -419,12 +524,12 @@ pub fn set_current_pid(pid: PID) {
             None | Some(false) => panic!("PID {} does not exist", pid),
             _ => (),
         }
-        pt.current = pid;
+        pt.current[crate::arch::hart_id()] = pid;
     }
 }
 
 pub fn current_pid() -> PID {
-    unsafe { PROCESS_TABLE.current }
+    unsafe { PROCESS_TABLE.current[crate::arch::hart_id()] }
 }
 
 pub fn current_tid() -> TID {
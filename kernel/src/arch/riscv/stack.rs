@@ -0,0 +1,89 @@
+//! Stack high-water marks.
+//!
+//! Every page of a thread's stack is filled with `FILL_PATTERN` when the
+//! thread is set up, or when a reserved stack page is first touched.  The
+//! deepest word that no longer holds the pattern shows how far the stack
+//! has grown.  Pages that are still reserved have never been touched, so
+//! they are skipped without being allocated.
+
+use riscv::register::sstatus;
+use xous_kernel::{PID, TID};
+
+use crate::arch::mem::{virt_to_phys, PAGE_SIZE};
+use crate::watermark::StackTable;
+
+/// Written over every word of a fresh stack page
+pub const FILL_PATTERN: usize = 0x5354_4b21;
+
+/// The stacks being tracked.  This is only touched with interrupts
+/// disabled, so there is no contention.
+static mut STACKS: StackTable = StackTable::new();
+
+/// Fill the page at `virt` with the pattern.  The page must be mapped.
+unsafe fn fill_page(virt: usize) {
+    // Allow access to pages that have already been handed to userspace
+    sstatus::set_sum();
+    let page = virt as *mut usize;
+    for word in 0..PAGE_SIZE / core::mem::size_of::<usize>() {
+        page.add(word).write_volatile(FILL_PATTERN);
+    }
+    sstatus::clear_sum();
+}
+
+/// Start tracking the stack of a new thread, filling any pages that are
+/// already mapped.  Must be called with `pid`'s address space active and
+/// before the thread first runs.
+pub fn track(pid: PID, tid: TID, base: usize, size: usize) {
+    let base = base & !(PAGE_SIZE - 1);
+    let size = (size + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+    if !unsafe { STACKS.track(pid, tid, base, size) } {
+        return;
+    }
+    for page in (base..base + size).step_by(PAGE_SIZE) {
+        if virt_to_phys(page).is_ok() {
+            unsafe { fill_page(page) };
+        }
+    }
+}
+
+/// Stop tracking every stack in a process that is going away
+pub fn forget(pid: PID) {
+    unsafe { STACKS.forget(pid) };
+}
+
+/// Fill a newly-allocated page if it belongs to one of `pid`'s stacks
+pub fn fill_if_stack(pid: PID, virt: usize) {
+    let virt = virt & !(PAGE_SIZE - 1);
+    if unsafe { STACKS.contains(pid, virt) } {
+        unsafe { fill_page(virt) };
+    }
+}
+
+/// Return a `Scalar2` of the number of bytes of `tid`'s stack that have been
+/// used, and the size of the stack.  `pid`'s address space must be active.
+pub fn usage(pid: PID, tid: TID) -> Result<xous_kernel::Result, xous_kernel::Error> {
+    let (base, size) =
+        unsafe { STACKS.stack_of(pid, tid) }.ok_or(xous_kernel::Error::ThreadNotAvailable)?;
+    let top = base + size;
+    let mut used = 0;
+    // Stacks grow down, so the first changed word from the bottom is the
+    // deepest point the stack has reached.
+    for page in (base..top).step_by(PAGE_SIZE) {
+        // Reserved pages and guard pages have never been written
+        if virt_to_phys(page).is_err() {
+            continue;
+        }
+        unsafe { sstatus::set_sum() };
+        for addr in (page..page + PAGE_SIZE).step_by(core::mem::size_of::<usize>()) {
+            if unsafe { (addr as *const usize).read_volatile() } != FILL_PATTERN {
+                used = top - addr;
+                break;
+            }
+        }
+        unsafe { sstatus::clear_sum() };
+        if used != 0 {
+            break;
+        }
+    }
+    Ok(xous_kernel::Result::Scalar2(used, size))
+}
@@ -10,8 +10,17 @@ macro_rules! make_type {
     }};
 }
 
+#[cfg(baremetal)]
 static mut KERNEL_ARGUMENTS_BASE: *const u32 = 0 as *const u32;
 
+// Each hosted kernel runs on its own thread, and there is usually no
+// argument block at all.
+#[cfg(not(baremetal))]
+std::thread_local!(
+    static KERNEL_ARGUMENTS_BASE: core::cell::Cell<*const u32> =
+        core::cell::Cell::new(core::ptr::null())
+);
+
 pub struct KernelArguments {
     pub base: *const u32,
 }
@@ -24,14 +33,26 @@ pub struct KernelArgumentsIterator {
 
 #[allow(dead_code)]
 impl KernelArguments {
+    #[cfg(baremetal)]
     pub fn get() -> Self {
         KernelArguments { base: unsafe { KERNEL_ARGUMENTS_BASE } }
     }
 
+    #[cfg(not(baremetal))]
+    pub fn get() -> Self {
+        KernelArguments { base: KERNEL_ARGUMENTS_BASE.with(|base| base.get()) }
+    }
+
+    #[cfg(baremetal)]
     pub unsafe fn init(base: *const u32) {
         KERNEL_ARGUMENTS_BASE = base;
     }
 
+    #[cfg(not(baremetal))]
+    pub unsafe fn init(base: *const u32) {
+        KERNEL_ARGUMENTS_BASE.with(|b| b.set(base));
+    }
+
     pub fn iter(&self) -> KernelArgumentsIterator {
         KernelArgumentsIterator {
             base: self.base,
@@ -84,6 +105,35 @@ impl Iterator for KernelArgumentsIterator {
     }
 }
 
+/// Return half of the measurement the loader recorded in the `Meas` tag,
+/// along with the number of images measured.  The tag holds the count
+/// followed by the 32-byte measurement as eight words.  The image ships
+/// the tag zeroed and the loader always measures at least the kernel, so a
+/// count of zero means the tag was never filled in and there is no
+/// measurement to report.
+#[cfg(baremetal)]
+pub fn boot_measurement(half: usize) -> Result<xous_kernel::Result, xous_kernel::Error> {
+    if half > 1 {
+        return Err(xous_kernel::Error::InvalidSyscall);
+    }
+    let args = KernelArguments::get();
+    let record = args
+        .iter()
+        .find(|tag| tag.name == make_type!("Meas") && tag.data.len() == 9)
+        .ok_or(xous_kernel::Error::InvalidSyscall)?;
+    if record.data[0] == 0 {
+        return Err(xous_kernel::Error::InvalidSyscall);
+    }
+    let words = &record.data[1 + half * 4..5 + half * 4];
+    Ok(xous_kernel::Result::Scalar5(
+        words[0] as usize,
+        words[1] as usize,
+        words[2] as usize,
+        words[3] as usize,
+        record.data[0] as usize,
+    ))
+}
+
 impl fmt::Display for KernelArgument {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let tag_name_bytes = self.name.to_le_bytes();
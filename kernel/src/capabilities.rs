@@ -0,0 +1,127 @@
+use crate::args::KernelArguments;
+#[cfg(feature = "capabilities")]
+use xous_kernel::SysCall;
+use xous_kernel::{Capabilities, PID};
+
+/// How many denied operations to remember
+#[cfg(feature = "capabilities")]
+const AUDIT_LOG_SIZE: usize = 32;
+
+#[cfg(feature = "capabilities")]
+#[derive(Copy, Clone)]
+struct AuditEntry {
+    pid: PID,
+    syscall: usize,
+    arg1: usize,
+    arg2: usize,
+}
+
+// The kernel is single-threaded, and these are only touched while
+// handling a syscall.
+#[cfg(feature = "capabilities")]
+static mut AUDIT_LOG: [Option<AuditEntry>; AUDIT_LOG_SIZE] = [None; AUDIT_LOG_SIZE];
+#[cfg(feature = "capabilities")]
+static mut AUDIT_SEQUENCE: usize = 0;
+
+/// The capabilities required to perform the given call
+#[cfg(feature = "capabilities")]
+fn required(call: &SysCall) -> Capabilities {
+    match call {
+        SysCall::MapMemory(Some(_), _, _, _) => Capabilities::MAP_PHYSICAL,
+        SysCall::ClaimInterrupt(_, _, _) | SysCall::ClaimSharedInterrupt(_, _, _) => {
+            Capabilities::CLAIM_INTERRUPT
+        }
+        SysCall::CreateServer(_) => Capabilities::CREATE_SERVER,
+        SysCall::GetAuditEntry(_) => Capabilities::READ_AUDIT_LOG,
+        SysCall::PauseWorld | SysCall::ReadThreadRegisters(_, _, _) => Capabilities::DEBUG,
+        _ => Capabilities::empty(),
+    }
+}
+
+/// Look up the capabilities granted to `pid` in the `Caps` tag. Returns
+/// `None` if the process is unrestricted, either because it is the kernel
+/// or because the image has no capability table.
+///
+/// The tag is a list of `(pid, capabilities)` pairs. Processes that are not
+/// listed are granted nothing.
+fn granted(pid: PID) -> Option<Capabilities> {
+    if pid.get() == 1 {
+        return None;
+    }
+    let args = KernelArguments::get();
+    if args.base.is_null() {
+        return None;
+    }
+    for tag in args.iter() {
+        if tag.name != make_type!("Caps") {
+            continue;
+        }
+        let caps = tag
+            .data
+            .chunks_exact(2)
+            .find(|entry| entry[0] as usize == pid.get() as usize)
+            .map(|entry| Capabilities::from_bits_truncate(entry[1] as usize));
+        return Some(caps.unwrap_or_else(Capabilities::empty));
+    }
+    None
+}
+
+/// Returns `true` if `pid` was granted every capability in `caps`.
+///
+/// Unlike `check()`, this is compiled in even without the `capabilities`
+/// feature, for the few calls that must never be open to everyone.  An
+/// image without a capability table grants these to nobody but PID 1.
+pub fn holds(pid: PID, caps: Capabilities) -> bool {
+    pid.get() == 1 || granted(pid).map(|granted| granted.contains(caps)).unwrap_or(false)
+}
+
+/// Make sure `pid` is allowed to perform `call`, recording it in the audit
+/// log if it is not.
+#[cfg(feature = "capabilities")]
+pub fn check(pid: PID, call: &SysCall) -> Result<(), xous_kernel::Error> {
+    let required = required(call);
+    if required.is_empty() {
+        return Ok(());
+    }
+    match granted(pid) {
+        Some(caps) if !caps.contains(required) => {
+            let args = call.as_args();
+            println!(
+                "KERNEL({}): denied syscall {} ({:?} not granted)",
+                pid, args[0], required
+            );
+            unsafe {
+                AUDIT_LOG[AUDIT_SEQUENCE % AUDIT_LOG_SIZE] = Some(AuditEntry {
+                    pid,
+                    syscall: args[0],
+                    arg1: args[1],
+                    arg2: args[2],
+                });
+                AUDIT_SEQUENCE += 1;
+            }
+            Err(xous_kernel::Error::AccessDenied)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Return the oldest audit entry with a sequence number of at least
+/// `sequence`, or `Ok` if there is none.
+#[cfg(feature = "capabilities")]
+pub fn audit_entry(sequence: usize) -> xous_kernel::Result {
+    let next = unsafe { AUDIT_SEQUENCE };
+    if sequence >= next {
+        return xous_kernel::Result::Ok;
+    }
+    let sequence = sequence.max(next.saturating_sub(AUDIT_LOG_SIZE));
+    match unsafe { AUDIT_LOG[sequence % AUDIT_LOG_SIZE] } {
+        Some(entry) => xous_kernel::Result::Scalar5(
+            sequence,
+            entry.pid.get() as usize,
+            entry.syscall,
+            entry.arg1,
+            entry.arg2,
+        ),
+        None => xous_kernel::Result::Ok,
+    }
+}
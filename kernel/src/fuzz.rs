@@ -0,0 +1,147 @@
+//! Feed arbitrary bytes to the syscall handler.
+//!
+//! Hosted kernel state is thread-local, so every input gets a fresh kernel
+//! by running in a new thread.  The input is split into `CALL_SIZE`-byte
+//! chunks: a syscall number followed by seven little-endian 32-bit
+//! arguments.  Each chunk is decoded with `SysCall::from_args()` and handed
+//! to `syscall::handle()` as though a client process had sent it.  The
+//! kernel may refuse any call, but it must not panic.
+//!
+//! The tests run this on random input.  Building with the `fuzzing`
+//! feature exports `fuzz_syscall()` from the kernel library, so the
+//! cargo-fuzz target in `fuzz/` can drive it too.
+
+use std::convert::TryInto;
+use std::io::Read;
+use std::net::{TcpListener, TcpStream};
+
+use crate::arch::process::Process as ArchProcess;
+use crate::services::SystemServices;
+use xous_kernel::syscall::SysCallNumber;
+use xous_kernel::{Message, ProcessInit, ProcessKey, SysCall, ThreadInit, PID};
+
+/// Bytes consumed by each syscall
+pub const CALL_SIZE: usize = 1 + 7 * 4;
+
+/// Buffers attached to messages are allocated for real, so cap their size
+const MAX_BUFFER: usize = 65536;
+
+/// The thread that every call is made from
+const FUZZ_TID: usize = 1;
+
+/// Replace the address of a memory buffer with a real allocation of the
+/// same size, the way the hosted transport does.  Returns `false` if the
+/// buffer is too big.
+fn attach_buffer(buf: &mut xous_kernel::MemoryRange) -> bool {
+    if buf.len() > MAX_BUFFER {
+        return false;
+    }
+    let data = vec![0u8; buf.len()].into_boxed_slice();
+    buf.addr = xous_kernel::MemoryAddress::new(Box::into_raw(data) as *mut u8 as usize).unwrap();
+    true
+}
+
+/// Decode one chunk of input into a syscall that a hosted client could
+/// have made, or `None` if it can't be represented.
+fn decode(chunk: &[u8]) -> Option<SysCall> {
+    let mut args = [0usize; 8];
+    args[0] = chunk[0] as usize % (SysCallNumber::Invalid as usize + 1);
+    for (arg, bytes) in args[1..].iter_mut().zip(chunk[1..].chunks_exact(4)) {
+        *arg = u32::from_le_bytes(bytes.try_into().unwrap()) as usize;
+    }
+    let mut call = SysCall::from_args(
+        args[0], args[1], args[2], args[3], args[4], args[5], args[6], args[7],
+    )
+    .ok()?;
+    let fits = match &mut call {
+        // There are no interrupts, page tables or context switches when
+        // hosted, so the arch layer leaves these unimplemented.
+        SysCall::ClaimInterrupt(..)
+        | SysCall::ClaimSharedInterrupt(..)
+        | SysCall::FreeInterrupt(..)
+        | SysCall::SwitchTo(..)
+        | SysCall::WaitEvent
+        | SysCall::ReturnToParent(..)
+        | SysCall::MapMemory(..) => false,
+        SysCall::SendMessage(_, message) | SysCall::TrySendMessage(_, message) => match message {
+            Message::MutableBorrow(msg) | Message::Borrow(msg) | Message::Move(msg) => {
+                attach_buffer(&mut msg.buf)
+            }
+            Message::Scalar(_) | Message::BlockingScalar(_) => true,
+        },
+        SysCall::ReturnMemory(_, buf) => attach_buffer(buf),
+        _ => true,
+    };
+    if fits {
+        Some(call)
+    } else {
+        None
+    }
+}
+
+/// Set up PID 1 and a client process connected over loopback, the way
+/// `arch::idle()` does.  Responses sent to the client are discarded.
+fn start_client() -> PID {
+    let pid1 = SystemServices::with_mut(|ss| {
+        ss.create_process(ProcessInit {
+            key: ProcessKey::new([0; 16]),
+        })
+    })
+    .unwrap();
+    crate::arch::process::set_current_pid(pid1);
+
+    let key = ProcessKey::new([1; 16]);
+    let pid = SystemServices::with_mut(|ss| ss.create_process(ProcessInit { key })).unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (conn, _) = listener.accept().unwrap();
+    std::thread::spawn(move || {
+        let mut sink = [0u8; 4096];
+        while let Ok(len) = client.read(&mut sink) {
+            if len == 0 {
+                break;
+            }
+        }
+    });
+    crate::arch::process::register_connection_for_key(conn, key).unwrap();
+
+    SystemServices::with_mut(|ss| {
+        ss.create_thread(pid, ThreadInit {})?;
+        ss.switch_to_thread(pid, None)
+    })
+    .unwrap();
+    pid
+}
+
+/// Run the syscalls encoded in `data` against a fresh kernel.  Panics if
+/// the kernel does.
+pub fn fuzz_syscall(data: &[u8]) {
+    let data = data.to_vec();
+    std::thread::spawn(move || {
+        let pid = start_client();
+        for chunk in data.chunks_exact(CALL_SIZE) {
+            let call = match decode(chunk) {
+                Some(call) => call,
+                None => continue,
+            };
+            let last = call == SysCall::TerminateProcess || call == SysCall::Shutdown;
+            crate::arch::process::set_current_pid(pid);
+            let result = crate::syscall::handle(pid, FUZZ_TID, false, call);
+            // A blocked thread can't make any more calls, and neither can
+            // a process that has gone away.
+            if last || result == Ok(xous_kernel::Result::BlockedProcess) {
+                break;
+            }
+            if let Ok(response) = result {
+                let mut packet = FUZZ_TID.to_le_bytes().to_vec();
+                for word in response.to_args().iter() {
+                    packet.extend_from_slice(&word.to_le_bytes());
+                }
+                ArchProcess::current().send(&packet).unwrap();
+            }
+        }
+    })
+    .join()
+    .expect("kernel panicked");
+}
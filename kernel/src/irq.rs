@@ -1,66 +1,299 @@
 use crate::arch;
 use xous_kernel::{MemoryAddress, PID};
 
-static mut IRQ_HANDLERS: [Option<(PID, MemoryAddress, Option<MemoryAddress>)>; 32] = [None; 32];
+type IrqHandler = (PID, MemoryAddress, Option<MemoryAddress>);
 
+/// How many processes can share one interrupt line
+pub const MAX_SHARED: usize = 4;
+
+#[derive(Copy, Clone)]
+struct IrqLine {
+    /// Handlers are called in slot order
+    handlers: [Option<IrqHandler>; MAX_SHARED],
+
+    /// Set if the handlers claimed this line with `ClaimSharedInterrupt`
+    shared: bool,
+}
+
+const FREE_LINE: IrqLine = IrqLine {
+    handlers: [None; MAX_SHARED],
+    shared: false,
+};
+
+static mut IRQ_HANDLERS: [IrqLine; 32] = [FREE_LINE; 32];
+
+/// The shared interrupt whose handlers each hart is calling, and the slot
+/// to look at next.  Only touched by the owning hart from its trap handler,
+/// with interrupts disabled, so it needs no lock.
 #[cfg(baremetal)]
-pub fn handle(irqs_pending: usize) -> Result<xous_kernel::Result, xous_kernel::Error> {
+static mut CHAIN: [Option<(usize, usize)>; arch::MAX_HARTS] = [None; arch::MAX_HARTS];
+
+/// Guards `IRQ_HANDLERS` against other harts.  A single hart only ever touches
+/// the table with interrupts disabled, so no lock is needed there.
+#[cfg(feature = "multicore")]
+static IRQ_HANDLERS_LOCK: core::sync::atomic::AtomicBool = core::sync::atomic::AtomicBool::new(false);
+
+/// Run `f` with exclusive access to the IRQ handler table.
+fn with_irq_handlers<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut [IrqLine; 32]) -> R,
+{
+    #[cfg(feature = "multicore")]
+    {
+        use core::sync::atomic::Ordering;
+        while IRQ_HANDLERS_LOCK
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+    }
+    let result = f(unsafe { &mut IRQ_HANDLERS });
+    #[cfg(feature = "multicore")]
+    IRQ_HANDLERS_LOCK.store(false, core::sync::atomic::Ordering::Release);
+    result
+}
+
+/// The first handler for `irq_no` at or after `slot`.  The handler is
+/// copied out of the table so that the lock isn't held while the callback
+/// is being set up.
+#[cfg(baremetal)]
+fn next_handler(irq_no: usize, slot: usize) -> Option<(usize, IrqHandler)> {
+    with_irq_handlers(|lines| {
+        lines[irq_no].handlers[slot..]
+            .iter()
+            .enumerate()
+            .find_map(|(idx, handler)| handler.map(|handler| (slot + idx, handler)))
+    })
+}
+
+#[cfg(baremetal)]
+fn dispatch(
+    irq_no: usize,
+    slot: usize,
+    (pid, f, arg): IrqHandler,
+) -> Result<xous_kernel::Result, xous_kernel::Error> {
     use crate::services::SystemServices;
-    // Unsafe is required here because we're accessing a static
-    // mutable value, and it could be modified from various threads.
-    // However, this is fine because this is run from an IRQ context
-    // with interrupts disabled.
-    // NOTE: This will become an issue when running with multiple cores,
-    // so this should be protected by a mutex.
-    unsafe {
-        for irq_no in 0..IRQ_HANDLERS.len() {
-            if irqs_pending & (1 << irq_no) != 0 {
-                if let Some((pid, f, arg)) = IRQ_HANDLERS[irq_no] {
-                    return SystemServices::with_mut(|ss| {
-                        // Disable all other IRQs and redirect into userspace
-                        arch::irq::disable_all_irqs();
-                        // println!("Making a callback to PID{}: {:08x} ({:08x}, {:08x})", pid, f as usize, irq_no as usize, arg as usize);
-                        ss.make_callback_to(
-                            pid,
-                            f.get() as *mut usize,
-                            irq_no,
-                            arg.map(|x| x.get() as *mut usize)
-                                .unwrap_or(core::ptr::null_mut::<usize>()),
-                        )
-                        .map(|_| xous_kernel::Result::ResumeProcess)
-                    });
-                } else {
-                    // If there is no handler, mask this interrupt
-                    // to prevent an IRQ storm.  This is considered
-                    // an error.
-                    arch::irq::disable_irq(irq_no)?;
-                }
+    unsafe { CHAIN[arch::hart_id()] = Some((irq_no, slot + 1)) };
+    #[cfg(feature = "irq-latency")]
+    latency::dispatch(irq_no);
+    SystemServices::with_mut(|ss| {
+        // Disable all other IRQs and redirect into userspace
+        arch::irq::disable_all_irqs();
+        // println!("Making a callback to PID{}: {:08x} ({:08x}, {:08x})", pid, f as usize, irq_no as usize, arg as usize);
+        ss.make_callback_to(
+            pid,
+            f.get() as *mut usize,
+            irq_no,
+            arg.map(|x| x.get() as *mut usize)
+                .unwrap_or(core::ptr::null_mut::<usize>()),
+        )
+        .map(|_| xous_kernel::Result::ResumeProcess)
+    })
+}
+
+#[cfg(baremetal)]
+pub fn handle(irqs_pending: usize) -> Result<xous_kernel::Result, xous_kernel::Error> {
+    // This is run from an IRQ context with interrupts disabled.
+    for irq_no in 0..32 {
+        if irqs_pending & (1 << irq_no) != 0 {
+            if let Some((slot, handler)) = next_handler(irq_no, 0) {
+                return dispatch(irq_no, slot, handler);
+            } else {
+                // If there is no handler, mask this interrupt
+                // to prevent an IRQ storm.  This is considered
+                // an error.
+                arch::irq::disable_irq(irq_no)?;
             }
         }
     }
     Ok(xous_kernel::Result::ResumeProcess)
 }
 
+/// Called once an interrupt handler has returned and the interrupted
+/// thread is current again.  If other processes share the interrupt that
+/// was just handled, set up a callback to the next one and return `true`.
+/// Interrupts stay disabled until every handler has run.
+#[cfg(baremetal)]
+pub fn dispatch_next(previous_pid: PID, previous_tid: xous_kernel::TID) -> bool {
+    let (irq_no, slot) = match unsafe { CHAIN[arch::hart_id()].take() } {
+        Some(chain) => chain,
+        None => return false,
+    };
+    let (slot, handler) = match next_handler(irq_no, slot) {
+        Some(next) => next,
+        None => return false,
+    };
+    unsafe { arch::irq::set_isr_return_pair(previous_pid, previous_tid) };
+    dispatch(irq_no, slot, handler).expect("couldn't call shared interrupt handler");
+    true
+}
+
+/// Claim `irq` for `pid`.  A line that is free is enabled straight away.
+/// A line that already has a handler can only be claimed if it and the
+/// new claim are both `shared`.
 pub fn interrupt_claim(
     irq: usize,
     pid: PID,
     f: MemoryAddress,
     arg: Option<MemoryAddress>,
+    shared: bool,
 ) -> Result<(), xous_kernel::Error> {
-    // Unsafe is required since we're accessing a static mut array.
-    // However, we disable interrupts to prevent contention on this array.
-    unsafe {
-        arch::irq::disable_all_irqs();
-        let result = if irq > IRQ_HANDLERS.len() {
-            Err(xous_kernel::Error::InterruptNotFound)
-        } else if IRQ_HANDLERS[irq].is_some() {
-            Err(xous_kernel::Error::InterruptInUse)
-        } else {
-            IRQ_HANDLERS[irq] = Some((pid, f, arg));
+    // Disable interrupts to prevent contention on this hart, and take
+    // the table lock to prevent contention with other harts.
+    arch::irq::disable_all_irqs();
+    let result = with_irq_handlers(|lines| {
+        let line = lines
+            .get_mut(irq)
+            .ok_or(xous_kernel::Error::InterruptNotFound)?;
+        if line.handlers.iter().all(|handler| handler.is_none()) {
+            line.handlers[0] = Some((pid, f, arg));
+            line.shared = shared;
             arch::irq::enable_irq(irq);
-            Ok(())
-        };
-        arch::irq::enable_all_irqs();
-        result
+            return Ok(());
+        }
+
+        // Each process is only called once per interrupt.
+        if !(shared && line.shared) || line.handlers.iter().flatten().any(|h| h.0 == pid) {
+            return Err(xous_kernel::Error::InterruptInUse);
+        }
+        let slot = line
+            .handlers
+            .iter_mut()
+            .find(|handler| handler.is_none())
+            .ok_or(xous_kernel::Error::InterruptInUse)?;
+        *slot = Some((pid, f, arg));
+        Ok(())
+    });
+    arch::irq::enable_all_irqs();
+    result
+}
+
+/// Remove `pid`'s handler for `irq`, masking the interrupt if that was the
+/// last one.
+pub fn interrupt_free(irq: usize, pid: PID) -> Result<(), xous_kernel::Error> {
+    arch::irq::disable_all_irqs();
+    let result = with_irq_handlers(|lines| {
+        let line = lines
+            .get_mut(irq)
+            .ok_or(xous_kernel::Error::InterruptNotFound)?;
+        let handler = line
+            .handlers
+            .iter_mut()
+            .find(|handler| matches!(handler, Some((owner, _, _)) if *owner == pid))
+            .ok_or(xous_kernel::Error::InterruptNotFound)?;
+        *handler = None;
+        if line.handlers.iter().all(|handler| handler.is_none()) {
+            line.shared = false;
+            arch::irq::disable_irq(irq)?;
+        }
+        Ok(())
+    });
+    arch::irq::enable_all_irqs();
+    result
+}
+
+/// Free every interrupt held by `pid`, which is exiting.
+pub fn release_all(pid: PID) {
+    for irq in 0..32 {
+        interrupt_free(irq, pid).ok();
+    }
+}
+
+/// Interrupt latency instrumentation. The cycle counter is read when the
+/// trap handler is entered, when the userspace handler is dispatched, and
+/// when it returns, and the differences are accumulated into per-IRQ
+/// histograms.  `arch::timestamp()` only counts milliseconds, which would
+/// put every sample in the first bucket.
+#[cfg(all(baremetal, feature = "irq-latency"))]
+pub mod latency {
+    /// Bucket `n` counts samples that took less than `2^(n+1)` cycles.
+    /// The final bucket also holds everything longer than that.
+    pub const BUCKETS: usize = 16;
+
+    #[derive(Copy, Clone)]
+    struct Histogram {
+        /// Trap entry to handler dispatch
+        dispatch: [u32; BUCKETS],
+
+        /// Handler dispatch to handler completion
+        handler: [u32; BUCKETS],
+
+        max_dispatch: u32,
+        max_handler: u32,
+        count: u32,
+    }
+
+    const EMPTY: Histogram = Histogram {
+        dispatch: [0; BUCKETS],
+        handler: [0; BUCKETS],
+        max_dispatch: 0,
+        max_handler: 0,
+        count: 0,
+    };
+
+    // These are only touched from the trap handler with interrupts
+    // disabled, or from a syscall, so there is no contention.
+    static mut HISTOGRAMS: [Histogram; 32] = [EMPTY; 32];
+    static mut ENTRY_TIME: u64 = 0;
+    static mut ACTIVE: Option<(usize, u64)> = None;
+
+    fn bucket(cycles: u64) -> usize {
+        let log2 = 63 - (cycles | 1).leading_zeros() as usize;
+        log2.min(BUCKETS - 1)
+    }
+
+    /// Worst-case times are kept as `u32`, which is many seconds of cycles
+    fn clamp(cycles: u64) -> u32 {
+        cycles.min(u32::MAX as u64) as u32
+    }
+
+    /// Called when the trap handler is entered because of an interrupt
+    pub fn entry() {
+        unsafe { ENTRY_TIME = crate::arch::cycles() };
+    }
+
+    /// Called just before `irq_no`'s userspace handler is invoked
+    pub fn dispatch(irq_no: usize) {
+        unsafe {
+            let now = crate::arch::cycles();
+            let cycles = now.wrapping_sub(ENTRY_TIME);
+            let hist = &mut HISTOGRAMS[irq_no];
+            hist.dispatch[bucket(cycles)] += 1;
+            hist.max_dispatch = hist.max_dispatch.max(clamp(cycles));
+            ACTIVE = Some((irq_no, now));
+        }
+    }
+
+    /// Called when a userspace handler returns to the kernel
+    pub fn complete() {
+        unsafe {
+            if let Some((irq_no, start)) = ACTIVE.take() {
+                let cycles = crate::arch::cycles().wrapping_sub(start);
+                let hist = &mut HISTOGRAMS[irq_no];
+                hist.handler[bucket(cycles)] += 1;
+                hist.max_handler = hist.max_handler.max(clamp(cycles));
+                hist.count = hist.count.wrapping_add(1);
+            }
+        }
+    }
+
+    /// Return one bucket of the dispatch and handler histograms for the
+    /// given IRQ, along with the worst-case times and the number of calls.
+    pub fn get(irq_no: usize, bucket: usize) -> Result<xous_kernel::Result, xous_kernel::Error> {
+        if irq_no >= 32 {
+            return Err(xous_kernel::Error::InterruptNotFound);
+        }
+        if bucket >= BUCKETS {
+            return Err(xous_kernel::Error::InvalidSyscall);
+        }
+        let hist = unsafe { &HISTOGRAMS[irq_no] };
+        Ok(xous_kernel::Result::Scalar5(
+            hist.dispatch[bucket] as usize,
+            hist.handler[bucket] as usize,
+            hist.max_dispatch as usize,
+            hist.max_handler as usize,
+            hist.count as usize,
+        ))
     }
 }
@@ -0,0 +1,31 @@
+//! The hosted kernel as a library, so that the cargo-fuzz target in `fuzz/`
+//! can hand its input straight to the syscall handler.
+//!
+//! The kernel itself is the binary in `main.rs`.  Without the `fuzzing`
+//! feature this library is empty, and it is never built for the device.
+#![cfg_attr(baremetal, no_std)]
+#![cfg(all(feature = "fuzzing", not(baremetal)))]
+// Only the syscall path is reachable from `fuzz_syscall()`, so much of the
+// kernel looks unused from here.
+#![allow(dead_code)]
+
+#[macro_use]
+mod debug;
+
+mod arch;
+
+#[macro_use]
+mod args;
+mod capabilities;
+mod fuzz;
+mod irq;
+mod macros;
+mod mem;
+mod pagelist;
+mod quota;
+mod sandbox;
+mod server;
+mod services;
+mod syscall;
+
+pub use fuzz::{fuzz_syscall, CALL_SIZE};
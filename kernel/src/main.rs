@@ -10,17 +10,27 @@ mod debug;
 
 #[cfg(all(test, not(baremetal)))]
 mod test;
+#[cfg(all(test, not(baremetal)))]
+mod fuzz;
 
 mod arch;
 
 #[macro_use]
 mod args;
+mod capabilities;
 mod irq;
 mod macros;
 mod mem;
+mod pagelist;
+mod quota;
+mod sandbox;
 mod server;
 mod services;
+#[cfg(all(baremetal, any(feature = "debug-print", feature = "print-panics")))]
+mod symbols;
 mod syscall;
+#[cfg(any(all(test, not(baremetal)), all(baremetal, feature = "stack-watermark")))]
+mod watermark;
 
 use services::SystemServices;
 use xous_kernel::*;
@@ -96,35 +106,38 @@ fn next_pid_to_run(last_pid: Option<PID>) -> Option<PID> {
     // 1 from the PID when we use it as an array index, we automatically
     // pick the next process in the list.
     let current_pid = last_pid.unwrap_or(unsafe { PID::new_unchecked(1) }).get() as usize;
+    let now = arch::timestamp();
 
-    SystemServices::with(|system_services| {
-        for test_idx in current_pid..system_services.processes.len() {
-            if system_services.processes[test_idx].ppid.get() == 1 {
-                // print!("PID {} is owned by PID1... ", test_idx + 1);
-                if system_services.processes[test_idx].runnable() {
-                    // println!(" and is runnable");
-                    return match pid_from_usize(test_idx + 1) {
-                        Ok(x) => Some(x),
-                        Err(_) => None,
-                    };
-                }
-                // println!(" and is NOT RUNNABLE");
-            }
+    SystemServices::with_mut(|system_services| {
+        // While the world is paused, only the debug agent runs.
+        if let Some(agent) = system_services.paused_by() {
+            return system_services
+                .get_process(agent)
+                .ok()
+                .filter(|process| process.runnable())
+                .map(|_| agent);
         }
-        for test_idx in 0..current_pid {
-            if system_services.processes[test_idx].ppid.get() == 1 {
-                // print!("PID {} is owned by PID1... ", test_idx + 1);
-                if system_services.processes[test_idx].runnable() {
-                    // println!(" and is runnable");
-                    return match pid_from_usize(test_idx + 1) {
-                        Ok(x) => Some(x),
-                        Err(_) => None,
-                    };
-                }
-                // println!(" and is NOT RUNNABLE");
+
+        let processes = &mut system_services.processes;
+        let candidates = (current_pid..processes.len()).chain(0..current_pid);
+        let runnable = |idx: usize| processes[idx].ppid.get() == 1 && processes[idx].runnable();
+
+        // Processes that have used up their CPU quota only get to run if
+        // nothing else can.
+        let next_idx = match candidates
+            .clone()
+            .find(|&idx| runnable(idx) && !processes[idx].cpu.over_quota(now))
+        {
+            Some(next_idx) => next_idx,
+            None => candidates.clone().find(|&idx| runnable(idx))?,
+        };
+        for idx in candidates.filter(|&idx| idx != next_idx) {
+            let process = &mut processes[idx];
+            if process.ppid.get() == 1 && process.runnable() && process.cpu.over_quota(now) {
+                process.cpu.throttle();
             }
         }
-        None
+        pid_from_usize(next_idx + 1).ok()
     })
 }
 
@@ -153,8 +166,11 @@ pub extern "C" fn kmain() {
             Some(pid) => {
                 // #[cfg(feature = "debug-print")]
                 // klog!("switching to pid {}", pid);
+                let start = arch::timestamp();
                 xous_kernel::rsyscall(xous_kernel::SysCall::SwitchTo(pid, 0))
                     .expect("couldn't switch to pid");
+                let end = arch::timestamp();
+                SystemServices::with_mut(|ss| ss.charge_cpu(pid, start, end));
             }
             None => {
                 #[cfg(feature = "debug-print")]
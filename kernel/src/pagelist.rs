@@ -0,0 +1,149 @@
+// Hosted kernels can't reach into a client's memory to read a list, so
+// outside of the tests only the types are used there.
+#![cfg_attr(not(baremetal), allow(dead_code))]
+
+use xous_kernel::{PID, MAX_PAGE_LIST};
+
+use crate::mem::PAGE_SIZE;
+
+/// How many page lists can be lent out at once, across all processes
+pub const MAX_PAGE_LISTS: usize = 8;
+
+/// Pages lent to a server as one buffer, gathered from several ranges in
+/// the client.  The server sees the ranges back to back, in order.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PageList {
+    /// `(address, length)` of each range in the client.  Only the first
+    /// `count` are used.
+    ranges: [(usize, usize); MAX_PAGE_LIST],
+    count: usize,
+}
+
+impl PageList {
+    /// Build a list out of the `(address, length)` pairs in `words`, as the
+    /// client laid them out.  Every range must be page-aligned and
+    /// non-empty.
+    ///
+    /// # Errors
+    ///
+    /// * **BadAddress**: There are no ranges or too many, a range is empty,
+    ///   or a range runs off the end of the address space
+    /// * **BadAlignment**: A range isn't page-aligned
+    pub fn parse(words: &[usize]) -> Result<PageList, xous_kernel::Error> {
+        if words.is_empty() || words.len() % 2 != 0 || words.len() / 2 > MAX_PAGE_LIST {
+            return Err(xous_kernel::Error::BadAddress);
+        }
+        let mut list = PageList {
+            ranges: [(0, 0); MAX_PAGE_LIST],
+            count: words.len() / 2,
+        };
+        let mut total: usize = 0;
+        for (range, pair) in list.ranges.iter_mut().zip(words.chunks_exact(2)) {
+            let (addr, len) = (pair[0], pair[1]);
+            if addr == 0 || len == 0 {
+                return Err(xous_kernel::Error::BadAddress);
+            }
+            if addr & (PAGE_SIZE - 1) != 0 || len & (PAGE_SIZE - 1) != 0 {
+                return Err(xous_kernel::Error::BadAlignment);
+            }
+            addr.checked_add(len).ok_or(xous_kernel::Error::BadAddress)?;
+            total = total.checked_add(len).ok_or(xous_kernel::Error::BadAddress)?;
+            *range = (addr, len);
+        }
+        Ok(list)
+    }
+
+    /// Total length of the ranges, which is the size of the server's buffer
+    pub fn len(&self) -> usize {
+        self.ranges[..self.count].iter().map(|&(_, len)| len).sum()
+    }
+
+    /// Every page in the list, as its address in the client and its offset
+    /// in the server's buffer
+    pub fn pages(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let mut offset = 0;
+        self.ranges[..self.count].iter().flat_map(move |&(addr, len)| {
+            let start = offset;
+            offset += len;
+            (0..len)
+                .step_by(PAGE_SIZE)
+                .map(move |page| (addr + page, start + page))
+        })
+    }
+}
+
+/// A page list that has been lent, and where it went
+#[derive(Copy, Clone)]
+struct Lent {
+    client: PID,
+    server: PID,
+    server_addr: usize,
+    list: PageList,
+}
+
+/// The page lists that have been lent and not yet returned.  A borrow only
+/// remembers one client address, so the rest of the list lives here until
+/// the server gives the pages back.
+pub struct PageListTable {
+    lent: [Option<Lent>; MAX_PAGE_LISTS],
+}
+
+impl PageListTable {
+    pub const fn new() -> PageListTable {
+        PageListTable {
+            lent: [None; MAX_PAGE_LISTS],
+        }
+    }
+
+    /// Remember that `list` was lent from `client` to `server`, landing at
+    /// `server_addr`.
+    ///
+    /// # Errors
+    ///
+    /// * **OutOfMemory**: Too many page lists are already out
+    pub fn insert(
+        &mut self,
+        client: PID,
+        server: PID,
+        server_addr: usize,
+        list: PageList,
+    ) -> Result<(), xous_kernel::Error> {
+        let slot = self
+            .lent
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(xous_kernel::Error::OutOfMemory)?;
+        *slot = Some(Lent {
+            client,
+            server,
+            server_addr,
+            list,
+        });
+        Ok(())
+    }
+
+    /// Returns `true` if another page list can be lent
+    pub fn has_room(&self) -> bool {
+        self.lent.iter().any(|slot| slot.is_none())
+    }
+
+    /// Stop tracking the list that `server` holds at `server_addr`,
+    /// returning it and the client it came from.  Returns `None` for an
+    /// ordinary borrow.
+    pub fn take(&mut self, server: PID, server_addr: usize) -> Option<(PID, PageList)> {
+        self.lent
+            .iter_mut()
+            .find(|slot| matches!(slot, Some(l) if l.server == server && l.server_addr == server_addr))
+            .and_then(|slot| slot.take())
+            .map(|l| (l.client, l.list))
+    }
+
+    /// Forget every list lent to `server`, because it has exited
+    pub fn forget_server(&mut self, server: PID) {
+        for slot in self.lent.iter_mut() {
+            if matches!(slot, Some(l) if l.server == server) {
+                *slot = None;
+            }
+        }
+    }
+}
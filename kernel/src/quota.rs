@@ -0,0 +1,95 @@
+/// Length of a CPU accounting window, in units of the platform timer.  The
+/// ticktimer counts milliseconds, so this is about a second.
+pub const WINDOW: u64 = 1 << 10;
+
+/// CPU time used by one process, and how much it is allowed to use.
+///
+/// The kernel has no periodic tick, so a process is only charged for the
+/// time between being switched to and returning to the scheduler, and a
+/// quota is only enforced when the scheduler picks the next process.
+/// Processes that have used up their share of the current window are
+/// passed over while anything else is runnable, so a busy background
+/// process can't starve the rest of the system.  If they are the only
+/// processes with work to do they still run, because nothing would wake
+/// the CPU at the end of the window otherwise.
+#[derive(Copy, Clone, PartialEq)]
+pub struct CpuAccount {
+    /// Percentage of each window this process may use.  100 means the
+    /// process is unlimited.
+    quota: usize,
+
+    /// When the current window started
+    window_start: u64,
+
+    /// Time used since `window_start`
+    window_used: u64,
+
+    /// Time used since the process was created
+    total: u64,
+
+    /// How many times the scheduler passed this process over
+    throttled: usize,
+}
+
+impl CpuAccount {
+    pub const fn new() -> CpuAccount {
+        CpuAccount {
+            quota: 100,
+            window_start: 0,
+            window_used: 0,
+            total: 0,
+            throttled: 0,
+        }
+    }
+
+    /// Limit the process to `percent` of each window.
+    pub fn set_quota(&mut self, percent: usize) -> Result<(), xous_kernel::Error> {
+        if percent == 0 || percent > 100 {
+            return Err(xous_kernel::Error::InvalidSyscall);
+        }
+        self.quota = percent;
+        Ok(())
+    }
+
+    /// Start a new window if the current one is over.
+    fn roll_window(&mut self, now: u64) {
+        if now.wrapping_sub(self.window_start) >= WINDOW {
+            self.window_start = now;
+            self.window_used = 0;
+        }
+    }
+
+    /// Charge the process for running from `start` until `end`.
+    pub fn charge(&mut self, start: u64, end: u64) {
+        self.roll_window(start);
+        let used = end.wrapping_sub(start);
+        self.window_used += used;
+        self.total += used;
+    }
+
+    /// Returns `true` if the process has used up its share of the window
+    /// that `now` falls in.
+    pub fn over_quota(&self, now: u64) -> bool {
+        self.quota < 100
+            && now.wrapping_sub(self.window_start) < WINDOW
+            && self.window_used * 100 >= WINDOW * self.quota as u64
+    }
+
+    /// Note that the scheduler passed this process over.
+    pub fn throttle(&mut self) {
+        self.throttled = self.throttled.wrapping_add(1);
+    }
+
+    /// Return the quota, the total time used (low, high), the time used in
+    /// the current window, and the number of times the process was passed
+    /// over.
+    pub fn stats(&self) -> xous_kernel::Result {
+        xous_kernel::Result::Scalar5(
+            self.quota,
+            self.total as u32 as usize,
+            (self.total >> 32) as usize,
+            self.window_used.min(u32::MAX as u64) as usize,
+            self.throttled,
+        )
+    }
+}
@@ -0,0 +1,84 @@
+use xous_kernel::{SysCall, PID, SID};
+
+/// How many servers a sandboxed process can be allowed to connect to
+pub const MAX_ALLOWED_SERVERS: usize = 4;
+
+/// Restrictions placed on a process by its parent before it starts running.
+/// A sandboxed process may only make the syscalls an ordinary client needs:
+/// managing its own memory and threads, and sending and receiving
+/// messages.  It may only connect to the servers on its allow-list.
+/// Processes it creates inherit the same restrictions.
+#[derive(Copy, Clone, PartialEq)]
+pub struct Sandbox {
+    allowed: [Option<SID>; MAX_ALLOWED_SERVERS],
+}
+
+impl Sandbox {
+    pub const fn new() -> Sandbox {
+        Sandbox {
+            allowed: [None; MAX_ALLOWED_SERVERS],
+        }
+    }
+
+    pub fn allows(&self, sid: SID) -> bool {
+        self.allowed.contains(&Some(sid))
+    }
+
+    /// Add `sid` to the allow-list.
+    pub fn allow(&mut self, sid: SID) -> Result<(), xous_kernel::Error> {
+        if self.allows(sid) {
+            return Ok(());
+        }
+        let slot = self
+            .allowed
+            .iter_mut()
+            .find(|s| s.is_none())
+            .ok_or(xous_kernel::Error::OutOfMemory)?;
+        *slot = Some(sid);
+        Ok(())
+    }
+
+    /// Make sure the sandboxed process `pid` is allowed to perform `call`.
+    ///
+    /// This is an allow-list, so that syscalls added later are denied until
+    /// someone decides otherwise.
+    pub fn check(&self, pid: PID, call: &SysCall) -> Result<(), xous_kernel::Error> {
+        let allowed = match call {
+            SysCall::MapMemory(None, _, _, _)
+            | SysCall::UnmapMemory(_)
+            | SysCall::IncreaseHeap(_, _)
+            | SysCall::DecreaseHeap(_)
+            | SysCall::UpdateMemoryFlags(_, _, _)
+            | SysCall::GetMemoryFlags(_)
+            | SysCall::Yield
+            | SysCall::WaitEvent
+            | SysCall::ReturnToParent(_, _)
+            | SysCall::ReceiveMessage(_)
+            | SysCall::SendMessage(_, _)
+            | SysCall::TrySendMessage(_, _)
+            | SysCall::LendPages(_, _)
+            | SysCall::Disconnect(_)
+            | SysCall::ReturnMemory(_, _)
+            | SysCall::CreateThread(_)
+            | SysCall::CreateProcess(_)
+            | SysCall::TerminateProcess
+            | SysCall::GetProcessId
+            | SysCall::GetStackUsage(_)
+            | SysCall::GetTimestamp
+            // Let the kernel reject these as it would for anyone else
+            | SysCall::Invalid(_, _, _, _, _, _, _) => true,
+            SysCall::Connect(sid) | SysCall::TryConnect(sid) => self.allows(*sid),
+            _ => false,
+        };
+        if allowed {
+            Ok(())
+        } else {
+            println!(
+                "KERNEL({}): sandbox denied syscall {}",
+                pid,
+                call.as_args()[0]
+            );
+            Err(xous_kernel::Error::AccessDenied)
+        }
+    }
+}
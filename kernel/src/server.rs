@@ -1,5 +1,9 @@
 pub use crate::arch::process::Thread;
 use core::mem;
+
+/// How many senders can wait for room in one server's queue.  Any more
+/// than this poll instead.
+pub const MAX_BLOCKED_SENDERS: usize = 8;
 use xous_kernel::{MemoryAddress, MemoryRange, MemorySize, Message, PID, SID, TID};
 
 pub struct SenderID {
@@ -189,10 +193,18 @@ pub struct Server {
     #[cfg(not(baremetal))]
     queue: Vec<QueuedMessage>,
 
+    /// How many messages may wait to be received before senders are
+    /// turned away
+    queue_limit: usize,
+
     /// The `context mask` is a bitfield of contexts that are able to handle
     /// this message. If there are no available contexts, then messages will
     /// need to be queued.
     ready_threads: usize,
+
+    /// Threads that tried to send a message while the queue was full, in
+    /// the order they arrived.  They sleep until a slot frees up.
+    blocked_senders: [Option<(PID, TID)>; MAX_BLOCKED_SENDERS],
 }
 
 impl Server {
@@ -236,8 +248,10 @@ impl Server {
             pid,
             queue_head: 0,
             queue_tail: 0,
+            queue_limit: queue.len(),
             queue,
             ready_threads: 0,
+            blocked_senders: [None; MAX_BLOCKED_SENDERS],
         });
         Ok(())
     }
@@ -322,6 +336,18 @@ impl Server {
                 _ => (),
             }
         }
+
+        // Its threads are gone, so there is nothing to wake.
+        let mut kept = 0;
+        for idx in 0..MAX_BLOCKED_SENDERS {
+            match self.blocked_senders[idx].take() {
+                Some((sender_pid, _)) if sender_pid == pid => (),
+                sender => {
+                    self.blocked_senders[kept] = sender;
+                    kept += 1;
+                }
+            }
+        }
     }
 
     /// Convert a `QueuedMesage::WaitingReturnMemory` into `QueuedMessage::Empty`
@@ -611,11 +637,42 @@ impl Server {
         Some(result)
     }
 
+    /// Set how many messages may wait in the queue, clamped to the size of
+    /// the queue.  Messages already queued are kept.  Returns the new limit.
+    pub fn set_queue_limit(&mut self, limit: usize) -> usize {
+        self.queue_limit = limit.max(1).min(self.queue.len());
+        self.queue_limit
+    }
+
+    /// The number of messages that have been queued but not yet received.
+    /// Slots held by messages waiting for a response don't count.
+    pub fn pending_messages(&self) -> usize {
+        self.queue
+            .iter()
+            .filter(|entry| {
+                !matches!(
+                    entry,
+                    QueuedMessage::Empty
+                        | QueuedMessage::WaitingReturnMemory(..)
+                        | QueuedMessage::WaitingForget(..)
+                        | QueuedMessage::WaitingReturnScalar(..)
+                )
+            })
+            .count()
+    }
+
+    /// Returns `true` if there is no room to queue another message
+    pub fn queue_full(&self) -> bool {
+        self.queue[self.queue_head] != QueuedMessage::Empty
+            || (self.queue_limit < self.queue.len() && self.pending_messages() >= self.queue_limit)
+    }
+
     /// Add the given message to this server's queue.
     ///
     /// # Errors
     ///
-    /// * **ServerQueueFull**: The server queue cannot accept any more messages
+    /// * **ServerQueueFull**: The server queue cannot accept any more
+    ///   messages, or already holds as many as its limit allows
     pub fn queue_message(
         &mut self,
         pid: PID,
@@ -624,7 +681,7 @@ impl Server {
         original_address: Option<MemoryAddress>,
     ) -> core::result::Result<usize, xous_kernel::Error> {
         // println!("Queueing message: {:?} for pid: {}  tid: {}", message, pid.get(), context);
-        if self.queue[self.queue_head] != QueuedMessage::Empty {
+        if self.queue_full() {
             return Err(xous_kernel::Error::ServerQueueFull);
         }
 
@@ -744,6 +801,11 @@ impl Server {
     //     mem::size_of::<QueuedMessage>()
     // );
 
+    /// Returns `true` if a thread is waiting to receive a message
+    pub fn has_available_thread(&self) -> bool {
+        self.ready_threads != 0
+    }
+
     /// Return a context ID that is available and blocking.  If no such context
     /// ID exists, or if this server isn't actually ready to receive packets,
     /// return None.
@@ -787,6 +849,25 @@ impl Server {
         self.ready_threads |= 1 << tid;
     }
 
+    /// Remember that thread `tid` of `pid` is waiting for room in the
+    /// queue.  Returns `false` if too many senders are already waiting.
+    pub fn block_sender(&mut self, pid: PID, tid: TID) -> bool {
+        match self.blocked_senders.iter_mut().find(|sender| sender.is_none()) {
+            Some(slot) => {
+                *slot = Some((pid, tid));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Take the sender that has waited longest for room in the queue
+    pub fn take_blocked_sender(&mut self) -> Option<(PID, TID)> {
+        let sender = self.blocked_senders[0].take();
+        self.blocked_senders.rotate_left(1);
+        sender
+    }
+
     /// Add the given context to the list of ready and waiting contexts.
     pub fn park_thread(&mut self, tid: TID) {
         klog!("parking thread {}", tid);
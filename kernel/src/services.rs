@@ -7,10 +7,14 @@ use xous_kernel::MemoryRange;
 use core::num::NonZeroU8;
 
 use crate::filled_array;
+use crate::pagelist::{PageList, PageListTable};
+use crate::quota::CpuAccount;
+use crate::sandbox::Sandbox;
 use crate::server::Server;
 // use core::mem;
 use xous_kernel::{
-    pid_from_usize, Error, MemoryAddress, Message, ProcessInit, ThreadInit, CID, PID, SID, TID,
+    pid_from_usize, Error, MemoryAddress, Message, ProcessInit, SysCall, ThreadInit, CID, PID, SID,
+    TID,
 };
 
 const MAX_SERVER_COUNT: usize = 32;
@@ -31,6 +35,12 @@ pub struct SystemServices {
 
     /// How many entries there are on the syscall stack
     _syscall_depth: usize,
+
+    /// The process that has paused every other process, if any
+    debug_agent: Option<PID>,
+
+    /// Page lists that servers are still holding
+    page_lists: PageListTable,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -88,6 +98,19 @@ pub struct Process {
     /// The context number that was active before this process was switched
     /// away.
     previous_thread: TID,
+
+    /// Restrictions on what this process may do, if it was started in
+    /// sandboxed mode
+    pub sandbox: Option<Sandbox>,
+
+    /// CPU time this process has used, and its quota
+    pub cpu: CpuAccount,
+
+    /// How many times each connection in `ProcessInner::connection_map`
+    /// has been handed out.  A slot is only freed once every `Connect` has
+    /// been matched by a `Disconnect`.  The count saturates, and a slot
+    /// that hits the limit stays open.
+    connection_refs: [u8; 32],
 }
 
 impl Default for Process {
@@ -203,12 +226,17 @@ std::thread_local!(static SYSTEM_SERVICES: core::cell::RefCell<SystemServices> =
         mapping: arch::mem::DEFAULT_MEMORY_MAPPING,
         current_thread: 0 as TID,
         previous_thread: INITIAL_TID as TID,
+        sandbox: None,
+        cpu: CpuAccount::new(),
+        connection_refs: [0; 32],
     }; MAX_PROCESS_COUNT],
     // Note we can't use MAX_SERVER_COUNT here because of how Rust's
     // macro tokenization works
     servers: filled_array![None; 32],
     _syscall_stack: [(0, 0), (0, 0), (0, 0)],
     _syscall_depth: 0,
+    debug_agent: None,
+    page_lists: PageListTable::new(),
 }));
 
 #[cfg(baremetal)]
@@ -220,12 +248,17 @@ static mut SYSTEM_SERVICES: SystemServices = SystemServices {
         mapping: arch::mem::DEFAULT_MEMORY_MAPPING,
         current_thread: 0 as TID,
         previous_thread: INITIAL_TID as TID,
+        sandbox: None,
+        cpu: CpuAccount::new(),
+        connection_refs: [0; 32],
     }; MAX_PROCESS_COUNT],
     // Note we can't use MAX_SERVER_COUNT here because of how Rust's
     // macro tokenization works
     servers: filled_array![None; 32],
     _syscall_stack: [(0, 0), (0, 0), (0, 0)],
     _syscall_depth: 0,
+    debug_agent: None,
+    page_lists: PageListTable::new(),
 };
 
 impl core::fmt::Debug for Process {
@@ -308,6 +341,7 @@ impl SystemServices {
                 process.ppid = PID::new_unchecked(1);
                 process.pid = PID::new(pid as _).unwrap();
             };
+            crate::arch::process::set_aslr_seed(process.pid, init.aslr_seed);
             if pid == 1 {
                 process.state = ProcessState::Running(0);
             } else {
@@ -329,6 +363,8 @@ impl SystemServices {
     /// Add a new entry to the process table. This results in a new address space
     /// and a new PID, though the process is in the state `Setup()`.
     pub fn create_process(&mut self, init_process: ProcessInit) -> Result<PID, xous_kernel::Error> {
+        // Children of a sandboxed process may not do any more than it can.
+        let sandbox = self.get_process(crate::arch::process::current_pid())?.sandbox;
         for (idx, mut entry) in self.processes.iter_mut().enumerate() {
             if entry.state != ProcessState::Free {
                 continue;
@@ -340,11 +376,192 @@ impl SystemServices {
             entry.state = ProcessState::Allocated;
             entry.ppid = ppid;
             entry.pid = new_pid;
+            entry.sandbox = sandbox;
+            entry.cpu = CpuAccount::new();
+            entry.connection_refs = [0; 32];
             return Ok(new_pid);
         }
         Err(xous_kernel::Error::ProcessNotFound)
     }
 
+    /// Get the sandbox of `target`, a process that `caller` created and that
+    /// hasn't started running yet, so that its restrictions can be changed.
+    fn sandbox_of_new_child(
+        &mut self,
+        caller: PID,
+        target: PID,
+    ) -> Result<&mut Option<Sandbox>, xous_kernel::Error> {
+        let process = self.get_process_mut(target)?;
+        if process.free() {
+            return Err(xous_kernel::Error::ProcessNotFound);
+        }
+        if process.ppid != caller {
+            return Err(xous_kernel::Error::ProcessNotChild);
+        }
+        match process.state {
+            ProcessState::Allocated | ProcessState::Setup(_) => Ok(&mut process.sandbox),
+            _ => Err(xous_kernel::Error::AccessDenied),
+        }
+    }
+
+    /// Start `target` in sandboxed mode, with an empty allow-list. This may
+    /// only be done by the process that created it, and only before it runs.
+    /// Sandboxing a process that is already sandboxed has no effect.
+    pub fn sandbox_process(&mut self, caller: PID, target: PID) -> Result<(), xous_kernel::Error> {
+        let sandbox = self.sandbox_of_new_child(caller, target)?;
+        if sandbox.is_none() {
+            *sandbox = Some(Sandbox::new());
+        }
+        Ok(())
+    }
+
+    /// Let the sandboxed process `target` connect to `sid`. A sandboxed
+    /// caller can only pass on servers that are on its own allow-list.
+    pub fn sandbox_allow_connection(
+        &mut self,
+        caller: PID,
+        target: PID,
+        sid: SID,
+    ) -> Result<(), xous_kernel::Error> {
+        if let Some(sandbox) = self.get_process(caller)?.sandbox {
+            if !sandbox.allows(sid) {
+                return Err(xous_kernel::Error::AccessDenied);
+            }
+        }
+        self.sandbox_of_new_child(caller, target)?
+            .as_mut()
+            .ok_or(xous_kernel::Error::InvalidSyscall)?
+            .allow(sid)
+    }
+
+    /// Limit `target`, a process that `caller` created, to `percent` of the
+    /// CPU whenever other processes are waiting to run.
+    pub fn set_cpu_quota(
+        &mut self,
+        caller: PID,
+        target: PID,
+        percent: usize,
+    ) -> Result<(), xous_kernel::Error> {
+        let process = self.get_process_mut(target)?;
+        if process.free() {
+            return Err(xous_kernel::Error::ProcessNotFound);
+        }
+        // The kernel schedules the processes it is the parent of itself, so
+        // nobody else would ever be able to set their quotas.
+        let delegated = process.ppid.get() == 1
+            && crate::capabilities::holds(caller, xous_kernel::Capabilities::SET_QUOTA);
+        if process.ppid != caller && !delegated {
+            return Err(xous_kernel::Error::ProcessNotChild);
+        }
+        process.cpu.set_quota(percent)
+    }
+
+    /// Charge `pid` for the time it ran between `start` and `end`.
+    pub fn charge_cpu(&mut self, pid: PID, start: u64, end: u64) {
+        // The process may have exited while it was running.
+        if let Ok(process) = self.get_process_mut(pid) {
+            if !process.free() {
+                process.cpu.charge(start, end);
+            }
+        }
+    }
+
+    pub fn cpu_stats(&self, pid: PID) -> Result<xous_kernel::Result, xous_kernel::Error> {
+        let process = self.get_process(pid)?;
+        if process.free() {
+            return Err(xous_kernel::Error::ProcessNotFound);
+        }
+        Ok(process.cpu.stats())
+    }
+
+    /// Stop scheduling every process except `caller`.  Pausing the world
+    /// again from the same process has no effect.  Only a process granted
+    /// `DEBUG` may do this, whether or not the kernel was built with the
+    /// `capabilities` feature.
+    pub fn pause_world(&mut self, caller: PID) -> Result<(), xous_kernel::Error> {
+        if !crate::capabilities::holds(caller, xous_kernel::Capabilities::DEBUG) {
+            return Err(xous_kernel::Error::AccessDenied);
+        }
+        if matches!(self.debug_agent, Some(agent) if agent != caller) {
+            return Err(xous_kernel::Error::AccessDenied);
+        }
+        // Only direct children of the kernel are scheduled by `kmain()`
+        if self.get_process(caller)?.ppid.get() != 1 {
+            return Err(xous_kernel::Error::ProcessNotChild);
+        }
+        self.debug_agent = Some(caller);
+        Ok(())
+    }
+
+    /// Undo a `pause_world()` by the same process.
+    pub fn resume_world(&mut self, caller: PID) -> Result<(), xous_kernel::Error> {
+        if self.debug_agent != Some(caller) {
+            return Err(xous_kernel::Error::AccessDenied);
+        }
+        self.debug_agent = None;
+        Ok(())
+    }
+
+    /// The process that has paused the world, if any
+    pub fn paused_by(&self) -> Option<PID> {
+        self.debug_agent
+    }
+
+    /// Read four saved registers of thread `tid` in `pid`, starting at
+    /// `first`, for the process that has paused the world.
+    #[cfg(baremetal)]
+    pub fn read_thread_registers(
+        &self,
+        caller: PID,
+        pid: PID,
+        tid: TID,
+        first: usize,
+    ) -> Result<xous_kernel::Result, xous_kernel::Error> {
+        // The agent held `DEBUG` when it paused the world.
+        if self.debug_agent != Some(caller) {
+            return Err(xous_kernel::Error::AccessDenied);
+        }
+        if first > xous_kernel::THREAD_REGISTER_COUNT - 4 {
+            return Err(xous_kernel::Error::InvalidSyscall);
+        }
+        let target_process = self.get_process(pid)?;
+        if target_process.free() {
+            return Err(xous_kernel::Error::ProcessNotFound);
+        }
+
+        // Temporarily switch into the target process memory space, where
+        // its threads are saved.
+        let current_pid = self.current_pid();
+        target_process.activate()?;
+        let arch_process = crate::arch::process::Process::current();
+        let mut registers = [None; 4];
+        for (offset, register) in registers.iter_mut().enumerate() {
+            *register = arch_process.thread_register(tid, first + offset);
+        }
+        self.get_process(current_pid)
+            .expect("couldn't switch back after reading registers")
+            .activate()?;
+
+        match registers {
+            [Some(r0), Some(r1), Some(r2), Some(r3)] => Ok(xous_kernel::Result::Scalar5(
+                r0,
+                r1,
+                r2,
+                r3,
+                xous_kernel::THREAD_REGISTER_COUNT,
+            )),
+            _ => Err(xous_kernel::Error::ThreadNotAvailable),
+        }
+    }
+
+    /// Make sure `pid` is allowed to perform `call` if it is sandboxed.
+    pub fn sandbox_check(&self, pid: PID, call: &SysCall) -> Result<(), xous_kernel::Error> {
+        match self.get_process(pid)?.sandbox {
+            Some(sandbox) => sandbox.check(pid, call),
+            None => Ok(()),
+        }
+    }
+
     pub fn get_process(&self, pid: PID) -> Result<&Process, xous_kernel::Error> {
         // PID0 doesn't exist -- process IDs are offset by 1.
         let pid_idx = pid.get() as usize - 1;
@@ -1029,10 +1246,8 @@ impl SystemServices {
 
             let mut error = None;
 
-            // Lend each subsequent page.
-            for offset in (0..(len / core::mem::size_of::<usize>()))
-                .step_by(crate::mem::PAGE_SIZE / core::mem::size_of::<usize>())
-            {
+            // Move each subsequent page.
+            for offset in (0..len).step_by(crate::mem::PAGE_SIZE) {
                 mm.move_page(
                     &src_mapping,
                     src_virt.wrapping_add(offset),
@@ -1119,26 +1334,37 @@ impl SystemServices {
                 })?;
             src_mapping.activate().unwrap();
 
-            let mut error = None;
-
             // Lend each subsequent page.
-            for offset in (0..(len / core::mem::size_of::<usize>()))
-                .step_by(crate::mem::PAGE_SIZE / core::mem::size_of::<usize>())
-            {
-                mm.lend_page(
+            let mut lent = 0;
+            while lent < len {
+                if let Err(e) = mm.lend_page(
                     &src_mapping,
-                    src_virt.wrapping_add(offset),
+                    src_virt.wrapping_add(lent),
                     dest_pid,
                     &dest_mapping,
-                    dest_virt.wrapping_add(offset),
+                    dest_virt.wrapping_add(lent),
                     mutable,
-                )
-                .unwrap_or_else(|e| {
-                    error = Some(e);
-                    0
-                });
+                ) {
+                    // A bad page partway through the buffer fails the
+                    // message.  Take back the pages that were already lent so
+                    // the sender is left as it was.
+                    dest_mapping.activate()?;
+                    for offset in (0..lent).step_by(crate::mem::PAGE_SIZE) {
+                        mm.unlend_page(
+                            &dest_mapping,
+                            dest_virt.wrapping_add(offset),
+                            current_pid,
+                            &src_mapping,
+                            src_virt.wrapping_add(offset),
+                        )
+                        .expect("couldn't take back lent page");
+                    }
+                    src_mapping.activate().unwrap();
+                    return Err(e);
+                }
+                lent += crate::mem::PAGE_SIZE;
             }
-            error.map_or_else(|| Ok(dest_virt), |e| panic!("unable to lend: {:?}", e))
+            Ok(dest_virt)
         })
     }
 
@@ -1154,6 +1380,168 @@ impl SystemServices {
         Ok(src_virt)
     }
 
+    /// Read the list of ranges the current process wants to lend, which it
+    /// laid out in `buf` as `(address, length)` pairs.
+    ///
+    /// # Errors
+    ///
+    /// * **BadAddress**: The list can't be read, or one of its ranges is bad
+    /// * **BadAlignment**: A range isn't page-aligned
+    #[cfg(baremetal)]
+    pub fn read_page_list(&self, buf: MemoryRange) -> Result<PageList, xous_kernel::Error> {
+        let mut words = [0usize; xous_kernel::MAX_PAGE_LIST * 2];
+        let count = buf.len() / core::mem::size_of::<usize>();
+        if count > words.len() {
+            return Err(xous_kernel::Error::BadAddress);
+        }
+        arch::mem::read_user_words(buf.as_ptr() as usize, &mut words[..count])?;
+        PageList::parse(&words[..count])
+    }
+
+    /// The hosted kernel can't see into client memory, so it has no page
+    /// lists.
+    #[cfg(not(baremetal))]
+    pub fn read_page_list(&self, _buf: MemoryRange) -> Result<PageList, xous_kernel::Error> {
+        Err(xous_kernel::Error::UnhandledSyscall)
+    }
+
+    /// Lend every page in `list` from the current process to `dest_pid`,
+    /// where they show up back to back in one buffer.
+    ///
+    /// # Returns
+    ///
+    /// Returns the virtual address of the buffer in the target process.
+    ///
+    /// # Errors
+    ///
+    /// * **OutOfMemory**: Too many page lists are already lent out, or the
+    ///   target has no room for the buffer
+    /// * **ShareViolation**: Tried to mutably share a page that was already
+    ///   shared
+    /// * **BadAddress**: One of the pages isn't mapped
+    #[cfg(baremetal)]
+    pub fn lend_page_list(
+        &mut self,
+        list: &PageList,
+        dest_pid: PID,
+        mutable: bool,
+    ) -> Result<*mut u8, xous_kernel::Error> {
+        if !self.page_lists.has_room() {
+            return Err(xous_kernel::Error::OutOfMemory);
+        }
+        let current_pid = self.current_pid();
+        let src_mapping = self.get_process(current_pid)?.mapping;
+        let dest_mapping = self.get_process(dest_pid)?.mapping;
+        use crate::mem::MemoryManager;
+        let dest_virt = MemoryManager::with_mut(|mm| {
+            // Locate an address to fit the whole list.
+            dest_mapping.activate()?;
+            let dest_virt = mm
+                .find_virtual_address(
+                    core::ptr::null_mut(),
+                    list.len(),
+                    xous_kernel::MemoryType::Messages,
+                )
+                .or_else(|e| {
+                    src_mapping.activate().unwrap();
+                    Err(e)
+                })?;
+            src_mapping.activate().unwrap();
+
+            for (index, (src_virt, offset)) in list.pages().enumerate() {
+                if let Err(e) = mm.lend_page(
+                    &src_mapping,
+                    src_virt as *mut u8,
+                    dest_pid,
+                    &dest_mapping,
+                    dest_virt.wrapping_add(offset),
+                    mutable,
+                ) {
+                    // Take back the pages that were already lent, just like
+                    // `lend_memory()` does.
+                    dest_mapping.activate()?;
+                    for (src_virt, offset) in list.pages().take(index) {
+                        mm.unlend_page(
+                            &dest_mapping,
+                            dest_virt.wrapping_add(offset),
+                            current_pid,
+                            &src_mapping,
+                            src_virt as *mut u8,
+                        )
+                        .expect("couldn't take back lent page");
+                    }
+                    src_mapping.activate().unwrap();
+                    return Err(e);
+                }
+            }
+            Ok(dest_virt)
+        })?;
+        self.page_lists
+            .insert(current_pid, dest_pid, dest_virt as usize, *list)?;
+        Ok(dest_virt)
+    }
+
+    #[cfg(not(baremetal))]
+    pub fn lend_page_list(
+        &mut self,
+        _list: &PageList,
+        _dest_pid: PID,
+        _mutable: bool,
+    ) -> Result<*mut u8, xous_kernel::Error> {
+        Err(xous_kernel::Error::UnhandledSyscall)
+    }
+
+    /// If the current process is holding a page list at `src_virt`, stop
+    /// tracking it and return it.  Returns `None` for an ordinary borrow.
+    pub fn take_page_list(&mut self, src_virt: usize) -> Option<PageList> {
+        let current_pid = self.current_pid();
+        self.page_lists
+            .take(current_pid, src_virt)
+            .map(|(_client, list)| list)
+    }
+
+    /// Give every page in `list` back to `dest_pid`, which lent them with
+    /// `lend_page_list()` and had them land at `src_virt`.
+    #[cfg(baremetal)]
+    pub fn return_page_list(
+        &mut self,
+        src_virt: *mut u8,
+        dest_pid: PID,
+        list: &PageList,
+    ) -> Result<(), xous_kernel::Error> {
+        let current_pid = self.current_pid();
+        let src_mapping = self.get_process(current_pid)?.mapping;
+        let dest_mapping = self.get_process(dest_pid)?.mapping;
+        use crate::mem::MemoryManager;
+        MemoryManager::with_mut(|mm| {
+            let mut error = None;
+            for (dest_virt, offset) in list.pages() {
+                mm.unlend_page(
+                    &src_mapping,
+                    src_virt.wrapping_add(offset),
+                    dest_pid,
+                    &dest_mapping,
+                    dest_virt as *mut u8,
+                )
+                .unwrap_or_else(|e| {
+                    error = Some(e);
+                    0
+                });
+            }
+            error.map_or(Ok(()), Err)
+        })
+    }
+
+    #[cfg(not(baremetal))]
+    pub fn return_page_list(
+        &mut self,
+        _src_virt: *mut u8,
+        _dest_pid: PID,
+        _list: &PageList,
+    ) -> Result<(), xous_kernel::Error> {
+        Err(xous_kernel::Error::UnhandledSyscall)
+    }
+
     /// Return memory from one process back to another
     ///
     /// During this process, memory is unmapped from the source process.
@@ -1196,10 +1584,8 @@ impl SystemServices {
         MemoryManager::with_mut(|mm| {
             let mut error = None;
 
-            // Lend each subsequent page.
-            for offset in (0..(len / core::mem::size_of::<usize>()))
-                .step_by(crate::mem::PAGE_SIZE / core::mem::size_of::<usize>())
-            {
+            // Return each subsequent page.
+            for offset in (0..len).step_by(crate::mem::PAGE_SIZE) {
                 mm.unlend_page(
                     &src_mapping,
                     src_virt.wrapping_add(offset),
@@ -1344,7 +1730,8 @@ impl SystemServices {
     }
 
     /// Allocate a new server ID for this process and return the address. If the
-    /// server table is full, return an error.
+    /// server table is full, return an error.  Every call takes another
+    /// reference to the connection, which `disconnect()` gives back.
     pub fn connect_to_server(&mut self, sid: SID) -> Result<CID, xous_kernel::Error> {
         // Check to see if we've already connected to this server.
         // While doing this, find a free slot in case we haven't
@@ -1352,7 +1739,7 @@ impl SystemServices {
 
         let pid = crate::arch::process::current_pid();
         // println!("KERNEL({}): Server table: {:?}", _pid.get(), self.servers);
-        ArchProcess::with_inner_mut(|process_inner| {
+        let cid = ArchProcess::with_inner_mut(|process_inner| {
             assert_eq!(pid, process_inner.pid);
             let mut slot_idx = None;
             // Look through the connection map for (1) a free slot, and (2) an
@@ -1400,6 +1787,39 @@ impl SystemServices {
                 }
             }
             Err(xous_kernel::Error::ServerNotFound) // May also be OutOfMemory if the table is full
+        })?;
+        let refs = &mut self.get_process_mut(pid)?.connection_refs[cid - 2];
+        *refs = refs.saturating_add(1);
+        Ok(cid)
+    }
+
+    /// Give back one reference to the connection `cid` in the current
+    /// process.  Once every reference is gone the slot is closed so it can
+    /// be reused.  Messages already sent on it are unaffected.
+    pub fn disconnect(&mut self, cid: CID) -> Result<(), xous_kernel::Error> {
+        let pid = crate::arch::process::current_pid();
+        // Connection IDs are offset by two, the same as the map entries.
+        let idx = cid
+            .checked_sub(2)
+            .filter(|&idx| idx < 32)
+            .ok_or(xous_kernel::Error::ServerNotFound)?;
+        let refs = &mut self.get_process_mut(pid)?.connection_refs[idx];
+        ArchProcess::with_inner_mut(|process_inner| {
+            assert_eq!(pid, process_inner.pid);
+            // A tombstone left by a server that exited is cleared the same way.
+            let mapping = &mut process_inner.connection_map[idx];
+            if mapping.is_none() {
+                return Err(xous_kernel::Error::ServerNotFound);
+            }
+            match *refs {
+                u8::MAX => (),
+                0 | 1 => {
+                    *mapping = None;
+                    *refs = 0;
+                }
+                _ => *refs -= 1,
+            }
+            Ok(())
         })
     }
 
@@ -1578,6 +1998,7 @@ impl SystemServices {
         // 3. If there are any incoming server requests queued, dequeue them and return an error
         // 4. Mark all "Borrowed" memory as "Free-when-returned". That way, if we've shared
         //    memory to a Server, it will be reclaimed by the system when it comes back
+        // 5. Free any interrupts this process has claimed
 
         // 1. Find all servers associated with this PID and remove them.
         for (idx, server) in self.servers.iter_mut().enumerate() {
@@ -1611,6 +2032,32 @@ impl SystemServices {
                 server.discard_messages_for_pid(target_pid);
             }
         }
+
+        // Senders waiting for room in one of its queues try again, and find
+        // the server gone.
+        for sidx in 0..self.servers.len() {
+            if self.servers[sidx].as_ref().map(|s| s.pid) != Some(target_pid) {
+                continue;
+            }
+            while let Some((pid, tid)) = self.servers[sidx]
+                .as_mut()
+                .and_then(|server| server.take_blocked_sender())
+            {
+                self.ready_thread(pid, tid)?;
+            }
+        }
+
+        // 5. Stop delivering interrupts to this process.
+        crate::irq::release_all(target_pid);
+
+        // Its page lists went away with its memory.
+        self.page_lists.forget_server(target_pid);
+
+        // A debug agent that exits lets everything else run again.
+        if self.debug_agent == Some(target_pid) {
+            self.debug_agent = None;
+        }
+
         let process = self.get_process_mut(target_pid)?;
         process.activate()?;
         let parent_pid = process.ppid;
@@ -0,0 +1,73 @@
+use crate::args::KernelArguments;
+use core::fmt;
+use xous_kernel::PID;
+
+/// A resolved address: the function it lies in, and how far into that
+/// function it is.
+pub struct Symbol {
+    pub name: &'static str,
+    pub offset: usize,
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}+0x{:x}", self.name, self.offset)
+    }
+}
+
+/// Resolve `addr` to a function name using the `Symb` tag for the given
+/// process, if the image was created with symbols.
+///
+/// The tag consists of `pid` and `count`, followed by `count` entries of
+/// `(start, size, name_offset)` sorted by `start`, followed by a table of
+/// NUL-terminated names.
+pub fn lookup(pid: PID, addr: usize) -> Option<Symbol> {
+    let args = KernelArguments::get();
+    if args.base.is_null() {
+        return None;
+    }
+
+    for tag in args.iter() {
+        if tag.name != make_type!("Symb") || tag.data.len() < 2 {
+            continue;
+        }
+        if tag.data[0] as usize != pid.get() as usize {
+            continue;
+        }
+        let count = tag.data[1] as usize;
+        let entries = tag.data.get(2..2 + count * 3)?;
+        let names = &tag.data[2 + count * 3..];
+        let names = unsafe {
+            core::slice::from_raw_parts(names.as_ptr() as *const u8, names.len() * 4)
+        };
+
+        // Find the last entry that starts at or before `addr`
+        let mut low = 0;
+        let mut high = count;
+        while low < high {
+            let mid = (low + high) / 2;
+            if entries[mid * 3] as usize <= addr {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        if low == 0 {
+            return None;
+        }
+        let start = entries[(low - 1) * 3] as usize;
+        let size = entries[(low - 1) * 3 + 1] as usize;
+        let name_offset = entries[(low - 1) * 3 + 2] as usize;
+        if addr >= start + size {
+            return None;
+        }
+
+        let name = names.get(name_offset..)?;
+        let name_len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+        return Some(Symbol {
+            name: core::str::from_utf8(&name[..name_len]).unwrap_or("<<invalid>>"),
+            offset: addr - start,
+        });
+    }
+    None
+}
@@ -1,6 +1,6 @@
 use crate::arch;
 use crate::arch::process::Process as ArchProcess;
-use crate::irq::interrupt_claim;
+use crate::irq::{interrupt_claim, interrupt_free};
 use crate::mem::{MemoryManager, PAGE_SIZE};
 use crate::server::{SenderID, WaitingMessage};
 use crate::services::SystemServices;
@@ -19,6 +19,48 @@ fn retry_syscall(pid: PID, tid: TID) -> SysCallResult {
     }
 }
 
+/// The queue behind `cid` is full, so put the sending thread to sleep until
+/// `wake_blocked_sender()` lets it try the syscall again.  If too many
+/// senders are already waiting, or on hosted targets where the client
+/// retries by itself, fall back to `retry_syscall()`.
+fn block_sender(pid: PID, tid: TID, cid: CID) -> SysCallResult {
+    if !cfg!(baremetal) {
+        return retry_syscall(pid, tid);
+    }
+    let blocked = SystemServices::with_mut(|ss| {
+        ss.sidx_from_cid(cid)
+            .and_then(|sidx| ss.server_from_sidx_mut(sidx))
+            .map(|server| server.block_sender(pid, tid))
+            .unwrap_or(false)
+    });
+    if !blocked {
+        return retry_syscall(pid, tid);
+    }
+    arch::process::Process::with_current_mut(|p| p.retry_instruction(tid))?;
+    SystemServices::with_mut(|ss| {
+        unsafe { SWITCHTO_CALLER = None };
+        let ppid = ss.get_process(pid).expect("Can't get current process").ppid;
+        ss.activate_process_thread(tid, ppid, 0, false)
+            .map(|_| Ok(xous_kernel::Result::ResumeProcess))
+            .unwrap_or(Err(xous_kernel::Error::ProcessNotFound))
+    })
+}
+
+/// A slot in the queue of server `sidx` was freed, so let the sender that
+/// has waited longest try again.
+fn wake_blocked_sender(
+    ss: &mut SystemServices,
+    sidx: usize,
+) -> core::result::Result<(), xous_kernel::Error> {
+    let sender = ss
+        .server_from_sidx_mut(sidx)
+        .and_then(|server| server.take_blocked_sender());
+    if let Some((pid, tid)) = sender {
+        ss.ready_thread(pid, tid)?;
+    }
+    Ok(())
+}
+
 fn do_yield(_pid: PID, tid: TID) -> SysCallResult {
     // If we're not running on bare metal, treat this as a no-op.
     if !cfg!(baremetal) {
@@ -38,23 +80,57 @@ fn do_yield(_pid: PID, tid: TID) -> SysCallResult {
     })
 }
 
-fn send_message(pid: PID, thread: TID, cid: CID, message: Message) -> SysCallResult {
+/// Send `message` to the server on `cid`.  If `page_list` is set, the
+/// buffer of a borrow is a list of ranges to lend instead of the memory
+/// itself, as described for `SysCall::LendPages`.
+fn send_message(
+    pid: PID,
+    thread: TID,
+    cid: CID,
+    message: Message,
+    page_list: bool,
+) -> SysCallResult {
     SystemServices::with_mut(|ss| {
         let sidx = ss
             .sidx_from_cid(cid)
             .ok_or(xous_kernel::Error::ServerNotFound)?;
         // ::debug_here::debug_here!();
 
-        let server_pid = ss
+        let server = ss
             .server_from_sidx(sidx)
-            .expect("server couldn't be located")
-            .pid;
+            .expect("server couldn't be located");
+        let server_pid = server.pid;
+
+        // If the message would have to be queued and there's no room, turn
+        // it away now, before any memory has been handed to the server.
+        if !server.has_available_thread() && server.queue_full() {
+            return Err(xous_kernel::Error::ServerQueueFull);
+        }
+
+        // Read the list of ranges to lend before touching anything, so that
+        // a bad list leaves everything as it was.
+        let pages = match &message {
+            Message::MutableBorrow(msg) | Message::Borrow(msg) if page_list => {
+                Some(ss.read_page_list(msg.buf)?)
+            }
+            _ if page_list => return Err(xous_kernel::Error::InvalidSyscall),
+            _ => None,
+        };
 
         // Remember the address the message came from, in case we need to
-        // return it after the borrow is through.
+        // return it after the borrow is through.  Reject messages whose
+        // valid range falls outside the buffer, so servers can trust it.
         let client_address = match &message {
             Message::Scalar(_) | Message::BlockingScalar(_) => None,
             Message::Move(msg) | Message::MutableBorrow(msg) | Message::Borrow(msg) => {
+                match &pages {
+                    Some(pages) => MemoryMessage {
+                        buf: MemoryRange::new(msg.buf.addr.get(), pages.len())?,
+                        ..*msg
+                    }
+                    .valid_range()?,
+                    None => msg.valid_range()?,
+                };
                 Some(msg.buf.addr)
             }
         };
@@ -80,28 +156,40 @@ fn send_message(pid: PID, thread: TID, cid: CID, message: Message) -> SysCallRes
                 })
             }
             Message::MutableBorrow(msg) => {
-                let new_virt = ss.lend_memory(
-                    msg.buf.as_mut_ptr(),
-                    server_pid,
-                    core::ptr::null_mut(),
-                    msg.buf.len(),
-                    true,
-                )?;
+                let (new_virt, len) = match &pages {
+                    Some(pages) => (ss.lend_page_list(pages, server_pid, true)?, pages.len()),
+                    None => (
+                        ss.lend_memory(
+                            msg.buf.as_mut_ptr(),
+                            server_pid,
+                            core::ptr::null_mut(),
+                            msg.buf.len(),
+                            true,
+                        )?,
+                        msg.buf.len(),
+                    ),
+                };
                 Message::MutableBorrow(MemoryMessage {
                     id: msg.id,
-                    buf: MemoryRange::new(new_virt as usize, msg.buf.len())?,
+                    buf: MemoryRange::new(new_virt as usize, len)?,
                     offset: msg.offset,
                     valid: msg.valid,
                 })
             }
             Message::Borrow(msg) => {
-                let new_virt = ss.lend_memory(
-                    msg.buf.as_mut_ptr(),
-                    server_pid,
-                    core::ptr::null_mut(),
-                    msg.buf.len(),
-                    false,
-                )?;
+                let (new_virt, len) = match &pages {
+                    Some(pages) => (ss.lend_page_list(pages, server_pid, false)?, pages.len()),
+                    None => (
+                        ss.lend_memory(
+                            msg.buf.as_mut_ptr(),
+                            server_pid,
+                            core::ptr::null_mut(),
+                            msg.buf.len(),
+                            false,
+                        )?,
+                        msg.buf.len(),
+                    ),
+                };
                 // println!(
                 //     "Lending {} bytes from {:08x} in PID {} to {:08x} in PID {}",
                 //     msg.buf.len(),
@@ -112,7 +200,7 @@ fn send_message(pid: PID, thread: TID, cid: CID, message: Message) -> SysCallRes
                 // );
                 Message::Borrow(MemoryMessage {
                     id: msg.id,
-                    buf: MemoryRange::new(new_virt as usize, msg.buf.len())?,
+                    buf: MemoryRange::new(new_virt as usize, len)?,
                     offset: msg.offset,
                     valid: msg.valid,
                 })
@@ -252,6 +340,7 @@ fn return_memory(
             return Err(xous_kernel::Error::ServerNotFound);
         }
         let result = server.take_waiting_message(sender.idx, Some(&buf))?;
+        wake_blocked_sender(ss, sender.sidx)?;
         klog!("waiting message was: {:?}", result);
         let (client_pid, client_tid, server_addr, client_addr, len) = match result {
             WaitingMessage::BorrowedMemory(
@@ -265,6 +354,8 @@ fn return_memory(
                 return Ok(xous_kernel::Result::Ok);
             }
             WaitingMessage::ForgetMemory(range) => {
+                // The client is gone, so a page list has nowhere to go back to
+                ss.take_page_list(range.addr.get());
                 return MemoryManager::with_mut(|mm| {
                     let mut result = Ok(xous_kernel::Result::Ok);
                     let virt = range.addr.get();
@@ -303,16 +394,22 @@ fn return_memory(
         //     client_tid
         // );
 
-        // Return the memory to the calling process
-        ss.return_memory(
-            server_addr.get() as _,
-            tid,
-            client_pid,
-            client_tid,
-            client_addr.get() as _,
-            len.get(),
-            buf,
-        )?;
+        // Return the memory to the calling process.  A page list goes back
+        // to the ranges it was gathered from.
+        match ss.take_page_list(server_addr.get()) {
+            Some(list) => ss.return_page_list(server_addr.get() as _, client_pid, &list)?,
+            None => {
+                ss.return_memory(
+                    server_addr.get() as _,
+                    tid,
+                    client_pid,
+                    client_tid,
+                    client_addr.get() as _,
+                    len.get(),
+                    buf,
+                )?;
+            }
+        }
 
         // Unblock the client context to allow it to continue.
         if !cfg!(baremetal) || in_irq {
@@ -355,6 +452,7 @@ fn return_scalar(
             return Err(xous_kernel::Error::ServerNotFound);
         }
         let result = server.take_waiting_message(sender.idx, None)?;
+        wake_blocked_sender(ss, sender.sidx)?;
         let (client_pid, client_tid) = match result {
             WaitingMessage::ScalarMessage(pid, tid) => (pid, tid),
             WaitingMessage::ForgetMemory(_) => {
@@ -426,6 +524,7 @@ fn return_scalar2(
             return Err(xous_kernel::Error::ServerNotFound);
         }
         let result = server.take_waiting_message(sender.idx, None)?;
+        wake_blocked_sender(ss, sender.sidx)?;
         let (client_pid, client_tid) = match result {
             WaitingMessage::ScalarMessage(pid, tid) => (pid, tid),
             WaitingMessage::ForgetMemory(_) => {
@@ -503,6 +602,7 @@ fn receive_message(pid: PID, tid: TID, sid: SID) -> SysCallResult {
         // If there is a pending message, return it immediately.
         if let Some(msg) = server.take_next_message(sidx) {
             klog!("waiting messages found -- returning {:?}", msg);
+            wake_blocked_sender(ss, sidx)?;
             return Ok(xous_kernel::Result::Message(msg));
         }
 
@@ -541,7 +641,13 @@ pub fn handle(pid: PID, tid: TID, in_irq: bool, call: SysCall) -> SysCallResult
     let result = if in_irq && !call.can_call_from_interrupt() {
         Err(xous_kernel::Error::InvalidSyscall)
     } else {
-        handle_inner(pid, tid, in_irq, call)
+        #[cfg(feature = "capabilities")]
+        let allowed = crate::capabilities::check(pid, &call);
+        #[cfg(not(feature = "capabilities"))]
+        let allowed = Ok(());
+        allowed
+            .and_then(|_| SystemServices::with(|ss| ss.sandbox_check(pid, &call)))
+            .and_then(|_| handle_inner(pid, tid, in_irq, call))
     };
 
     #[cfg(feature = "debug-print")]
@@ -576,6 +682,13 @@ pub fn handle_inner(pid: PID, tid: TID, in_irq: bool, call: SysCall) -> SysCallR
                     println!("Exceeded user area");
                     return Err(xous_kernel::Error::BadAddress);
 
+                // Don't allow user pages to be both writable and executable
+                } else if pid.get() != 1
+                    && req_flags.contains(MemoryFlags::W | MemoryFlags::X)
+                {
+                    println!("Refusing writable and executable mapping");
+                    return Err(xous_kernel::Error::AccessDenied);
+
                 // Don't allow mapping non-page values
                 } else if size.get() & (PAGE_SIZE - 1) != 0 {
                     // println!("map: bad alignment of size {:08x}", size);
@@ -700,9 +813,52 @@ pub fn handle_inner(pid: PID, tid: TID, in_irq: bool, call: SysCall) -> SysCallR
             result
         }),
         SysCall::ClaimInterrupt(no, callback, arg) => {
-            interrupt_claim(no, pid as definitions::PID, callback, arg)
+            interrupt_claim(no, pid as definitions::PID, callback, arg, false)
                 .map(|_| xous_kernel::Result::Ok)
         }
+        SysCall::ClaimSharedInterrupt(no, callback, arg) => {
+            interrupt_claim(no, pid as definitions::PID, callback, arg, true)
+                .map(|_| xous_kernel::Result::Ok)
+        }
+        SysCall::FreeInterrupt(no) => interrupt_free(no, pid).map(|_| xous_kernel::Result::Ok),
+        SysCall::SetQueueLimit(sid, limit) => SystemServices::with_mut(|ss| {
+            let sidx = ss
+                .sidx_from_sid(sid, pid)
+                .ok_or(xous_kernel::Error::ServerNotFound)?;
+            let server = ss
+                .server_from_sidx_mut(sidx)
+                .expect("server couldn't be located");
+            Ok(xous_kernel::Result::Scalar1(server.set_queue_limit(limit)))
+        }),
+        SysCall::GetQueueDepth(sid) => SystemServices::with_mut(|ss| {
+            let sidx = ss
+                .sidx_from_sid(sid, pid)
+                .ok_or(xous_kernel::Error::ServerNotFound)?;
+            let server = ss
+                .server_from_sidx(sidx)
+                .expect("server couldn't be located");
+            Ok(xous_kernel::Result::Scalar1(server.pending_messages()))
+        }),
+        SysCall::SandboxProcess(target) => SystemServices::with_mut(|ss| {
+            ss.sandbox_process(pid, target)
+                .map(|_| xous_kernel::Result::Ok)
+        }),
+        SysCall::SandboxAllowConnection(target, sid) => SystemServices::with_mut(|ss| {
+            ss.sandbox_allow_connection(pid, target, sid)
+                .map(|_| xous_kernel::Result::Ok)
+        }),
+        SysCall::SetCpuQuota(target, percent) => SystemServices::with_mut(|ss| {
+            ss.set_cpu_quota(pid, target, percent)
+                .map(|_| xous_kernel::Result::Ok)
+        }),
+        SysCall::GetCpuStats(target) => SystemServices::with(|ss| ss.cpu_stats(target)),
+        SysCall::GetTimestamp => {
+            let now = arch::cycles();
+            Ok(xous_kernel::Result::Scalar2(
+                now as u32 as usize,
+                (now >> 32) as usize,
+            ))
+        }
         SysCall::Yield => do_yield(pid, tid),
         SysCall::ReturnToParent(_pid, _cpuid) => {
             unsafe {
@@ -743,12 +899,15 @@ pub fn handle_inner(pid: PID, tid: TID, in_irq: bool, call: SysCall) -> SysCallR
             ss.connect_to_server(sid)
                 .map(xous_kernel::Result::ConnectionID)
         }),
+        SysCall::Disconnect(cid) => SystemServices::with_mut(|ss| {
+            ss.disconnect(cid).map(|_| xous_kernel::Result::Ok)
+        }),
         SysCall::ReturnMemory(sender, buf) => return_memory(pid, tid, in_irq, sender, buf),
         SysCall::ReturnScalar1(sender, arg) => return_scalar(pid, tid, in_irq, sender, arg),
         SysCall::ReturnScalar2(sender, arg1, arg2) => {
             return_scalar2(pid, tid, in_irq, sender, arg1, arg2)
         }
-        SysCall::TrySendMessage(cid, message) => send_message(pid, tid, cid, message),
+        SysCall::TrySendMessage(cid, message) => send_message(pid, tid, cid, message, false),
         SysCall::TerminateProcess => SystemServices::with_mut(|ss| {
             ss.switch_from_thread(pid, tid)?;
             let ppid = ss.terminate_process(pid)?;
@@ -762,6 +921,44 @@ pub fn handle_inner(pid: PID, tid: TID, in_irq: bool, call: SysCall) -> SysCallR
         SysCall::Shutdown => {
             SystemServices::with_mut(|ss| ss.shutdown().map(|_| xous_kernel::Result::Ok))
         }
+        #[cfg(all(baremetal, feature = "irq-latency"))]
+        SysCall::GetInterruptLatency(irq_no, bucket) => crate::irq::latency::get(irq_no, bucket),
+        #[cfg(baremetal)]
+        SysCall::GetMemoryFlags(addr) => {
+            let (flags, state) = arch::mem::page_flags(addr);
+            Ok(xous_kernel::Result::Scalar2(flags.bits(), state))
+        }
+        #[cfg(baremetal)]
+        SysCall::GetPmpRange(index) => arch::mem::pmp_range(index),
+        #[cfg(baremetal)]
+        SysCall::GetBootMeasurement(half) => crate::args::boot_measurement(half),
+        SysCall::PauseWorld => {
+            SystemServices::with_mut(|ss| ss.pause_world(pid).map(|_| xous_kernel::Result::Ok))
+        }
+        SysCall::ResumeWorld => {
+            SystemServices::with_mut(|ss| ss.resume_world(pid).map(|_| xous_kernel::Result::Ok))
+        }
+        #[cfg(baremetal)]
+        SysCall::ReadThreadRegisters(target, target_tid, first) => SystemServices::with(|ss| {
+            ss.read_thread_registers(pid, target, target_tid, first)
+        }),
+        #[cfg(feature = "capabilities")]
+        SysCall::GetAuditEntry(sequence) => Ok(crate::capabilities::audit_entry(sequence)),
+        SysCall::GetProcessId => Ok(xous_kernel::Result::Scalar1(pid.get() as usize)),
+        #[cfg(all(baremetal, feature = "stack-watermark"))]
+        SysCall::GetStackUsage(target) => {
+            arch::stack::usage(pid, if target == 0 { tid } else { target })
+        }
+        SysCall::GetIdleStats => {
+            let (idle, total, wakeups) = arch::idle_stats();
+            Ok(xous_kernel::Result::Scalar5(
+                idle as u32 as usize,
+                (idle >> 32) as usize,
+                total as u32 as usize,
+                (total >> 32) as usize,
+                wakeups,
+            ))
+        }
 
         SysCall::Connect(sid) => {
             let result = SystemServices::with_mut(|ss| {
@@ -775,10 +972,18 @@ pub fn handle_inner(pid: PID, tid: TID, in_irq: bool, call: SysCall) -> SysCallR
             }
         }
         SysCall::SendMessage(cid, message) => {
-            let result = send_message(pid, tid, cid, message);
+            let result = send_message(pid, tid, cid, message, false);
+            match result {
+                Ok(o) => Ok(o),
+                Err(xous_kernel::Error::ServerQueueFull) => block_sender(pid, tid, cid),
+                Err(e) => Err(e),
+            }
+        }
+        SysCall::LendPages(cid, message) => {
+            let result = send_message(pid, tid, cid, message, true);
             match result {
                 Ok(o) => Ok(o),
-                Err(xous_kernel::Error::ServerQueueFull) => retry_syscall(pid, tid),
+                Err(xous_kernel::Error::ServerQueueFull) => block_sender(pid, tid, cid),
                 Err(e) => Err(e),
             }
         }
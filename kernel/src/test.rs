@@ -16,6 +16,12 @@ static GLOBAL: &StatsAlloc<std::alloc::System> = &INSTRUMENTED_SYSTEM;
 const SERVER_SPEC: &str = "127.0.0.1:0";
 
 fn start_kernel(server_spec: &str) -> JoinHandle<()> {
+    start_kernel_with_args(server_spec, None)
+}
+
+/// Start a kernel that sees `args` as the argument block the loader would
+/// have passed it, for tests of things the boot image configures.
+fn start_kernel_with_args(server_spec: &str, args: Option<&'static [u32]>) -> JoinHandle<()> {
     assert!(
         std::env::var("XOUS_LISTEN_ADDR").is_err(),
         "XOUS_LISTEN_ADDR environment variable must be unset to run tests"
@@ -54,6 +60,9 @@ fn start_kernel(server_spec: &str) -> JoinHandle<()> {
             crate::arch::set_pid1_key(pid1_key);
             crate::arch::set_send_addr(send_addr);
             crate::arch::set_listen_address(&server_spec_server);
+            if let Some(args) = args {
+                unsafe { crate::args::KernelArguments::init(args.as_ptr()) };
+            }
             kmain()
         })
         .expect("couldn't start kernel thread");
@@ -566,6 +575,201 @@ fn send_mutableborrow_message() {
     main_thread.join().expect("couldn't join kernel process");
 }
 
+#[test]
+fn lend_page_list() {
+    let main_thread = start_kernel(SERVER_SPEC);
+    let (server_addr_send, server_addr_recv) = channel();
+
+    let xous_server = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "lend_page_list server",
+        move || {
+            let sid = xous_kernel::create_server(b"lend_page_list_s")
+                .expect("couldn't create test server");
+            server_addr_send.send(sid).unwrap();
+            let envelope = xous_kernel::receive_message(sid).expect("couldn't receive messages");
+            if let xous_kernel::Message::MutableBorrow(m) = envelope.body {
+                // Both ranges show up as one buffer, in order.
+                assert_eq!(m.buf.len(), 0x2000);
+                let bt = unsafe { core::slice::from_raw_parts_mut(m.buf.as_mut_ptr(), m.buf.len()) };
+                assert_eq!(bt[0], 1);
+                assert_eq!(bt[0x1000], 2);
+                for byte in bt.iter_mut() {
+                    *byte += 1;
+                }
+                xous_kernel::return_memory(envelope.sender, m.buf).unwrap();
+            } else {
+                panic!("unexpected message type");
+            }
+        },
+    ))
+    .expect("couldn't start server");
+
+    let xous_client = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "lend_page_list client",
+        move || {
+            let sid = server_addr_recv.recv().unwrap();
+            let conn = xous_kernel::connect(sid).expect("couldn't connect to server");
+
+            let flags = xous_kernel::MemoryFlags::R | xous_kernel::MemoryFlags::W;
+            let mut first = xous_kernel::map_memory(None, None, 0x1000, flags).unwrap();
+            let mut second = xous_kernel::map_memory(None, None, 0x1000, flags).unwrap();
+            unsafe {
+                core::slice::from_raw_parts_mut(first.as_mut_ptr(), first.len()).fill(1);
+                core::slice::from_raw_parts_mut(second.as_mut_ptr(), second.len()).fill(2);
+            }
+
+            xous_kernel::lend_pages(conn, 3, &[first, second], true, None, None)
+                .expect("couldn't lend page list");
+
+            // Each range gets its own part of the server's changes back.
+            let first_bytes = unsafe { core::slice::from_raw_parts(first.as_ptr(), first.len()) };
+            let second_bytes = unsafe { core::slice::from_raw_parts(second.as_ptr(), second.len()) };
+            assert!(first_bytes.iter().all(|&byte| byte == 2));
+            assert!(second_bytes.iter().all(|&byte| byte == 3));
+
+            // A range that isn't page-aligned is turned away.
+            let unaligned = xous_kernel::MemoryRange::new(first.as_ptr() as usize + 4, 0x800).unwrap();
+            assert_eq!(
+                xous_kernel::lend_pages(conn, 3, &[unaligned], false, None, None),
+                Err(xous_kernel::Error::BadAlignment)
+            );
+            assert_eq!(
+                xous_kernel::lend_pages(conn, 3, &[], false, None, None),
+                Err(xous_kernel::Error::BadAddress)
+            );
+        },
+    ))
+    .expect("couldn't start client");
+
+    crate::wait_process_as_thread(xous_server).expect("couldn't join server process");
+    crate::wait_process_as_thread(xous_client).expect("couldn't join client process");
+
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+/// Answer `count` protocol handshakes on `sid`.  Without a `protocol`,
+/// behave like a server that predates the handshake and hand the buffer
+/// back untouched.
+fn answer_handshakes(sid: xous_kernel::SID, count: usize, protocol: Option<xous_kernel::Protocol>) {
+    for _ in 0..count {
+        let envelope = xous_kernel::receive_message(sid).expect("couldn't receive messages");
+        if let xous_kernel::Message::MutableBorrow(m) = envelope.body {
+            assert_eq!(m.id, xous_kernel::GET_PROTOCOL);
+            if let Some(protocol) = protocol {
+                xous_kernel::answer_protocol(m.buf, protocol);
+            }
+            xous_kernel::return_memory(envelope.sender, m.buf).unwrap();
+        } else {
+            panic!("unexpected message type");
+        }
+    }
+}
+
+#[test]
+fn protocol_handshake() {
+    let main_thread = start_kernel(SERVER_SPEC);
+    let (server_addr_send, server_addr_recv) = channel();
+    let offered = xous_kernel::Protocol::new(2, 0b11);
+
+    let xous_server = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "protocol_handshake server",
+        move || {
+            let sid = xous_kernel::create_server(b"protocol_answers")
+                .expect("couldn't create test server");
+            server_addr_send.send(sid).unwrap();
+            answer_handshakes(sid, 3, Some(offered));
+        },
+    ))
+    .expect("couldn't start server");
+
+    let xous_client = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "protocol_handshake client",
+        move || {
+            let sid = server_addr_recv.recv().unwrap();
+
+            let (conn, protocol) = xous_kernel::connect_checked(sid, xous_kernel::Protocol::new(2, 0b01))
+                .expect("handshake failed");
+            assert_eq!(protocol, offered);
+            assert!(protocol.has(0b10));
+
+            // A client built for another version is turned away.  It only
+            // gives back its own reference, so `conn` keeps working.
+            assert_eq!(
+                xous_kernel::connect_checked(sid, xous_kernel::Protocol::new(3, 0)),
+                Err(xous_kernel::Error::VersionMismatch)
+            );
+            assert_eq!(xous_kernel::query_protocol(conn), Ok(offered));
+            assert_eq!(xous_kernel::disconnect(conn), Ok(()));
+            assert_eq!(
+                xous_kernel::disconnect(conn),
+                Err(xous_kernel::Error::ServerNotFound)
+            );
+        },
+    ))
+    .expect("couldn't start client");
+
+    crate::wait_process_as_thread(xous_server).expect("couldn't join server process");
+    crate::wait_process_as_thread(xous_client).expect("couldn't join client process");
+
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn protocol_handshake_old_server() {
+    let main_thread = start_kernel(SERVER_SPEC);
+    let (server_addr_send, server_addr_recv) = channel();
+
+    let xous_server = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "protocol_handshake_old_server server",
+        move || {
+            let sid = xous_kernel::create_server(b"protocol_old_srv")
+                .expect("couldn't create test server");
+            server_addr_send.send(sid).unwrap();
+            answer_handshakes(sid, 2, None);
+        },
+    ))
+    .expect("couldn't start server");
+
+    let xous_client = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "protocol_handshake_old_server client",
+        move || {
+            let sid = server_addr_recv.recv().unwrap();
+
+            // The untouched buffer reads back as version 0.
+            let conn = xous_kernel::connect(sid).expect("couldn't connect to server");
+            assert_eq!(
+                xous_kernel::query_protocol(conn),
+                Ok(xous_kernel::Protocol::default())
+            );
+
+            assert_eq!(
+                xous_kernel::connect_checked(sid, xous_kernel::Protocol::new(1, 0)),
+                Err(xous_kernel::Error::VersionMismatch)
+            );
+            assert_eq!(xous_kernel::disconnect(conn), Ok(()));
+            assert_eq!(
+                xous_kernel::disconnect(conn),
+                Err(xous_kernel::Error::ServerNotFound)
+            );
+
+            // The slot is free again, so connecting still works.
+            xous_kernel::connect(sid).expect("couldn't reconnect to server");
+        },
+    ))
+    .expect("couldn't start client");
+
+    crate::wait_process_as_thread(xous_server).expect("couldn't join server process");
+    crate::wait_process_as_thread(xous_client).expect("couldn't join client process");
+
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
 #[test]
 fn send_repeat_mutableborrow_message() {
     let main_thread = start_kernel(SERVER_SPEC);
@@ -708,6 +912,377 @@ fn server_client_same_process() {
     main_thread.join().expect("couldn't join kernel process");
 }
 
+#[test]
+fn server_queue_limit() {
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let internal_server = xous_kernel::create_process_as_thread(
+        xous_kernel::arch::ProcessArgsAsThread::new("server_queue_limit process", || {
+            let server =
+                xous_kernel::create_server(b"server_q_limit  ").expect("couldn't create server");
+            let connection =
+                xous_kernel::try_connect(server).expect("couldn't connect to our own server");
+            assert_eq!(
+                xous_kernel::set_queue_limit(server, 2).expect("couldn't set queue limit"),
+                2
+            );
+
+            let message = |id| {
+                xous_kernel::Message::Scalar(xous_kernel::ScalarMessage {
+                    id,
+                    arg1: 0,
+                    arg2: 0,
+                    arg3: 0,
+                    arg4: 0,
+                })
+            };
+            xous_kernel::try_send_message(connection, message(1)).expect("couldn't send message");
+            xous_kernel::try_send_message(connection, message(2)).expect("couldn't send message");
+            assert_eq!(
+                xous_kernel::try_send_message(connection, message(3)),
+                Err(xous_kernel::Error::ServerQueueFull)
+            );
+            assert_eq!(xous_kernel::queue_depth(server), Ok(2));
+
+            // Receiving a message makes room for another.
+            let msg = xous_kernel::receive_message(server).expect("couldn't receive message");
+            assert_eq!(msg.body, message(1));
+            assert_eq!(xous_kernel::queue_depth(server), Ok(1));
+            xous_kernel::try_send_message(connection, message(3)).expect("couldn't send message");
+            assert_eq!(xous_kernel::queue_depth(server), Ok(2));
+        }),
+    )
+    .expect("couldn't start server");
+
+    xous_kernel::wait_process_as_thread(internal_server)
+        .expect("couldn't join internal_server process");
+
+    // Any process ought to be able to shut down the system currently.
+    rsyscall(SysCall::Shutdown).expect("unable to shutdown server");
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+/// Senders that find a queue full are woken in the order they arrived, and
+/// forgotten if their process goes away.
+#[test]
+fn blocked_senders() {
+    use crate::server::{Server, MAX_BLOCKED_SENDERS};
+    let mut slot = None;
+    Server::init(
+        &mut slot,
+        xous_kernel::PID::new(2).unwrap(),
+        xous_kernel::SID::from_bytes(b"blocked_senders ").unwrap(),
+        xous_kernel::MemoryRange::new(4096, 4096).unwrap(),
+    )
+    .unwrap();
+    let server = slot.as_mut().unwrap();
+    let pid = |n| xous_kernel::PID::new(n).unwrap();
+
+    assert_eq!(server.take_blocked_sender(), None);
+    for tid in 0..MAX_BLOCKED_SENDERS {
+        assert!(server.block_sender(pid(3 + tid as u8 % 2), tid));
+    }
+    assert!(!server.block_sender(pid(5), 0));
+
+    assert_eq!(server.take_blocked_sender(), Some((pid(3), 0)));
+    assert_eq!(server.take_blocked_sender(), Some((pid(4), 1)));
+    assert!(server.block_sender(pid(5), 9));
+
+    // PID 4 exits, and the rest keep their order.
+    server.discard_messages_for_pid(pid(4));
+    for tid in (2..MAX_BLOCKED_SENDERS).step_by(2) {
+        assert_eq!(server.take_blocked_sender(), Some((pid(3), tid)));
+    }
+    assert_eq!(server.take_blocked_sender(), Some((pid(5), 9)));
+    assert_eq!(server.take_blocked_sender(), None);
+}
+
+#[test]
+fn sandboxed_process() {
+    let main_thread = start_kernel(SERVER_SPEC);
+    xous_kernel::arch::ensure_connection().expect("couldn't connect to kernel");
+
+    let allowed = xous_kernel::create_server(b"sandbox allowed ").expect("couldn't create server");
+    let forbidden =
+        xous_kernel::create_server(b"sandbox forbid  ").expect("couldn't create server");
+
+    let args = xous_kernel::arch::ProcessArgsAsThread::new("sandboxed process", move || {
+        assert_eq!(
+            xous_kernel::create_server(b"sandbox escape  "),
+            Err(xous_kernel::Error::AccessDenied)
+        );
+        assert_eq!(
+            xous_kernel::try_connect(forbidden),
+            Err(xous_kernel::Error::AccessDenied)
+        );
+        xous_kernel::try_connect(allowed).expect("couldn't connect to allowed server");
+        assert_eq!(
+            rsyscall(SysCall::Shutdown),
+            Err(xous_kernel::Error::AccessDenied)
+        );
+        assert_eq!(
+            xous_kernel::pause_world(),
+            Err(xous_kernel::Error::AccessDenied)
+        );
+    });
+
+    // Create the process by hand, so that it can be sandboxed before it runs.
+    let init = xous_kernel::arch::create_process_pre_as_thread(&args)
+        .expect("couldn't prepare process");
+    let pid = match rsyscall(SysCall::CreateProcess(init)).expect("couldn't create process") {
+        xous_kernel::Result::ProcessID(pid) => pid,
+        other => panic!("unexpected result: {:?}", other),
+    };
+    assert_eq!(
+        xous_kernel::sandbox_allow_connection(pid, allowed),
+        Err(xous_kernel::Error::InvalidSyscall)
+    );
+    xous_kernel::sandbox_process(pid).expect("couldn't sandbox process");
+    xous_kernel::sandbox_allow_connection(pid, allowed).expect("couldn't allow connection");
+    let sandboxed = xous_kernel::arch::create_process_post_as_thread(args, init, pid)
+        .expect("couldn't start sandboxed process");
+
+    xous_kernel::wait_process_as_thread(sandboxed)
+        .expect("couldn't join sandboxed process");
+
+    // Any process ought to be able to shut down the system currently.
+    rsyscall(SysCall::Shutdown).expect("unable to shutdown server");
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn cpu_quota() {
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let args = xous_kernel::arch::ProcessArgsAsThread::new("cpu quota process", || {
+        // Only the parent may change a process' quota.
+        let pid = xous_kernel::process_id().expect("couldn't get process id");
+        assert_eq!(
+            xous_kernel::set_cpu_quota(pid, 100),
+            Err(xous_kernel::Error::ProcessNotChild)
+        );
+        assert_eq!(xous_kernel::cpu_stats(pid).expect("couldn't get cpu stats").quota, 10);
+    });
+    let init = xous_kernel::arch::create_process_pre_as_thread(&args)
+        .expect("couldn't prepare process");
+    let pid = match rsyscall(SysCall::CreateProcess(init)).expect("couldn't create process") {
+        xous_kernel::Result::ProcessID(pid) => pid,
+        other => panic!("unexpected result: {:?}", other),
+    };
+    assert_eq!(xous_kernel::cpu_stats(pid).expect("couldn't get cpu stats").quota, 100);
+    assert_eq!(
+        xous_kernel::set_cpu_quota(pid, 0),
+        Err(xous_kernel::Error::InvalidSyscall)
+    );
+    assert_eq!(
+        xous_kernel::set_cpu_quota(pid, 101),
+        Err(xous_kernel::Error::InvalidSyscall)
+    );
+    xous_kernel::set_cpu_quota(pid, 10).expect("couldn't set cpu quota");
+    let limited = xous_kernel::arch::create_process_post_as_thread(args, init, pid)
+        .expect("couldn't start process");
+
+    xous_kernel::wait_process_as_thread(limited).expect("couldn't join process");
+
+    // Any process ought to be able to shut down the system currently.
+    rsyscall(SysCall::Shutdown).expect("unable to shutdown server");
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+/// An argument block granting `SET_QUOTA` to PID 2
+static QUOTA_ARGS: [u32; 11] = [
+    u32::from_le_bytes(*b"XArg"),
+    5 << 16,
+    11,
+    1,
+    0,
+    0,
+    0,
+    u32::from_le_bytes(*b"Caps"),
+    2 << 16,
+    2,
+    0b0010_0000,
+];
+
+#[test]
+fn delegated_cpu_quota() {
+    let main_thread = start_kernel_with_args(SERVER_SPEC, Some(&QUOTA_ARGS));
+    xous_kernel::arch::ensure_connection().expect("couldn't connect to kernel");
+
+    // This process is scheduled by the kernel, and has no parent that could
+    // limit it.  The first service it starts is PID 2, which the argument
+    // block lets set the quota of any such process.  Both processes are
+    // started before either is joined so that they get different PIDs.
+    let scheduled = xous_kernel::process_id().expect("couldn't get process id");
+    let service = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("quota service", move || {
+            xous_kernel::set_cpu_quota(scheduled, 25).expect("couldn't set cpu quota");
+        }),
+    )
+    .expect("couldn't start process");
+
+    // Processes that weren't granted it are still limited to their children.
+    let other = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("unprivileged process", move || {
+            assert_eq!(
+                xous_kernel::set_cpu_quota(scheduled, 50),
+                Err(xous_kernel::Error::ProcessNotChild)
+            );
+        }),
+    )
+    .expect("couldn't start process");
+    xous_kernel::wait_process_as_thread(service).expect("couldn't join process");
+    xous_kernel::wait_process_as_thread(other).expect("couldn't join process");
+    assert_eq!(
+        xous_kernel::cpu_stats(scheduled)
+            .expect("couldn't get cpu stats")
+            .quota,
+        25
+    );
+
+    // Any process ought to be able to shut down the system currently.
+    rsyscall(SysCall::Shutdown).expect("unable to shutdown server");
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn pause_world() {
+    let main_thread = start_kernel(SERVER_SPEC);
+    xous_kernel::arch::ensure_connection().expect("couldn't connect to kernel");
+
+    assert_eq!(
+        xous_kernel::resume_world(),
+        Err(xous_kernel::Error::AccessDenied)
+    );
+    xous_kernel::pause_world().expect("couldn't pause world");
+    xous_kernel::pause_world().expect("couldn't pause world a second time");
+
+    // Nobody else may take over or end the pause.
+    let other = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("pause world process", || {
+            assert_eq!(
+                xous_kernel::pause_world(),
+                Err(xous_kernel::Error::AccessDenied)
+            );
+            assert_eq!(
+                xous_kernel::resume_world(),
+                Err(xous_kernel::Error::AccessDenied)
+            );
+        }),
+    )
+    .expect("couldn't start process");
+    xous_kernel::wait_process_as_thread(other).expect("couldn't join process");
+
+    xous_kernel::resume_world().expect("couldn't resume world");
+    assert_eq!(
+        xous_kernel::resume_world(),
+        Err(xous_kernel::Error::AccessDenied)
+    );
+
+    // There is no capability table, so only PID 1 may debug.
+    let other = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("debug process", || {
+            assert_eq!(
+                xous_kernel::pause_world(),
+                Err(xous_kernel::Error::AccessDenied)
+            );
+        }),
+    )
+    .expect("couldn't start process");
+    xous_kernel::wait_process_as_thread(other).expect("couldn't join process");
+
+    // Any process ought to be able to shut down the system currently.
+    rsyscall(SysCall::Shutdown).expect("unable to shutdown server");
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+/// A thread or process that reuses an ID must not inherit the old stack,
+/// or fresh pages at the old address would be filled with the pattern.
+#[test]
+fn stack_watermark_reuse() {
+    use crate::watermark::StackTable;
+    let pid = xous_kernel::PID::new(2).unwrap();
+    let other = xous_kernel::PID::new(3).unwrap();
+    let mut stacks = StackTable::new();
+    assert!(stacks.track(pid, 1, 0x8000_0000, 0x2000));
+    assert!(stacks.track(pid, 2, 0x7000_0000, 0x2000));
+    assert!(stacks.track(other, 1, 0x8000_0000, 0x2000));
+    assert!(!stacks.track(pid, crate::arch::process::MAX_THREAD, 0x6000_0000, 0x1000));
+    assert!(stacks.contains(pid, 0x8000_1000));
+
+    // A new thread with the same TID only claims its own stack.
+    assert!(stacks.track(pid, 1, 0x9000_0000, 0x1000));
+    assert_eq!(stacks.stack_of(pid, 1), Some((0x9000_0000, 0x1000)));
+    assert!(!stacks.contains(pid, 0x8000_1000));
+    assert!(stacks.contains(pid, 0x7000_1000));
+
+    // The process exits, leaving nothing for the next process with its PID.
+    stacks.forget(pid);
+    assert_eq!(stacks.stack_of(pid, 2), None);
+    assert!(!stacks.contains(pid, 0x7000_1000));
+    assert!(!stacks.contains(pid, 0x9000_0000));
+    assert!(stacks.contains(other, 0x8000_1000));
+}
+
+/// Page lists are checked before anything is lent, and every list is
+/// tracked until its server returns it or goes away.
+#[test]
+fn page_list_table() {
+    use crate::pagelist::{PageList, PageListTable, MAX_PAGE_LISTS};
+    use xous_kernel::Error;
+    assert_eq!(PageList::parse(&[]), Err(Error::BadAddress));
+    assert_eq!(PageList::parse(&[0x1000]), Err(Error::BadAddress));
+    assert_eq!(PageList::parse(&[0, 0x1000]), Err(Error::BadAddress));
+    assert_eq!(PageList::parse(&[0x1000, 0]), Err(Error::BadAddress));
+    assert_eq!(PageList::parse(&[0x1004, 0x1000]), Err(Error::BadAlignment));
+    assert_eq!(PageList::parse(&[0x1000, 0x800]), Err(Error::BadAlignment));
+    assert_eq!(
+        PageList::parse(&[usize::MAX & !0xfff, 0x2000]),
+        Err(Error::BadAddress)
+    );
+    let too_many = [0x1000usize; xous_kernel::MAX_PAGE_LIST * 2 + 2];
+    assert_eq!(PageList::parse(&too_many), Err(Error::BadAddress));
+
+    // The ranges land back to back in the server.
+    let list = PageList::parse(&[0x2000_0000, 0x2000, 0x1000_0000, 0x1000]).unwrap();
+    assert_eq!(list.len(), 0x3000);
+    let pages: Vec<(usize, usize)> = list.pages().collect();
+    assert_eq!(
+        pages,
+        [(0x2000_0000, 0), (0x2000_1000, 0x1000), (0x1000_0000, 0x2000)]
+    );
+
+    let client = xous_kernel::PID::new(2).unwrap();
+    let server = xous_kernel::PID::new(3).unwrap();
+    let other = xous_kernel::PID::new(4).unwrap();
+    let mut table = PageListTable::new();
+    for index in 0..MAX_PAGE_LISTS {
+        let owner = if index % 2 == 0 { server } else { other };
+        table.insert(client, owner, 0x4000_0000 + index * 0x10000, list).unwrap();
+    }
+    assert!(!table.has_room());
+    assert_eq!(
+        table.insert(client, server, 0x5000_0000, list),
+        Err(Error::OutOfMemory)
+    );
+
+    // Only the server holding the list can give it back, and only once.
+    assert_eq!(table.take(other, 0x4000_0000), None);
+    assert_eq!(table.take(server, 0x4000_0000), Some((client, list)));
+    assert_eq!(table.take(server, 0x4000_0000), None);
+    assert!(table.has_room());
+
+    // A server that exits drops every list it held, and nobody else's.
+    table.forget_server(server);
+    assert_eq!(table.take(server, 0x4002_0000), None);
+    assert_eq!(table.take(other, 0x4001_0000), Some((client, list)));
+}
+
 /// Test that one process can have multiple contexts
 #[test]
 fn multiple_contexts() {
@@ -836,3 +1411,27 @@ fn process_restart_server() {
 
     main_thread.join().expect("couldn't join kernel process");
 }
+
+/// Random syscalls may be refused, but must never bring down the kernel
+#[test]
+fn fuzz_random_syscalls() {
+    use rand::{Rng, SeedableRng};
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0x584f_5553);
+    for _ in 0..256 {
+        let mut data = vec![];
+        for _ in 0..rng.gen_range(1, 16) {
+            data.push(rng.gen::<u8>());
+            // Small arguments are far more likely to be valid IDs, so
+            // make them common.
+            for _ in 0..7 {
+                let arg: u32 = if rng.gen_bool(0.75) {
+                    rng.gen_range(0, 8)
+                } else {
+                    rng.gen()
+                };
+                data.extend_from_slice(&arg.to_le_bytes());
+            }
+        }
+        crate::fuzz::fuzz_syscall(&data);
+    }
+}
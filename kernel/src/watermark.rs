@@ -0,0 +1,67 @@
+use xous_kernel::{PID, TID};
+
+use crate::arch::process::{MAX_PROCESS_COUNT, MAX_THREAD};
+
+/// The stack of every thread whose high-water mark is being tracked.
+///
+/// Stack pages are filled with a pattern when they are first touched, so an
+/// entry must be replaced or removed as soon as its IDs are reused.
+/// Otherwise a new thread or process with the same IDs would be handed
+/// pages at the old address full of the pattern instead of zeroes.
+pub struct StackTable {
+    /// `(base, size)` indexed by PID and TID.  A size of `0` means the
+    /// thread is not tracked.
+    stacks: [[(usize, usize); MAX_THREAD]; MAX_PROCESS_COUNT],
+}
+
+impl StackTable {
+    pub const fn new() -> StackTable {
+        StackTable {
+            stacks: [[(0, 0); MAX_THREAD]; MAX_PROCESS_COUNT],
+        }
+    }
+
+    fn slot(&mut self, pid: PID, tid: TID) -> Option<&mut (usize, usize)> {
+        self.stacks.get_mut(pid.get() as usize - 1)?.get_mut(tid)
+    }
+
+    /// Start tracking `tid`'s stack, replacing whatever was there before.
+    /// Returns `false` if the IDs are out of range.
+    pub fn track(&mut self, pid: PID, tid: TID, base: usize, size: usize) -> bool {
+        match self.slot(pid, tid) {
+            Some(slot) => {
+                *slot = (base, size);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Stop tracking every stack in `pid`, because the process has exited
+    /// or its PID is about to be reused.
+    pub fn forget(&mut self, pid: PID) {
+        if let Some(threads) = self.stacks.get_mut(pid.get() as usize - 1) {
+            *threads = [(0, 0); MAX_THREAD];
+        }
+    }
+
+    /// The `(base, size)` of `tid`'s stack, if it is being tracked
+    pub fn stack_of(&self, pid: PID, tid: TID) -> Option<(usize, usize)> {
+        match *self.stacks.get(pid.get() as usize - 1)?.get(tid)? {
+            (_, 0) => None,
+            stack => Some(stack),
+        }
+    }
+
+    /// Returns `true` if `virt` is in one of `pid`'s tracked stacks
+    pub fn contains(&self, pid: PID, virt: usize) -> bool {
+        self.stacks
+            .get(pid.get() as usize - 1)
+            .map(|threads| {
+                threads
+                    .iter()
+                    .any(|&(base, size)| virt >= base && virt < base + size)
+            })
+            .unwrap_or(false)
+    }
+}
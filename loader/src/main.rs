@@ -22,17 +22,27 @@ const USER_AREA_END: usize = 0xff00_0000;
 const EXCEPTION_STACK_TOP: usize = 0xffff_0000;
 const KERNEL_LOAD_OFFSET: usize = 0xffd0_0000;
 const KERNEL_ARGUMENT_OFFSET: usize = 0xffc0_0000;
+const KERNEL_TICKTIMER_OFFSET: usize = 0xffce_0000;
 
 const FLG_VALID: usize = 0x1;
 const FLG_X: usize = 0x8;
 const FLG_W: usize = 0x4;
 const FLG_R: usize = 0x2;
 const FLG_U: usize = 0x10;
+const FLG_G: usize = 0x20;
 const FLG_A: usize = 0x40;
 const FLG_D: usize = 0x80;
 const STACK_PAGE_COUNT: usize = 5;
 
+/// Number of `(start, end, flags)` records in the `PMPr` tag.  Each one takes
+/// two PMP entries, and one more entry covers everything else.
+const PMP_RANGES: usize = 7;
+const PMP_R: u32 = 1 << 0;
+const PMP_W: u32 = 1 << 1;
+const PMP_X: u32 = 1 << 2;
+
 mod debug;
+mod sha256;
 
 // Install a panic handler when not running tests.
 #[cfg(not(test))]
@@ -69,12 +79,19 @@ pub struct BootConfig {
     /// kernel to access user memory.
     debug: bool,
 
+    /// `true` if process stacks and memory areas should be left at
+    /// their fixed addresses rather than randomized.
+    no_aslr: bool,
+
     /// Where the tagged args list starts in RAM.
     args: KernelArguments,
 
     /// Additional memory regions in this system
     regions: &'static [MemoryRegionExtra],
 
+    /// Physical start and end of the kernel's text section
+    kernel_text: (usize, usize),
+
     /// The origin of usable memory.  This is where heap lives.
     sram_start: *mut usize,
 
@@ -109,8 +126,10 @@ impl Default for BootConfig {
         BootConfig {
             no_copy: false,
             debug: false,
+            no_aslr: false,
             base_addr: core::ptr::null::<usize>(),
             regions: Default::default(),
+            kernel_text: (0, 0),
             sram_start: core::ptr::null_mut::<usize>(),
             sram_size: 0,
             args: KernelArguments::new(core::ptr::null::<usize>()),
@@ -141,6 +160,10 @@ pub struct InitialProcess {
 
     /// Address of the top of the stack
     sp: usize,
+
+    /// Random bits used to lay out this process, or `0` if
+    /// randomization is disabled.
+    aslr_seed: usize,
 }
 
 #[repr(C)]
@@ -213,7 +236,11 @@ impl MiniElf {
         // The load offset is the end of this process.  Shift it down by one page
         // so we get the start of the first page.
         let mut top = load_offset - PAGE_SIZE;
-        let stack_addr = USER_STACK_TOP - 4;
+
+        // The top byte of the seed moves the stack down by up to 255 pages.
+        // The kernel uses the remaining bits to slide the heap and mapping areas.
+        let aslr_seed = allocator.aslr_seed();
+        let stack_addr = USER_STACK_TOP - (aslr_seed >> 24) * PAGE_SIZE - 4;
 
         // Allocate a page to handle the top-level memory translation
         let satp_address = allocator.alloc() as usize;
@@ -242,6 +269,10 @@ impl MiniElf {
             );
             allocator.change_owner(pid as XousPid, sp_page);
         }
+        allocator.guard_page(
+            satp,
+            (stack_addr - PAGE_SIZE * STACK_PAGE_COUNT) & !(PAGE_SIZE - 1),
+        );
 
         // Example: Page starts at 0xf0c0 and is 8192 bytes long.
         // 1. Copy 3094 bytes to page 1
@@ -310,6 +341,7 @@ impl MiniElf {
         let mut process = &mut allocator.processes[pid as usize - 1];
         process.entrypoint = self.entry_point as usize;
         process.sp = stack_addr;
+        process.aslr_seed = aslr_seed;
         process.satp = 0x8000_0000 | ((pid as usize) << 22) | (satp_address >> 12);
 
         allocated_bytes
@@ -371,7 +403,12 @@ impl ProgramDescription {
         let pid_idx = (pid - 1) as usize;
         let is_kernel = pid == 1;
         let flag_defaults = FLG_R | FLG_W | if is_kernel { 0 } else { FLG_U };
-        let stack_addr = USER_STACK_TOP - 4;
+        let aslr_seed = allocator.aslr_seed();
+        let stack_addr = if is_kernel {
+            USER_STACK_TOP - 4
+        } else {
+            USER_STACK_TOP - (aslr_seed >> 24) * PAGE_SIZE - 4
+        };
         if is_kernel {
             assert!(self.text_offset as usize == KERNEL_LOAD_OFFSET);
             assert!(((self.text_offset + self.text_size) as usize) < EXCEPTION_STACK_TOP);
@@ -429,6 +466,12 @@ impl ProgramDescription {
                 allocator.change_owner(pid as XousPid, sp_page);
             }
         }
+        if !is_kernel {
+            allocator.guard_page(
+                satp,
+                (stack_addr - PAGE_SIZE * STACK_PAGE_COUNT) & !(PAGE_SIZE - 1),
+            );
+        }
 
         assert!((self.text_offset as usize & (PAGE_SIZE - 1)) == 0);
         assert!((self.data_offset as usize & (PAGE_SIZE - 1)) == 0);
@@ -459,6 +502,14 @@ impl ProgramDescription {
             allocator.change_owner(pid as XousPid, load_offset + offset);
         }
 
+        if is_kernel {
+            let text_start = load_offset + rounded_data_bss;
+            allocator.kernel_text = (
+                text_start,
+                text_start + ((self.text_size as usize + PAGE_SIZE - 1) & !(PAGE_SIZE - 1)),
+            );
+        }
+
         // Map the process data section into RAM.
         for offset in (0..(self.data_size + self.bss_size) as usize).step_by(PAGE_SIZE as usize) {
             // let page_addr = allocator.alloc();
@@ -484,13 +535,217 @@ impl ProgramDescription {
             allocator.change_owner(pid as XousPid, 0xF000_2000);
         }
 
+        // The kernel keeps time with the ticktimer.  The page is left
+        // unowned so the ticktimer server can still map it.
+        if is_kernel {
+            allocator.map_page(
+                satp,
+                utralib::generated::utra::ticktimer::HW_TICKTIMER_BASE & !(PAGE_SIZE - 1),
+                KERNEL_TICKTIMER_OFFSET,
+                FLG_R,
+            );
+        }
+
         let mut process = &mut allocator.processes[pid_idx];
         process.entrypoint = self.entrypoint as usize;
         process.sp = stack_addr;
+        process.aslr_seed = aslr_seed;
         process.satp = 0x8000_0000 | ((pid as usize) << 22) | (satp_address >> 12);
     }
 }
 
+/// If the image contains a `PMPr` tag, use the PMP to make the kernel's text
+/// read-only and the additional memory regions non-executable, then record
+/// the ranges in the tag so that the kernel can report them.
+fn setup_pmp(cfg: &mut BootConfig) {
+    let tag = match cfg
+        .args
+        .iter()
+        .find(|tag| tag.name == u32::from_le_bytes(*b"PMPr"))
+    {
+        Some(tag) => tag,
+        None => return,
+    };
+    assert!(tag.size as usize == PMP_RANGES * 12, "invalid PMPr size");
+    if cfg.no_copy {
+        println!("PMP: arguments are not in RAM, leaving PMP disabled");
+        return;
+    }
+
+    let mut ranges = [(0usize, 0usize, 0u32); PMP_RANGES];
+    ranges[0] = (cfg.kernel_text.0, cfg.kernel_text.1, PMP_R | PMP_X);
+    if cfg.regions.len() > PMP_RANGES - 1 {
+        println!(
+            "PMP: only protecting the first {} of {} regions",
+            PMP_RANGES - 1,
+            cfg.regions.len()
+        );
+    }
+    for (range, region) in ranges[1..].iter_mut().zip(cfg.regions.iter()) {
+        let start = region.start as usize;
+        *range = (start, start + region.length as usize, PMP_R | PMP_W);
+    }
+
+    // The args were copied to RAM, so the tag can be filled in place.
+    let record = tag.data.as_ptr() as *mut u32;
+    for (i, (start, end, flags)) in ranges.iter().enumerate() {
+        println!("PMP: {:08x} - {:08x} flags {:x}", start, end, flags);
+        unsafe {
+            record.add(i * 3).write_volatile(*start as u32);
+            record.add(i * 3 + 1).write_volatile(*end as u32);
+            record.add(i * 3 + 2).write_volatile(*flags);
+        }
+    }
+    write_pmp(&ranges);
+}
+
+/// If the image contains a `Meas` tag, hash the kernel and every initial
+/// process as they are stored in the image, and record the result in the
+/// tag.  Each image is folded into a running measurement the way a TPM
+/// extends a PCR: `m = SHA256(m || SHA256(image))`, starting from zero, in
+/// the order the images appear in the arguments.  Nothing writes the tag
+/// after this, so the kernel can report it as evidence of what was booted.
+///
+/// An XIP image keeps its arguments in flash where the tag can't be
+/// written, so the tag would still hold whatever the image shipped with.
+/// Rather than let the kernel report those bytes as a measurement, refuse
+/// to boot such an image.
+fn measure_images(cfg: &mut BootConfig) {
+    let tag = match cfg
+        .args
+        .iter()
+        .find(|tag| tag.name == u32::from_le_bytes(*b"Meas"))
+    {
+        Some(tag) => tag,
+        None => return,
+    };
+    assert!(tag.size == 36, "invalid Meas size");
+    if cfg.no_copy {
+        panic!("Meas tag present but arguments are not in RAM, refusing to boot unmeasured");
+    }
+
+    let mut measurement = [0u8; 32];
+    let mut count = 0u32;
+    for tag in cfg.args.iter() {
+        let (load_offset, len) = if tag.name == u32::from_le_bytes(*b"XKrn") {
+            // Data follows text, starting on the word boundary at or before
+            // the end of the text.  See `copy_processes()`.
+            let xkrn = unsafe { &*(tag.data.as_ptr() as *const ProgramDescription) };
+            let data_start = (xkrn.load_offset + xkrn.text_size) & !3;
+            (xkrn.load_offset, (data_start + xkrn.data_size - xkrn.load_offset) as usize)
+        } else if tag.name == u32::from_le_bytes(*b"IniE") {
+            let inie = MiniElf::new(&tag);
+            let len = inie
+                .sections
+                .iter()
+                .filter(|section| !section.no_copy())
+                .map(|section| section.len())
+                .sum();
+            (inie.load_offset, len)
+        } else {
+            continue;
+        };
+        let image = unsafe {
+            slice::from_raw_parts(
+                (cfg.base_addr as *const u8).add(load_offset as usize),
+                len,
+            )
+        };
+        let mut extend = sha256::Sha256::new();
+        extend.update(&measurement);
+        extend.update(&sha256::Sha256::digest(image));
+        measurement = extend.finish();
+        count += 1;
+    }
+    println!("Measure: {} images measured", count);
+
+    // The args were copied to RAM, so the tag can be filled in place.
+    let record = tag.data.as_ptr() as *mut u32;
+    unsafe {
+        record.write_volatile(count);
+        for (i, word) in measurement.chunks_exact(4).enumerate() {
+            record
+                .add(1 + i)
+                .write_volatile(u32::from_le_bytes([word[0], word[1], word[2], word[3]]));
+        }
+    }
+}
+
+/// Program the PMP with one top-of-range entry pair per range, followed by
+/// an entry that leaves the rest of the address space accessible.
+#[cfg(target_arch = "riscv32")]
+fn write_pmp(ranges: &[(usize, usize, u32); PMP_RANGES]) {
+    use riscv::register::*;
+    const PMP_TOR: usize = 1 << 3;
+    const PMP_NAPOT: usize = 3 << 3;
+
+    let mut addrs = [0usize; 16];
+    let mut cfgs = [0usize; 4];
+    for (i, (start, end, flags)) in ranges.iter().enumerate() {
+        if start == end {
+            continue;
+        }
+        addrs[i * 2] = start >> 2;
+        addrs[i * 2 + 1] = end >> 2;
+        let entry = i * 2 + 1;
+        cfgs[entry / 4] |= (PMP_TOR | *flags as usize) << ((entry % 4) * 8);
+    }
+    let catch_all = PMP_RANGES * 2;
+    addrs[catch_all] = usize::MAX;
+    cfgs[catch_all / 4] |= (PMP_NAPOT | (PMP_R | PMP_W | PMP_X) as usize) << ((catch_all % 4) * 8);
+
+    pmpaddr0::write(addrs[0]);
+    pmpaddr1::write(addrs[1]);
+    pmpaddr2::write(addrs[2]);
+    pmpaddr3::write(addrs[3]);
+    pmpaddr4::write(addrs[4]);
+    pmpaddr5::write(addrs[5]);
+    pmpaddr6::write(addrs[6]);
+    pmpaddr7::write(addrs[7]);
+    pmpaddr8::write(addrs[8]);
+    pmpaddr9::write(addrs[9]);
+    pmpaddr10::write(addrs[10]);
+    pmpaddr11::write(addrs[11]);
+    pmpaddr12::write(addrs[12]);
+    pmpaddr13::write(addrs[13]);
+    pmpaddr14::write(addrs[14]);
+    pmpaddr15::write(addrs[15]);
+    pmpcfg0::write(cfgs[0]);
+    pmpcfg1::write(cfgs[1]);
+    pmpcfg2::write(cfgs[2]);
+    pmpcfg3::write(cfgs[3]);
+}
+
+#[cfg(not(target_arch = "riscv32"))]
+fn write_pmp(_ranges: &[(usize, usize, u32); PMP_RANGES]) {}
+
+/// How many times to poll the TRNG for fresh data before giving up.  The
+/// hardware produces a word long before this, but a simulator without a
+/// TRNG model never does.
+#[cfg(not(test))]
+const TRNG_POLL_LIMIT: usize = 1_000_000;
+
+/// Read one word from the TRNG, waiting for it to produce fresh data.
+/// Returns `None` if it never does.
+#[cfg(not(test))]
+fn read_trng() -> Option<u32> {
+    use utralib::generated::*;
+    let mut trng = CSR::new(utra::trng_osc::HW_TRNG_OSC_BASE as *mut u32);
+    trng.rmwf(utra::trng_osc::CTL_ENA, 1);
+    for _ in 0..TRNG_POLL_LIMIT {
+        if trng.rf(utra::trng_osc::STATUS_FRESH) != 0 {
+            return Some(trng.rf(utra::trng_osc::RAND_RAND));
+        }
+    }
+    None
+}
+
+/// There is no TRNG when running tests, so leave processes unrandomized.
+#[cfg(test)]
+fn read_trng() -> Option<u32> {
+    Some(0)
+}
+
 unsafe fn bzero<T>(mut sbss: *mut T, ebss: *mut T)
 where
     T: Copy,
@@ -556,6 +811,9 @@ pub fn read_initial_config(cfg: &mut BootConfig) {
             if boot_flags & (1 << 2) != 0 {
                 cfg.debug = true;
             }
+            if boot_flags & (1 << 3) != 0 {
+                cfg.no_aslr = true;
+            }
         } else if tag.name == u32::from_le_bytes(*b"XKrn") {
             assert!(!kernel_seen, "kernel appears twice");
             assert!(
@@ -794,6 +1052,27 @@ fn copy_processes(cfg: &mut BootConfig) {
 }
 
 impl BootConfig {
+    /// Draw random bits for laying out a new process, or return `0` if
+    /// randomization was turned off with the `no_aslr` boot flag.  Only
+    /// stacks and the kernel-managed memory areas move; program images stay
+    /// at the addresses they were linked at.
+    ///
+    /// If the TRNG doesn't respond, randomization is turned off for the
+    /// rest of the boot rather than waiting on it for every process.
+    fn aslr_seed(&mut self) -> usize {
+        if self.no_aslr {
+            return 0;
+        }
+        match read_trng() {
+            Some(seed) => seed as usize,
+            None => {
+                println!("WARNING: TRNG isn't responding -- process layout will not be randomized");
+                self.no_aslr = true;
+                0
+            }
+        }
+    }
+
     fn get_top(&self) -> *mut usize {
         let val = unsafe {
             self.sram_start.add(
@@ -873,6 +1152,19 @@ impl BootConfig {
         }
     }
 
+    /// Turn the page at `virt` into a guard page, which faults on any access
+    /// and which the kernel will never demand-page or hand out.  The kernel
+    /// recognises an invalid entry with only the global bit set.
+    pub fn guard_page(&mut self, root: &mut PageTable, virt: usize) {
+        // Map a placeholder first, so that the second-level table exists.
+        self.map_page(root, 0, virt, FLG_R);
+        let vpn1 = (virt >> 22) & ((1 << 10) - 1);
+        let vpn0 = (virt >> 12) & ((1 << 10) - 1);
+        let l0_pt =
+            unsafe { &mut (*(((root.entries[vpn1] << 2) & !((1 << 12) - 1)) as *mut PageTable)) };
+        l0_pt.entries[vpn0] = FLG_G;
+    }
+
     pub fn map_page_32(&mut self, root: &mut PageTable, phys: usize, virt: usize, flags: usize) {
         let ppn1 = (phys >> 22) & ((1 << 12) - 1);
         let ppn0 = (phys >> 12) & ((1 << 10) - 1);
@@ -909,6 +1201,13 @@ impl BootConfig {
         //     panic!("Page already allocated!");
         // }
         let previous_flags = l0_pt[vpn0] & 0xf;
+        if (flags | previous_flags) & (FLG_W | FLG_X) == (FLG_W | FLG_X) && flags & FLG_U != 0 {
+            panic!(
+                "user page {:08x} is both writable and executable -- \
+                 sections must be page-aligned",
+                virt
+            );
+        }
         l0_pt[vpn0] =
             (ppn1 << 20) | (ppn0 << 10) | flags | previous_flags | FLG_VALID | FLG_D | FLG_A;
 
@@ -1022,6 +1321,8 @@ fn boot_sequence(args: KernelArguments, _signature: u32) -> ! {
 
     phase_1(&mut cfg);
     phase_2(&mut cfg);
+    measure_images(&mut cfg);
+    setup_pmp(&mut cfg);
 
     // The MMU should be set up now, and memory pages assigned to their
     // respective processes.
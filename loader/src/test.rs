@@ -413,3 +413,32 @@ pub unsafe extern "C" fn start_kernel(
 ) -> ! {
     panic!("not running natively");
 }
+
+#[test]
+fn sha256_known_answers() {
+    use crate::sha256::Sha256;
+    let hex = |digest: [u8; 32]| {
+        digest
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>()
+    };
+
+    assert_eq!(
+        hex(Sha256::digest(b"")),
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    );
+    assert_eq!(
+        hex(Sha256::digest(b"abc")),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+
+    // Two blocks, fed in pieces that straddle the block boundary
+    let mut hasher = Sha256::new();
+    hasher.update(b"abcdbcdecdefdefgefghfghighijhijkijkl");
+    hasher.update(b"jklmklmnlmnomnopnopq");
+    assert_eq!(
+        hex(hasher.finish()),
+        "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+    );
+}
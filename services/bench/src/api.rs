@@ -0,0 +1,47 @@
+use xous::{MemoryMessage, MemoryRange, Message, ScalarMessage};
+
+#[derive(Debug)]
+pub enum Opcode {
+    /// Return the argument unchanged
+    Ping(usize),
+
+    /// Read every byte of a lent buffer, then return it
+    Touch(MemoryRange),
+}
+
+impl<'a> core::convert::TryFrom<&'a Message> for Opcode {
+    type Error = &'static str;
+    fn try_from(message: &'a Message) -> Result<Self, Self::Error> {
+        match message {
+            Message::BlockingScalar(m) => match m.id {
+                1 => Ok(Opcode::Ping(m.arg1)),
+                _ => Err("unrecognized opcode"),
+            },
+            Message::Borrow(m) => match m.id {
+                2 => Ok(Opcode::Touch(m.buf)),
+                _ => Err("unrecognized opcode"),
+            },
+            _ => Err("unhandled message type"),
+        }
+    }
+}
+
+impl Into<Message> for Opcode {
+    fn into(self) -> Message {
+        match self {
+            Opcode::Ping(value) => Message::BlockingScalar(ScalarMessage {
+                id: 1,
+                arg1: value,
+                arg2: 0,
+                arg3: 0,
+                arg4: 0,
+            }),
+            Opcode::Touch(buf) => Message::Borrow(MemoryMessage {
+                id: 2,
+                buf,
+                offset: None,
+                valid: None,
+            }),
+        }
+    }
+}
@@ -0,0 +1,180 @@
+//! Microbenchmarks for messaging and scheduling.
+//!
+//! There is no shellchat in this tree, so there are no shell verbs to start
+//! a run.  Instead, building `shell` with its `bench` feature (which is what
+//! `cargo xtask run-bench` does) calls [`run_all`] once at startup, and the
+//! results are written to the log.
+//!
+//! Only the kernel paths are covered.  There is no pddb, SHA or AES engine
+//! driver yet to measure.
+#![cfg_attr(target_os = "none", no_std)]
+
+pub mod api;
+
+use log::info;
+use xous::{send_message, Error, MemoryFlags, CID};
+
+/// Name the benchmark server registers under
+pub const SERVER_NAME: &[u8; 16] = b"bench-server    ";
+
+/// Samples thrown away before measuring, so caches and the scheduler have
+/// a chance to settle
+pub const WARMUP: usize = 4;
+
+/// Samples kept for each benchmark
+pub const SAMPLES: usize = 32;
+
+/// The ticktimer only counts milliseconds, so each sample times a batch of
+/// operations and divides by the batch size.
+const BATCH: usize = 1000;
+
+/// Timings of one benchmark, in nanoseconds per operation
+#[derive(Debug, Copy, Clone)]
+pub struct Stats {
+    pub mean: u32,
+    pub p95: u32,
+    pub min: u32,
+    pub max: u32,
+}
+
+impl Stats {
+    pub fn from_samples(samples: &mut [u32]) -> Stats {
+        assert!(!samples.is_empty(), "no samples were taken");
+        samples.sort_unstable();
+        let sum: u64 = samples.iter().map(|s| *s as u64).sum();
+        let p95 = (samples.len() * 95 - 1) / 100;
+        Stats {
+            mean: (sum / samples.len() as u64) as u32,
+            p95: samples[p95],
+            min: samples[0],
+            max: samples[samples.len() - 1],
+        }
+    }
+}
+
+impl core::fmt::Display for Stats {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "mean {} ns, p95 {} ns (min {} ns, max {} ns)",
+            self.mean, self.p95, self.min, self.max
+        )
+    }
+}
+
+/// Bytes per second moved by an operation that handles `bytes` in `ns`
+pub fn bytes_per_sec(bytes: usize, ns: u32) -> u64 {
+    if ns == 0 {
+        return 0;
+    }
+    bytes as u64 * 1_000_000_000 / ns as u64
+}
+
+/// Run `op` in batches, discarding the warmup samples
+fn measure<F>(ticktimer: CID, mut op: F) -> Result<Stats, Error>
+where
+    F: FnMut() -> Result<(), Error>,
+{
+    let mut samples = [0u32; SAMPLES];
+    for i in 0..(WARMUP + SAMPLES) {
+        let start = ticktimer_server::elapsed_ms(ticktimer)?;
+        for _ in 0..BATCH {
+            op()?;
+        }
+        let end = ticktimer_server::elapsed_ms(ticktimer)?;
+        if i >= WARMUP {
+            samples[i - WARMUP] = ((end - start) * 1_000_000 / BATCH as u64) as u32;
+        }
+    }
+    Ok(Stats::from_samples(&mut samples))
+}
+
+pub fn ping(cid: CID, value: usize) -> Result<usize, Error> {
+    let response = send_message(cid, api::Opcode::Ping(value).into())?;
+    if let xous::Result::Scalar1(echo) = response {
+        Ok(echo)
+    } else {
+        panic!("unexpected return value: {:#?}", response);
+    }
+}
+
+/// Time to send a blocking scalar to the benchmark server and get the
+/// answer back.  This includes two context switches.
+pub fn message_round_trip(cid: CID, ticktimer: CID) -> Result<Stats, Error> {
+    let mut value = 0;
+    measure(ticktimer, || {
+        value += 1;
+        assert_eq!(ping(cid, value)?, value, "benchmark server echoed the wrong value");
+        Ok(())
+    })
+}
+
+/// Time to lend a `size`-byte buffer to the benchmark server, have it read
+/// every byte, and get the buffer back.  `size` is rounded up to a page.
+pub fn lend(cid: CID, ticktimer: CID, size: usize) -> Result<Stats, Error> {
+    let size = (size + 4095) & !4095;
+    let buf = xous::map_memory(None, None, size, MemoryFlags::R | MemoryFlags::W)?;
+    let stats = measure(ticktimer, || {
+        send_message(cid, api::Opcode::Touch(buf).into()).map(|_| ())
+    });
+    xous::unmap_memory(buf)?;
+    stats
+}
+
+/// Time to give up the CPU and be scheduled again
+pub fn yield_slice(ticktimer: CID) -> Result<Stats, Error> {
+    measure(ticktimer, || {
+        xous::yield_slice();
+        Ok(())
+    })
+}
+
+/// Run every benchmark and log the results
+pub fn run_all(cid: CID, ticktimer: CID) -> Result<(), Error> {
+    info!("BENCH: message round trip: {}", message_round_trip(cid, ticktimer)?);
+    info!("BENCH: yield: {}", yield_slice(ticktimer)?);
+    for size in &[4096, 65536] {
+        let stats = lend(cid, ticktimer, *size)?;
+        info!(
+            "BENCH: lend {} bytes: {} ({} bytes/s)",
+            size,
+            stats,
+            bytes_per_sec(*size, stats.mean)
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats_from_samples() {
+        let mut samples = [40, 10, 30, 20];
+        let stats = Stats::from_samples(&mut samples);
+        assert_eq!(stats.min, 10);
+        assert_eq!(stats.max, 40);
+        assert_eq!(stats.mean, 25);
+        assert_eq!(stats.p95, 40);
+
+        let mut one = [7];
+        let stats = Stats::from_samples(&mut one);
+        assert_eq!((stats.min, stats.max, stats.mean, stats.p95), (7, 7, 7, 7));
+    }
+
+    #[test]
+    fn mean_rounds_down_without_overflow() {
+        let mut samples = [u32::MAX, u32::MAX, u32::MAX - 1];
+        let stats = Stats::from_samples(&mut samples);
+        assert_eq!(stats.mean, u32::MAX - 1);
+        assert_eq!(stats.min, u32::MAX - 1);
+        assert_eq!(stats.max, u32::MAX);
+    }
+
+    #[test]
+    fn bandwidth() {
+        assert_eq!(bytes_per_sec(4096, 1_000_000_000), 4096);
+        assert_eq!(bytes_per_sec(4096, 0), 0);
+    }
+}
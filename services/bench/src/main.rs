@@ -0,0 +1,35 @@
+#![cfg_attr(target_os = "none", no_std)]
+#![cfg_attr(target_os = "none", no_main)]
+
+mod api;
+use api::Opcode;
+
+use core::convert::TryFrom;
+
+use log::{error, info};
+
+#[xous::xous_main]
+fn xmain() -> ! {
+    log_server::init_wait().unwrap();
+
+    let bench_server = xous::create_server(bench::SERVER_NAME).expect("Couldn't create bench server");
+    info!("BENCH: server listening on {:?}", bench_server);
+
+    loop {
+        let envelope = xous::receive_message(bench_server).unwrap();
+        match Opcode::try_from(&envelope.body) {
+            Ok(Opcode::Ping(value)) => {
+                xous::return_scalar(envelope.sender, value).expect("BENCH: couldn't return ping");
+            }
+            Ok(Opcode::Touch(buf)) => {
+                // Read the buffer so the pages are really touched.  It is
+                // returned to the client when the envelope is dropped.
+                let data = unsafe { core::slice::from_raw_parts(buf.as_ptr(), buf.len()) };
+                for b in data {
+                    unsafe { core::ptr::read_volatile(b) };
+                }
+            }
+            Err(e) => error!("BENCH: {}", e),
+        }
+    }
+}
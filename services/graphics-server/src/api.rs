@@ -1,14 +1,16 @@
 use crate::op::PixelColor;
-use xous::{Message, ScalarMessage};
+use xous::{MemoryMessage, MemoryRange, Message, ScalarMessage};
 use core::ops::{Add, AddAssign, Index, Neg, Sub, SubAssign};
 
 
 /// Available typeface glyph sets
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum GlyphSet {
     Bold,
     Regular,
     Small,
+    /// Bold glyphs drawn at twice their size, for readers who need large print
+    Large,
 }
 
 pub fn glyph_to_arg(glyph: GlyphSet) -> usize {
@@ -16,6 +18,7 @@ pub fn glyph_to_arg(glyph: GlyphSet) -> usize {
         GlyphSet::Small => 0,
         GlyphSet::Regular => 1,
         GlyphSet::Bold => 2,
+        GlyphSet::Large => 3,
     }
 }
 
@@ -24,6 +27,7 @@ pub fn arg_to_glyph(arg: usize) -> GlyphSet {
         0 => GlyphSet::Small,
         1 => GlyphSet::Regular,
         2 => GlyphSet::Bold,
+        3 => GlyphSet::Large,
         _ => GlyphSet::Regular,
     }
 }
@@ -33,6 +37,7 @@ pub fn glyph_to_height(glyph: GlyphSet) -> usize {
         GlyphSet::Small => crate::fonts::small::MAX_HEIGHT as usize,
         GlyphSet::Regular => crate::fonts::regular::MAX_HEIGHT as usize,
         GlyphSet::Bold => crate::fonts::bold::MAX_HEIGHT as usize,
+        GlyphSet::Large => crate::fonts::bold::MAX_HEIGHT as usize * crate::fonts::LARGE_SCALE,
     }
 }
 /// 2D size.
@@ -221,6 +226,7 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use core::convert::TryFrom;
 
     #[test]
     fn sizes_can_be_added() {
@@ -276,6 +282,94 @@ mod tests {
 
         assert_eq!(Size::from(left - right), Size::new(20, 20));
     }
+
+    fn sample_opcodes<'a>() -> [Opcode<'a>; 6] {
+        [
+            Opcode::Style(1, Color::from(1usize), Color::from(0usize)),
+            Opcode::Line(Point::new(10, 10), Point::new(200, 300)),
+            Opcode::Circle(Point::new(100, 100), 40),
+            Opcode::SetGlyph(GlyphSet::Small),
+            Opcode::String("hello"),
+            Opcode::DrawQr(Rect::new(50, 200, 250, 400), b"https://betrusted.io"),
+        ]
+    }
+
+    fn push(commands: &mut [u8], offset: usize, opcode: Opcode) -> Option<usize> {
+        match opcode {
+            Opcode::String(s) => push_borrow(commands, offset, 1, s.as_bytes()),
+            Opcode::DrawQr(rect, data) => {
+                let mut request = [0u8; QR_HEADER + crate::qr::MAX_DATA];
+                let len = qr_request(rect, data, &mut request);
+                push_borrow(commands, offset, 2, &request[..len])
+            }
+            opcode => match opcode.into() {
+                Message::Scalar(m) => push_scalar(commands, offset, &m),
+                _ => None,
+            },
+        }
+    }
+
+    #[test]
+    fn render_commands_match_direct_drawing() {
+        let mut commands = [0u8; RENDER_COMMANDS_SIZE];
+        let mut offset = 0;
+        for opcode in IntoIterator::into_iter(sample_opcodes()) {
+            offset = push(&mut commands, offset, opcode).unwrap();
+        }
+
+        let mut rendered = [0u32; crate::op::LCD_FRAME_BUF_SIZE];
+        let mut pen = Pen::default();
+        let mut offset = 0;
+        let mut count = 0;
+        while let Some((message, next)) = next_command(&commands, offset) {
+            let opcode = Opcode::try_from(&message).unwrap();
+            assert!(draw(&mut rendered, &mut pen, opcode));
+            offset = next;
+            count += 1;
+        }
+        assert_eq!(count, sample_opcodes().len());
+
+        let mut direct = [0u32; crate::op::LCD_FRAME_BUF_SIZE];
+        let mut pen = Pen::default();
+        for opcode in IntoIterator::into_iter(sample_opcodes()) {
+            assert!(draw(&mut direct, &mut pen, opcode));
+        }
+        assert!(direct.iter().any(|&word| word != 0));
+        assert!(rendered[..] == direct[..]);
+    }
+
+    #[test]
+    fn render_commands_must_fit() {
+        let mut commands = [0u8; 28];
+        let message = ScalarMessage { id: 3, arg1: 1, arg2: 2, arg3: 3, arg4: 4 };
+        let offset = push_scalar(&mut commands, 0, &message).unwrap();
+        assert_eq!(offset, 20);
+        assert_eq!(push_scalar(&mut commands, offset, &message), None);
+        assert_eq!(push_borrow(&mut commands, offset, 1, b"abcde"), None);
+        assert_eq!(push_borrow(&mut commands, offset, 1, b""), None);
+        assert_eq!(push_borrow(&mut commands, offset, 1, b"abcd"), Some(28));
+
+        // A length that runs off the end isn't read
+        commands[offset..offset + 4].copy_from_slice(&[2, 1, 200, 0]);
+        assert!(next_command(&commands, offset).is_none());
+    }
+
+    #[test]
+    fn undrawable_opcodes_are_refused() {
+        let mut fb = [0u32; crate::op::LCD_FRAME_BUF_SIZE];
+        let mut pen = Pen::default();
+        assert!(!draw(&mut fb, &mut pen, Opcode::Rectangle(Point::new(1, 1), Point::new(9, 9))));
+        assert!(!draw(&mut fb, &mut pen, Opcode::Flush));
+        assert!(fb.iter().all(|&word| word == 0));
+    }
+
+    #[test]
+    fn strings_must_be_utf8() {
+        let mut commands = [0u8; 16];
+        push_borrow(&mut commands, 0, 1, &[b'h', 0xff, b'i']).unwrap();
+        let (message, _) = next_command(&commands, 0).unwrap();
+        assert!(Opcode::try_from(&message).is_err());
+    }
 }
 
 
@@ -627,8 +721,197 @@ pub enum Opcode<'a> {
 
     /// Retrieve the current Glyph characteristics
     QueryGlyph,
+
+    /// Fill in a `health::Health` record
+    GetHealth(MemoryRange),
+
+    /// Draw a QR code of the given bytes, as large as fits in the rect
+    DrawQr(Rect, &'a [u8]),
+
+    /// Run the drawing commands in the lent buffer, which is laid out as
+    /// described by `RENDER_SIZE`, drawing into the frame at its start
+    /// instead of the screen.  Each request starts with a clear frame and
+    /// the default pen, so clients don't affect the screen or each other.
+    Render(MemoryRange),
+
+    /// Copy the screen into the lent buffer, as an `op::LcdFB`
+    Capture(MemoryRange),
+
+    /// Fill in an `xous::Protocol` record with `PROTOCOL`
+    GetProtocol(MemoryRange),
+}
+
+/// The server can draw QR codes with `DrawQr`
+pub const FEATURE_QR: u32 = 1 << 0;
+
+/// The server supports `Render` and `Capture`
+pub const FEATURE_CAPTURE: u32 = 1 << 1;
+
+/// The protocol this server speaks.  Bump the version when an existing
+/// opcode changes, and add a feature bit when one is added.
+pub const PROTOCOL: xous::Protocol = xous::Protocol::new(2, FEATURE_QR | FEATURE_CAPTURE);
+
+/// Bytes needed to hold an `op::LcdFB`, rounded up to a whole number of pages
+pub const CAPTURE_SIZE: usize = (crate::op::LCD_FRAME_BUF_SIZE * 4 + 4095) & !4095;
+
+/// Bytes of drawing commands that fit in a `Render` buffer
+pub const RENDER_COMMANDS_SIZE: usize = 4096;
+
+/// Size of the buffer lent with `Render`: an `op::LcdFB` padded to
+/// `CAPTURE_SIZE`, followed by `RENDER_COMMANDS_SIZE` bytes of commands.
+///
+/// Each command is the message it stands for.  It starts with the message
+/// type, its id, and the length of what follows as a little-endian `u16`.
+/// A scalar is followed by its four arguments as `u32`s, and a borrow by
+/// the bytes it would have lent.  Commands are padded to a multiple of four
+/// bytes, and a type of `0` ends the list.
+pub const RENDER_SIZE: usize = CAPTURE_SIZE + RENDER_COMMANDS_SIZE;
+
+const COMMAND_HEADER: usize = 4;
+const COMMAND_END: u8 = 0;
+const COMMAND_SCALAR: u8 = 1;
+const COMMAND_BORROW: u8 = 2;
+
+/// Append a command to `commands` at `offset`.  Returns the offset of the
+/// next command, or `None` if it doesn't fit.
+fn push_command(commands: &mut [u8], offset: usize, kind: u8, id: usize, payload: &[u8]) -> Option<usize> {
+    let next = offset + COMMAND_HEADER + ((payload.len() + 3) & !3);
+    if next > commands.len() || payload.len() > u16::MAX as usize || id > u8::MAX as usize {
+        return None;
+    }
+    commands[offset] = kind;
+    commands[offset + 1] = id as u8;
+    commands[offset + 2..offset + 4].copy_from_slice(&(payload.len() as u16).to_le_bytes());
+    commands[offset + COMMAND_HEADER..offset + COMMAND_HEADER + payload.len()].copy_from_slice(payload);
+    Some(next)
+}
+
+/// Append a scalar message to a `Render` command list
+pub fn push_scalar(commands: &mut [u8], offset: usize, message: &ScalarMessage) -> Option<usize> {
+    let mut args = [0u8; 16];
+    for (i, arg) in [message.arg1, message.arg2, message.arg3, message.arg4].iter().enumerate() {
+        args[i * 4..i * 4 + 4].copy_from_slice(&(*arg as u32).to_le_bytes());
+    }
+    push_command(commands, offset, COMMAND_SCALAR, message.id, &args)
 }
 
+/// Append a borrow of `bytes` with the given id to a `Render` command list
+pub fn push_borrow(commands: &mut [u8], offset: usize, id: usize, bytes: &[u8]) -> Option<usize> {
+    if bytes.is_empty() {
+        return None;
+    }
+    push_command(commands, offset, COMMAND_BORROW, id, bytes)
+}
+
+/// Read the command at `offset` in a `Render` command list, returning the
+/// message it stands for and the offset of the next command.  Borrows
+/// point into `commands`.
+pub fn next_command(commands: &[u8], offset: usize) -> Option<(Message, usize)> {
+    let header = commands.get(offset..offset + COMMAND_HEADER)?;
+    let (kind, id) = (header[0], header[1] as usize);
+    let len = u16::from_le_bytes([header[2], header[3]]) as usize;
+    let payload = commands.get(offset + COMMAND_HEADER..offset + COMMAND_HEADER + len)?;
+    let next = offset + COMMAND_HEADER + ((len + 3) & !3);
+    let message = match kind {
+        COMMAND_END => return None,
+        COMMAND_SCALAR if len == 16 => {
+            let arg = |i: usize| {
+                u32::from_le_bytes([payload[i * 4], payload[i * 4 + 1], payload[i * 4 + 2], payload[i * 4 + 3]]) as usize
+            };
+            Message::Scalar(ScalarMessage { id, arg1: arg(0), arg2: arg(1), arg3: arg(2), arg4: arg(3) })
+        }
+        COMMAND_BORROW => Message::Borrow(MemoryMessage {
+            id,
+            buf: MemoryRange::new(payload.as_ptr() as usize, len).ok()?,
+            offset: None,
+            valid: xous::MemorySize::new(len),
+        }),
+        _ => return None,
+    };
+    Some((message, next))
+}
+
+/// The pen state that drawing opcodes change
+#[derive(Copy, Clone, Debug)]
+pub struct Pen {
+    pub color: Color,
+    pub glyph: GlyphSet,
+}
+
+impl Default for Pen {
+    fn default() -> Pen {
+        Pen { color: Color::from(0usize), glyph: GlyphSet::Regular }
+    }
+}
+
+/// Carry out a drawing opcode on `fb`.  Returns `false` if it couldn't be
+/// drawn, or isn't a drawing opcode at all.
+pub fn draw(fb: &mut crate::op::LcdFB, pen: &mut Pen, opcode: Opcode) -> bool {
+    use crate::op;
+    match opcode {
+        Opcode::Clear(_color) => op::clear_region(fb, op::ClipRegion::screen()),
+        Opcode::Line(start, end) => {
+            log::info!("GFX: Drawing line from {:?} to {:?}", start, end);
+            let color = if pen.color.color == 0 { op::PixelColor::Off } else { op::PixelColor::On };
+            op::line(fb, start.x as _, start.y as _, end.x as _, end.y as _, color);
+        }
+        // There's no rectangle primitive in `op` yet
+        Opcode::Rectangle(_start, _end) => return false,
+        Opcode::Circle(mid, radius) => {
+            log::info!("GFX: Drawing cicrle at {:?} radius {:?}", mid, radius);
+            op::circle(fb, mid.x as _, mid.y as _, radius as _, 0, op::PixelColor::On);
+        }
+        Opcode::Style(_stroke_width, stroke_color, _fill_color) => {
+            pen.color = stroke_color;
+            // todo!();
+            // current_style.stroke_width = stroke_width;
+            // current_style.stroke_color = Some(if stroke_color.color == 0 {
+            //     BinaryColor::Off
+            // } else {
+            //     BinaryColor::On
+            // });
+            // current_style.fill_color = Some(if fill_color.color == 0 {
+            //     BinaryColor::Off
+            // } else {
+            //     BinaryColor::On
+            // });
+        }
+        Opcode::ClearRegion(rect) => op::clear_region(fb, op::ClipRegion {
+            x0: rect.x0 as _,
+            y0: rect.y0 as _,
+            x1: rect.x1 as _,
+            y1: rect.y1 as _,
+        }),
+        Opcode::String(s) => op::string_left(fb, op::ClipRegion::screen(), s, pen.glyph),
+        Opcode::SetGlyph(glyph) => pen.glyph = glyph,
+        Opcode::DrawQr(rect, data) => match crate::qr::QrCode::encode(data) {
+            Some(code) => op::qr(fb, op::ClipRegion {
+                x0: rect.x0 as _,
+                y0: rect.y0 as _,
+                x1: rect.x1 as _,
+                y1: rect.y1 as _,
+            }, &code),
+            None => return false,
+        },
+        _ => return false,
+    }
+    true
+}
+
+/// Encode a `DrawQr` request into `request`, returning its length
+pub fn qr_request(rect: Rect, data: &[u8], request: &mut [u8; QR_HEADER + crate::qr::MAX_DATA]) -> usize {
+    let len = data.len().min(crate::qr::MAX_DATA);
+    for (i, word) in [rect.x0, rect.y0, rect.x1, rect.y1, len as i16].iter().enumerate() {
+        request[i * 2..i * 2 + 2].copy_from_slice(&word.to_le_bytes());
+    }
+    request[QR_HEADER..QR_HEADER + len].copy_from_slice(&data[..len]);
+    QR_HEADER + len
+}
+
+/// Bytes before the data in a `DrawQr` message: the rect as four `i16`s
+/// followed by the data length as a `u16`, all little-endian
+pub const QR_HEADER: usize = 10;
+
 impl<'a> core::convert::TryFrom<&'a Message> for Opcode<'a> {
     type Error = &'static str;
     fn try_from(message: &'a Message) -> Result<Self, Self::Error> {
@@ -660,16 +943,32 @@ impl<'a> core::convert::TryFrom<&'a Message> for Opcode<'a> {
             },
             Message::Borrow(m) => match m.id {
                 1 => {
-                    let s = unsafe {
-                        core::slice::from_raw_parts(
-                            m.buf.as_ptr(),
-                            m.valid.map(|x| x.get()).unwrap_or_else(|| m.buf.len()),
-                        )
-                    };
-                    Ok(Opcode::String(core::str::from_utf8(s).unwrap()))
+                    let s = unsafe { m.valid_bytes() };
+                    core::str::from_utf8(s)
+                        .map(Opcode::String)
+                        .map_err(|_| "string is not valid UTF-8")
+                }
+                2 => {
+                    let b = unsafe { m.valid_bytes() };
+                    if b.len() < QR_HEADER {
+                        return Err("QR request is too short");
+                    }
+                    let word = |i: usize| i16::from_le_bytes([b[i * 2], b[i * 2 + 1]]);
+                    let rect = Rect::new(word(0), word(1), word(2), word(3));
+                    let len = word(4) as u16 as usize;
+                    b.get(QR_HEADER..QR_HEADER + len)
+                        .map(|data| Opcode::DrawQr(rect, data))
+                        .ok_or("QR data is truncated")
                 }
                 _ => Err("unrecognized opcode"),
             },
+            Message::MutableBorrow(m) => match m.id {
+                health::GET_HEALTH => Ok(Opcode::GetHealth(m.buf)),
+                1 => Ok(Opcode::Capture(m.buf)),
+                2 => Ok(Opcode::Render(m.buf)),
+                xous::GET_PROTOCOL => Ok(Opcode::GetProtocol(m.buf)),
+                _ => Err("unrecognized opcode"),
+            },
             _ => Err("unhandled message type"),
         }
     }
@@ -736,6 +1035,36 @@ impl<'a> Into<Message> for Opcode<'a> {
                 let region = xous::carton::Carton::from_bytes(string.as_bytes());
                 Message::Borrow(region.into_message(1))
             }
+            Opcode::GetHealth(buf) => Message::MutableBorrow(MemoryMessage {
+                id: health::GET_HEALTH,
+                buf,
+                offset: None,
+                valid: None,
+            }),
+            Opcode::GetProtocol(buf) => Message::MutableBorrow(MemoryMessage {
+                id: xous::GET_PROTOCOL,
+                buf,
+                offset: None,
+                valid: None,
+            }),
+            Opcode::DrawQr(rect, data) => {
+                let mut request = [0u8; QR_HEADER + crate::qr::MAX_DATA];
+                let len = qr_request(rect, data, &mut request);
+                let region = xous::carton::Carton::from_bytes(&request[..len]);
+                Message::Borrow(region.into_message(2))
+            }
+            Opcode::Capture(buf) => Message::MutableBorrow(MemoryMessage {
+                id: 1,
+                buf,
+                offset: None,
+                valid: None,
+            }),
+            Opcode::Render(buf) => Message::MutableBorrow(MemoryMessage {
+                id: 2,
+                buf,
+                offset: None,
+                valid: None,
+            }),
         }
     }
 }
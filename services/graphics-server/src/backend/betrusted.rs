@@ -7,6 +7,9 @@ const FB_LINES: usize = 536;
 const FB_SIZE: usize = FB_WIDTH_WORDS * FB_LINES; // 44 bytes by 536 lines
 const CONFIG_CLOCK_FREQUENCY: u32 = 100_000_000;
 
+/// Set in the last word of a line to have `update_dirty` send it
+const DIRTY_BIT: u32 = 0x1_0000;
+
 const COMMAND_OFFSET: usize = 0;
 const BUSY_OFFSET: usize = 1;
 const PRESCALER_OFFSET: usize = 2;
@@ -44,9 +47,14 @@ impl XousDisplay {
         display
     }
 
+    /// Send only the lines that have been drawn to since the last redraw.
+    /// The LCD controller picks them out by their dirty bit, which is
+    /// cleared once the transfer is complete.
     pub fn redraw(&mut self) {
         while self.busy() {}
         self.update_dirty();
+        while self.busy() {}
+        self.clear_dirty();
     }
 
     pub fn update(&mut self) {}
@@ -66,6 +74,7 @@ impl XousDisplay {
         self.update_all();
 
         while self.busy() {}
+        self.clear_dirty();
     }
 
     /// Beneath this line are pure-HAL layer, and should not be user-visible
@@ -110,6 +119,16 @@ impl XousDisplay {
         while self.busy() {}
     }
 
+    fn clear_dirty(&mut self) {
+        let framebuffer = self.fb.as_mut_ptr() as *mut u32;
+        for line in 0..FB_LINES {
+            unsafe {
+                let last_word = framebuffer.add(line * FB_WIDTH_WORDS + (FB_WIDTH_WORDS - 1));
+                last_word.write_volatile(last_word.read_volatile() & !DIRTY_BIT);
+            }
+        }
+    }
+
     fn busy(&self) -> bool {
         unsafe {
             (self.control.as_ptr() as *mut u32)
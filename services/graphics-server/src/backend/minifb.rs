@@ -7,6 +7,10 @@ const HEIGHT: usize = 536;
 const WIDTH_WORDS: usize = 11;
 const FB_SIZE: usize = WIDTH_WORDS * HEIGHT; // 44 bytes by 536 lines
 
+/// Set in the last word of a line when it has been drawn to, mirroring
+/// the memory LCD's partial-update flag
+const DIRTY_BIT: u32 = 0x1_0000;
+
 const MAX_FPS: u64 = 15;
 const DARK_COLOUR: u32 = 0xB5B5AD;
 const LIGHT_COLOUR: u32 = 0x1B1B19;
@@ -54,6 +58,9 @@ impl XousDisplay {
         for (dest, src) in self.emulated_buffer.iter_mut().zip(bmp.iter()) {
             *dest = *src;
         }
+        for y in 0..HEIGHT {
+            self.emulated_buffer[y * WIDTH_WORDS + (WIDTH_WORDS - 1)] |= DIRTY_BIT;
+        }
     }
 
     pub fn native_buffer(&mut self) -> &mut [u32; FB_SIZE] {
@@ -75,8 +82,15 @@ impl XousDisplay {
         }
     }
 
+    /// Convert only the lines that have been drawn to since the last call,
+    /// the same way the hardware only transfers dirty lines.
     fn emulated_to_native(&mut self) {
         for y in 0..HEIGHT {
+            let last_word = y * WIDTH_WORDS + (WIDTH_WORDS - 1);
+            if self.emulated_buffer[last_word] & DIRTY_BIT == 0 {
+                continue;
+            }
+            self.emulated_buffer[last_word] &= !DIRTY_BIT;
             for x in 0..WIDTH {
                 // print!("({}, {}): {} @ {}: ", x, y, (x + y * 44 * 8) / 8, self.emulated_buffer.len());
                 // println!("{:08x}", self.emulated_buffer[(x + y * 44 * 8) / 8]);
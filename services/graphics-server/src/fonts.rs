@@ -3,6 +3,9 @@ pub mod regular;
 pub mod small;
 use crate::api::GlyphSet;
 
+/// How many screen pixels each glyph pixel covers in the large print glyph set
+pub const LARGE_SCALE: usize = 2;
+
 /// Strings with Unicode Private Use Area characters for UI Sprites
 pub mod pua {
     pub const BATTERY_05: &str = &"\u{E700}";
@@ -39,8 +42,11 @@ impl GlyphHeader {
 /// Abstraction for working with typeface glyph sets
 #[derive(Copy, Clone)]
 pub struct Font {
+    pub glyph_set: GlyphSet,
     pub glyph_pattern_offset: GlyphPatternOffsetFnPtr,
     pub glyph_data: GlyphDataFnPtr,
+    /// Screen pixels per glyph pixel, in each direction
+    pub scale: usize,
 }
 pub type GlyphPatternOffsetFnPtr = fn(char) -> usize;
 pub type GlyphDataFnPtr = fn(usize) -> u32;
@@ -48,19 +54,63 @@ impl Font {
     pub fn new(gs: GlyphSet) -> Font {
         match gs {
             GlyphSet::Bold => Font {
+                glyph_set: gs,
                 glyph_pattern_offset: bold::get_glyph_pattern_offset,
                 glyph_data: bold_data,
+                scale: 1,
             },
             GlyphSet::Regular => Font {
+                glyph_set: gs,
                 glyph_pattern_offset: regular::get_glyph_pattern_offset,
                 glyph_data: regular_data,
+                scale: 1,
             },
             GlyphSet::Small => Font {
+                glyph_set: gs,
                 glyph_pattern_offset: small::get_glyph_pattern_offset,
                 glyph_data: small_data,
+                scale: 1,
+            },
+            GlyphSet::Large => Font {
+                glyph_set: gs,
+                glyph_pattern_offset: bold::get_glyph_pattern_offset,
+                glyph_data: bold_data,
+                scale: LARGE_SCALE,
             },
         }
     }
+
+    /// Find a font that can draw `c`, starting with the requested glyph set
+    /// and working down its fallback chain. If none of them have the glyph,
+    /// the requested font is returned and will draw its replacement glyph.
+    /// Fallback glyphs are drawn at the requested font's scale.
+    pub fn for_char(gs: GlyphSet, c: char) -> Font {
+        let scale = Font::new(gs).scale;
+        for fallback in fallback_chain(gs).iter() {
+            let f = Font::new(*fallback);
+            if f.has_glyph(c) {
+                return Font { scale, ..f };
+            }
+        }
+        Font::new(gs)
+    }
+
+    /// Returns `true` if this font has a glyph for `c`, rather than
+    /// mapping it to the U+FFFD replacement glyph.
+    pub fn has_glyph(&self, c: char) -> bool {
+        c == '\u{FFFD}' || (self.glyph_pattern_offset)(c) != (self.glyph_pattern_offset)('\u{FFFD}')
+    }
+}
+
+/// Glyph sets to try, in order, when a font is missing a character.
+/// Fonts of a similar size are preferred so that text keeps its layout.
+pub fn fallback_chain(gs: GlyphSet) -> [GlyphSet; 3] {
+    match gs {
+        GlyphSet::Bold => [GlyphSet::Bold, GlyphSet::Regular, GlyphSet::Small],
+        GlyphSet::Regular => [GlyphSet::Regular, GlyphSet::Bold, GlyphSet::Small],
+        GlyphSet::Small => [GlyphSet::Small, GlyphSet::Regular, GlyphSet::Bold],
+        GlyphSet::Large => [GlyphSet::Large, GlyphSet::Regular, GlyphSet::Small],
+    }
 }
 
 /// Get word of packed glyph data for bold
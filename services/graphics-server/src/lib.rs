@@ -6,6 +6,7 @@ pub use api::{Point, Color, Rect, GlyphSet};
 use xous::String;
 pub mod op;
 pub mod fonts;
+pub mod qr;
 
 use xous::{send_message, CID};
 
@@ -37,6 +38,15 @@ pub fn draw_string(cid: CID, s: &String) -> Result<(), xous::Error> {
     s.lend(cid, 1).map(|_| ())
 }
 
+/// Draw a QR code encoding `data`, as large as fits in `rect`.  Up to
+/// `qr::MAX_DATA` bytes can be encoded.
+pub fn draw_qr(cid: CID, rect: Rect, data: &[u8]) -> Result<(), xous::Error> {
+    if data.len() > qr::MAX_DATA {
+        return Err(xous::Error::OutOfMemory);
+    }
+    send_message(cid, api::Opcode::DrawQr(rect, data).into()).map(|_| ())
+}
+
 pub fn set_glyph(cid: CID, glyph: GlyphSet) -> Result<(), xous::Error> {
     send_message(cid, api::Opcode::SetGlyph(glyph).into()).map( |_| ())
 }
@@ -50,6 +60,13 @@ pub fn screen_size(cid: CID) -> Result<Point, xous::Error> {
     }
 }
 
+/// Compute the width and height in pixels of a string drawn in the given
+/// glyph set. This runs locally, so apps can lay out text without a round
+/// trip to the server.
+pub fn text_extents(s: &str, glyph: GlyphSet) -> (usize, usize) {
+    op::string_extents(s, glyph)
+}
+
 pub fn query_glyph(cid: CID) -> Result<(GlyphSet, usize), xous::Error> {
     let response = send_message(cid, api::Opcode::QueryGlyph.into())?;
     if let xous::Result::Scalar2(glyph, h) = response {
@@ -57,4 +74,120 @@ pub fn query_glyph(cid: CID) -> Result<(GlyphSet, usize), xous::Error> {
     } else {
         panic!("unexpected return value: {:#?}", response);
     }
-}
\ No newline at end of file
+}
+
+/// Drawing commands collected by a client and run by the server in one
+/// `Render` request, into a frame that belongs to the client.  Nothing
+/// drawn on a `Canvas` reaches the screen.
+pub struct Canvas {
+    buf: xous::MemoryRange,
+    offset: usize,
+}
+
+impl Canvas {
+    pub fn new() -> Result<Canvas, xous::Error> {
+        let buf = xous::map_memory(None, None, api::RENDER_SIZE, xous::MemoryFlags::R | xous::MemoryFlags::W)?;
+        Ok(Canvas { buf, offset: 0 })
+    }
+
+    fn commands(&mut self) -> &mut [u8] {
+        let data = unsafe { core::slice::from_raw_parts_mut(self.buf.as_mut_ptr(), self.buf.len()) };
+        &mut data[api::CAPTURE_SIZE..]
+    }
+
+    fn push(&mut self, opcode: api::Opcode) -> Result<(), xous::Error> {
+        let offset = self.offset;
+        let message: xous::Message = opcode.into();
+        let next = match message {
+            xous::Message::Scalar(m) => api::push_scalar(self.commands(), offset, &m),
+            _ => None,
+        };
+        self.offset = next.ok_or(xous::Error::OutOfMemory)?;
+        Ok(())
+    }
+
+    fn push_bytes(&mut self, id: usize, bytes: &[u8]) -> Result<(), xous::Error> {
+        let offset = self.offset;
+        self.offset = api::push_borrow(self.commands(), offset, id, bytes).ok_or(xous::Error::OutOfMemory)?;
+        Ok(())
+    }
+
+    pub fn line(&mut self, start: Point, end: Point) -> Result<(), xous::Error> {
+        self.push(api::Opcode::Line(start, end))
+    }
+
+    pub fn circle(&mut self, center: Point, radius: u16) -> Result<(), xous::Error> {
+        self.push(api::Opcode::Circle(center, radius))
+    }
+
+    pub fn style(&mut self, width: u16, stroke: Color, fill: Color) -> Result<(), xous::Error> {
+        self.push(api::Opcode::Style(width, stroke, fill))
+    }
+
+    pub fn clear(&mut self) -> Result<(), xous::Error> {
+        self.push(api::Opcode::Clear(Color::from(0usize)))
+    }
+
+    pub fn clear_region(&mut self, x0: usize, y0: usize, x1: usize, y1: usize) -> Result<(), xous::Error> {
+        self.push(api::Opcode::ClearRegion(Rect::new(x0 as _, y0 as _, x1 as _, y1 as _)))
+    }
+
+    pub fn set_glyph(&mut self, glyph: GlyphSet) -> Result<(), xous::Error> {
+        self.push(api::Opcode::SetGlyph(glyph))
+    }
+
+    pub fn string(&mut self, s: &str) -> Result<(), xous::Error> {
+        // An empty string draws nothing, and can't be lent
+        if s.is_empty() {
+            return Ok(());
+        }
+        self.push_bytes(1, s.as_bytes())
+    }
+
+    /// Draw a QR code encoding `data`, as large as fits in `rect`
+    pub fn qr(&mut self, rect: Rect, data: &[u8]) -> Result<(), xous::Error> {
+        if data.len() > qr::MAX_DATA {
+            return Err(xous::Error::OutOfMemory);
+        }
+        let mut request = [0u8; api::QR_HEADER + qr::MAX_DATA];
+        let len = api::qr_request(rect, data, &mut request);
+        self.push_bytes(2, &request[..len])
+    }
+
+    /// Have the server run every command added since the last render,
+    /// starting from a clear frame.  The result is in `frame()`.
+    pub fn render(&mut self, cid: CID) -> Result<(), xous::Error> {
+        let result = send_message(cid, api::Opcode::Render(self.buf).into()).map(|_| ());
+        for byte in self.commands().iter_mut() {
+            *byte = 0;
+        }
+        self.offset = 0;
+        result
+    }
+
+    /// What the last `render()` drew
+    pub fn frame(&self) -> &op::LcdFB {
+        // The buffer is page-aligned and starts with the frame
+        unsafe { &*(self.buf.as_ptr() as *const op::LcdFB) }
+    }
+}
+
+impl Drop for Canvas {
+    fn drop(&mut self) {
+        xous::unmap_memory(self.buf).ok();
+    }
+}
+
+/// Copy the screen into `fb`.  The screen is left as it is, so this can be
+/// used for screenshots as well as tests.
+pub fn capture(cid: CID, fb: &mut op::LcdFB) -> Result<(), xous::Error> {
+    let buf = xous::map_memory(None, None, api::CAPTURE_SIZE, xous::MemoryFlags::R | xous::MemoryFlags::W)?;
+    let result = send_message(cid, api::Opcode::Capture(buf).into()).map(|_| {
+        let data = unsafe { core::slice::from_raw_parts(buf.as_ptr(), buf.len()) };
+        for (word, bytes) in fb.iter_mut().zip(data.chunks_exact(4)) {
+            *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+    });
+    xous::unmap_memory(buf)?;
+    result
+}
@@ -5,8 +5,6 @@
 #[macro_use]
 mod debug;
 
-use log::info;
-
 mod backend;
 use backend::XousDisplay;
 
@@ -15,6 +13,7 @@ use api::Opcode;
 
 mod op;
 mod fonts;
+mod qr;
 
 use core::convert::TryFrom;
 
@@ -33,11 +32,12 @@ fn xmain() -> ! {
 
     draw_boot_logo(&mut display);
 
-    let mut current_color = api::Color::from(0usize);
-    let mut current_glyph = api::GlyphSet::Regular;
+    let mut pen = api::Pen::default();
 
     display.redraw();
 
+    let mut monitor = health::Monitor::new();
+
     let sid = xous::create_server(b"graphics-server ").unwrap();
     // info!("GFX: Server listening on address {:?}", sid);
     // ::debug_here::debug_here!();
@@ -46,62 +46,14 @@ fn xmain() -> ! {
         // info!("GFX: Message: {:?}", msg);
         if let Ok(opcode) = Opcode::try_from(&msg.body) {
             // info!("GFX: Opcode: {:?}", opcode);
+            if !matches!(opcode, Opcode::GetHealth(_)) {
+                monitor.activity();
+            }
             match opcode {
                 Opcode::Flush => {
                     display.update();
                     display.redraw();
                 },
-                Opcode::Clear(_color) => {
-                    op::clear_region(display.native_buffer(), op::ClipRegion::screen());
-                }
-                Opcode::Line(start, end) => {
-                    info!("GFX: Drawing line from {:?} to {:?}", start, end);
-                    op::line(display.native_buffer(), start.x as _, start.y as _, end.x as _, end.y as _, if current_color.color == 0 { op::PixelColor::Off } else {op::PixelColor::On });
-                }
-                Opcode::Rectangle(start, end) => {
-                    todo!();
-                    // Line::new(start.into(), end.into())
-                    //     .into_styled(current_style)
-                    //     .draw(&mut display)
-                    //     .ok();
-                }
-                Opcode::Circle(mid, radius) => {
-                    info!("GFX: Drawing cicrle at {:?} radius {:?}", mid, radius);
-                    op::circle(display.native_buffer(), mid.x as _, mid.y as _, radius as _, 0, op::PixelColor::On);
-                }
-                Opcode::Style(stroke_width, stroke_color, fill_color) => {
-                    current_color = stroke_color;
-                    // todo!();
-                    // current_style.stroke_width = stroke_width;
-                    // current_style.stroke_color = Some(if stroke_color.color == 0 {
-                    //     BinaryColor::Off
-                    // } else {
-                    //     BinaryColor::On
-                    // });
-                    // current_style.fill_color = Some(if fill_color.color == 0 {
-                    //     BinaryColor::Off
-                    // } else {
-                    //     BinaryColor::On
-                    // });
-                }
-                Opcode::ClearRegion(rect) => {
-                    op::clear_region(display.native_buffer(), op::ClipRegion {
-                        x0: rect.x0 as _,
-                        y0: rect.y0 as _,
-                        x1: rect.x1 as _,
-                        y1: rect.y1 as _,
-                    });
-                }
-                Opcode::String(s) => {
-                    match current_glyph {
-                        api::GlyphSet::Small => op::string_small_left(display.native_buffer(), op::ClipRegion::screen(), s),
-                        api::GlyphSet::Regular => op::string_regular_left(display.native_buffer(), op::ClipRegion::screen(), s),
-                        api::GlyphSet::Bold => op::string_bold_left(display.native_buffer(), op::ClipRegion::screen(), s),
-                    }
-                }
-                Opcode::SetGlyph(glyph) => {
-                    current_glyph = glyph;
-                }
                 Opcode::ScreenSize => {
                     xous::return_scalar2(
                         msg.sender,
@@ -113,14 +65,55 @@ fn xmain() -> ! {
                 Opcode::QueryGlyph => {
                     xous::return_scalar2(
                         msg.sender,
-                        api::glyph_to_arg(current_glyph),
-                        api::glyph_to_height(current_glyph),
+                        api::glyph_to_arg(pen.glyph),
+                        api::glyph_to_height(pen.glyph),
                     )
                     .expect("GFX: could not return QueryGlyph request");
                 }
+                Opcode::GetHealth(buf) => monitor.reply(buf, sid),
+                Opcode::GetProtocol(buf) => xous::answer_protocol(buf, api::PROTOCOL),
+                Opcode::Render(buf) => {
+                    // The buffer is lent by the client, so anything drawn
+                    // here stays out of the screen and out of other
+                    // clients' way.  It is `RENDER_SIZE` bytes, starting
+                    // with the frame.
+                    if buf.len() < api::RENDER_SIZE {
+                        monitor.error();
+                    } else {
+                        let fb = unsafe { &mut *(buf.as_mut_ptr() as *mut op::LcdFB) };
+                        let commands = unsafe {
+                            core::slice::from_raw_parts(buf.as_ptr().add(api::CAPTURE_SIZE), api::RENDER_COMMANDS_SIZE)
+                        };
+                        op::clear_region(fb, op::ClipRegion::screen());
+                        let mut pen = api::Pen::default();
+                        let mut offset = 0;
+                        while let Some((command, next)) = api::next_command(commands, offset) {
+                            let drawn = match Opcode::try_from(&command) {
+                                Ok(opcode) => api::draw(fb, &mut pen, opcode),
+                                Err(_) => false,
+                            };
+                            if !drawn {
+                                monitor.error();
+                            }
+                            offset = next;
+                        }
+                    }
+                }
+                Opcode::Capture(buf) => {
+                    let data = unsafe { core::slice::from_raw_parts_mut(buf.as_mut_ptr(), buf.len()) };
+                    for (word, bytes) in display.native_buffer().iter().zip(data.chunks_exact_mut(4)) {
+                        bytes.copy_from_slice(&word.to_le_bytes());
+                    }
+                }
+                opcode => {
+                    if !api::draw(display.native_buffer(), &mut pen, opcode) {
+                        monitor.error();
+                    }
+                }
             }
         } else {
             // info!("Couldn't convert opcode");
+            monitor.error();
         }
         // if let Some(mem) = msg.body.memory() {
         //     xous::return_memory(msg.sender, *mem).expect("couldn't return message");
@@ -1,6 +1,7 @@
 use super::fonts;
 use super::fonts::{Font, GlyphHeader};
 use crate::api::{Point, Style, Pixel, GlyphSet};
+use crate::qr::{self, QrCode};
 
 /// LCD Frame buffer bounds
 pub const LCD_WORDS_PER_LINE: usize = 11;
@@ -8,6 +9,11 @@ pub const LCD_PX_PER_LINE: usize = 336;
 pub const LCD_LINES: usize = 536;
 pub const LCD_FRAME_BUF_SIZE: usize = LCD_WORDS_PER_LINE * LCD_LINES;
 
+/// The memory LCD only sends lines that have this bit set in their last
+/// word when asked to do a partial update.  The bit falls in the padding
+/// beyond the last visible pixel, so it never shows up on the screen.
+pub const LCD_DIRTY_BIT: u32 = 0x1_0000;
+
 const WIDTH: usize = 336;
 const HEIGHT: usize = 536;
 
@@ -49,38 +55,69 @@ impl ClipRegion {
     }
 }
 
-/// Blit string with: XOR, bold font, align xr left yr top
-pub fn string_bold_left(fb: &mut LcdFB, mut cr: ClipRegion, s: &str) {
-    let f = Font::new(GlyphSet::Bold);
-    for c in s.chars() {
-        cr.x0 += xor_char(fb, cr, c, f);
+/// Mark a line as needing to be sent to the LCD on the next redraw
+pub fn mark_dirty(fb: &mut LcdFB, y: usize) {
+    if y < LCD_LINES {
+        fb[y * LCD_WORDS_PER_LINE + (LCD_WORDS_PER_LINE - 1)] |= LCD_DIRTY_BIT;
     }
 }
 
-/// Blit string with: XOR, regular font, align xr left yr top
-pub fn string_regular_left(fb: &mut LcdFB, mut cr: ClipRegion, s: &str) {
-    let f = Font::new(GlyphSet::Regular);
-    for c in s.chars() {
-        cr.x0 += xor_char(fb, cr, c, f);
+/// Returns `true` if the line has been drawn to since the last redraw
+pub fn is_dirty(fb: &LcdFB, y: usize) -> bool {
+    y < LCD_LINES && fb[y * LCD_WORDS_PER_LINE + (LCD_WORDS_PER_LINE - 1)] & LCD_DIRTY_BIT != 0
+}
+
+/// Clear the dirty bit on every line, once the lines have been sent
+pub fn clear_dirty(fb: &mut LcdFB) {
+    for y in 0..LCD_LINES {
+        fb[y * LCD_WORDS_PER_LINE + (LCD_WORDS_PER_LINE - 1)] &= !LCD_DIRTY_BIT;
     }
 }
 
-/// Blit string with: XOR, small font, align xr left yr top
-pub fn string_small_left(fb: &mut LcdFB, mut cr: ClipRegion, s: &str) {
-    let f = Font::new(GlyphSet::Small);
+/// Blit string with: XOR, align xr left yr top. Characters missing from
+/// the requested glyph set are drawn from the next font in its fallback chain.
+pub fn string_left(fb: &mut LcdFB, mut cr: ClipRegion, s: &str, gs: GlyphSet) {
     for c in s.chars() {
-        cr.x0 += xor_char(fb, cr, c, f);
+        cr.x0 += xor_char(fb, cr, c, Font::for_char(gs, c));
     }
 }
 
+/// Blit string with: XOR, bold font, align xr left yr top
+pub fn string_bold_left(fb: &mut LcdFB, cr: ClipRegion, s: &str) {
+    string_left(fb, cr, s, GlyphSet::Bold);
+}
+
+/// Blit string with: XOR, regular font, align xr left yr top
+pub fn string_regular_left(fb: &mut LcdFB, cr: ClipRegion, s: &str) {
+    string_left(fb, cr, s, GlyphSet::Regular);
+}
+
+/// Blit string with: XOR, small font, align xr left yr top
+pub fn string_small_left(fb: &mut LcdFB, cr: ClipRegion, s: &str) {
+    string_left(fb, cr, s, GlyphSet::Small);
+}
+
 /// Calculate the width of all glpyhs and padding for a string
 pub fn string_width(s: &str, f: Font) -> usize {
     let mut w = 0;
     for c in s.chars() {
-        w += char_width(c, f) + 3;
+        w += char_width(c, Font::for_char(f.glyph_set, c)) + 3 * f.scale;
+    }
+    // Subtle padding math: 3px between chars, 1px at left and right ends,
+    // all multiplied by the font's scale
+    w.saturating_sub(f.scale)
+}
+
+/// Calculate the width and height in pixels that a string will occupy when
+/// drawn with the given glyph set, including any fallback glyphs.
+pub fn string_extents(s: &str, gs: GlyphSet) -> (usize, usize) {
+    let mut height = 0;
+    for c in s.chars() {
+        let f = Font::for_char(gs, c);
+        let gh = GlyphHeader::new((f.glyph_data)((f.glyph_pattern_offset)(c)));
+        height = height.max((gh.y_offset + gh.h) * f.scale);
     }
-    // Subtle padding math: 3px between chars, 1px at left and right ends
-    w - 1
+    (string_width(s, Font::new(gs)), height)
 }
 
 /// Blit a char with: XOR, align left:xr.0 top:yr.0, pad L:1px R:2px
@@ -117,6 +154,9 @@ pub fn xor_char(fb: &mut LcdFB, cr: ClipRegion, c: char, f: Font) -> usize {
     if gh.w > 32 {
         return 0;
     }
+    if f.scale > 1 {
+        return xor_char_scaled(fb, cr, f, gpo, &gh);
+    }
     // Add 1px pad to left
     let x0 = cr.x0 + 1;
     // Calculate word alignment for destination buffer
@@ -132,40 +172,67 @@ pub fn xor_char(fb: &mut LcdFB, cr: ClipRegion, c: char, f: Font) -> usize {
         cr.y1 - y0
     };
     for y in 0..y_max {
-        // Unpack pixels for this glyph row.
-        // px_in_low_word can include some or all of the pixels for this row of
-        // the pattern. It may also include pixels for the next row, or, in the
-        // case of the last row, it may include padding bits.
-        let px_offset = y * gh.w;
-        let low_word = gpo + 1 + (px_offset >> 5);
-        let px_in_low_word = 32 - (px_offset & 0x1f);
-        let mut pattern = (f.glyph_data)(low_word);
-        // Mask and align pixels from low word of glyph data array
-        pattern <<= 32 - px_in_low_word;
-        pattern >>= 32 - gh.w;
-        if gh.w > px_in_low_word {
-            // When pixels for this row span two words in the glyph data array,
-            // get pixels from the high word too
-            let px_in_high_word = gh.w - px_in_low_word;
-            let mut pattern_h = (f.glyph_data)(low_word + 1);
-            pattern_h >>= 32 - px_in_high_word;
-            pattern |= pattern_h;
-        }
+        let pattern = glyph_row(f, gpo, &gh, y);
         // XOR glyph pixels onto destination buffer
         let base = (y0 + y) * LCD_WORDS_PER_LINE;
         fb[base + dest_low_word] ^= pattern << (32 - px_in_dest_low_word);
         if px_in_dest_low_word < gh.w {
             fb[base + dest_high_word] ^= pattern >> px_in_dest_low_word;
         }
+        mark_dirty(fb, y0 + y);
     }
     (x0 + gh.w + 2) - cr.x0
 }
 
-/// Calculate the width of glpyh for a char
+/// Unpack the pixels for row `y` of a glyph, with the leftmost pixel in bit 0.
+/// The glyph's rows are packed back to back, so a row can start part way
+/// through one word of the glyph data array and finish in the next one. The
+/// last word may also include padding bits.
+fn glyph_row(f: Font, gpo: usize, gh: &GlyphHeader, y: usize) -> u32 {
+    let px_offset = y * gh.w;
+    let low_word = gpo + 1 + (px_offset >> 5);
+    let px_in_low_word = 32 - (px_offset & 0x1f);
+    let mut pattern = (f.glyph_data)(low_word);
+    // Mask and align pixels from low word of glyph data array
+    pattern <<= 32 - px_in_low_word;
+    pattern >>= 32 - gh.w;
+    if gh.w > px_in_low_word {
+        // When pixels for this row span two words in the glyph data array,
+        // get pixels from the high word too
+        let px_in_high_word = gh.w - px_in_low_word;
+        let mut pattern_h = (f.glyph_data)(low_word + 1);
+        pattern_h >>= 32 - px_in_high_word;
+        pattern |= pattern_h;
+    }
+    pattern
+}
+
+/// Blit a glyph with every pixel drawn as an f.scale x f.scale block. The
+/// padding is scaled too, so large print keeps the proportions of the
+/// normal fonts. Pixels outside of `cr` are not drawn.
+fn xor_char_scaled(fb: &mut LcdFB, cr: ClipRegion, f: Font, gpo: usize, gh: &GlyphHeader) -> usize {
+    let s = f.scale;
+    let x0 = cr.x0 + s;
+    let y0 = cr.y0 + gh.y_offset * s;
+    for gy in 0..gh.h {
+        let pattern = glyph_row(f, gpo, gh, gy);
+        for y in (y0 + gy * s..y0 + (gy + 1) * s).filter(|y| *y < cr.y1) {
+            for gx in (0..gh.w).filter(|gx| pattern & (1 << gx) != 0) {
+                for x in (x0 + gx * s..x0 + (gx + 1) * s).filter(|x| *x < cr.x1) {
+                    fb[(x + y * LCD_WORDS_PER_LINE * 32) / 32] ^= 1 << (x % 32);
+                }
+            }
+            mark_dirty(fb, y);
+        }
+    }
+    (x0 + (gh.w + 2) * s) - cr.x0
+}
+
+/// Calculate the width of glpyh for a char, in screen pixels
 pub fn char_width(c: char, f: Font) -> usize {
     let gpo = (f.glyph_pattern_offset)(c);
     let gh = GlyphHeader::new((f.glyph_data)(gpo));
-    gh.w
+    gh.w * f.scale
 }
 
 /// Clear a screen region bounded by (cr.x0,cr.y0)..(cr.x0,cr.y1)
@@ -188,6 +255,7 @@ pub fn clear_region(fb: &mut LcdFB, cr: ClipRegion) {
         if dest_low_word < dest_high_word {
             fb[base + dest_high_word] |= 0xffffffff >> (32 - px_in_dest_high_word);
         }
+        mark_dirty(fb, y);
     }
 }
 
@@ -211,6 +279,41 @@ pub fn invert_region(fb: &mut LcdFB, cr: ClipRegion) {
         if dest_low_word < dest_high_word {
             fb[base + dest_high_word] ^= 0xffffffff >> (32 - px_in_dest_high_word);
         }
+        mark_dirty(fb, y);
+    }
+}
+
+/// Draw a QR code as large as fits in the region, centred, with its quiet
+/// zone cleared.  Each module is drawn as a square of whole pixels so that
+/// it stays sharp; nothing is drawn if the region is too small for one
+/// pixel per module.
+pub fn qr(fb: &mut LcdFB, cr: ClipRegion, code: &QrCode) {
+    if cr.y1 > LCD_LINES || cr.y0 >= cr.y1 || cr.x1 > LCD_PX_PER_LINE || cr.x0 >= cr.x1 {
+        return;
+    }
+    let modules = code.size() + 2 * qr::QUIET_ZONE;
+    let scale = (cr.x1 - cr.x0).min(cr.y1 - cr.y0) / modules;
+    if scale == 0 {
+        return;
+    }
+    let side = modules * scale;
+    let x0 = cr.x0 + (cr.x1 - cr.x0 - side) / 2;
+    let y0 = cr.y0 + (cr.y1 - cr.y0 - side) / 2;
+    clear_region(fb, ClipRegion { x0, x1: x0 + side, y0, y1: y0 + side });
+
+    let origin_x = x0 + qr::QUIET_ZONE * scale;
+    let origin_y = y0 + qr::QUIET_ZONE * scale;
+    for my in 0..code.size() {
+        for mx in 0..code.size() {
+            if !code.get(mx, my) {
+                continue;
+            }
+            for y in origin_y + my * scale..origin_y + (my + 1) * scale {
+                for x in origin_x + mx * scale..origin_x + (mx + 1) * scale {
+                    put_pixel(fb, x, y, PixelColor::On);
+                }
+            }
+        }
     }
 }
 
@@ -240,6 +343,7 @@ pub fn line_fill_clear(fb: &mut LcdFB, y: usize) {
         fb[base + i] = 0xffff_ffff;
     }
     fb[base + 10] = 0x0000_ffff;
+    mark_dirty(fb, y);
 }
 
 /// Fill a line of the screen with full-width pattern
@@ -251,6 +355,7 @@ pub fn line_fill_pattern(fb: &mut LcdFB, y: usize, pattern: &BlitRow) {
     for (i, v) in pattern.iter().enumerate() {
         fb[base + i] = *v;
     }
+    mark_dirty(fb, y);
 }
 
 /// Fill a line of the screen with black, padded with clear to left and right
@@ -264,6 +369,7 @@ fn line_fill_padded_solid(fb: &mut LcdFB, y: usize) {
         fb[base + i] = 0x0000_0000;
     }
     fb[base + 10] = 0x0000_c000;
+    mark_dirty(fb, y);
 }
 
 /// Fill a line of the screen with clear, bordered by black, padded with clear
@@ -277,6 +383,7 @@ fn line_fill_padded_border(fb: &mut LcdFB, y: usize) {
         fb[base + i] = 0xffff_ffff;
     }
     fb[base + 10] = 0x0000_dfff;
+    mark_dirty(fb, y);
 }
 
 fn put_pixel(fb: &mut LcdFB, x: usize, y: usize, color: PixelColor) {
@@ -288,6 +395,7 @@ fn put_pixel(fb: &mut LcdFB, x: usize, y: usize, color: PixelColor) {
     } else {
         fb[(x + y * LCD_WORDS_PER_LINE * 32) / 32] &= !(1 << (x % 32))
     }
+    mark_dirty(fb, y);
 }
 
 // plotLine(int x0, int y0, int x1, int y1)
@@ -425,6 +533,78 @@ pub fn circle(fb: &mut LcdFB, x: usize, y: usize, r: usize, stroke_width: usize,
 #[cfg(test)]
 mod tests {
     use super::fonts;
+    use super::*;
+
+    #[test]
+    fn small_font_falls_back_for_sprites() {
+        let battery = fonts::pua::BATTERY_05.chars().next().unwrap();
+        assert!(!Font::new(GlyphSet::Small).has_glyph(battery));
+        assert_eq!(Font::for_char(GlyphSet::Small, battery).glyph_set, GlyphSet::Regular);
+        assert_eq!(Font::for_char(GlyphSet::Small, 'a').glyph_set, GlyphSet::Small);
+    }
+
+    #[test]
+    fn string_extents_include_fallback() {
+        assert_eq!(string_extents("", GlyphSet::Regular), (0, 0));
+        let (w, h) = string_extents("ab", GlyphSet::Small);
+        assert_eq!(w, string_width("ab", Font::new(GlyphSet::Small)));
+        assert!(h > 0 && h <= fonts::small::MAX_HEIGHT as usize);
+        let (_, h) = string_extents(fonts::pua::BATTERY_05, GlyphSet::Small);
+        assert!(h <= fonts::regular::MAX_HEIGHT as usize);
+    }
+
+    #[test]
+    fn large_print_doubles_bold_glyphs() {
+        let (bold_w, bold_h) = string_extents("Hi", GlyphSet::Bold);
+        assert_eq!(string_extents("Hi", GlyphSet::Large), (bold_w * 2, bold_h * 2));
+        assert_eq!(Font::for_char(GlyphSet::Large, '\u{E700}').scale, fonts::LARGE_SCALE);
+
+        let mut bold = [0u32; LCD_FRAME_BUF_SIZE];
+        let mut large = [0u32; LCD_FRAME_BUF_SIZE];
+        let cr = ClipRegion { x0: 0, x1: LCD_PX_PER_LINE, y0: 0, y1: LCD_LINES };
+        let w = xor_char(&mut bold, cr, 'H', Font::new(GlyphSet::Bold));
+        assert_eq!(xor_char(&mut large, cr, 'H', Font::new(GlyphSet::Large)), w * 2);
+        let px = |fb: &LcdFB, x: usize, y: usize| fb[(x + y * LCD_WORDS_PER_LINE * 32) / 32] & (1 << (x % 32)) != 0;
+        for y in 0..bold_h * 2 {
+            for x in 0..w * 2 {
+                assert_eq!(px(&large, x, y), px(&bold, x / 2, y / 2), "({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn clear_region_marks_only_touched_lines() {
+        let mut fb = [0u32; LCD_FRAME_BUF_SIZE];
+        clear_region(&mut fb, ClipRegion { x0: 8, x1: 40, y0: 10, y1: 20 });
+        for y in 0..LCD_LINES {
+            assert_eq!(is_dirty(&fb, y), (10..20).contains(&y), "line {}", y);
+        }
+        clear_dirty(&mut fb);
+        assert!((0..LCD_LINES).all(|y| !is_dirty(&fb, y)));
+    }
+
+    #[test]
+    fn line_fill_keeps_dirty_bit() {
+        let mut fb = [0u32; LCD_FRAME_BUF_SIZE];
+        line_fill_clear(&mut fb, 5);
+        assert!(is_dirty(&fb, 5));
+        assert_eq!(fb[5 * LCD_WORDS_PER_LINE + 10] & 0xffff, 0xffff);
+    }
+
+    #[test]
+    fn qr_is_centred_and_scaled() {
+        let mut fb = [0u32; LCD_FRAME_BUF_SIZE];
+        let code = QrCode::encode(b"xous").unwrap();
+        // 21 modules plus the quiet zone is 29, so a 100px square region
+        // gets 3px modules and 6px of margin on each side.
+        qr(&mut fb, ClipRegion { x0: 0, x1: 100, y0: 0, y1: 100 }, &code);
+        let dark = |x: usize, y: usize| fb[(x + y * LCD_WORDS_PER_LINE * 32) / 32] & (1 << (x % 32)) == 0;
+        let origin = 6 + qr::QUIET_ZONE * 3;
+        // The quiet zone is light and the top-left finder starts dark.
+        assert!(!dark(origin - 1, origin));
+        assert!(dark(origin, origin) && dark(origin + 2, origin + 2));
+        assert!(is_dirty(&fb, 6) && !is_dirty(&fb, 5) && !is_dirty(&fb, 6 + 29 * 3));
+    }
 
     #[test]
     fn bold_font_at_sign() {
@@ -0,0 +1,559 @@
+//! QR code encoder.
+//!
+//! Encodes up to `MAX_DATA` bytes as a QR symbol in byte mode with error
+//! correction level M, which survives about 15% of the symbol being damaged
+//! or badly printed.  The smallest version that fits the data is chosen,
+//! up to version 10 (57x57 modules).  That is enough for pairing secrets,
+//! wallet addresses and TOTP provisioning URIs, and keeps the tables and
+//! the symbol small enough to live on the stack.
+
+/// Largest version this encoder produces
+pub const MAX_VERSION: usize = 10;
+
+/// Width and height in modules of a `MAX_VERSION` symbol
+pub const MAX_SIZE: usize = MAX_VERSION * 4 + 17;
+
+/// Most bytes that fit in a `MAX_VERSION` symbol
+pub const MAX_DATA: usize = 213;
+
+/// Light modules that must surround the symbol for it to be scanned
+pub const QUIET_ZONE: usize = 4;
+
+/// Error correction codewords per block, by version
+const ECC_PER_BLOCK: [usize; MAX_VERSION + 1] = [0, 10, 16, 26, 18, 24, 16, 18, 22, 22, 26];
+
+/// Error correction blocks, by version
+const NUM_BLOCKS: [usize; MAX_VERSION + 1] = [0, 1, 1, 1, 2, 2, 4, 4, 4, 5, 5];
+
+/// Format bits that select error correction level M
+const ECC_LEVEL_M: u32 = 0b00;
+
+/// Most codewords in any version
+const MAX_CODEWORDS: usize = 346;
+
+/// Most error correction codewords in one block
+const MAX_ECC: usize = 26;
+
+#[derive(Copy, Clone)]
+pub struct QrCode {
+    version: usize,
+    size: usize,
+    /// One bit per module, bit `x` of row `y`.  A set bit is dark.
+    modules: [u64; MAX_SIZE],
+}
+
+impl QrCode {
+    /// Encode `data`, or return `None` if it's longer than `MAX_DATA`
+    pub fn encode(data: &[u8]) -> Option<QrCode> {
+        QrCode::encode_with_mask(data, None)
+    }
+
+    /// Encode `data` with the given mask, or with the best one if `None`
+    fn encode_with_mask(data: &[u8], mask: Option<u32>) -> Option<QrCode> {
+        let version = (1..=MAX_VERSION).find(|v| data.len() <= data_capacity(*v))?;
+        let size = version * 4 + 17;
+        let mut qr = QrCode {
+            version,
+            size,
+            modules: [0; MAX_SIZE],
+        };
+        let mut function = [0u64; MAX_SIZE];
+        qr.draw_function_patterns(&mut function);
+
+        let mut codewords = [0u8; MAX_CODEWORDS];
+        let len = data_codewords(version, data, &mut codewords);
+        let mut interleaved = [0u8; MAX_CODEWORDS];
+        add_ecc_and_interleave(version, &codewords[..len], &mut interleaved);
+        qr.draw_codewords(&function, &interleaved[..num_raw_modules(version) / 8]);
+
+        // Pick the mask that leaves the symbol easiest to scan.
+        let mut best = (u32::MAX, mask.unwrap_or(0));
+        for mask in (0..8).filter(|_| mask.is_none()) {
+            qr.apply_mask(&function, mask);
+            qr.draw_format_bits(&mut function, mask);
+            let penalty = qr.penalty();
+            if penalty < best.0 {
+                best = (penalty, mask);
+            }
+            qr.apply_mask(&function, mask);
+        }
+        qr.apply_mask(&function, best.1);
+        qr.draw_format_bits(&mut function, best.1);
+        Some(qr)
+    }
+
+    pub fn version(&self) -> usize {
+        self.version
+    }
+
+    /// Width and height of the symbol in modules, not counting the quiet
+    /// zone
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns `true` if the module at (`x`, `y`) is dark.  Anything
+    /// outside the symbol is light.
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        x < self.size && y < self.size && self.modules[y] & (1 << x) != 0
+    }
+
+    fn set(&mut self, function: &mut [u64; MAX_SIZE], x: usize, y: usize, dark: bool) {
+        if dark {
+            self.modules[y] |= 1 << x;
+        } else {
+            self.modules[y] &= !(1 << x);
+        }
+        function[y] |= 1 << x;
+    }
+
+    fn draw_function_patterns(&mut self, function: &mut [u64; MAX_SIZE]) {
+        for i in 0..self.size {
+            self.set(function, 6, i, i % 2 == 0);
+            self.set(function, i, 6, i % 2 == 0);
+        }
+
+        let far = self.size - 4;
+        self.draw_finder(function, 3, 3);
+        self.draw_finder(function, far, 3);
+        self.draw_finder(function, 3, far);
+
+        let mut positions = [0usize; 7];
+        let count = alignment_positions(self.version, &mut positions);
+        for (i, &x) in positions[..count].iter().enumerate() {
+            for (j, &y) in positions[..count].iter().enumerate() {
+                // Skip the three corners that hold finder patterns.
+                let corner = (i == 0 && (j == 0 || j == count - 1)) || (i == count - 1 && j == 0);
+                if !corner {
+                    self.draw_alignment(function, x, y);
+                }
+            }
+        }
+
+        // Reserve the format bits; they're drawn once the mask is chosen.
+        self.draw_format_bits(function, 0);
+        self.draw_version(function);
+    }
+
+    /// Draw a finder pattern and its separator centred on (`x`, `y`)
+    fn draw_finder(&mut self, function: &mut [u64; MAX_SIZE], x: usize, y: usize) {
+        for dy in -4i32..=4 {
+            for dx in -4i32..=4 {
+                let (xx, yy) = (x as i32 + dx, y as i32 + dy);
+                if xx < 0 || yy < 0 || xx >= self.size as i32 || yy >= self.size as i32 {
+                    continue;
+                }
+                let dist = dx.abs().max(dy.abs());
+                self.set(function, xx as usize, yy as usize, dist != 2 && dist != 4);
+            }
+        }
+    }
+
+    fn draw_alignment(&mut self, function: &mut [u64; MAX_SIZE], x: usize, y: usize) {
+        for dy in -2i32..=2 {
+            for dx in -2i32..=2 {
+                let dist = dx.abs().max(dy.abs());
+                self.set(
+                    function,
+                    (x as i32 + dx) as usize,
+                    (y as i32 + dy) as usize,
+                    dist != 1,
+                );
+            }
+        }
+    }
+
+    fn draw_format_bits(&mut self, function: &mut [u64; MAX_SIZE], mask: u32) {
+        let data = (ECC_LEVEL_M << 3) | mask;
+        let mut rem = data;
+        for _ in 0..10 {
+            rem = (rem << 1) ^ ((rem >> 9) * 0x537);
+        }
+        let bits = ((data << 10) | rem) ^ 0x5412;
+        let bit = |i: usize| (bits >> i) & 1 != 0;
+
+        // The copy around the top-left finder
+        for i in 0..=5 {
+            self.set(function, 8, i, bit(i));
+        }
+        self.set(function, 8, 7, bit(6));
+        self.set(function, 8, 8, bit(7));
+        self.set(function, 7, 8, bit(8));
+        for i in 9..15 {
+            self.set(function, 14 - i, 8, bit(i));
+        }
+
+        // The copy split between the other two finders
+        for i in 0..8 {
+            self.set(function, self.size - 1 - i, 8, bit(i));
+        }
+        for i in 8..15 {
+            self.set(function, 8, self.size - 15 + i, bit(i));
+        }
+        self.set(function, 8, self.size - 8, true);
+    }
+
+    fn draw_version(&mut self, function: &mut [u64; MAX_SIZE]) {
+        if self.version < 7 {
+            return;
+        }
+        let mut rem = self.version as u32;
+        for _ in 0..12 {
+            rem = (rem << 1) ^ ((rem >> 11) * 0x1f25);
+        }
+        let bits = ((self.version as u32) << 12) | rem;
+        for i in 0..18 {
+            let dark = (bits >> i) & 1 != 0;
+            let a = self.size - 11 + i % 3;
+            let b = i / 3;
+            self.set(function, a, b, dark);
+            self.set(function, b, a, dark);
+        }
+    }
+
+    /// Fill the modules that aren't part of a function pattern, two columns
+    /// at a time in a zig-zag from the bottom right corner
+    fn draw_codewords(&mut self, function: &[u64; MAX_SIZE], data: &[u8]) {
+        let mut i = 0;
+        let mut right = self.size as i32 - 1;
+        while right >= 1 {
+            if right == 6 {
+                right = 5;
+            }
+            for vert in 0..self.size {
+                for j in 0..2 {
+                    let x = (right - j) as usize;
+                    let upward = (right + 1) & 2 == 0;
+                    let y = if upward { self.size - 1 - vert } else { vert };
+                    if function[y] & (1 << x) == 0 && i < data.len() * 8 {
+                        if (data[i >> 3] >> (7 - (i & 7))) & 1 != 0 {
+                            self.modules[y] |= 1 << x;
+                        }
+                        i += 1;
+                    }
+                }
+            }
+            right -= 2;
+        }
+    }
+
+    /// XOR `mask` onto every module that isn't part of a function pattern.
+    /// Applying the same mask twice undoes it.
+    fn apply_mask(&mut self, function: &[u64; MAX_SIZE], mask: u32) {
+        for (y, function_row) in function[..self.size].iter().enumerate() {
+            for x in 0..self.size {
+                let invert = match mask {
+                    0 => (x + y) % 2 == 0,
+                    1 => y % 2 == 0,
+                    2 => x % 3 == 0,
+                    3 => (x + y) % 3 == 0,
+                    4 => (x / 3 + y / 2) % 2 == 0,
+                    5 => x * y % 2 + x * y % 3 == 0,
+                    6 => (x * y % 2 + x * y % 3) % 2 == 0,
+                    _ => ((x + y) % 2 + x * y % 3) % 2 == 0,
+                };
+                if invert && function_row & (1 << x) == 0 {
+                    self.modules[y] ^= 1 << x;
+                }
+            }
+        }
+    }
+
+    /// Score the symbol the way the standard does when choosing a mask.
+    /// Lower is better.
+    fn penalty(&self) -> u32 {
+        let mut penalty = 0;
+        let size = self.size;
+
+        // Runs of five or more modules of one colour, and patterns that
+        // look like finders, in both rows and columns
+        for transpose in &[false, true] {
+            for a in 0..size {
+                let module = |b: usize| {
+                    if *transpose {
+                        self.get(a, b)
+                    } else {
+                        self.get(b, a)
+                    }
+                };
+                let mut runs = RunHistory::new(size);
+                let mut run = 0;
+                let mut colour = false;
+                for b in 0..size {
+                    if module(b) == colour {
+                        run += 1;
+                        if run == 5 {
+                            penalty += 3;
+                        } else if run > 5 {
+                            penalty += 1;
+                        }
+                    } else {
+                        runs.push(run);
+                        if !colour {
+                            penalty += runs.finder_patterns() * 40;
+                        }
+                        colour = module(b);
+                        run = 1;
+                    }
+                }
+                penalty += runs.finish(colour, run) * 40;
+            }
+        }
+
+        // 2x2 blocks of one colour
+        for y in 0..size - 1 {
+            for x in 0..size - 1 {
+                let c = self.get(x, y);
+                if c == self.get(x + 1, y) && c == self.get(x, y + 1) && c == self.get(x + 1, y + 1)
+                {
+                    penalty += 3;
+                }
+            }
+        }
+
+        // Balance of dark and light modules
+        let dark: u32 = self.modules[..size].iter().map(|row| row.count_ones()).sum();
+        let total = (size * size) as u32;
+        let k = ((dark * 20).max(total * 10) - (dark * 20).min(total * 10)).div_ceil(total) - 1;
+        penalty + k * 10
+    }
+}
+
+/// The last seven runs of one colour along a row or column, most recent
+/// first, for spotting patterns that look like finders.  The quiet zone
+/// counts as part of the first and last light runs.
+struct RunHistory {
+    size: usize,
+    runs: [usize; 7],
+}
+
+impl RunHistory {
+    fn new(size: usize) -> RunHistory {
+        RunHistory { size, runs: [0; 7] }
+    }
+
+    fn push(&mut self, mut run: usize) {
+        if self.runs[0] == 0 {
+            run += self.size;
+        }
+        self.runs.copy_within(0..6, 1);
+        self.runs[0] = run;
+    }
+
+    /// How many dark-light-dark-light-dark runs in a 1:1:3:1:1 ratio end
+    /// here, with light runs four times as wide on either side
+    fn finder_patterns(&self) -> u32 {
+        let r = &self.runs;
+        let n = r[1];
+        let core = n > 0 && r[2] == n && r[3] == n * 3 && r[4] == n && r[5] == n;
+        (core && r[0] >= n * 4 && r[6] >= n) as u32 + (core && r[6] >= n * 4 && r[0] >= n) as u32
+    }
+
+    /// Close off the final run, counting the quiet zone after it
+    fn finish(mut self, colour: bool, mut run: usize) -> u32 {
+        if colour {
+            self.push(run);
+            run = 0;
+        }
+        self.push(run + self.size);
+        self.finder_patterns()
+    }
+}
+
+/// Centre coordinates of the alignment patterns.  Returns how many were
+/// written to `positions`.
+fn alignment_positions(version: usize, positions: &mut [usize; 7]) -> usize {
+    if version == 1 {
+        return 0;
+    }
+    let count = version / 7 + 2;
+    let step = (version * 4 + count * 2 + 1) / (count * 2 - 2) * 2;
+    positions[0] = 6;
+    let mut pos = version * 4 + 10;
+    for i in (1..count).rev() {
+        positions[i] = pos;
+        pos -= step;
+    }
+    count
+}
+
+/// Modules available for data and error correction, including remainder
+/// bits
+fn num_raw_modules(version: usize) -> usize {
+    let mut result = (16 * version + 128) * version + 64;
+    if version >= 2 {
+        let count = version / 7 + 2;
+        result -= (25 * count - 10) * count - 55;
+        if version >= 7 {
+            result -= 36;
+        }
+    }
+    result
+}
+
+fn num_data_codewords(version: usize) -> usize {
+    num_raw_modules(version) / 8 - ECC_PER_BLOCK[version] * NUM_BLOCKS[version]
+}
+
+/// Bytes of data that fit in `version`
+fn data_capacity(version: usize) -> usize {
+    let count_bits = if version < 10 { 8 } else { 16 };
+    (num_data_codewords(version) * 8 - 4 - count_bits) / 8
+}
+
+/// Build the data codewords: mode, length, data, terminator and padding.
+/// Returns how many codewords were written.
+fn data_codewords(version: usize, data: &[u8], out: &mut [u8; MAX_CODEWORDS]) -> usize {
+    let capacity = num_data_codewords(version);
+    let mut bits = 0;
+    let mut push = |value: u32, len: usize| {
+        for i in (0..len).rev() {
+            if (value >> i) & 1 != 0 {
+                out[bits >> 3] |= 0x80 >> (bits & 7);
+            }
+            bits += 1;
+        }
+    };
+    push(0b0100, 4);
+    push(data.len() as u32, if version < 10 { 8 } else { 16 });
+    for byte in data {
+        push(*byte as u32, 8);
+    }
+    // The terminator and padding to a byte boundary are zero bits, which
+    // are already there.
+    let len = (bits + 4).min(capacity * 8).max(bits).div_ceil(8);
+    for (i, byte) in out[len..capacity].iter_mut().enumerate() {
+        *byte = if i % 2 == 0 { 0xec } else { 0x11 };
+    }
+    capacity
+}
+
+/// Split the data into blocks, append error correction to each, and
+/// interleave them into `out`
+fn add_ecc_and_interleave(version: usize, data: &[u8], out: &mut [u8; MAX_CODEWORDS]) {
+    let num_blocks = NUM_BLOCKS[version];
+    let ecc_len = ECC_PER_BLOCK[version];
+    let raw = num_raw_modules(version) / 8;
+    let num_short = num_blocks - raw % num_blocks;
+    let short_len = raw / num_blocks - ecc_len;
+
+    let mut divisor = [0u8; MAX_ECC];
+    rs_divisor(ecc_len, &mut divisor);
+
+    let mut start = 0;
+    for block in 0..num_blocks {
+        let len = short_len + if block < num_short { 0 } else { 1 };
+        let block_data = &data[start..start + len];
+        start += len;
+
+        // Codewords are taken a column at a time across the blocks.  Only
+        // the long blocks have a codeword in the last data column.
+        for (i, byte) in block_data.iter().enumerate() {
+            let idx = if i < short_len {
+                i * num_blocks + block
+            } else {
+                short_len * num_blocks + (block - num_short)
+            };
+            out[idx] = *byte;
+        }
+
+        let mut ecc = [0u8; MAX_ECC];
+        rs_remainder(block_data, &divisor[..ecc_len], &mut ecc[..ecc_len]);
+        let data_total = short_len * num_blocks + (num_blocks - num_short);
+        for (i, byte) in ecc[..ecc_len].iter().enumerate() {
+            out[data_total + i * num_blocks + block] = *byte;
+        }
+    }
+}
+
+/// Multiply in GF(2^8) modulo x^8 + x^4 + x^3 + x^2 + 1
+fn gf_mul(x: u8, y: u8) -> u8 {
+    let mut z: u8 = 0;
+    for i in (0..8).rev() {
+        z = (z << 1) ^ ((z >> 7) * 0x1d);
+        z ^= ((y >> i) & 1) * x;
+    }
+    z
+}
+
+/// The Reed-Solomon generator polynomial of degree `degree`, without its
+/// leading term
+fn rs_divisor(degree: usize, out: &mut [u8; MAX_ECC]) {
+    out[degree - 1] = 1;
+    let mut root: u8 = 1;
+    for _ in 0..degree {
+        for j in 0..degree {
+            out[j] = gf_mul(out[j], root);
+            if j + 1 < degree {
+                out[j] ^= out[j + 1];
+            }
+        }
+        root = gf_mul(root, 0x02);
+    }
+}
+
+fn rs_remainder(data: &[u8], divisor: &[u8], out: &mut [u8]) {
+    for b in data {
+        let factor = b ^ out[0];
+        out.copy_within(1.., 0);
+        let last = out.len() - 1;
+        out[last] = 0;
+        for (x, y) in out.iter_mut().zip(divisor.iter()) {
+            *x ^= gf_mul(*y, factor);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(qr: &QrCode) -> [u64; MAX_SIZE] {
+        let mut rows = [0; MAX_SIZE];
+        for (y, row) in rows[..qr.size()].iter_mut().enumerate() {
+            for x in 0..qr.size() {
+                if qr.get(x, y) {
+                    *row |= 1 << x;
+                }
+            }
+        }
+        rows
+    }
+
+    #[test]
+    fn encodes_known_symbol() {
+        // Checked against an independent encoder
+        let expected: [u64; 25] = [
+            0x01fcc17f, 0x01041a41, 0x0174925d, 0x0175fd5d, 0x0175db5d, 0x01057941, 0x01fd557f,
+            0x0000cd00, 0x013e1dd1, 0x00b1daad, 0x0071ed4e, 0x00c0d238, 0x01ef625d, 0x0093d4bf,
+            0x0078fbd0, 0x00db2894, 0x007f36df, 0x0011e100, 0x0015077f, 0x00f15041, 0x01ff695d,
+            0x01ce965d, 0x00a57c5d, 0x00fa2e41, 0x01c0b77f,
+        ];
+        let qr = QrCode::encode(b"https://betrusted.io").unwrap();
+        assert_eq!(qr.version(), 2);
+        assert_eq!(qr.size(), 25);
+        assert_eq!(rows(&qr)[..25], expected[..]);
+    }
+
+    #[test]
+    fn picks_smallest_version() {
+        assert_eq!(QrCode::encode(&[0; 14]).unwrap().version(), 1);
+        assert_eq!(QrCode::encode(&[0; 15]).unwrap().version(), 2);
+        assert_eq!(QrCode::encode(&[0; MAX_DATA]).unwrap().version(), MAX_VERSION);
+        assert_eq!(QrCode::encode(&[0; MAX_DATA]).unwrap().size(), MAX_SIZE);
+        assert!(QrCode::encode(&[0; MAX_DATA + 1]).is_none());
+    }
+
+    #[test]
+    fn large_symbols_have_version_and_alignment_patterns() {
+        let qr = QrCode::encode(&[0x5a; 150]).unwrap();
+        assert_eq!(qr.version(), 8);
+        // Alignment patterns sit at 6, 24 and 42, so (24, 24) is the dark
+        // centre of one with a light ring around it.
+        assert!(qr.get(24, 24));
+        assert!(!qr.get(25, 24) && !qr.get(23, 23));
+        // Timing patterns alternate between the finders.
+        assert!((8..qr.size() - 8).all(|i| qr.get(i, 6) == (i % 2 == 0)));
+        assert!((8..qr.size() - 8).all(|i| qr.get(6, i) == (i % 2 == 0)));
+    }
+}
@@ -0,0 +1,195 @@
+//! A health check that every server answers in the same way.
+//!
+//! A client lends a server a page using `MutableBorrow` with the id
+//! `GET_HEALTH`.  The server replaces the `Health` record in the page, which
+//! is an `xous::Envelope`, and returns it.  All integers are little-endian:
+//!
+//! | Size | Contents                                         |
+//! |------|--------------------------------------------------|
+//! | 4    | Requests queued but not yet received             |
+//! | 4    | Requests that could not be handled               |
+//! | 4    | Requests received, excluding health checks       |
+//! | 8    | When new requests were last seen, in timer ticks |
+//!
+//! Servers keep their counters in a `Monitor`, and clients use `query()`.
+
+#![cfg_attr(target_os = "none", no_std)]
+
+use xous::{Envelope, Error, MemoryRange, Record, CID, SID};
+
+/// Message id reserved in every server for health checks.  It is far above
+/// the ids servers use for their own opcodes.
+pub const GET_HEALTH: usize = 0x4845_4c54;
+
+/// A server with more requests than this waiting is considered backed up
+pub const MAX_QUEUE_DEPTH: u32 = 8;
+
+/// Size of an encoded `Health` record
+const RECORD_SIZE: usize = 20;
+
+/// A snapshot of a server's counters
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct Health {
+    /// Requests waiting in the server's queue that it hasn't received yet
+    pub queue_depth: u32,
+
+    /// Requests that could not be handled
+    pub errors: u32,
+
+    /// Requests received, not counting health checks
+    pub requests: u32,
+
+    /// When a health check first saw the latest requests, in platform
+    /// timer ticks since boot.  Servers only read the clock when they answer
+    /// a health check, so the last request arrived between the check before
+    /// this one and this time.
+    pub last_activity: u64,
+}
+
+/// Something that looks wrong when comparing two `Health` snapshots
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Anomaly {
+    /// The error counter went up by this much
+    Errors(u32),
+
+    /// More than `MAX_QUEUE_DEPTH` requests are waiting
+    Backlog(u32),
+}
+
+impl Health {
+    pub fn to_bytes(&self) -> [u8; RECORD_SIZE] {
+        let mut data = [0u8; RECORD_SIZE];
+        data[0..4].copy_from_slice(&self.queue_depth.to_le_bytes());
+        data[4..8].copy_from_slice(&self.errors.to_le_bytes());
+        data[8..12].copy_from_slice(&self.requests.to_le_bytes());
+        data[12..20].copy_from_slice(&self.last_activity.to_le_bytes());
+        data
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Option<Health> {
+        if data.len() < RECORD_SIZE {
+            return None;
+        }
+        let word = |i: usize| u32::from_le_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]);
+        Some(Health {
+            queue_depth: word(0),
+            errors: word(4),
+            requests: word(8),
+            last_activity: word(12) as u64 | ((word(16) as u64) << 32),
+        })
+    }
+
+    /// Compare against an earlier snapshot of the same server
+    pub fn anomaly(&self, previous: &Health) -> Option<Anomaly> {
+        let new_errors = self.errors.wrapping_sub(previous.errors);
+        if new_errors != 0 {
+            Some(Anomaly::Errors(new_errors))
+        } else if self.queue_depth > MAX_QUEUE_DEPTH {
+            Some(Anomaly::Backlog(self.queue_depth))
+        } else {
+            None
+        }
+    }
+}
+
+impl Record for Health {
+    const TAG: u32 = u32::from_le_bytes(*b"Hlth");
+    const VERSION: u16 = 1;
+    const MAX_SIZE: usize = RECORD_SIZE;
+
+    fn encode(&self, buf: &mut [u8]) -> usize {
+        buf[..RECORD_SIZE].copy_from_slice(&self.to_bytes());
+        RECORD_SIZE
+    }
+
+    fn decode(buf: &[u8]) -> Option<Health> {
+        Health::from_bytes(buf)
+    }
+}
+
+/// Platform timer ticks since boot, or `0` if the kernel can't say
+pub fn now() -> u64 {
+    xous::idle_stats().map(|s| s.total).unwrap_or(0)
+}
+
+/// The counters a server keeps so it can answer health checks.  Counting
+/// a request is cheap: the clock is only read when a health check comes in.
+#[derive(Debug, Default)]
+pub struct Monitor {
+    requests: u32,
+    errors: u32,
+    last_activity: u64,
+    /// `requests` as of the last health check
+    seen: u32,
+}
+
+impl Monitor {
+    pub fn new() -> Monitor {
+        Default::default()
+    }
+
+    /// Note that a request arrived.  Don't call this for health checks.
+    pub fn activity(&mut self) {
+        self.requests = self.requests.wrapping_add(1);
+    }
+
+    /// Note that a request could not be handled
+    pub fn error(&mut self) {
+        self.errors = self.errors.wrapping_add(1);
+    }
+
+    /// Take a snapshot of the counters, stamping any requests that arrived
+    /// since the last snapshot with `timestamp`
+    pub fn health_at(&mut self, queue_depth: u32, timestamp: u64) -> Health {
+        if self.requests != self.seen {
+            self.seen = self.requests;
+            self.last_activity = timestamp;
+        }
+        Health {
+            queue_depth,
+            errors: self.errors,
+            requests: self.requests,
+            last_activity: self.last_activity,
+        }
+    }
+
+    /// Answer a health check for `server` by filling in the lent buffer.
+    /// The buffer is returned to the client when the message envelope is
+    /// dropped.
+    pub fn reply(&mut self, buf: MemoryRange, server: SID) {
+        let queue_depth = xous::queue_depth(server).unwrap_or(0);
+        let health = self.health_at(queue_depth as u32, now());
+        // A buffer too small to hold the answer is the client's problem
+        xous::seal_record(&buf, &health).ok();
+    }
+}
+
+/// Ask the server on the other end of `cid` how it is doing
+pub fn query(cid: CID) -> Result<Health, Error> {
+    let mut envelope = Envelope::new(&Health::default())?;
+    envelope.lend_mut(cid, GET_HEALTH)?;
+    envelope.record()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn activity_is_stamped_at_the_next_check() {
+        let mut monitor = Monitor::new();
+        assert_eq!(monitor.health_at(0, 10), Health::default());
+
+        monitor.activity();
+        monitor.activity();
+        let health = monitor.health_at(3, 20);
+        assert_eq!(health.requests, 2);
+        assert_eq!(health.queue_depth, 3);
+        assert_eq!(health.last_activity, 20);
+
+        // Nothing new arrived, so the time stays put
+        assert_eq!(monitor.health_at(0, 30).last_activity, 20);
+        monitor.activity();
+        assert_eq!(monitor.health_at(0, 40).last_activity, 40);
+    }
+}
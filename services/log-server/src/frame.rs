@@ -0,0 +1,449 @@
+//! Structured log records and the binary frames that carry them.
+//!
+//! Clients lend the log server a *record* using the `LOG_RECORD` message id.
+//! All integers are little-endian, and strings are prefixed with a 16-bit
+//! byte count:
+//!
+//! | Size    | Contents                                      |
+//! |---------|-----------------------------------------------|
+//! | 4       | PID of the process that logged the message    |
+//! | 1       | Level: 1 = error, 2 = warn, ... 5 = trace     |
+//! | 1       | Number of key-value pairs                     |
+//! | 2 + n   | Target                                        |
+//! | 2 + n   | Message                                       |
+//! | 4 + n   | Each key followed by its value                |
+//!
+//! When binary output is enabled, the server writes each record to the
+//! console wrapped in a *frame*:
+//!
+//! | Size    | Contents                                      |
+//! |---------|-----------------------------------------------|
+//! | 2       | `FRAME_MAGIC`                                 |
+//! | 2       | Length of the timestamp and record            |
+//! | 8       | Timestamp, in platform timer ticks since boot |
+//! | n       | Record                                        |
+//! | 2       | CRC-16/X25 of the timestamp and record        |
+//!
+//! Frames are mixed in with ordinary console text.  `scan_frame()` picks
+//! them back out, and is shared with the `log-decode` tool.
+
+use core::fmt;
+
+/// Message id used to lend a plain text line to the log server
+pub const LOG_TEXT: usize = 1;
+
+/// Message id used to lend a structured record to the log server
+pub const LOG_RECORD: usize = 2;
+
+/// Scalar message id that selects text (`0`) or binary (`1`) output
+pub const SET_OUTPUT_FORMAT: usize = 3;
+
+/// Bytes that begin every binary frame
+pub const FRAME_MAGIC: [u8; 2] = [0xa5, 0x4c];
+
+/// Largest record a client can send, which is the page it lends
+pub const MAX_RECORD: usize = 4096;
+
+/// Size of the magic and length at the start of a frame
+const FRAME_HEADER: usize = 4;
+
+/// Size of the timestamp at the start of a frame's body
+const TIMESTAMP_SIZE: usize = 8;
+
+/// Size of the CRC at the end of a frame
+const FRAME_CRC: usize = 2;
+
+/// Size of the fixed part of a record, before the target
+const RECORD_HEADER: usize = 6;
+
+/// Builds a record in a caller-supplied buffer.  Anything that doesn't fit
+/// is silently dropped, and the record is still well-formed.
+pub struct RecordWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+    /// Offset of the length prefix of the string currently being written,
+    /// or `None` if there was no room for it
+    open: Option<usize>,
+}
+
+impl<'a> RecordWriter<'a> {
+    pub fn new(buf: &'a mut [u8], pid: u32, level: log::Level) -> Self {
+        assert!(buf.len() >= RECORD_HEADER, "record buffer is too small");
+        buf[0..4].copy_from_slice(&pid.to_le_bytes());
+        buf[4] = level as u8;
+        buf[5] = 0;
+        RecordWriter {
+            buf,
+            len: RECORD_HEADER,
+            open: None,
+        }
+    }
+
+    /// Start a new length-prefixed string.  Text sent through the
+    /// `fmt::Write` impl is appended to it.
+    pub fn begin_str(&mut self) {
+        let start = self.len;
+        self.open = if self.put(&[0, 0]) { Some(start) } else { None };
+    }
+
+    /// Write a complete length-prefixed string
+    pub fn str(&mut self, s: &str) {
+        self.begin_str();
+        self.append(s.as_bytes());
+    }
+
+    /// Count one more key-value pair in the header.  Call this after writing
+    /// the key and value.
+    pub fn finish_pair(&mut self) {
+        self.buf[5] = self.buf[5].saturating_add(1);
+    }
+
+    /// The bytes of the record so far
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    fn put(&mut self, bytes: &[u8]) -> bool {
+        if self.len + bytes.len() > self.buf.len() {
+            return false;
+        }
+        self.buf[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+        self.len += bytes.len();
+        true
+    }
+
+    fn append(&mut self, bytes: &[u8]) {
+        let open = match self.open {
+            Some(open) => open,
+            None => return,
+        };
+        let room = self.buf.len() - self.len;
+        // Truncate on a character boundary so the string stays valid UTF-8.
+        let mut take = bytes.len().min(room);
+        while take < bytes.len() && take > 0 && (bytes[take] & 0xc0) == 0x80 {
+            take -= 1;
+        }
+        self.put(&bytes[..take]);
+        let str_len = (self.len - open - 2) as u16;
+        self.buf[open..open + 2].copy_from_slice(&str_len.to_le_bytes());
+    }
+}
+
+impl<'a> fmt::Write for RecordWriter<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.append(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// A record that has been received from a client
+pub struct Record<'a> {
+    pub pid: u32,
+    pub level: Option<log::Level>,
+    pub target: &'a str,
+    pub message: &'a str,
+    pair_count: usize,
+    pairs: &'a [u8],
+}
+
+fn take_str<'a>(data: &mut &'a [u8]) -> Option<&'a str> {
+    if data.len() < 2 {
+        return None;
+    }
+    let len = u16::from_le_bytes([data[0], data[1]]) as usize;
+    if data.len() < 2 + len {
+        return None;
+    }
+    let s = core::str::from_utf8(&data[2..2 + len]).ok()?;
+    *data = &data[2 + len..];
+    Some(s)
+}
+
+impl<'a> Record<'a> {
+    pub fn parse(data: &'a [u8]) -> Option<Record<'a>> {
+        if data.len() < RECORD_HEADER {
+            return None;
+        }
+        let pid = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+        let level = match data[4] {
+            1 => Some(log::Level::Error),
+            2 => Some(log::Level::Warn),
+            3 => Some(log::Level::Info),
+            4 => Some(log::Level::Debug),
+            5 => Some(log::Level::Trace),
+            _ => None,
+        };
+        let pair_count = data[5] as usize;
+        let mut rest = &data[RECORD_HEADER..];
+        let target = take_str(&mut rest)?;
+        let message = take_str(&mut rest)?;
+        Some(Record {
+            pid,
+            level,
+            target,
+            message,
+            pair_count,
+            pairs: rest,
+        })
+    }
+
+    /// Iterate over the key-value pairs attached to this record
+    pub fn pairs(&self) -> Pairs<'a> {
+        Pairs {
+            remaining: self.pair_count,
+            data: self.pairs,
+        }
+    }
+}
+
+pub struct Pairs<'a> {
+    remaining: usize,
+    data: &'a [u8],
+}
+
+impl<'a> Iterator for Pairs<'a> {
+    type Item = (&'a str, &'a str);
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let key = take_str(&mut self.data)?;
+        let value = take_str(&mut self.data)?;
+        Some((key, value))
+    }
+}
+
+impl<'a> fmt::Display for Record<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.level {
+            Some(level) => write!(f, "{}", level)?,
+            None => write!(f, "?")?,
+        }
+        write!(f, " PID{} {}: {}", self.pid, self.target, self.message)?;
+        for (key, value) in self.pairs() {
+            write!(f, " {}={}", key, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// CRC-16/X25, as used for the tags in the boot image
+pub fn crc16(data: &[u8], mut crc: u16) -> u16 {
+    for byte in data {
+        crc ^= *byte as u16;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0x8408 } else { crc >> 1 };
+        }
+    }
+    crc
+}
+
+/// Write `record` as a binary frame, using `put` to emit each piece
+pub fn write_frame<F>(timestamp: u64, record: &[u8], mut put: F)
+where
+    F: FnMut(&[u8]),
+{
+    let ts = timestamp.to_le_bytes();
+    let len = (ts.len() + record.len()) as u16;
+    let crc = !crc16(record, crc16(&ts, 0xffff));
+    put(&FRAME_MAGIC);
+    put(&len.to_le_bytes());
+    put(&ts);
+    put(record);
+    put(&crc.to_le_bytes());
+}
+
+/// What `scan_frame()` found at the start of some console output
+#[derive(Debug, PartialEq)]
+pub enum Scanned<'a> {
+    /// This many bytes of ordinary text come before the next frame
+    Text(usize),
+
+    /// A frame `len` bytes long whose CRC checks out
+    Frame {
+        timestamp: u64,
+        record: &'a [u8],
+        len: usize,
+    },
+
+    /// A frame that is damaged.  Drop this many bytes, which reaches the
+    /// next magic if there is one in the frame, and scan again.
+    Corrupt(usize),
+
+    /// There aren't enough bytes yet to tell
+    Incomplete,
+}
+
+/// Look for a frame at the start of `data`, which is console output that
+/// may have text and frames mixed together.
+pub fn scan_frame(data: &[u8]) -> Scanned<'_> {
+    match data.windows(2).position(|w| w == FRAME_MAGIC) {
+        Some(0) => (),
+        Some(start) => return Scanned::Text(start),
+        // A magic byte at the very end may be the start of a frame
+        None if data.last() == Some(&FRAME_MAGIC[0]) => {
+            return match data.len() - 1 {
+                0 => Scanned::Incomplete,
+                text => Scanned::Text(text),
+            };
+        }
+        None if data.is_empty() => return Scanned::Incomplete,
+        None => return Scanned::Text(data.len()),
+    }
+    if data.len() < FRAME_HEADER {
+        return Scanned::Incomplete;
+    }
+
+    // The length itself may be what was damaged, so if a frame turns out
+    // to be bad, the next one could start anywhere inside it.
+    let body_len = u16::from_le_bytes([data[2], data[3]]) as usize;
+    let len = FRAME_HEADER + body_len + FRAME_CRC;
+    let resync = |end: usize| {
+        data[1..end.min(data.len())]
+            .windows(2)
+            .position(|w| w == FRAME_MAGIC)
+            .map_or(end.min(data.len()), |next| next + 1)
+    };
+    if !(TIMESTAMP_SIZE..=TIMESTAMP_SIZE + MAX_RECORD).contains(&body_len) {
+        return Scanned::Corrupt(resync(FRAME_HEADER));
+    }
+    if data.len() < len {
+        return Scanned::Incomplete;
+    }
+
+    let body = &data[FRAME_HEADER..FRAME_HEADER + body_len];
+    let crc = u16::from_le_bytes([data[len - 2], data[len - 1]]);
+    if !crc16(body, 0xffff) != crc {
+        return Scanned::Corrupt(resync(len));
+    }
+    let mut timestamp = [0u8; TIMESTAMP_SIZE];
+    timestamp.copy_from_slice(&body[..TIMESTAMP_SIZE]);
+    Scanned::Frame {
+        timestamp: u64::from_le_bytes(timestamp),
+        record: &body[TIMESTAMP_SIZE..],
+        len,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::fmt::Write;
+
+    fn sample_record(buf: &mut [u8]) -> usize {
+        let mut writer = RecordWriter::new(buf, 7, log::Level::Warn);
+        writer.str("shell");
+        writer.begin_str();
+        write!(writer, "battery at {}%", 12).unwrap();
+        writer.str("cell");
+        writer.str("2");
+        writer.finish_pair();
+        writer.as_bytes().len()
+    }
+
+    fn frame(timestamp: u64, record: &[u8]) -> Vec<u8> {
+        let mut out = vec![];
+        write_frame(timestamp, record, |b| out.extend_from_slice(b));
+        out
+    }
+
+    #[test]
+    fn crc_check_value() {
+        // The standard check value for CRC-16/X25
+        assert_eq!(!crc16(b"123456789", 0xffff), 0x906e);
+    }
+
+    #[test]
+    fn record_round_trip() {
+        let mut buf = [0u8; 64];
+        let len = sample_record(&mut buf);
+        let framed = frame(0x1234_5678_9abc, &buf[..len]);
+
+        let (timestamp, record) = match scan_frame(&framed) {
+            Scanned::Frame {
+                timestamp,
+                record,
+                len,
+            } => {
+                assert_eq!(len, framed.len());
+                (timestamp, record)
+            }
+            other => panic!("expected a frame, got {:?}", other),
+        };
+        assert_eq!(timestamp, 0x1234_5678_9abc);
+        let record = Record::parse(record).unwrap();
+        assert_eq!(record.pid, 7);
+        assert_eq!(record.level, Some(log::Level::Warn));
+        assert_eq!(record.target, "shell");
+        assert_eq!(record.message, "battery at 12%");
+        assert_eq!(record.pairs().collect::<Vec<_>>(), [("cell", "2")]);
+    }
+
+    #[test]
+    fn truncated_record() {
+        // Strings that don't fit are cut short, but the record still parses
+        let mut buf = [0u8; 16];
+        let len = sample_record(&mut buf);
+        let record = Record::parse(&buf[..len]).unwrap();
+        assert_eq!(record.target, "shell");
+        assert_eq!(record.message, "b");
+        assert_eq!(record.pairs().count(), 0);
+    }
+
+    #[test]
+    fn text_and_partial_frames() {
+        let mut buf = [0u8; 64];
+        let len = sample_record(&mut buf);
+        let framed = frame(1, &buf[..len]);
+
+        let mut stream = b"boot ok\n".to_vec();
+        stream.extend_from_slice(&framed);
+        assert_eq!(scan_frame(&stream), Scanned::Text(8));
+        assert_eq!(scan_frame(&[]), Scanned::Incomplete);
+        assert_eq!(scan_frame(b"ok\xa5"), Scanned::Text(2));
+        assert_eq!(scan_frame(&FRAME_MAGIC[..1]), Scanned::Incomplete);
+        assert_eq!(scan_frame(&framed[..framed.len() - 1]), Scanned::Incomplete);
+    }
+
+    #[test]
+    fn corrupted_byte_resyncs() {
+        let mut buf = [0u8; 64];
+        let len = sample_record(&mut buf);
+        let first = frame(1, &buf[..len]);
+        let second = frame(2, &buf[..len]);
+
+        // Every byte of the body is covered by the CRC
+        for index in FRAME_HEADER..first.len() {
+            let mut damaged = first.clone();
+            damaged[index] ^= 0x10;
+            assert_eq!(scan_frame(&damaged), Scanned::Corrupt(first.len()), "byte {}", index);
+        }
+
+        // A bad length can swallow the frame that follows, so scanning
+        // picks up again at the next magic rather than after the claimed
+        // length.
+        let mut stream = first.clone();
+        stream[2] = stream[2].wrapping_add(4);
+        stream.truncate(first.len() - 2);
+        stream.extend_from_slice(&second);
+        stream.extend_from_slice(&[0; 8]);
+        let skip = match scan_frame(&stream) {
+            Scanned::Corrupt(skip) => skip,
+            other => panic!("expected a corrupt frame, got {:?}", other),
+        };
+        assert_eq!(skip, first.len() - 2);
+        match scan_frame(&stream[skip..]) {
+            Scanned::Frame { timestamp, len, .. } => {
+                assert_eq!(timestamp, 2);
+                assert_eq!(len, second.len());
+            }
+            other => panic!("expected a frame, got {:?}", other),
+        }
+
+        // A length that can't be right is dropped without waiting for it
+        let mut huge = first.clone();
+        huge[3] = 0xff;
+        assert_eq!(scan_frame(&huge), Scanned::Corrupt(FRAME_HEADER));
+    }
+}
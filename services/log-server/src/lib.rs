@@ -1,7 +1,12 @@
 #![cfg_attr(target_os = "none", no_std)]
 use core::fmt::Write;
 use core::sync::atomic::{AtomicBool, Ordering};
-use xous::String;
+
+pub mod frame;
+
+/// The protocol this server speaks.  Bump the version when the way log
+/// lines or records are sent changes.
+pub const PROTOCOL: xous::Protocol = xous::Protocol::new(1, 0);
 
 static XOUS_LOGGER: XousLogger = XousLogger {
     locked: AtomicBool::new(false),
@@ -15,21 +20,47 @@ static mut XOUS_LOGGER_BACKING: XousLoggerBacking = XousLoggerBacking {
     conn: 0,
     initialized: false,
     buffer: None,
+    pid: 0,
 };
 
 struct XousLoggerBacking {
     conn: xous::CID,
-    buffer: Option<String<'static>>,
+    buffer: Option<xous::MemoryRange>,
+    pid: u32,
     initialized: bool,
 }
 
+/// Adds each key-value pair attached to a log record to the record
+/// being built.
+struct PairWriter<'a, 'b>(&'a mut frame::RecordWriter<'b>);
+
+impl<'kvs, 'a, 'b> log::kv::Visitor<'kvs> for PairWriter<'a, 'b> {
+    fn visit_pair(
+        &mut self,
+        key: log::kv::Key<'kvs>,
+        value: log::kv::Value<'kvs>,
+    ) -> Result<(), log::kv::Error> {
+        self.0.str(key.as_str());
+        self.0.begin_str();
+        write!(self.0, "{}", value).ok();
+        self.0.finish_pair();
+        Ok(())
+    }
+}
+
 impl XousLoggerBacking {
     fn init(&mut self) -> Result<(), xous::Error> {
         if self.initialized {
             return Ok(());
         }
         self.conn = xous::connect(xous::SID::from_bytes(b"xous-log-server ").unwrap())?;
-        self.buffer = Some(String::new(4096));
+        self.buffer = Some(xous::map_memory(
+            None,
+            None,
+            frame::MAX_RECORD,
+            xous::MemoryFlags::R | xous::MemoryFlags::W,
+        )?);
+        self.pid = xous::process_id().map(|pid| pid.get() as u32).unwrap_or(0);
         self.initialized = true;
         Ok(())
     }
@@ -38,10 +69,21 @@ impl XousLoggerBacking {
         if !self.initialized && self.init().is_err() {
             return;
         }
-        if let Some(ref mut buf) = self.buffer {
-            buf.clear();
-            write!(buf, "{} - {}", record.level(), record.args()).unwrap();
-            buf.lend(self.conn, 1).unwrap();
+        if let Some(buffer) = self.buffer {
+            let buf = unsafe { core::slice::from_raw_parts_mut(buffer.as_mut_ptr(), buffer.len()) };
+            let mut writer = frame::RecordWriter::new(buf, self.pid, record.level());
+            writer.str(record.target());
+            writer.begin_str();
+            write!(writer, "{}", record.args()).ok();
+            record.key_values().visit(&mut PairWriter(&mut writer)).ok();
+
+            let msg = xous::MemoryMessage {
+                id: frame::LOG_RECORD,
+                buf: buffer,
+                offset: None,
+                valid: xous::MemorySize::new(writer.as_bytes().len()),
+            };
+            xous::send_message(self.conn, xous::Message::Borrow(msg)).unwrap();
         }
     }
 }
@@ -74,6 +116,23 @@ pub fn init() -> Result<(), log::SetLoggerError> {
     Ok(())
 }
 
+/// Choose whether the log server writes plain text lines (the default) or
+/// binary frames, which can be decoded on the host with `log-decode`.
+pub fn set_binary_output(binary: bool) -> Result<(), xous::Error> {
+    let conn = xous::connect(xous::SID::from_bytes(b"xous-log-server ").unwrap())?;
+    xous::send_message(
+        conn,
+        xous::Message::Scalar(xous::ScalarMessage {
+            id: frame::SET_OUTPUT_FORMAT,
+            arg1: binary as usize,
+            arg2: 0,
+            arg3: 0,
+            arg4: 0,
+        }),
+    )
+    .map(|_| ())
+}
+
 pub fn init_wait() -> Result<(), log::SetLoggerError> {
     log::set_logger(&XOUS_LOGGER)?;
     log::set_max_level(log::LevelFilter::Info);
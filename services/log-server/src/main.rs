@@ -6,6 +6,7 @@
 mod debug;
 
 use core::fmt::Write;
+use log_server::frame;
 use xous::String;
 
 #[cfg(not(target_os = "none"))]
@@ -16,6 +17,7 @@ mod implementation {
 
     enum ControlMessage {
         Text(String),
+        Bytes(Vec<u8>),
         Exit,
     }
 
@@ -48,6 +50,10 @@ mod implementation {
                             // self.window.as_ref().unwrap().printw(s);
                             // self.window.as_ref().unwrap().refresh();
                         }
+                        ControlMessage::Bytes(b) => {
+                            use std::io::Write;
+                            std::io::stdout().write_all(&b).unwrap();
+                        }
                     },
                     Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {
                         // Calling `getch` refreshes the screen
@@ -84,6 +90,13 @@ mod implementation {
         tx: Sender<ControlMessage>,
     }
 
+    impl OutputWriter {
+        /// Write raw bytes, such as a binary log frame
+        pub fn write_bytes(&mut self, bytes: &[u8]) {
+            self.tx.send(ControlMessage::Bytes(bytes.to_vec())).unwrap();
+        }
+    }
+
     impl Write for OutputWriter {
         fn write_str(&mut self, s: &str) -> Result<(), Error> {
             // It would be nice if this worked with &str
@@ -184,6 +197,13 @@ mod implementation {
             while uart_csr.r(utra::uart::TXFULL) != 0 {}
             uart_csr.wo(utra::uart::RXTX, c as u32);
         }
+
+        /// Write raw bytes, such as a binary log frame
+        pub fn write_bytes(&mut self, bytes: &[u8]) {
+            for c in bytes {
+                self.putc(*c);
+            }
+        }
     }
 
     impl Write for OutputWriter {
@@ -206,9 +226,15 @@ fn reader_thread(mut output: implementation::OutputWriter) {
     let server_addr = xous::create_server(b"xous-log-server ").unwrap();
     writeln!(output, "LOG: Server listening on address {:?}", server_addr).unwrap();
 
+    // When set, structured records are written as binary frames rather
+    // than as text, and the periodic counter message is suppressed.
+    let mut binary_output = false;
+
+    let mut monitor = health::Monitor::new();
+
     let mut counter: usize = 0;
     loop {
-        if counter.trailing_zeros() >= 12 {
+        if counter.trailing_zeros() >= 12 && !binary_output {
             writeln!(output, "LOG: Counter tick: {}", counter).unwrap();
         }
         counter += 1;
@@ -217,7 +243,17 @@ fn reader_thread(mut output: implementation::OutputWriter) {
             xous::syscall::receive_message(server_addr).expect("couldn't get address");
         let sender = envelope.sender;
         // writeln!(output, "LOG: Got message envelope: {:?}", envelope).unwrap();
+        let health_check = matches!(
+            &envelope.body,
+            xous::Message::MutableBorrow(msg) if msg.id == health::GET_HEALTH
+        );
+        if !health_check {
+            monitor.activity();
+        }
         match &mut envelope.body {
+            xous::Message::Scalar(msg) if msg.id == frame::SET_OUTPUT_FORMAT => {
+                binary_output = msg.arg1 != 0;
+            }
             xous::Message::Scalar(msg) => {
                 writeln!(
                     output,
@@ -245,14 +281,37 @@ fn reader_thread(mut output: implementation::OutputWriter) {
                         .unwrap()
                     })
                     .or_else(|e| {
+                        monitor.error();
                         writeln!(output, "LOG: unable to convert Move message to str: {}", e)
                     })
                     .ok();
             }
+            xous::Message::Borrow(msg) if msg.id == frame::LOG_RECORD => {
+                let data = unsafe {
+                    core::slice::from_raw_parts(
+                        msg.buf.as_ptr(),
+                        msg.valid.map(|v| v.get()).unwrap_or(0).min(msg.buf.len()),
+                    )
+                };
+                // A record longer than a page wouldn't be picked back out
+                // of a frame, so it counts as malformed.
+                match frame::Record::parse(data).filter(|_| data.len() <= frame::MAX_RECORD) {
+                    Some(_) if binary_output => {
+                        let timestamp = xous::idle_stats().map(|s| s.total).unwrap_or(0);
+                        frame::write_frame(timestamp, data, |b| output.write_bytes(b));
+                    }
+                    Some(record) => writeln!(output, "{}", record).unwrap(),
+                    None => {
+                        monitor.error();
+                        writeln!(output, "LOG: malformed log record from {}", sender).unwrap()
+                    }
+                }
+            }
             xous::Message::Borrow(msg) => {
                 String::from_message(msg)
                     .map(|log_entry| writeln!(output, "{}", log_entry).unwrap())
                     .or_else(|e| {
+                        monitor.error();
                         writeln!(
                             output,
                             "LOG: unable to convert Borrow message to str: {}",
@@ -261,6 +320,12 @@ fn reader_thread(mut output: implementation::OutputWriter) {
                     })
                     .ok();
             }
+            xous::Message::MutableBorrow(msg) if msg.id == health::GET_HEALTH => {
+                monitor.reply(msg.buf, server_addr);
+            }
+            xous::Message::MutableBorrow(msg) if msg.id == xous::GET_PROTOCOL => {
+                xous::answer_protocol(msg.buf, log_server::PROTOCOL);
+            }
             xous::Message::MutableBorrow(msg) => {
                 String::from_message(msg)
                     .map(|mut log_entry| {
@@ -275,6 +340,7 @@ fn reader_thread(mut output: implementation::OutputWriter) {
                         writeln!(log_entry, " << HELLO FROM THE SERVER").unwrap();
                     })
                     .or_else(|e| {
+                        monitor.error();
                         writeln!(
                             output,
                             "LOG: unable to convert MutableBorrow message to str: {}",
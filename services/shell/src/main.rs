@@ -6,6 +6,7 @@
 mod debug;
 
 mod logstr;
+mod status;
 mod timer;
 use core::fmt::Write;
 use log::{error, info};
@@ -124,9 +125,14 @@ fn shell_main() -> ! {
     let log_server_id = xous::SID::from_bytes(b"xous-log-server ").unwrap();
 
     println!("SHELL: Attempting to connect to servers...");
-    let log_conn = xous::connect(log_server_id).unwrap();
-    let graphics_conn = xous::connect(graphics_server_id).unwrap();
-    let ticktimer_conn = xous::connect(ticktimer_server_id).unwrap();
+    let (log_conn, _) = xous::connect_checked(log_server_id, log_server::PROTOCOL)
+        .expect("SHELL: log server speaks an incompatible protocol");
+    let (graphics_conn, _) =
+        xous::connect_checked(graphics_server_id, graphics_server::api::PROTOCOL)
+            .expect("SHELL: graphics server speaks an incompatible protocol");
+    let (ticktimer_conn, _) =
+        xous::connect_checked(ticktimer_server_id, ticktimer_server::api::PROTOCOL)
+            .expect("SHELL: ticktimer server speaks an incompatible protocol");
 
     println!(
         "SHELL: Connected to Log server: {}  Graphics server: {}  Ticktimer server: {}",
@@ -143,6 +149,12 @@ fn shell_main() -> ! {
         "SHELL: graphics and ticktimer connections are the same!"
     );
 
+    #[cfg(feature = "bench")]
+    {
+        let bench_conn = xous::connect(xous::SID::from_bytes(bench::SERVER_NAME).unwrap()).unwrap();
+        bench::run_all(bench_conn, ticktimer_conn).expect("couldn't run benchmarks");
+    }
+
     let screensize = graphics_server::screen_size(graphics_conn).expect("Couldn't get screen size");
 
     // let mut counter: usize = 0;
@@ -176,6 +188,13 @@ fn shell_main() -> ! {
     )
     .expect("unable to draw to screen: {:?}");
 
+    let mut servers = [
+        status::Server::new("log", log_conn),
+        status::Server::new("graphics", graphics_conn),
+        status::Server::new("ticktimer", ticktimer_conn),
+    ];
+    let mut last_status: u64 = 0;
+
     let mut last_time: u64 = 0;
     ticktimer_server::reset(ticktimer_conn).unwrap();
     let mut string_buffer = String::new(4096);
@@ -207,6 +226,16 @@ fn shell_main() -> ! {
 
                 xous::syscall::send_message(log_conn, xous::Message::MutableBorrow(mm))
                         .expect("couldn't send memory message");
+
+                // Only available when the kernel has `stack-watermark`
+                if let Ok(stack) = xous::syscall::stack_usage(0) {
+                    info!("SHELL: stack used {} of {} bytes", stack.used, stack.size);
+                }
+
+                if elapsed_time - last_status >= status::INTERVAL_MS {
+                    last_status = elapsed_time;
+                    status::check(&mut servers);
+                }
             }
         } else {
             error!("error requesting ticktimer!")
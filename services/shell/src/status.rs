@@ -0,0 +1,56 @@
+//! Periodic health checks of the servers the shell talks to.
+
+use health::{Anomaly, Health};
+use log::{info, warn};
+
+/// How often to check on servers, in milliseconds
+pub const INTERVAL_MS: u64 = 5000;
+
+/// A server being watched, along with what it said last time
+pub struct Server {
+    name: &'static str,
+    cid: xous::CID,
+    last: Option<Health>,
+}
+
+impl Server {
+    pub fn new(name: &'static str, cid: xous::CID) -> Server {
+        Server {
+            name,
+            cid,
+            last: None,
+        }
+    }
+}
+
+/// Ask every server how it is doing, and log any that look unwell.  Returns
+/// the number of servers that were flagged.
+pub fn check(servers: &mut [Server]) -> usize {
+    let mut flagged = 0;
+    for server in servers.iter_mut() {
+        let current = match health::query(server.cid) {
+            Ok(current) => current,
+            Err(e) => {
+                warn!("STATUS: {} did not answer a health check: {:?}", server.name, e);
+                flagged += 1;
+                continue;
+            }
+        };
+        match server.last.and_then(|last| current.anomaly(&last)) {
+            Some(Anomaly::Errors(count)) => {
+                warn!("STATUS: {} reported {} new errors", server.name, count);
+                flagged += 1;
+            }
+            Some(Anomaly::Backlog(depth)) => {
+                warn!("STATUS: {} has {} requests waiting", server.name, depth);
+                flagged += 1;
+            }
+            None => (),
+        }
+        server.last = Some(current);
+    }
+    if flagged == 0 {
+        info!("STATUS: all {} servers healthy", servers.len());
+    }
+    flagged
+}
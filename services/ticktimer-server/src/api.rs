@@ -1,4 +1,8 @@
-use xous::{Message, ScalarMessage};
+use xous::{MemoryMessage, MemoryRange, Message, ScalarMessage};
+
+/// Sent in both words of the `WallClockMs` reply when the wall clock has
+/// not been set.  Every real time is far below `u32::MAX << 32` ms.
+pub const WALL_CLOCK_UNSET: usize = usize::MAX;
 
 #[derive(Debug)]
 pub enum Opcode {
@@ -13,6 +17,106 @@ pub enum Opcode {
 
     /// Recalculate the sleep time
     RecalculateSleep,
+
+    /// Set the wall clock, in milliseconds since the Unix epoch (UTC)
+    SetWallClock(u64),
+
+    /// Get the wall clock in milliseconds since the Unix epoch, as the low
+    /// and high words of a `Scalar2`.  Both words are `WALL_CLOCK_UNSET`
+    /// if the clock has not been set.
+    WallClockMs,
+
+    /// Set the offset of local time from UTC, in seconds
+    SetUtcOffset(i32),
+
+    /// Get the offset of local time from UTC, in seconds
+    UtcOffset,
+
+    /// Fill in a `health::Health` record
+    GetHealth(MemoryRange),
+
+    /// Add a process' trace events to the merged trace.  The buffer holds a
+    /// `TraceHeader` followed by `TraceEvent`s.
+    SubmitTrace(MemoryRange),
+
+    /// Log the merged trace as Chrome trace-event JSON and clear it
+    DumpTrace,
+
+    /// Fill in an `xous::Protocol` record with `PROTOCOL`
+    GetProtocol(MemoryRange),
+}
+
+/// The server keeps a wall clock and a UTC offset
+pub const FEATURE_WALL_CLOCK: u32 = 1 << 0;
+
+/// The server merges and dumps trace events
+pub const FEATURE_TRACE: u32 = 1 << 1;
+
+/// The protocol this server speaks.  Bump the version when an existing
+/// opcode changes, and add a feature bit when one is added.
+pub const PROTOCOL: xous::Protocol = xous::Protocol::new(1, FEATURE_WALL_CLOCK | FEATURE_TRACE);
+
+/// Size of an encoded `TraceHeader`
+pub const TRACE_HEADER_SIZE: usize = 8;
+
+/// Size of an encoded `TraceEvent`
+pub const TRACE_EVENT_SIZE: usize = 12;
+
+/// Start of a buffer of trace events submitted by one process
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TraceHeader {
+    pub pid: u32,
+    pub count: u32,
+}
+
+impl TraceHeader {
+    // Only used by clients, and the server also includes this file
+    #[allow(dead_code)]
+    pub fn to_bytes(self) -> [u8; TRACE_HEADER_SIZE] {
+        let mut bytes = [0u8; TRACE_HEADER_SIZE];
+        bytes[0..4].copy_from_slice(&self.pid.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.count.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<TraceHeader> {
+        if bytes.len() < TRACE_HEADER_SIZE {
+            return None;
+        }
+        Some(TraceHeader {
+            pid: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            count: u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+        })
+    }
+}
+
+/// One tracepoint hit
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TraceEvent {
+    pub id: u32,
+    pub timestamp: u64,
+}
+
+impl TraceEvent {
+    #[allow(dead_code)]
+    pub fn to_bytes(self) -> [u8; TRACE_EVENT_SIZE] {
+        let mut bytes = [0u8; TRACE_EVENT_SIZE];
+        bytes[0..4].copy_from_slice(&self.id.to_le_bytes());
+        bytes[4..12].copy_from_slice(&self.timestamp.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<TraceEvent> {
+        if bytes.len() < TRACE_EVENT_SIZE {
+            return None;
+        }
+        let mut timestamp = [0u8; 8];
+        timestamp.copy_from_slice(&bytes[4..12]);
+        Some(TraceEvent {
+            id: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            timestamp: u64::from_le_bytes(timestamp),
+        })
+    }
 }
 
 impl<'a> core::convert::TryFrom<&'a Message> for Opcode {
@@ -21,12 +125,28 @@ impl<'a> core::convert::TryFrom<&'a Message> for Opcode {
         match message {
             Message::Scalar(m) => match m.id {
                 1 => Ok(Opcode::Reset),
+                5 => Ok(Opcode::SetWallClock(
+                    m.arg1 as u64 | ((m.arg2 as u64) << 32),
+                )),
+                7 => Ok(Opcode::SetUtcOffset(m.arg1 as i32)),
+                10 => Ok(Opcode::DumpTrace),
                 131072 => Ok(Opcode::RecalculateSleep),
                 _ => Err("unrecognized opcode"),
             },
             Message::BlockingScalar(m) => match m.id {
                 4919 => Ok(Opcode::ElapsedMs),
                 3 => Ok(Opcode::SleepMs(m.arg1)),
+                6 => Ok(Opcode::WallClockMs),
+                8 => Ok(Opcode::UtcOffset),
+                _ => Err("unrecognized opcode"),
+            },
+            Message::MutableBorrow(m) => match m.id {
+                health::GET_HEALTH => Ok(Opcode::GetHealth(m.buf)),
+                xous::GET_PROTOCOL => Ok(Opcode::GetProtocol(m.buf)),
+                _ => Err("unrecognized opcode"),
+            },
+            Message::Borrow(m) => match m.id {
+                9 => Ok(Opcode::SubmitTrace(m.buf)),
                 _ => Err("unrecognized opcode"),
             },
             _ => Err("unhandled message type"),
@@ -65,6 +185,80 @@ impl Into<Message> for Opcode {
                 arg3: 0,
                 arg4: 0,
             }),
+            Opcode::SetWallClock(ms) => Message::Scalar(ScalarMessage {
+                id: 5,
+                arg1: (ms & 0xFFFF_FFFF) as usize,
+                arg2: (ms >> 32) as usize,
+                arg3: 0,
+                arg4: 0,
+            }),
+            Opcode::WallClockMs => Message::BlockingScalar(ScalarMessage {
+                id: 6,
+                arg1: 0,
+                arg2: 0,
+                arg3: 0,
+                arg4: 0,
+            }),
+            Opcode::SetUtcOffset(seconds) => Message::Scalar(ScalarMessage {
+                id: 7,
+                arg1: seconds as usize,
+                arg2: 0,
+                arg3: 0,
+                arg4: 0,
+            }),
+            Opcode::UtcOffset => Message::BlockingScalar(ScalarMessage {
+                id: 8,
+                arg1: 0,
+                arg2: 0,
+                arg3: 0,
+                arg4: 0,
+            }),
+            Opcode::GetHealth(buf) => Message::MutableBorrow(MemoryMessage {
+                id: health::GET_HEALTH,
+                buf,
+                offset: None,
+                valid: None,
+            }),
+            Opcode::SubmitTrace(buf) => Message::Borrow(MemoryMessage {
+                id: 9,
+                buf,
+                offset: None,
+                valid: None,
+            }),
+            Opcode::DumpTrace => Message::Scalar(ScalarMessage {
+                id: 10,
+                arg1: 0,
+                arg2: 0,
+                arg3: 0,
+                arg4: 0,
+            }),
+            Opcode::GetProtocol(buf) => Message::MutableBorrow(MemoryMessage {
+                id: xous::GET_PROTOCOL,
+                buf,
+                offset: None,
+                valid: None,
+            }),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trace_header_round_trip() {
+        let header = TraceHeader { pid: 7, count: 0x1234_5678 };
+        assert_eq!(TraceHeader::from_bytes(&header.to_bytes()), Some(header));
+        assert_eq!(TraceHeader::from_bytes(&header.to_bytes()[..TRACE_HEADER_SIZE - 1]), None);
+    }
+
+    #[test]
+    fn trace_event_round_trip() {
+        let event = TraceEvent { id: 0xdead_beef, timestamp: 0x0102_0304_0506_0708 };
+        let bytes = event.to_bytes();
+        assert_eq!(bytes[4..12], [8, 7, 6, 5, 4, 3, 2, 1]);
+        assert_eq!(TraceEvent::from_bytes(&bytes), Some(event));
+        assert_eq!(TraceEvent::from_bytes(&bytes[..TRACE_EVENT_SIZE - 1]), None);
+    }
+}
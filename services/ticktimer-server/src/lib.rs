@@ -1,6 +1,8 @@
 #![cfg_attr(target_os = "none", no_std)]
 
 pub mod api;
+pub mod time;
+pub mod trace;
 
 use xous::{send_message, Error, CID};
 
@@ -12,6 +12,7 @@ use core::convert::TryFrom;
 
 use heapless::binary_heap::{BinaryHeap, Min};
 use heapless::consts::*;
+use heapless::Vec;
 
 use log::{error, info};
 
@@ -262,6 +263,83 @@ fn recalculate_sleep(
     }
 }
 
+/// A trace event, along with the process that recorded it
+#[derive(Debug, Copy, Clone)]
+struct MergedEvent {
+    pid: u32,
+    event: api::TraceEvent,
+}
+
+/// Converts trace timestamps to the microseconds that the trace-event
+/// format uses.  Hosted processes already record microseconds.  On
+/// hardware the rate of the cycle counter is worked out by comparing how
+/// far it and the ticktimer have moved since the server started.
+struct TraceClock {
+    start_ms: u64,
+    start_ticks: u64,
+}
+
+impl TraceClock {
+    fn new(ticktimer: &XousTickTimer) -> TraceClock {
+        TraceClock {
+            start_ms: ticktimer.elapsed_ms(),
+            start_ticks: xous::timestamp().unwrap_or(0),
+        }
+    }
+
+    /// Return a function that turns a timestamp into microseconds
+    fn to_us(&self, ticktimer: &XousTickTimer) -> impl Fn(u64) -> u64 {
+        let ms = ticktimer.elapsed_ms().saturating_sub(self.start_ms);
+        let ticks = xous::timestamp().unwrap_or(0).saturating_sub(self.start_ticks);
+        let ticks_per_ms = if ms == 0 || ticks == 0 { 1000 } else { ticks / ms };
+        move |timestamp| timestamp * 1000 / ticks_per_ms.max(1)
+    }
+}
+
+/// Log the merged trace in the trace-event JSON array format.  That format
+/// lets the closing bracket be left off, so every event ends in a comma and
+/// a log that is cut short still loads.
+fn dump_trace(events: &mut Vec<MergedEvent, U256>, dropped: u32, to_us: impl Fn(u64) -> u64) {
+    info!("TRACE: [");
+    for line in trace_lines(events, &to_us) {
+        info!("TRACE: {}", line);
+    }
+    if dropped > 0 {
+        error!("TickTimer: {} trace events didn't fit and were dropped", dropped);
+    }
+    events.clear();
+}
+
+/// Sort `events` by time and return the trace-event JSON for each of them
+fn trace_lines<'a>(
+    events: &'a mut Vec<MergedEvent, U256>,
+    to_us: &'a impl Fn(u64) -> u64,
+) -> impl Iterator<Item = TraceLine> + 'a {
+    events.sort_unstable_by_key(|e| e.event.timestamp);
+    events.iter().map(move |e| TraceLine {
+        id: e.event.id,
+        pid: e.pid,
+        us: to_us(e.event.timestamp),
+    })
+}
+
+/// One instant event in the trace-event JSON format, with its trailing comma
+struct TraceLine {
+    id: u32,
+    pid: u32,
+    us: u64,
+}
+
+impl core::fmt::Display for TraceLine {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{{\"name\":\"{:#010x}\",\"ph\":\"i\",\"s\":\"p\",\"pid\":{},\"tid\":0,\"ts\":{}}},",
+            self.id, self.pid, self.us
+        )
+    }
+}
+
 #[xous::xous_main]
 fn xmain() -> ! {
     println!("Timer Init");
@@ -280,15 +358,36 @@ fn xmain() -> ! {
     // Create a new ticktimer object
     let mut ticktimer = XousTickTimer::new(ticktimer_client);
 
+    // Wall-clock time, in ms since the Unix epoch, at which `elapsed_ms()`
+    // was zero.  `None` until someone sets the clock.
+    let mut wall_clock_base: Option<u64> = None;
+
+    // Offset of local time from UTC, in seconds
+    let mut utc_offset: i32 = 0;
+
+    let mut monitor = health::Monitor::new();
+
+    // Trace events sent by every process, waiting to be dumped
+    let mut trace_events: Vec<MergedEvent, U256> = Vec::new();
+    let mut trace_dropped: u32 = 0;
+    let trace_clock = TraceClock::new(&ticktimer);
+
     loop {
         info!("TickTimer: waiting for message");
         let envelope = xous::receive_message(ticktimer_server).unwrap();
         info!("TickTimer: Message: {:?}", envelope);
         if let Ok(opcode) = Opcode::try_from(&envelope.body) {
             info!("TickTimer: Opcode: {:?}", opcode);
+            if !matches!(opcode, Opcode::GetHealth(_)) {
+                monitor.activity();
+            }
             match opcode {
                 Opcode::Reset => {
                     info!("TickTimer: reset called");
+                    // Keep the wall clock steady across the reset
+                    if let Some(base) = wall_clock_base.as_mut() {
+                        *base += ticktimer.elapsed_ms();
+                    }
                     ticktimer.reset();
                 }
                 Opcode::ElapsedMs => {
@@ -314,9 +413,84 @@ fn xmain() -> ! {
                     recalculate_sleep(&mut ticktimer, &mut sleep_heap, None);
                     info!("TickTimer: Done recalculating");
                 }
+                Opcode::SetWallClock(ms) => {
+                    info!("TickTimer: setting wall clock to {} ms", ms);
+                    wall_clock_base = Some(ms.saturating_sub(ticktimer.elapsed_ms()));
+                }
+                Opcode::WallClockMs => {
+                    let (lower, upper) = match wall_clock_base {
+                        Some(base) => {
+                            let time = base + ticktimer.elapsed_ms();
+                            ((time & 0xFFFF_FFFFu64) as usize, (time >> 32) as usize)
+                        }
+                        None => (api::WALL_CLOCK_UNSET, api::WALL_CLOCK_UNSET),
+                    };
+                    xous::return_scalar2(envelope.sender, lower, upper)
+                        .expect("TickTimer: couldn't return wall clock");
+                }
+                Opcode::SetUtcOffset(seconds) => {
+                    info!("TickTimer: setting UTC offset to {} s", seconds);
+                    utc_offset = seconds;
+                }
+                Opcode::UtcOffset => {
+                    xous::return_scalar(envelope.sender, utc_offset as usize)
+                        .expect("TickTimer: couldn't return UTC offset");
+                }
+                // Sleeping clients have already been received, so they
+                // don't count as a backlog.
+                Opcode::GetHealth(buf) => monitor.reply(buf, ticktimer_server),
+                Opcode::GetProtocol(buf) => xous::answer_protocol(buf, api::PROTOCOL),
+                Opcode::SubmitTrace(buf) => {
+                    let data = unsafe { core::slice::from_raw_parts(buf.as_ptr(), buf.len()) };
+                    if let Some(header) = api::TraceHeader::from_bytes(data) {
+                        let events = data[api::TRACE_HEADER_SIZE..]
+                            .chunks_exact(api::TRACE_EVENT_SIZE)
+                            .take(header.count as usize)
+                            .filter_map(api::TraceEvent::from_bytes);
+                        for event in events {
+                            let merged = MergedEvent { pid: header.pid, event };
+                            if trace_events.push(merged).is_err() {
+                                trace_dropped += 1;
+                            }
+                        }
+                    } else {
+                        monitor.error();
+                    }
+                }
+                Opcode::DumpTrace => {
+                    dump_trace(&mut trace_events, trace_dropped, trace_clock.to_us(&ticktimer));
+                    trace_dropped = 0;
+                }
             }
         } else {
             error!("couldn't convert opcode");
+            monitor.error();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trace_lines_are_sorted_json() {
+        let mut events: Vec<MergedEvent, U256> = Vec::new();
+        for &(pid, id, timestamp) in &[(3, 0xb, 2000), (2, 0xa, 1000), (2, 0xc, 3000)] {
+            events
+                .push(MergedEvent { pid, event: api::TraceEvent { id, timestamp } })
+                .unwrap();
         }
+        let to_us = |ticks| ticks / 10;
+        let lines: std::vec::Vec<std::string::String> =
+            trace_lines(&mut events, &to_us).map(|line| line.to_string()).collect();
+        assert_eq!(
+            lines,
+            [
+                r#"{"name":"0x0000000a","ph":"i","s":"p","pid":2,"tid":0,"ts":100},"#,
+                r#"{"name":"0x0000000b","ph":"i","s":"p","pid":3,"tid":0,"ts":200},"#,
+                r#"{"name":"0x0000000c","ph":"i","s":"p","pid":2,"tid":0,"ts":300},"#,
+            ]
+        );
     }
 }
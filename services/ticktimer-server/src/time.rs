@@ -0,0 +1,264 @@
+//! Monotonic and wall-clock time.
+//!
+//! An `Instant` counts milliseconds since boot and never goes backwards, so
+//! it is the right thing to use for timeouts and measuring intervals.  A
+//! `SystemTime` is the wall clock in UTC.  It is only available once
+//! something has told the ticktimer server what time it is, and it may jump
+//! when the clock is set again.
+
+use core::fmt;
+use core::ops::{Add, Sub};
+use core::time::Duration;
+
+use xous::{send_message, Error, CID};
+
+use crate::api::{Opcode, WALL_CLOCK_UNSET};
+
+const MS_PER_DAY: i64 = 86_400_000;
+
+/// A point on the monotonic clock
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant(u64);
+
+impl Instant {
+    pub fn now(cid: CID) -> Result<Instant, Error> {
+        crate::elapsed_ms(cid).map(Instant)
+    }
+
+    /// Time between boot and this instant
+    pub fn since_boot(&self) -> Duration {
+        Duration::from_millis(self.0)
+    }
+
+    /// Time between `earlier` and this instant, or zero if `earlier` is
+    /// actually later
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        Duration::from_millis(self.0.saturating_sub(earlier.0))
+    }
+
+    /// Time that has passed since this instant
+    pub fn elapsed(&self, cid: CID) -> Result<Duration, Error> {
+        Ok(Instant::now(cid)?.duration_since(*self))
+    }
+
+    pub fn checked_add(&self, duration: Duration) -> Option<Instant> {
+        self.0.checked_add(duration.as_millis() as u64).map(Instant)
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+    fn add(self, rhs: Duration) -> Instant {
+        self.checked_add(rhs)
+            .expect("overflow when adding duration to instant")
+    }
+}
+
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+    fn sub(self, rhs: Instant) -> Duration {
+        self.duration_since(rhs)
+    }
+}
+
+/// A point on the wall clock, in UTC
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SystemTime(u64);
+
+impl SystemTime {
+    pub const UNIX_EPOCH: SystemTime = SystemTime(0);
+
+    /// The current wall-clock time, or `None` if the clock has not been set
+    pub fn now(cid: CID) -> Result<Option<SystemTime>, Error> {
+        let response = send_message(cid, Opcode::WallClockMs.into())?;
+        if let xous::Result::Scalar2(lower, upper) = response {
+            if lower == WALL_CLOCK_UNSET && upper == WALL_CLOCK_UNSET {
+                return Ok(None);
+            }
+            Ok(Some(SystemTime(lower as u64 | ((upper as u64) << 32))))
+        } else {
+            panic!("unexpected return value: {:#?}", response);
+        }
+    }
+
+    pub fn from_unix(since_epoch: Duration) -> SystemTime {
+        SystemTime(since_epoch.as_millis() as u64)
+    }
+
+    pub fn since_unix_epoch(&self) -> Duration {
+        Duration::from_millis(self.0)
+    }
+
+    /// Break this time down into a calendar date and time, `utc_offset`
+    /// seconds away from UTC
+    pub fn to_date_time(&self, utc_offset: i32) -> DateTime {
+        DateTime::from_unix_ms(self.0 as i64 + utc_offset as i64 * 1000)
+    }
+}
+
+/// Set the wall clock.  Existing `Instant`s are unaffected.
+pub fn set_system_time(cid: CID, time: SystemTime) -> Result<(), Error> {
+    send_message(cid, Opcode::SetWallClock(time.0).into()).map(|_| ())
+}
+
+/// Offset of local time from UTC, in seconds
+pub fn utc_offset(cid: CID) -> Result<i32, Error> {
+    let response = send_message(cid, Opcode::UtcOffset.into())?;
+    if let xous::Result::Scalar1(offset) = response {
+        Ok(offset as i32)
+    } else {
+        panic!("unexpected return value: {:#?}", response);
+    }
+}
+
+pub fn set_utc_offset(cid: CID, seconds: i32) -> Result<(), Error> {
+    send_message(cid, Opcode::SetUtcOffset(seconds).into()).map(|_| ())
+}
+
+/// The current local date and time, or `None` if the clock has not been set
+pub fn local_now(cid: CID) -> Result<Option<DateTime>, Error> {
+    let offset = utc_offset(cid)?;
+    Ok(SystemTime::now(cid)?.map(|now| now.to_date_time(offset)))
+}
+
+/// A proleptic Gregorian calendar date and time
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: i32,
+    /// 1 - 12
+    pub month: u8,
+    /// 1 - 31
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub millisecond: u16,
+}
+
+impl DateTime {
+    /// Convert milliseconds since 1970-01-01 00:00:00
+    pub fn from_unix_ms(ms: i64) -> DateTime {
+        let days = ms.div_euclid(MS_PER_DAY);
+        let ms_of_day = ms.rem_euclid(MS_PER_DAY);
+
+        // Days since 0000-03-01, so that leap days fall at the end of the year
+        let days = days + 719_468;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days.rem_euclid(146_097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let mp = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        DateTime {
+            year: year as i32,
+            month: month as u8,
+            day: day as u8,
+            hour: (ms_of_day / 3_600_000) as u8,
+            minute: (ms_of_day / 60_000 % 60) as u8,
+            second: (ms_of_day / 1000 % 60) as u8,
+            millisecond: (ms_of_day % 1000) as u16,
+        }
+    }
+
+    /// Convert back to milliseconds since 1970-01-01 00:00:00
+    pub fn to_unix_ms(&self) -> i64 {
+        let month = self.month as i64;
+        let year = self.year as i64 - if month <= 2 { 1 } else { 0 };
+        let era = year.div_euclid(400);
+        let year_of_era = year.rem_euclid(400);
+        let mp = if month > 2 { month - 3 } else { month + 9 };
+        let day_of_year = (153 * mp + 2) / 5 + self.day as i64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * 146_097 + day_of_era - 719_468;
+
+        days * MS_PER_DAY
+            + self.hour as i64 * 3_600_000
+            + self.minute as i64 * 60_000
+            + self.second as i64 * 1000
+            + self.millisecond as i64
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u8, day: u8) -> DateTime {
+        DateTime {
+            year,
+            month,
+            day,
+            hour: 0,
+            minute: 0,
+            second: 0,
+            millisecond: 0,
+        }
+    }
+
+    #[test]
+    fn known_dates() {
+        let cases = [
+            (0, date(1970, 1, 1)),
+            (951_782_400_000, date(2000, 2, 29)),
+            (1_709_164_800_000, date(2024, 2, 29)),
+            (4_107_542_400_000, date(2100, 3, 1)),
+            (-86_400_000, date(1969, 12, 31)),
+        ];
+        for (ms, expected) in cases.iter() {
+            assert_eq!(DateTime::from_unix_ms(*ms), *expected);
+            assert_eq!(expected.to_unix_ms(), *ms);
+        }
+    }
+
+    #[test]
+    fn past_2038() {
+        // One second after a signed 32-bit time_t runs out
+        let dt = DateTime::from_unix_ms((i32::MAX as i64 + 1) * 1000);
+        assert_eq!(
+            dt,
+            DateTime {
+                hour: 3,
+                minute: 14,
+                second: 8,
+                ..date(2038, 1, 19)
+            }
+        );
+        assert_eq!(dt.to_string(), "2038-01-19 03:14:08");
+    }
+
+    #[test]
+    fn century_leap_rules() {
+        // 2000 is a leap year, 2100 is not
+        let feb_28_2000 = date(2000, 2, 28).to_unix_ms();
+        assert_eq!(DateTime::from_unix_ms(feb_28_2000 + MS_PER_DAY), date(2000, 2, 29));
+        let feb_28_2100 = date(2100, 2, 28).to_unix_ms();
+        assert_eq!(DateTime::from_unix_ms(feb_28_2100 + MS_PER_DAY), date(2100, 3, 1));
+    }
+
+    #[test]
+    fn round_trip() {
+        // Step by a little over a day so the time of day drifts too
+        let step = MS_PER_DAY + 3_723_004;
+        let end = date(2401, 1, 1).to_unix_ms();
+        let mut ms = date(1900, 1, 1).to_unix_ms();
+        while ms < end {
+            let dt = DateTime::from_unix_ms(ms);
+            assert_eq!(dt.to_unix_ms(), ms, "{} did not round trip", dt);
+            ms += step;
+        }
+    }
+}
@@ -0,0 +1,215 @@
+//! Tracepoints for following an event across services.
+//!
+//! `trace!(id)` records a 32-bit event ID and a timestamp in a ring buffer
+//! that belongs to the calling process.  Recording never blocks and never
+//! sends a message, so tracepoints are cheap enough to leave in hot paths.
+//! The ID means whatever the caller wants it to -- give the start and end
+//! of a keypress-to-render path their own IDs and the gap between them is
+//! the latency.
+//!
+//! `flush()` hands the recorded events to the ticktimer server, which
+//! merges the events from every process.  `dump()` asks it to log the
+//! merged trace as Chrome trace-event JSON, which can be loaded into
+//! `chrome://tracing` or Perfetto on the host.
+//!
+//! On hardware, timestamps are CPU cycles, read through the kernel.  Hosted
+//! processes use the host's clock, in microseconds.
+
+use core::ops::Range;
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+use xous::{send_message, Error, MemoryFlags, CID};
+
+use crate::api::{Opcode, TraceEvent, TraceHeader, TRACE_EVENT_SIZE, TRACE_HEADER_SIZE};
+
+/// How many events a process can record between flushes.  Older events
+/// are overwritten once the buffer is full.
+pub const TRACE_CAPACITY: usize = 256;
+
+struct Slot {
+    id: AtomicU32,
+    timestamp_lo: AtomicU32,
+    timestamp_hi: AtomicU32,
+}
+
+// Only used to initialise `SLOTS`, which is how arrays of atomics are built
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY: Slot = Slot {
+    id: AtomicU32::new(0),
+    timestamp_lo: AtomicU32::new(0),
+    timestamp_hi: AtomicU32::new(0),
+};
+
+/// The events a process has recorded.  Slot `n % TRACE_CAPACITY` holds
+/// the `n`th event, until event `n + TRACE_CAPACITY` overwrites it.
+struct Ring {
+    slots: [Slot; TRACE_CAPACITY],
+    /// Number of events ever recorded
+    recorded: AtomicUsize,
+    /// Number of events that have been drained or overwritten
+    flushed: AtomicUsize,
+}
+
+impl Ring {
+    const fn new() -> Ring {
+        Ring {
+            slots: [EMPTY; TRACE_CAPACITY],
+            recorded: AtomicUsize::new(0),
+            flushed: AtomicUsize::new(0),
+        }
+    }
+
+    fn record(&self, id: u32, timestamp: u64) {
+        let slot = &self.slots[self.recorded.fetch_add(1, Ordering::AcqRel) % TRACE_CAPACITY];
+        slot.id.store(id, Ordering::Relaxed);
+        slot.timestamp_lo.store(timestamp as u32, Ordering::Relaxed);
+        slot.timestamp_hi.store((timestamp >> 32) as u32, Ordering::Release);
+    }
+
+    /// The events that haven't been drained yet and are still in the ring
+    fn pending(&self) -> Range<usize> {
+        let recorded = self.recorded.load(Ordering::Acquire);
+        let first = self
+            .flushed
+            .load(Ordering::Relaxed)
+            .max(recorded.saturating_sub(TRACE_CAPACITY));
+        first..recorded
+    }
+
+    /// Encode pending events into `out`, oldest first, and forget them.
+    /// Events that don't fit are dropped.  Returns how many were encoded.
+    fn drain(&self, out: &mut [u8]) -> usize {
+        let pending = self.pending();
+        let end = pending.end;
+        let mut count = 0;
+        for (n, chunk) in pending.zip(out.chunks_exact_mut(TRACE_EVENT_SIZE)) {
+            let slot = &self.slots[n % TRACE_CAPACITY];
+            let hi = slot.timestamp_hi.load(Ordering::Acquire);
+            let event = TraceEvent {
+                id: slot.id.load(Ordering::Relaxed),
+                timestamp: slot.timestamp_lo.load(Ordering::Relaxed) as u64 | ((hi as u64) << 32),
+            };
+            chunk.copy_from_slice(&event.to_bytes());
+            count += 1;
+        }
+        self.flushed.store(end, Ordering::Relaxed);
+        count
+    }
+}
+
+static RING: Ring = Ring::new();
+
+/// Record a tracepoint.  See the `trace` module.
+#[macro_export]
+macro_rules! trace {
+    ($id:expr) => {
+        $crate::trace::record($id)
+    };
+}
+
+#[cfg(target_os = "none")]
+fn now() -> u64 {
+    xous::timestamp().unwrap_or(0)
+}
+
+#[cfg(not(target_os = "none"))]
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_micros() as u64)
+        .unwrap_or(0)
+}
+
+/// Record event `id` with the current time.  Use the `trace!` macro
+/// rather than calling this directly.
+pub fn record(id: u32) {
+    RING.record(id, now());
+}
+
+/// Send the events recorded since the last flush to the ticktimer server,
+/// and return how many there were.  Events recorded by another thread
+/// while this runs may be lost.
+pub fn flush(cid: CID) -> Result<usize, Error> {
+    if RING.pending().is_empty() {
+        return Ok(0);
+    }
+
+    let buf = xous::map_memory(None, None, 4096, MemoryFlags::R | MemoryFlags::W)?;
+    let data = unsafe { core::slice::from_raw_parts_mut(buf.as_mut_ptr(), buf.len()) };
+    let header = TraceHeader {
+        pid: xous::process_id()?.get() as u32,
+        count: RING.drain(&mut data[TRACE_HEADER_SIZE..]) as u32,
+    };
+    data[..TRACE_HEADER_SIZE].copy_from_slice(&header.to_bytes());
+
+    let result = send_message(cid, Opcode::SubmitTrace(buf).into());
+    xous::unmap_memory(buf)?;
+    result.map(|_| header.count as usize)
+}
+
+/// Ask the ticktimer server to log every event it has been sent so far as
+/// Chrome trace-event JSON, and then forget them.  Each line of the JSON is
+/// logged with a `TRACE: ` prefix.
+pub fn dump(cid: CID) -> Result<(), Error> {
+    send_message(cid, Opcode::DumpTrace.into()).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drain(ring: &Ring) -> std::vec::Vec<TraceEvent> {
+        let mut out = [0u8; TRACE_EVENT_SIZE * (TRACE_CAPACITY + 1)];
+        let count = ring.drain(&mut out);
+        out.chunks_exact(TRACE_EVENT_SIZE)
+            .take(count)
+            .map(|chunk| TraceEvent::from_bytes(chunk).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn drain_returns_each_event_once() {
+        let ring = Ring::new();
+        assert!(drain(&ring).is_empty());
+        ring.record(1, 10);
+        ring.record(2, 1 << 40);
+        assert_eq!(
+            drain(&ring),
+            [TraceEvent { id: 1, timestamp: 10 }, TraceEvent { id: 2, timestamp: 1 << 40 }]
+        );
+        assert!(drain(&ring).is_empty());
+        ring.record(3, 30);
+        assert_eq!(drain(&ring), [TraceEvent { id: 3, timestamp: 30 }]);
+    }
+
+    #[test]
+    fn full_ring_keeps_newest_events() {
+        let ring = Ring::new();
+        ring.record(0, 0);
+        assert_eq!(drain(&ring).len(), 1);
+
+        // Wrap around the end, then overwrite events that were never drained
+        let total = TRACE_CAPACITY + 10;
+        for n in 1..=total {
+            ring.record(n as u32, n as u64);
+        }
+        let events = drain(&ring);
+        assert_eq!(events.len(), TRACE_CAPACITY);
+        assert_eq!(events[0].id as usize, total + 1 - TRACE_CAPACITY);
+        assert_eq!(events[TRACE_CAPACITY - 1].id as usize, total);
+        assert!(events.windows(2).all(|pair| pair[1].id == pair[0].id + 1));
+        assert!(drain(&ring).is_empty());
+    }
+
+    #[test]
+    fn events_that_do_not_fit_are_dropped() {
+        let ring = Ring::new();
+        for n in 0..3 {
+            ring.record(n, n as u64);
+        }
+        let mut out = [0u8; TRACE_EVENT_SIZE * 2 + 1];
+        assert_eq!(ring.drain(&mut out), 2);
+        assert_eq!(TraceEvent::from_bytes(&out[TRACE_EVENT_SIZE..]).unwrap().id, 1);
+        assert!(ring.pending().is_empty());
+    }
+}
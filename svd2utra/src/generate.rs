@@ -1,14 +1,99 @@
 use quick_xml::events::Event;
 use quick_xml::Reader;
-use std::io::{BufRead, BufReader, Read, Write};
+use std::io::{BufRead, Read, Write};
 
 #[derive(Debug)]
 pub enum ParseError {
-    UnexpectedTag,
-    MissingValue,
-    ParseIntError,
-    NonUTF8,
+    /// A tag that isn't allowed where it appears
+    UnexpectedTag(String /* tag */, String /* parent */, Location),
+
+    /// The file ended before this element was closed
+    UnexpectedEof(String /* element */, Location),
+
+    /// A tag this generator can't turn into code yet
+    Unsupported(String /* tag */, String /* parent */, Location),
+
+    /// An element is missing a required child
+    MissingValue(String /* element */, String /* tag */, Location),
+
+    /// A value that should have been a number
+    ParseIntError(String /* value */, Location),
+
+    NonUTF8(Location),
+
+    /// The file is not well-formed XML
+    Xml(String /* message */, Location),
+
     WriteError,
+    ReadError,
+    Invalid(Vec<Diagnostic>),
+}
+
+/// Where in the SVD file a parse error happened. The parser only knows the
+/// byte offset, which `parse_svd()` turns into a line and column.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+    offset: usize,
+}
+
+impl Location {
+    fn at(offset: usize) -> Location {
+        Location {
+            offset,
+            ..Default::default()
+        }
+    }
+}
+
+/// A problem found while validating a parsed SVD file. `line` is the line
+/// of the offending element in the source file.
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub kind: DiagnosticKind,
+}
+
+#[derive(Debug)]
+pub enum DiagnosticKind {
+    /// The address window of this peripheral overlaps with another one
+    OverlappingPeripherals(String /* peripheral */, String /* other peripheral */),
+
+    /// Two registers within a peripheral share the same offset
+    DuplicateRegisterOffset(
+        String, /* peripheral */
+        String, /* register */
+        String, /* other register */
+        usize,  /* offset */
+    ),
+
+    /// A register starts inside an earlier register in the same peripheral
+    OverlappingRegisters(
+        String, /* peripheral */
+        String, /* register */
+        String, /* other register */
+    ),
+
+    /// A field extends past the width of its register
+    FieldTooWide(
+        String, /* register */
+        String, /* field */
+        usize,  /* msb */
+        usize,  /* register width */
+    ),
+
+    /// A field's `msb` is less than its `lsb`
+    FieldInverted(String /* register */, String /* field */),
+
+    /// Two fields would get accessors with the same name, or a field's
+    /// accessor would clash with one of the accessor struct's own methods
+    DuplicateAccessor(
+        String, /* peripheral */
+        String, /* field, as REGISTER.field */
+        String, /* accessor */
+        String, /* what already uses the name */
+    ),
 }
 
 #[derive(Default, Debug)]
@@ -16,6 +101,8 @@ pub struct Field {
     name: String,
     lsb: usize,
     msb: usize,
+    description: Option<String>,
+    line: usize,
 }
 
 #[derive(Default, Debug)]
@@ -23,7 +110,9 @@ pub struct Register {
     name: String,
     offset: usize,
     description: Option<String>,
+    size: usize,
     fields: Vec<Field>,
+    line: usize,
 }
 
 #[derive(Default, Debug)]
@@ -37,8 +126,10 @@ pub struct Peripheral {
     name: String,
     pub base: usize,
     size: usize,
+    description: Option<String>,
     interrupt: Vec<Interrupt>,
     registers: Vec<Register>,
+    line: usize,
 }
 
 #[derive(Default, Debug)]
@@ -54,21 +145,109 @@ pub struct Description {
     pub memory_regions: Vec<MemoryRegion>,
 }
 
+/// Choices that change what `generate_with()` writes
+#[derive(Default, Debug, Clone)]
+pub struct Options {
+    /// Also write a struct for each peripheral, with a getter and a setter
+    /// for every field, built on top of the `CSR` methods
+    pub accessors: bool,
+}
+
 impl core::fmt::Display for ParseError {
     fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
         use ParseError::*;
         match *self {
-            UnexpectedTag => write!(f, "unexpected XML tag encountered"),
-            MissingValue => write!(f, "XML tag should have contained a value"),
-            ParseIntError => write!(f, "unable to parse number"),
-            NonUTF8 => write!(f, "file is not UTF-8"),
+            UnexpectedTag(ref tag, ref parent, location) => {
+                write!(f, "{}: unexpected <{}> in <{}>", location, tag, parent)
+            }
+            Unsupported(ref tag, ref parent, location) => {
+                write!(f, "{}: <{}> in <{}> is not supported", location, tag, parent)
+            }
+            UnexpectedEof(ref element, location) => {
+                write!(f, "{}: file ended before <{}> was closed", location, element)
+            }
+            MissingValue(ref element, ref tag, location) => {
+                write!(f, "{}: <{}> has no <{}>", location, element, tag)
+            }
+            ParseIntError(ref value, location) => {
+                write!(f, "{}: unable to parse number {:?}", location, value)
+            }
+            NonUTF8(location) => write!(f, "{}: file is not UTF-8", location),
+            Xml(ref message, location) => write!(f, "{}: {}", location, message),
             WriteError => write!(f, "unable to write destination file"),
+            ReadError => write!(f, "unable to read source file"),
+            Invalid(ref diagnostics) => {
+                write!(f, "SVD file failed validation:")?;
+                for diagnostic in diagnostics {
+                    write!(f, "\n    {}", diagnostic)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl core::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        use DiagnosticKind::*;
+        write!(f, "line {}: ", self.line)?;
+        match self.kind {
+            OverlappingPeripherals(ref peripheral, ref other) => write!(
+                f,
+                "peripheral {} overlaps with peripheral {}",
+                peripheral, other
+            ),
+            DuplicateRegisterOffset(ref peripheral, ref register, ref other, offset) => write!(
+                f,
+                "register {}.{} has the same offset (0x{:x}) as {}",
+                peripheral, register, offset, other
+            ),
+            OverlappingRegisters(ref peripheral, ref register, ref other) => write!(
+                f,
+                "register {}.{} overlaps with register {}",
+                peripheral, register, other
+            ),
+            FieldTooWide(ref register, ref field, msb, width) => write!(
+                f,
+                "field {}.{} has msb {}, but the register is only {} bits wide",
+                register, field, msb, width
+            ),
+            FieldInverted(ref register, ref field) => {
+                write!(f, "field {}.{} has an msb lower than its lsb", register, field)
+            }
+            DuplicateAccessor(ref peripheral, ref field, ref accessor, ref other) => write!(
+                f,
+                "field {}.{} would get the accessor {}(), which is already used by {}",
+                peripheral, field, accessor, other
+            ),
         }
     }
 }
 
+impl core::fmt::Display for Location {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
 impl std::error::Error for ParseError {}
 
+impl ParseError {
+    fn location_mut(&mut self) -> Option<&mut Location> {
+        use ParseError::*;
+        match *self {
+            UnexpectedTag(_, _, ref mut location)
+            | Unsupported(_, _, ref mut location)
+            | UnexpectedEof(_, ref mut location)
+            | MissingValue(_, _, ref mut location)
+            | ParseIntError(_, ref mut location)
+            | NonUTF8(ref mut location)
+            | Xml(_, ref mut location) => Some(location),
+            WriteError | ReadError | Invalid(_) => None,
+        }
+    }
+}
+
 pub fn get_base(value: &str) -> (&str, u32) {
     if value.starts_with("0x") {
         (value.trim_start_matches("0x"), 16)
@@ -85,40 +264,88 @@ pub fn get_base(value: &str) -> (&str, u32) {
     }
 }
 
-fn parse_usize(value: &[u8]) -> Result<usize, ParseError> {
-    let value_as_str = String::from_utf8(value.to_vec()).or(Err(ParseError::NonUTF8))?;
-    let (value, base) = get_base(&value_as_str);
-    usize::from_str_radix(value, base).or(Err(ParseError::ParseIntError))
+fn unexpected_tag(tag: &[u8], parent: &str, position: usize) -> ParseError {
+    ParseError::UnexpectedTag(
+        String::from_utf8_lossy(tag).into_owned(),
+        parent.to_owned(),
+        Location::at(position),
+    )
+}
+
+fn missing_value(element: &str, tag: &str, position: usize) -> ParseError {
+    ParseError::MissingValue(element.to_owned(), tag.to_owned(), Location::at(position))
+}
+
+fn unexpected_eof(element: &str, position: usize) -> ParseError {
+    ParseError::UnexpectedEof(element.to_owned(), Location::at(position))
+}
+
+fn xml_error(error: quick_xml::Error, position: usize) -> ParseError {
+    ParseError::Xml(error.to_string(), Location::at(position))
+}
+
+fn tag_name<T: BufRead>(
+    e: &quick_xml::events::BytesStart,
+    reader: &Reader<T>,
+) -> Result<String, ParseError> {
+    e.unescape_and_decode(reader)
+        .map_err(|_| ParseError::NonUTF8(Location::at(reader.buffer_position())))
 }
 
-fn extract_contents<T: BufRead>(reader: &mut Reader<T>) -> Result<String, ParseError> {
+fn extract_contents<T: BufRead>(reader: &mut Reader<T>, tag: &str) -> Result<String, ParseError> {
     let mut buf = Vec::new();
-    let contents = reader
-        .read_event(&mut buf)
-        .map_err(|_| ParseError::UnexpectedTag)?;
-    match contents {
-        Event::Text(t) => t
-            .unescape_and_decode(reader)
-            .map_err(|_| ParseError::NonUTF8),
-        _ => Err(ParseError::UnexpectedTag),
+    let mut contents = String::new();
+    // Text may be split across several events, e.g. when a description is
+    // wrapped in a CDATA block.
+    loop {
+        let position = reader.buffer_position();
+        match reader
+            .read_event(&mut buf)
+            .map_err(|e| xml_error(e, position))?
+        {
+            Event::Text(t) => contents.push_str(
+                &t.unescape_and_decode(reader)
+                    .map_err(|_| ParseError::NonUTF8(Location::at(position)))?,
+            ),
+            Event::CData(t) => contents.push_str(
+                core::str::from_utf8(&t).map_err(|_| ParseError::NonUTF8(Location::at(position)))?,
+            ),
+            Event::End(_) => break,
+            Event::Start(ref e) | Event::Empty(ref e) => {
+                return Err(unexpected_tag(e.name(), tag, position))
+            }
+            Event::Eof => return Err(unexpected_eof(tag, position)),
+            _ => (),
+        }
+        buf.clear();
     }
+    Ok(contents)
+}
+
+fn extract_usize<T: BufRead>(reader: &mut Reader<T>, tag: &str) -> Result<usize, ParseError> {
+    let position = reader.buffer_position();
+    let value = extract_contents(reader, tag)?;
+    let (digits, base) = get_base(&value);
+    usize::from_str_radix(digits, base)
+        .map_err(|_| ParseError::ParseIntError(value, Location::at(position)))
 }
 
 fn generate_field<T: BufRead>(reader: &mut Reader<T>) -> Result<Field, ParseError> {
     let mut buf = Vec::new();
+    let position = reader.buffer_position();
     let mut name = None;
     let mut lsb = None;
     let mut msb = None;
+    let mut description = None;
     loop {
         match reader.read_event(&mut buf) {
             Ok(Event::Start(ref e)) => {
-                let tag_name = e
-                    .unescape_and_decode(reader)
-                    .map_err(|_| ParseError::NonUTF8)?;
+                let tag_name = tag_name(e, reader)?;
                 match tag_name.as_str() {
-                    "name" => name = Some(extract_contents(reader)?),
-                    "lsb" => lsb = Some(parse_usize(extract_contents(reader)?.as_bytes())?),
-                    "msb" => msb = Some(parse_usize(extract_contents(reader)?.as_bytes())?),
+                    "name" => name = Some(extract_contents(reader, &tag_name)?),
+                    "lsb" => lsb = Some(extract_usize(reader, &tag_name)?),
+                    "msb" => msb = Some(extract_usize(reader, &tag_name)?),
+                    "description" => description = Some(extract_contents(reader, &tag_name)?),
                     _ => (),
                 }
             }
@@ -127,15 +354,18 @@ fn generate_field<T: BufRead>(reader: &mut Reader<T>) -> Result<Field, ParseErro
                     break;
                 }
             }
+            Ok(Event::Eof) => return Err(unexpected_eof("field", position)),
             Ok(_) => (),
-            Err(e) => panic!("error parsing: {:?}", e),
+            Err(e) => return Err(xml_error(e, reader.buffer_position())),
         }
     }
 
     Ok(Field {
-        name: name.ok_or(ParseError::MissingValue)?,
-        lsb: lsb.ok_or(ParseError::MissingValue)?,
-        msb: msb.ok_or(ParseError::MissingValue)?,
+        name: name.ok_or_else(|| missing_value("field", "name", position))?,
+        lsb: lsb.ok_or_else(|| missing_value("field", "lsb", position))?,
+        msb: msb.ok_or_else(|| missing_value("field", "msb", position))?,
+        description,
+        line: position,
     })
 }
 
@@ -145,20 +375,17 @@ fn generate_fields<T: BufRead>(
 ) -> Result<(), ParseError> {
     let mut buf = Vec::new();
     loop {
+        let position = reader.buffer_position();
         match reader.read_event(&mut buf) {
             Ok(Event::Start(ref e)) => match e.name() {
                 b"field" => fields.push(generate_field(reader)?),
-                _ => panic!("unexpected tag in <field>: {:?}", e),
-            },
-            Ok(Event::End(ref e)) => match e.name() {
-                b"fields" => {
-                    // println!("End fields");
-                    break;
-                }
-                e => panic!("unhandled value: {:?}", e),
+                tag => return Err(unexpected_tag(tag, "fields", position)),
             },
-            Ok(Event::Text(_)) => (),
-            e => panic!("unhandled value: {:?}", e),
+            // quick-xml has already checked that this closes <fields>
+            Ok(Event::End(_)) => break,
+            Ok(Event::Eof) => return Err(unexpected_eof("fields", position)),
+            Ok(_) => (),
+            Err(e) => return Err(xml_error(e, position)),
         }
     }
     Ok(())
@@ -166,21 +393,21 @@ fn generate_fields<T: BufRead>(
 
 fn generate_register<T: BufRead>(reader: &mut Reader<T>) -> Result<Register, ParseError> {
     let mut buf = Vec::new();
+    let position = reader.buffer_position();
     let mut name = None;
     let mut offset = None;
-    let description = None;
+    let mut size = None;
+    let mut description = None;
     let mut fields = vec![];
     loop {
         match reader.read_event(&mut buf) {
             Ok(Event::Start(ref e)) => {
-                let tag_name = e
-                    .unescape_and_decode(reader)
-                    .map_err(|_| ParseError::NonUTF8)?;
+                let tag_name = tag_name(e, reader)?;
                 match tag_name.as_str() {
-                    "name" => name = Some(extract_contents(reader)?),
-                    "addressOffset" => {
-                        offset = Some(parse_usize(extract_contents(reader)?.as_bytes())?)
-                    }
+                    "name" => name = Some(extract_contents(reader, &tag_name)?),
+                    "addressOffset" => offset = Some(extract_usize(reader, &tag_name)?),
+                    "size" => size = Some(extract_usize(reader, &tag_name)?),
+                    "description" => description = Some(extract_contents(reader, &tag_name)?),
                     "fields" => generate_fields(reader, &mut fields)?,
                     _ => (),
                 }
@@ -190,16 +417,19 @@ fn generate_register<T: BufRead>(reader: &mut Reader<T>) -> Result<Register, Par
                     break;
                 }
             }
+            Ok(Event::Eof) => return Err(unexpected_eof("register", position)),
             Ok(_) => (),
-            Err(e) => panic!("error parsing: {:?}", e),
+            Err(e) => return Err(xml_error(e, reader.buffer_position())),
         }
     }
 
     Ok(Register {
-        name: name.ok_or(ParseError::MissingValue)?,
-        offset: offset.ok_or(ParseError::MissingValue)?,
+        name: name.ok_or_else(|| missing_value("register", "name", position))?,
+        offset: offset.ok_or_else(|| missing_value("register", "addressOffset", position))?,
         description,
+        size: size.unwrap_or(32),
         fields,
+        line: position,
     })
 }
 
@@ -208,19 +438,16 @@ fn generate_interrupts<T: BufRead>(
     interrupts: &mut Vec<Interrupt>,
 ) -> Result<(), ParseError> {
     let mut buf = Vec::new();
+    let position = reader.buffer_position();
     let mut name = None;
     let mut value = None;
     loop {
         match reader.read_event(&mut buf) {
             Ok(Event::Start(ref e)) => {
-                let tag_name = e
-                    .unescape_and_decode(reader)
-                    .map_err(|_| ParseError::NonUTF8)?;
+                let tag_name = tag_name(e, reader)?;
                 match tag_name.as_str() {
-                    "name" => name = Some(extract_contents(reader)?),
-                    "value" => {
-                        value = Some(parse_usize(extract_contents(reader)?.as_bytes())?)
-                    }
+                    "name" => name = Some(extract_contents(reader, &tag_name)?),
+                    "value" => value = Some(extract_usize(reader, &tag_name)?),
                     _ => (),
                 }
             }
@@ -229,39 +456,93 @@ fn generate_interrupts<T: BufRead>(
                     break;
                 }
             }
+            Ok(Event::Eof) => return Err(unexpected_eof("interrupt", position)),
             Ok(_) => (),
-            Err(e) => panic!("error parsing: {:?}", e),
+            Err(e) => return Err(xml_error(e, reader.buffer_position())),
         }
     }
 
     interrupts.push(
         Interrupt {
-            name: name.ok_or(ParseError::MissingValue)?,
-            value: value.ok_or(ParseError::MissingValue)?,
+            name: name.ok_or_else(|| missing_value("interrupt", "name", position))?,
+            value: value.ok_or_else(|| missing_value("interrupt", "value", position))?,
         });
 
     Ok(())
 }
 
+/// Clusters group registers at an offset within their peripheral. Flatten
+/// them into the peripheral's registers, prefixing each name with the name
+/// of the cluster, so that register `TX` in cluster `DMA` becomes `DMA_TX`.
+fn generate_cluster<T: BufRead>(
+    reader: &mut Reader<T>,
+    registers: &mut Vec<Register>,
+) -> Result<(), ParseError> {
+    let mut buf = Vec::new();
+    let position = reader.buffer_position();
+    let mut name = None;
+    let mut offset = None;
+    let mut members = vec![];
+    loop {
+        let tag_position = reader.buffer_position();
+        match reader.read_event(&mut buf) {
+            Ok(Event::Start(ref e)) => {
+                let tag_name = tag_name(e, reader)?;
+                match tag_name.as_str() {
+                    "name" => name = Some(extract_contents(reader, &tag_name)?),
+                    "addressOffset" => offset = Some(extract_usize(reader, &tag_name)?),
+                    "register" => members.push(generate_register(reader)?),
+                    "cluster" => generate_cluster(reader, &mut members)?,
+                    // Cluster arrays aren't expanded, and ignoring these would
+                    // quietly generate only the first element.
+                    "dim" | "dimIncrement" | "dimIndex" => {
+                        return Err(ParseError::Unsupported(
+                            tag_name,
+                            "cluster".to_owned(),
+                            Location::at(tag_position),
+                        ))
+                    }
+                    _ => (),
+                }
+            }
+            Ok(Event::End(ref e)) => {
+                if let b"cluster" = e.name() {
+                    break;
+                }
+            }
+            Ok(Event::Eof) => return Err(unexpected_eof("cluster", position)),
+            Ok(_) => (),
+            Err(e) => return Err(xml_error(e, reader.buffer_position())),
+        }
+    }
+
+    let name = name.ok_or_else(|| missing_value("cluster", "name", position))?;
+    let offset = offset.ok_or_else(|| missing_value("cluster", "addressOffset", position))?;
+    for mut register in members {
+        register.name = format!("{}_{}", name, register.name);
+        register.offset += offset;
+        registers.push(register);
+    }
+    Ok(())
+}
+
 fn generate_registers<T: BufRead>(
     reader: &mut Reader<T>,
     registers: &mut Vec<Register>,
 ) -> Result<(), ParseError> {
     let mut buf = Vec::new();
     loop {
+        let position = reader.buffer_position();
         match reader.read_event(&mut buf) {
             Ok(Event::Start(ref e)) => match e.name() {
                 b"register" => registers.push(generate_register(reader)?),
-                _ => panic!("unexpected tag in <registers>: {:?}", e),
-            },
-            Ok(Event::End(ref e)) => match e.name() {
-                b"registers" => {
-                    break;
-                }
-                e => panic!("unhandled value: {:?}", e),
+                b"cluster" => generate_cluster(reader, registers)?,
+                tag => return Err(unexpected_tag(tag, "registers", position)),
             },
-            Ok(Event::Text(_)) => (),
-            e => panic!("unhandled value: {:?}", e),
+            Ok(Event::End(_)) => break,
+            Ok(Event::Eof) => return Err(unexpected_eof("registers", position)),
+            Ok(_) => (),
+            Err(e) => return Err(xml_error(e, position)),
         }
     }
     Ok(())
@@ -269,23 +550,22 @@ fn generate_registers<T: BufRead>(
 
 fn generate_peripheral<T: BufRead>(reader: &mut Reader<T>) -> Result<Peripheral, ParseError> {
     let mut buf = Vec::new();
+    let position = reader.buffer_position();
     let mut name = None;
     let mut base = None;
     let mut size = None;
+    let mut description = None;
     let mut registers = vec![];
     let mut interrupts = vec![];
     loop {
         match reader.read_event(&mut buf) {
             Ok(Event::Start(ref e)) => {
-                let tag_name = e
-                    .unescape_and_decode(reader)
-                    .map_err(|_| ParseError::NonUTF8)?;
+                let tag_name = tag_name(e, reader)?;
                 match tag_name.as_str() {
-                    "name" => name = Some(extract_contents(reader)?),
-                    "baseAddress" => {
-                        base = Some(parse_usize(extract_contents(reader)?.as_bytes())?)
-                    }
-                    "size" => size = Some(parse_usize(extract_contents(reader)?.as_bytes())?),
+                    "name" => name = Some(extract_contents(reader, &tag_name)?),
+                    "baseAddress" => base = Some(extract_usize(reader, &tag_name)?),
+                    "size" => size = Some(extract_usize(reader, &tag_name)?),
+                    "description" => description = Some(extract_contents(reader, &tag_name)?),
                     "registers" => generate_registers(reader, &mut registers)?,
                     "interrupt" => generate_interrupts(reader, &mut interrupts)?,
                     _ => (),
@@ -296,17 +576,20 @@ fn generate_peripheral<T: BufRead>(reader: &mut Reader<T>) -> Result<Peripheral,
                     break;
                 }
             }
+            Ok(Event::Eof) => return Err(unexpected_eof("peripheral", position)),
             Ok(_) => (),
-            Err(e) => panic!("error parsing: {:?}", e),
+            Err(e) => return Err(xml_error(e, reader.buffer_position())),
         }
     }
 
     Ok(Peripheral {
-        name: name.ok_or(ParseError::MissingValue)?,
-        base: base.ok_or(ParseError::MissingValue)?,
-        size: size.ok_or(ParseError::MissingValue)?,
+        name: name.ok_or_else(|| missing_value("peripheral", "name", position))?,
+        base: base.ok_or_else(|| missing_value("peripheral", "baseAddress", position))?,
+        size: size.ok_or_else(|| missing_value("peripheral", "size", position))?,
+        description,
         interrupt: interrupts,
         registers,
+        line: position,
     })
 }
 
@@ -314,19 +597,16 @@ fn generate_peripherals<T: BufRead>(reader: &mut Reader<T>) -> Result<Vec<Periph
     let mut buf = Vec::new();
     let mut peripherals = vec![];
     loop {
+        let position = reader.buffer_position();
         match reader.read_event(&mut buf) {
             Ok(Event::Start(ref e)) => match e.name() {
                 b"peripheral" => peripherals.push(generate_peripheral(reader)?),
-                _ => panic!("unexpected tag in <peripherals>: {:?}", e),
-            },
-            Ok(Event::End(ref e)) => match e.name() {
-                b"peripherals" => {
-                    break;
-                }
-                e => panic!("unhandled value: {:?}", e),
+                tag => return Err(unexpected_tag(tag, "peripherals", position)),
             },
-            Ok(Event::Text(_)) => (),
-            e => panic!("unhandled value: {:?}", e),
+            Ok(Event::End(_)) => break,
+            Ok(Event::Eof) => return Err(unexpected_eof("peripherals", position)),
+            Ok(_) => (),
+            Err(e) => return Err(xml_error(e, position)),
         }
     }
     Ok(peripherals)
@@ -334,6 +614,7 @@ fn generate_peripherals<T: BufRead>(reader: &mut Reader<T>) -> Result<Vec<Periph
 
 fn generate_memory_region<T: BufRead>(reader: &mut Reader<T>) -> Result<MemoryRegion, ParseError> {
     let mut buf = Vec::new();
+    let position = reader.buffer_position();
     let mut name = None;
     let mut base = None;
     let mut size = None;
@@ -341,15 +622,11 @@ fn generate_memory_region<T: BufRead>(reader: &mut Reader<T>) -> Result<MemoryRe
     loop {
         match reader.read_event(&mut buf) {
             Ok(Event::Start(ref e)) => {
-                let tag_name = e
-                    .unescape_and_decode(reader)
-                    .map_err(|_| ParseError::NonUTF8)?;
+                let tag_name = tag_name(e, reader)?;
                 match tag_name.as_str() {
-                    "name" => name = Some(extract_contents(reader)?),
-                    "baseAddress" => {
-                        base = Some(parse_usize(extract_contents(reader)?.as_bytes())?)
-                    }
-                    "size" => size = Some(parse_usize(extract_contents(reader)?.as_bytes())?),
+                    "name" => name = Some(extract_contents(reader, &tag_name)?),
+                    "baseAddress" => base = Some(extract_usize(reader, &tag_name)?),
+                    "size" => size = Some(extract_usize(reader, &tag_name)?),
                     _ => (),
                 }
             }
@@ -358,15 +635,16 @@ fn generate_memory_region<T: BufRead>(reader: &mut Reader<T>) -> Result<MemoryRe
                     break;
                 }
             }
+            Ok(Event::Eof) => return Err(unexpected_eof("memoryRegion", position)),
             Ok(_) => (),
-            Err(e) => panic!("error parsing: {:?}", e),
+            Err(e) => return Err(xml_error(e, reader.buffer_position())),
         }
     }
 
     Ok(MemoryRegion {
-        name: name.ok_or(ParseError::MissingValue)?,
-        base: base.ok_or(ParseError::MissingValue)?,
-        size: size.ok_or(ParseError::MissingValue)?,
+        name: name.ok_or_else(|| missing_value("memoryRegion", "name", position))?,
+        base: base.ok_or_else(|| missing_value("memoryRegion", "baseAddress", position))?,
+        size: size.ok_or_else(|| missing_value("memoryRegion", "size", position))?,
     })
 }
 
@@ -376,21 +654,18 @@ fn parse_memory_regions<T: BufRead>(
 ) -> Result<(), ParseError> {
     let mut buf = Vec::new();
     loop {
+        let position = reader.buffer_position();
         match reader.read_event(&mut buf) {
             Ok(Event::Start(ref e)) => match e.name() {
                 b"memoryRegion" => description
                     .memory_regions
                     .push(generate_memory_region(reader)?),
-                _ => panic!("unexpected tag in <memoryRegions>: {:?}", e),
-            },
-            Ok(Event::End(ref e)) => match e.name() {
-                b"memoryRegions" => {
-                    break;
-                }
-                e => panic!("unhandled value: {:?}", e),
+                tag => return Err(unexpected_tag(tag, "memoryRegions", position)),
             },
-            Ok(Event::Text(_)) => (),
-            e => panic!("unhandled value: {:?}", e),
+            Ok(Event::End(_)) => break,
+            Ok(Event::Eof) => return Err(unexpected_eof("memoryRegions", position)),
+            Ok(_) => (),
+            Err(e) => return Err(xml_error(e, position)),
         }
     }
     Ok(())
@@ -402,25 +677,57 @@ fn parse_vendor_extensions<T: BufRead>(
 ) -> Result<(), ParseError> {
     let mut buf = Vec::new();
     loop {
+        let position = reader.buffer_position();
         match reader.read_event(&mut buf) {
             Ok(Event::Start(ref e)) => match e.name() {
                 b"memoryRegions" => parse_memory_regions(reader, description)?,
-                _ => panic!("unexpected tag in <vendorExtensions>: {:?}", e),
-            },
-            Ok(Event::End(ref e)) => match e.name() {
-                b"vendorExtensions" => {
-                    break;
-                }
-                e => panic!("unhandled value: {:?}", e),
+                tag => return Err(unexpected_tag(tag, "vendorExtensions", position)),
             },
-            Ok(Event::Text(_)) => (),
-            e => panic!("unhandled value: {:?}", e),
+            Ok(Event::End(_)) => break,
+            Ok(Event::Eof) => return Err(unexpected_eof("vendorExtensions", position)),
+            Ok(_) => (),
+            Err(e) => return Err(xml_error(e, position)),
         }
     }
     Ok(())
 }
 
-fn print_header<U: Write>(out: &mut U) -> std::io::Result<()> {
+/// A 64-bit FNV-1a hash of the source file. This is stable across
+/// compiler versions, unlike `DefaultHasher`.
+fn svd_hash(source: &[u8]) -> u64 {
+    source.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Sort everything by address and then by name, so that the generated
+/// file only changes when the hardware does and not when the SVD file
+/// happens to list elements in a different order.
+fn sort_description(description: &mut Description) {
+    description
+        .memory_regions
+        .sort_by(|a, b| (a.base, &a.name).cmp(&(b.base, &b.name)));
+    description
+        .peripherals
+        .sort_by(|a, b| (a.base, &a.name).cmp(&(b.base, &b.name)));
+    for peripheral in &mut description.peripherals {
+        peripheral
+            .registers
+            .sort_by(|a, b| (a.offset, &a.name).cmp(&(b.offset, &b.name)));
+        peripheral
+            .interrupt
+            .sort_by(|a, b| (a.value, &a.name).cmp(&(b.value, &b.name)));
+        for register in &mut peripheral.registers {
+            register
+                .fields
+                .sort_by(|a, b| (a.lsb, &a.name).cmp(&(b.lsb, &b.name)));
+        }
+    }
+}
+
+fn print_header<U: Write>(hash: u64, out: &mut U) -> std::io::Result<()> {
+    writeln!(out, "// Generated by svd2utra -- do not edit.")?;
+    writeln!(out, "// SVD hash (FNV-1a): 0x{:016x}", hash)?;
     let s = r####"
 use core::convert::TryInto;
 pub struct Register {
@@ -522,9 +829,9 @@ where
     /// Read-modify-write a given field in this CSR
     pub fn rmwf(&mut self, field: Field, value: T) {
         let usize_base: *mut usize = unsafe { core::mem::transmute(self.base) };
-        let value_as_usize: usize = value.try_into().unwrap_or_default() << field.offset;
+        let value_as_usize: usize = (value.try_into().unwrap_or_default() & field.mask) << field.offset;
         let previous =
-            unsafe { usize_base.add(field.register.offset).read_volatile() } & !field.mask;
+            unsafe { usize_base.add(field.register.offset).read_volatile() } & !(field.mask << field.offset);
         unsafe {
             usize_base
                 .add(field.register.offset)
@@ -576,7 +883,7 @@ fn print_memory_regions<U: Write>(regions: &[MemoryRegion], out: &mut U) -> std:
         )?;
         writeln!(
             out,
-            "pub const HW_{}_MEM_LEN: usize = {};",
+            "pub const HW_{}_MEM_LEN: usize = 0x{:08x};",
             region.name, region.size
         )?;
     }
@@ -584,7 +891,133 @@ fn print_memory_regions<U: Write>(regions: &[MemoryRegion], out: &mut U) -> std:
     Ok(())
 }
 
-fn print_peripherals<U: Write>(peripherals: &[Peripheral], out: &mut U) -> std::io::Result<()> {
+/// Write `description` as a `///` doc comment, one line at a time.
+fn print_description<U: Write>(
+    description: &Option<String>,
+    indent: &str,
+    out: &mut U,
+) -> std::io::Result<()> {
+    if let Some(description) = description {
+        for line in description.trim().lines() {
+            let line = line.trim_end();
+            if line.is_empty() {
+                writeln!(out, "{}///", indent)?;
+            } else {
+                writeln!(out, "{}/// {}", indent, line)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Rust keywords that a lowercased field name might collide with
+const KEYWORDS: &[&str] = &[
+    "as", "break", "const", "continue", "crate", "else", "enum", "extern", "false", "fn", "for",
+    "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return",
+    "self", "static", "struct", "super", "trait", "true", "type", "unsafe", "use", "where",
+    "while", "async", "await", "dyn",
+];
+
+/// `TRNG_OSC` becomes `TrngOsc`
+fn camel_case(name: &str) -> String {
+    name.split('_')
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let word = word.to_lowercase();
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+/// Name the accessor for `field` after its register, leaving out the field
+/// name when it just repeats the register name, as LiteX tends to do.
+fn accessor_name(register: &Register, field: &Field) -> String {
+    if field.name.eq_ignore_ascii_case(&register.name) {
+        register.name.to_lowercase()
+    } else {
+        format!("{}_{}", register.name, field.name).to_lowercase()
+    }
+}
+
+/// Keywords that can't be raw identifiers either
+const PATH_KEYWORDS: &[&str] = &["crate", "self", "super"];
+
+/// Methods of every accessor struct, which fields mustn't shadow
+const ACCESSOR_METHODS: &[&str] = &["new", "csr"];
+
+/// Getters are named after the field alone, so escape names that are
+/// keywords, such as LiteX's `IN` registers.  `r#self` and friends aren't
+/// allowed, so those get a trailing underscore instead.
+fn getter_name(name: &str) -> String {
+    if PATH_KEYWORDS.contains(&name) {
+        format!("{}_", name)
+    } else if KEYWORDS.contains(&name) {
+        format!("r#{}", name)
+    } else {
+        name.to_owned()
+    }
+}
+
+/// Write a struct for `peripheral` with a getter and a setter for every
+/// field. Single-bit fields are `bool`, and everything else is `u32`.
+/// Setters do a read-modify-write, like `CSR::rmwf()`.
+fn print_accessors<U: Write>(peripheral: &Peripheral, out: &mut U) -> std::io::Result<()> {
+    let struct_name = camel_case(&peripheral.name);
+    writeln!(out)?;
+    writeln!(
+        out,
+        "        /// Field accessors for {}",
+        peripheral.name.to_uppercase()
+    )?;
+    writeln!(out, "        pub struct {} {{", struct_name)?;
+    writeln!(out, "            csr: super::super::CSR<u32>,")?;
+    writeln!(out, "        }}")?;
+    writeln!(out)?;
+    writeln!(out, "        impl {} {{", struct_name)?;
+    writeln!(out, "            pub fn new(base: *mut u32) -> Self {{")?;
+    writeln!(out, "                {} {{ csr: super::super::CSR::new(base) }}", struct_name)?;
+    writeln!(out, "            }}")?;
+    writeln!(out)?;
+    writeln!(out, "            /// The underlying CSR, for accessing whole registers")?;
+    writeln!(out, "            pub fn csr(&mut self) -> &mut super::super::CSR<u32> {{")?;
+    writeln!(out, "                &mut self.csr")?;
+    writeln!(out, "            }}")?;
+    for register in &peripheral.registers {
+        for field in &register.fields {
+            let constant = format!("{}_{}", register.name, field.name.to_uppercase());
+            let name = accessor_name(register, field);
+            let getter = getter_name(&name);
+            writeln!(out)?;
+            print_description(&field.description, "            ", out)?;
+            if field.msb == field.lsb {
+                writeln!(out, "            pub fn {}(&self) -> bool {{", getter)?;
+                writeln!(out, "                self.csr.rf({}) != 0", constant)?;
+                writeln!(out, "            }}")?;
+                writeln!(out, "            pub fn set_{}(&mut self, value: bool) {{", name)?;
+                writeln!(out, "                self.csr.rmwf({}, value as u32)", constant)?;
+            } else {
+                writeln!(out, "            pub fn {}(&self) -> u32 {{", getter)?;
+                writeln!(out, "                self.csr.rf({})", constant)?;
+                writeln!(out, "            }}")?;
+                writeln!(out, "            pub fn set_{}(&mut self, value: u32) {{", name)?;
+                writeln!(out, "                self.csr.rmwf({}, value)", constant)?;
+            }
+            writeln!(out, "            }}")?;
+        }
+    }
+    writeln!(out, "        }}")?;
+    Ok(())
+}
+
+fn print_peripherals<U: Write>(
+    peripherals: &[Peripheral],
+    options: &Options,
+    out: &mut U,
+) -> std::io::Result<()> {
     writeln!(out, "// Physical base addresses of registers")?;
     for peripheral in peripherals {
         writeln!(
@@ -595,24 +1028,27 @@ fn print_peripherals<U: Write>(peripherals: &[Peripheral], out: &mut U) -> std::
     }
     writeln!(out)?;
 
+    // Peripheral modules name the shared types relative to themselves
+    // rather than through `crate::`, which only reaches the types of
+    // whichever register map utralib re-exports at its root.
     writeln!(out, "pub mod utra {{")?;
     for peripheral in peripherals {
         writeln!(out)?;
+        print_description(&peripheral.description, "    ", out)?;
         writeln!(out, "    pub mod {} {{", peripheral.name.to_lowercase())?;
         for register in &peripheral.registers {
             writeln!(out)?;
-            if let Some(description) = &register.description {
-                writeln!(out, "        /// {}", description)?;
-            }
+            print_description(&register.description, "        ", out)?;
             writeln!(
                 out,
-                "        pub const {}: crate::Register = crate::Register::new({});",
+                "        pub const {}: super::super::Register = super::super::Register::new({});",
                 register.name.to_uppercase(), register.offset / 4
             )?;
             for field in &register.fields {
+                print_description(&field.description, "        ", out)?;
                 writeln!(
                     out,
-                    "        pub const {}_{}: crate::Field = crate::Field::new({}, {}, {});",
+                    "        pub const {}_{}: super::super::Field = super::super::Field::new({}, {}, {});",
                     register.name,
                     field.name.to_uppercase(),
                     field.msb + 1 - field.lsb,
@@ -631,13 +1067,20 @@ fn print_peripherals<U: Write>(peripherals: &[Peripheral], out: &mut U) -> std::
             )?;
         }
         writeln!(out, "        pub const HW_{}_BASE: usize = 0x{:08x};", peripheral.name.to_uppercase(), peripheral.base)?;
+        if options.accessors {
+            print_accessors(peripheral, out)?;
+        }
         writeln!(out, "    }}")?;
     }
     writeln!(out, "}}")?;
     Ok(())
 }
 
-fn print_tests<U: Write>(peripherals: &[Peripheral], out: &mut U) -> std::io::Result<()> {
+fn print_tests<U: Write>(
+    peripherals: &[Peripheral],
+    options: &Options,
+    out: &mut U,
+) -> std::io::Result<()> {
     let test_header = r####"
 #[cfg(test)]
 mod tests {
@@ -665,30 +1108,306 @@ mod tests {
                 writeln!(out, "        {}.wfo(utra::{}::{}, baz);", per_name, mod_name, field_name)?;
             }
         }
+        if options.accessors && peripheral.registers.iter().any(|r| !r.fields.is_empty()) {
+            writeln!(out)?;
+            writeln!(
+                out,
+                "        let mut {} = utra::{}::{}::new(HW_{}_BASE as *mut u32);",
+                mod_name,
+                mod_name,
+                camel_case(&peripheral.name),
+                peripheral.name.to_uppercase()
+            )?;
+            for register in &peripheral.registers {
+                for field in &register.fields {
+                    let name = accessor_name(register, field);
+                    writeln!(out, "        let value = {}.{}();", mod_name, getter_name(&name))?;
+                    writeln!(out, "        {}.set_{}(value);", mod_name, name)?;
+                }
+            }
+        }
     }
     writeln!(out, "    }}")?;
     writeln!(out, "}}")?;
     Ok(())
 }
 
-pub fn parse_svd<T: Read>(src: T) -> Result<Description, ParseError> {
+/// Renode models for peripherals that have one, keyed by SVD peripheral
+/// name, along with any properties the model needs.  Everything else is
+/// tagged on the system bus so that accesses show up in the log by name.
+const REPL_MODELS: &[(&str, &str, &[&str])] = &[
+    ("CONSOLE", "UART.LiteX_UART", &[]),
+    ("KEYBOARD", "Input.BetrustedKbd", &[]),
+    ("MEMLCD", "Video.BetrustedLCD", &[]),
+    ("SERVER0", "UART.LiteX_UART", &[]),
+    ("SERVER1", "UART.LiteX_UART", &[]),
+    ("TICKTIMER", "Timers.TickTimer", &["periodInMs: 1"]),
+    ("TIMER0", "Timers.LiteX_Timer_32", &["frequency: 100000000"]),
+    ("TRNG_OSC", "Miscellaneous.BetrustedTrngOsc", &[]),
+    ("UART", "UART.LiteX_UART", &[]),
+];
+
+/// LiteX interrupts are wired to the VexRiscv external interrupt lines,
+/// which Renode numbers from 1000.
+const REPL_IRQ_BASE: usize = 1000;
+
+fn print_repl<U: Write>(hash: u64, description: &Description, out: &mut U) -> std::io::Result<()> {
+    writeln!(out, "// Generated by svd2utra -- do not edit.")?;
+    writeln!(out, "// SVD hash (FNV-1a): 0x{:016x}", hash)?;
+    writeln!(out, "//")?;
+    writeln!(out, "// This describes memory and peripherals only.  Include it with `using`")?;
+    writeln!(out, "// from a platform file that defines `cpu`.")?;
+
+    for region in &description.memory_regions {
+        // The CSR region holds the peripherals below, so it isn't memory.
+        if region.name == "CSR" {
+            continue;
+        }
+        // Renode maps memory in whole pages.
+        let size = (region.size + 0xfff) & !0xfff;
+        writeln!(out)?;
+        writeln!(
+            out,
+            "{}_mem: Memory.MappedMemory @ sysbus 0x{:08x}",
+            region.name.to_lowercase(),
+            region.base
+        )?;
+        writeln!(out, "    size: 0x{:08x}", size)?;
+    }
+
+    let mut tags = vec![];
+    for peripheral in &description.peripherals {
+        let model = REPL_MODELS
+            .iter()
+            .find(|(name, _, _)| peripheral.name.eq_ignore_ascii_case(name));
+        let (model, properties) = match model {
+            Some((_, model, properties)) => (model, properties),
+            None => {
+                tags.push(peripheral);
+                continue;
+            }
+        };
+        writeln!(out)?;
+        writeln!(
+            out,
+            "{}: {} @ sysbus 0x{:08x}",
+            peripheral.name.to_lowercase(),
+            model,
+            peripheral.base
+        )?;
+        for property in properties.iter() {
+            writeln!(out, "    {}", property)?;
+        }
+        for interrupt in &peripheral.interrupt {
+            writeln!(out, "    -> cpu@{}", REPL_IRQ_BASE + interrupt.value)?;
+        }
+    }
+
+    if !tags.is_empty() {
+        writeln!(out)?;
+        writeln!(out, "sysbus:")?;
+        writeln!(out, "    init:")?;
+        for peripheral in tags {
+            writeln!(
+                out,
+                "        Tag <0x{:08x} 0x{:x}> \"{}\"",
+                peripheral.base,
+                peripheral.size.max(4),
+                peripheral.name.to_uppercase()
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn line_starts(source: &[u8]) -> Vec<usize> {
+    core::iter::once(0)
+        .chain(
+            source
+                .iter()
+                .enumerate()
+                .filter(|&(_, &c)| c == b'\n')
+                .map(|(i, _)| i + 1),
+        )
+        .collect()
+}
+
+/// The 1-based line and column of byte `position`
+fn line_and_column(line_starts: &[usize], position: usize) -> (usize, usize) {
+    let line = match line_starts.binary_search(&position) {
+        Ok(idx) => idx + 1,
+        Err(idx) => idx,
+    };
+    (line, position - line_starts[line - 1] + 1)
+}
+
+/// Elements record their byte position while parsing. Turn those positions
+/// into line numbers now that the whole file is available.
+fn resolve_lines(description: &mut Description, source: &[u8]) {
+    let line_starts = line_starts(source);
+    let line_of = |position: usize| line_and_column(&line_starts, position).0;
+
+    for peripheral in &mut description.peripherals {
+        peripheral.line = line_of(peripheral.line);
+        for register in &mut peripheral.registers {
+            register.line = line_of(register.line);
+            for field in &mut register.fields {
+                field.line = line_of(field.line);
+            }
+        }
+    }
+}
+
+/// Check a parsed SVD file for mistakes that would otherwise result in
+/// broken constants: overlapping peripherals, registers that share an
+/// offset, and fields that don't fit in their register.
+pub fn validate(description: &Description) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+
+    let mut peripherals: Vec<&Peripheral> = description.peripherals.iter().collect();
+    peripherals.sort_by_key(|p| p.base);
+    // Compare each peripheral against whichever earlier one reaches the
+    // furthest, not just its neighbour, so that one large window covering
+    // several smaller ones is caught for all of them.
+    let mut furthest: Option<&Peripheral> = None;
+    for peripheral in peripherals {
+        if let Some(previous) = furthest {
+            if peripheral.base < previous.base + previous.size {
+                diagnostics.push(Diagnostic {
+                    line: peripheral.line,
+                    kind: DiagnosticKind::OverlappingPeripherals(
+                        peripheral.name.clone(),
+                        previous.name.clone(),
+                    ),
+                });
+            }
+        }
+        if furthest.map_or(true, |f| peripheral.base + peripheral.size > f.base + f.size) {
+            furthest = Some(peripheral);
+        }
+    }
+
+    for peripheral in &description.peripherals {
+        let mut registers: Vec<&Register> = peripheral.registers.iter().collect();
+        registers.sort_by_key(|r| r.offset);
+        // Register sizes are in bits
+        let end = |r: &Register| r.offset + ((r.size + 7) / 8).max(1);
+        let mut last: Option<&Register> = None;
+        let mut furthest: Option<&Register> = None;
+        for register in registers {
+            if let Some(previous) = last.filter(|l| l.offset == register.offset) {
+                diagnostics.push(Diagnostic {
+                    line: register.line,
+                    kind: DiagnosticKind::DuplicateRegisterOffset(
+                        peripheral.name.clone(),
+                        register.name.clone(),
+                        previous.name.clone(),
+                        register.offset,
+                    ),
+                });
+            } else if let Some(previous) = furthest.filter(|f| register.offset < end(f)) {
+                diagnostics.push(Diagnostic {
+                    line: register.line,
+                    kind: DiagnosticKind::OverlappingRegisters(
+                        peripheral.name.clone(),
+                        register.name.clone(),
+                        previous.name.clone(),
+                    ),
+                });
+            }
+            if furthest.map_or(true, |f| end(register) > end(f)) {
+                furthest = Some(register);
+            }
+            last = Some(register);
+        }
+
+        for register in &peripheral.registers {
+            for field in &register.fields {
+                if field.msb < field.lsb {
+                    diagnostics.push(Diagnostic {
+                        line: field.line,
+                        kind: DiagnosticKind::FieldInverted(
+                            register.name.clone(),
+                            field.name.clone(),
+                        ),
+                    });
+                } else if field.msb >= register.size {
+                    diagnostics.push(Diagnostic {
+                        line: field.line,
+                        kind: DiagnosticKind::FieldTooWide(
+                            register.name.clone(),
+                            field.name.clone(),
+                            field.msb,
+                            register.size,
+                        ),
+                    });
+                }
+            }
+        }
+    }
+
+    diagnostics.sort_by_key(|d| d.line);
+    diagnostics
+}
+
+/// Check that every field gets accessors of its own.  Names are lowercased,
+/// a field named after its register is shortened to the register name and
+/// some keywords get a suffix, so two different fields can end up with the
+/// same getter or setter.
+pub fn validate_accessors(description: &Description) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    for peripheral in &description.peripherals {
+        // Each method name, and what it belongs to
+        let mut taken: Vec<(String, String)> = ACCESSOR_METHODS
+            .iter()
+            .map(|method| (method.to_string(), "the accessor struct itself".to_string()))
+            .collect();
+        for register in &peripheral.registers {
+            for field in &register.fields {
+                let name = accessor_name(register, field);
+                let owner = format!("{}.{}", register.name, field.name);
+                let methods = [getter_name(&name), format!("set_{}", name)];
+                let clash = methods
+                    .iter()
+                    .find_map(|method| taken.iter().find(|(taken, _)| taken == method));
+                if let Some((method, other)) = clash {
+                    diagnostics.push(Diagnostic {
+                        line: field.line,
+                        kind: DiagnosticKind::DuplicateAccessor(
+                            peripheral.name.clone(),
+                            owner.clone(),
+                            method.clone(),
+                            other.clone(),
+                        ),
+                    });
+                }
+                for method in methods {
+                    taken.push((method, format!("field {}.{}", peripheral.name, owner)));
+                }
+            }
+        }
+    }
+    diagnostics.sort_by_key(|d| d.line);
+    diagnostics
+}
+
+fn parse_description<T: BufRead>(reader: &mut Reader<T>) -> Result<Description, ParseError> {
     let mut buf = Vec::new();
-    let buf_reader = BufReader::new(src);
-    let mut reader = Reader::from_reader(buf_reader);
     let mut description = Description::default();
     loop {
+        let position = reader.buffer_position();
         match reader.read_event(&mut buf) {
             Ok(Event::Start(ref e)) => match e.name() {
                 b"peripherals" => {
-                    description.peripherals = generate_peripherals(&mut reader)?;
+                    description.peripherals = generate_peripherals(reader)?;
                 }
                 b"vendorExtensions" => {
-                    parse_vendor_extensions(&mut reader, &mut description)?;
+                    parse_vendor_extensions(reader, &mut description)?;
                 }
                 _ => (),
             },
             Ok(Event::Eof) => break,
-            Err(e) => panic!("Error at position {}: {:?}", reader.buffer_position(), e),
+            Err(e) => return Err(xml_error(e, position)),
             _ => (),
         }
         buf.clear();
@@ -696,13 +1415,69 @@ pub fn parse_svd<T: Read>(src: T) -> Result<Description, ParseError> {
     Ok(description)
 }
 
+/// Parse an SVD file. Errors carry the line and column of the element that
+/// caused them.
+pub fn parse_svd<T: Read>(mut src: T) -> Result<Description, ParseError> {
+    let mut source = Vec::new();
+    src.read_to_end(&mut source).or(Err(ParseError::ReadError))?;
+    let mut reader = Reader::from_reader(source.as_slice());
+    let mut description = parse_description(&mut reader).map_err(|mut e| {
+        if let Some(location) = e.location_mut() {
+            let (line, column) = line_and_column(&line_starts(&source), location.offset);
+            location.line = line;
+            location.column = column;
+        }
+        e
+    })?;
+    resolve_lines(&mut description, &source);
+    Ok(description)
+}
+
 pub fn generate<T: Read, U: Write>(src: T, dest: &mut U) -> Result<(), ParseError> {
-    let description = parse_svd(src)?;
+    generate_with(src, dest, &Options::default())
+}
+
+pub fn generate_with<T: Read, U: Write>(
+    mut src: T,
+    dest: &mut U,
+    options: &Options,
+) -> Result<(), ParseError> {
+    let mut source = Vec::new();
+    src.read_to_end(&mut source).or(Err(ParseError::ReadError))?;
+    let mut description = parse_svd(source.as_slice())?;
+    let mut diagnostics = validate(&description);
+    if options.accessors {
+        diagnostics.extend(validate_accessors(&description));
+        diagnostics.sort_by_key(|d| d.line);
+    }
+    if !diagnostics.is_empty() {
+        return Err(ParseError::Invalid(diagnostics));
+    }
 
-    print_header(dest).or(Err(ParseError::WriteError))?;
+    sort_description(&mut description);
+
+    print_header(svd_hash(&source), dest).or(Err(ParseError::WriteError))?;
     print_memory_regions(&description.memory_regions, dest).or(Err(ParseError::WriteError))?;
-    print_peripherals(&description.peripherals, dest).or(Err(ParseError::WriteError))?;
-    print_tests(&description.peripherals, dest).or(Err(ParseError::WriteError))?;
+    print_peripherals(&description.peripherals, options, dest).or(Err(ParseError::WriteError))?;
+    print_tests(&description.peripherals, options, dest).or(Err(ParseError::WriteError))?;
+
+    Ok(())
+}
+
+/// Generate a Renode platform description (`.repl`) from the same SVD file,
+/// so the emulator's memory map can't drift from the generated UTRA.
+pub fn generate_repl<T: Read, U: Write>(mut src: T, dest: &mut U) -> Result<(), ParseError> {
+    let mut source = Vec::new();
+    src.read_to_end(&mut source).or(Err(ParseError::ReadError))?;
+    let mut description = parse_svd(source.as_slice())?;
+    let diagnostics = validate(&description);
+    if !diagnostics.is_empty() {
+        return Err(ParseError::Invalid(diagnostics));
+    }
+
+    sort_description(&mut description);
+
+    print_repl(svd_hash(&source), &description, dest).or(Err(ParseError::WriteError))?;
 
     Ok(())
 }
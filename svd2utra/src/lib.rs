@@ -12,4 +12,233 @@ mod tests {
         let mut dest = File::create("target/example.rs").unwrap();
         generate(src, &mut dest).unwrap();
     }
+
+    #[test]
+    fn basic_generate_repl() {
+        let src = File::open("examples/soc.svd").unwrap();
+        let mut dest = vec![];
+        generate_repl(src, &mut dest).unwrap();
+        let repl = String::from_utf8(dest).unwrap();
+        assert!(repl.contains("uart: UART.LiteX_UART @ sysbus 0xf0008000\n    -> cpu@1002\n"));
+        assert!(repl.contains("Tag <0xf0000000 0xc> \"CTRL\""));
+    }
+
+    #[test]
+    fn validate_collisions() {
+        let src = r#"<device>
+    <peripherals>
+        <peripheral>
+            <name>FIRST</name>
+            <baseAddress>0x1000</baseAddress>
+            <registers>
+                <register>
+                    <name>A</name>
+                    <addressOffset>0x0</addressOffset>
+                    <size>32</size>
+                    <fields>
+                        <field>
+                            <name>wide</name>
+                            <msb>32</msb>
+                            <lsb>0</lsb>
+                        </field>
+                    </fields>
+                </register>
+                <register>
+                    <name>B</name>
+                    <addressOffset>0x0</addressOffset>
+                    <fields>
+                    </fields>
+                </register>
+            </registers>
+            <addressBlock>
+                <offset>0</offset>
+                <size>0x100</size>
+            </addressBlock>
+        </peripheral>
+        <peripheral>
+            <name>SECOND</name>
+            <baseAddress>0x1080</baseAddress>
+            <registers>
+            </registers>
+            <addressBlock>
+                <offset>0</offset>
+                <size>0x100</size>
+            </addressBlock>
+        </peripheral>
+    </peripherals>
+</device>
+"#;
+        let description = parse_svd(src.as_bytes()).unwrap();
+        let diagnostics = validate(&description);
+        assert_eq!(diagnostics.len(), 3);
+        assert!(matches!(diagnostics[0].kind, DiagnosticKind::FieldTooWide(..)));
+        assert_eq!(diagnostics[0].line, 12);
+        assert!(matches!(diagnostics[1].kind, DiagnosticKind::DuplicateRegisterOffset(..)));
+        assert_eq!(diagnostics[1].line, 19);
+        assert!(matches!(diagnostics[2].kind, DiagnosticKind::OverlappingPeripherals(..)));
+        assert_eq!(diagnostics[2].line, 31);
+    }
+
+    #[test]
+    fn validate_nested_windows() {
+        let src = r#"<device>
+    <peripherals>
+        <peripheral>
+            <name>OUTER</name>
+            <baseAddress>0x1000</baseAddress>
+            <registers>
+                <register>
+                    <name>WIDE</name>
+                    <addressOffset>0x0</addressOffset>
+                    <size>128</size>
+                </register>
+                <register>
+                    <name>LOW</name>
+                    <addressOffset>0x4</addressOffset>
+                </register>
+                <register>
+                    <name>HIGH</name>
+                    <addressOffset>0xc</addressOffset>
+                </register>
+                <register>
+                    <name>AFTER</name>
+                    <addressOffset>0x10</addressOffset>
+                </register>
+            </registers>
+            <addressBlock>
+                <offset>0</offset>
+                <size>0x1000</size>
+            </addressBlock>
+        </peripheral>
+        <peripheral>
+            <name>FIRST</name>
+            <baseAddress>0x1100</baseAddress>
+            <registers>
+            </registers>
+            <addressBlock>
+                <offset>0</offset>
+                <size>0x100</size>
+            </addressBlock>
+        </peripheral>
+        <peripheral>
+            <name>SECOND</name>
+            <baseAddress>0x1400</baseAddress>
+            <registers>
+            </registers>
+            <addressBlock>
+                <offset>0</offset>
+                <size>0x100</size>
+            </addressBlock>
+        </peripheral>
+    </peripherals>
+</device>
+"#;
+        let description = parse_svd(src.as_bytes()).unwrap();
+        let diagnostics = validate(&description);
+        let messages: Vec<String> = diagnostics.iter().map(|d| d.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "line 12: register OUTER.LOW overlaps with register WIDE",
+                "line 16: register OUTER.HIGH overlaps with register WIDE",
+                "line 30: peripheral FIRST overlaps with peripheral OUTER",
+                "line 40: peripheral SECOND overlaps with peripheral OUTER",
+            ]
+        );
+    }
+
+    /// A peripheral with one register per `(register, field)` pair, each
+    /// holding a single one-bit field
+    fn accessor_svd(fields: &[(&str, &str)]) -> String {
+        let mut registers = String::new();
+        for (i, (register, field)) in fields.iter().enumerate() {
+            registers += &format!(
+                "
+                <register>
+                    <name>{}</name>
+                    <addressOffset>0x{:x}</addressOffset>
+                    <fields>
+                        <field>
+                            <name>{}</name>
+                            <msb>0</msb>
+                            <lsb>0</lsb>
+                        </field>
+                    </fields>
+                </register>",
+                register,
+                i * 4,
+                field
+            );
+        }
+        format!(
+            "<device>
+    <peripherals>
+        <peripheral>
+            <name>PERI</name>
+            <baseAddress>0x1000</baseAddress>
+            <registers>{}
+            </registers>
+            <addressBlock>
+                <offset>0</offset>
+                <size>0x100</size>
+            </addressBlock>
+        </peripheral>
+    </peripherals>
+</device>
+",
+            registers
+        )
+    }
+
+    #[test]
+    fn keyword_accessors() {
+        let src = accessor_svd(&[("IN", "in"), ("SELF", "self"), ("SUPER", "super")]);
+        let mut dest = vec![];
+        let options = Options { accessors: true };
+        generate_with(src.as_bytes(), &mut dest, &options).unwrap();
+        let utra = String::from_utf8(dest).unwrap();
+        assert!(utra.contains("pub fn r#in(&self) -> bool"));
+        assert!(utra.contains("pub fn set_in(&mut self, value: bool)"));
+        assert!(utra.contains("pub fn self_(&self) -> bool"));
+        assert!(utra.contains("pub fn set_self(&mut self, value: bool)"));
+        assert!(utra.contains("pub fn super_(&self) -> bool"));
+        assert!(!utra.contains("r#self"));
+        assert!(!utra.contains("r#super"));
+    }
+
+    #[test]
+    fn accessor_collisions() {
+        let src = accessor_svd(&[
+            ("A_B", "c"),
+            ("A", "b_c"),
+            ("CSR", "csr"),
+            ("X", "x"),
+            ("SET", "x"),
+            ("SELF", "self"),
+            ("SELF_", "self_"),
+        ]);
+        let description = parse_svd(src.as_bytes()).unwrap();
+        let messages: Vec<String> = validate_accessors(&description)
+            .iter()
+            .map(|d| d.to_string())
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "line 22: field PERI.A.b_c would get the accessor a_b_c(), which is already used by field PERI.A_B.c",
+                "line 33: field PERI.CSR.csr would get the accessor csr(), which is already used by the accessor struct itself",
+                "line 55: field PERI.SET.x would get the accessor set_x(), which is already used by field PERI.X.x",
+                "line 77: field PERI.SELF_.self_ would get the accessor self_(), which is already used by field PERI.SELF.self",
+            ]
+        );
+
+        // The accessors are only checked when they are generated
+        let mut dest = vec![];
+        let options = Options { accessors: true };
+        assert!(matches!(
+            generate_with(src.as_bytes(), &mut dest, &options),
+            Err(ParseError::Invalid(_))
+        ));
+        generate(src.as_bytes(), &mut vec![]).unwrap();
+    }
 }
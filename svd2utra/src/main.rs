@@ -15,6 +15,18 @@ fn main() -> anyhow::Result<()> {
                 .takes_value(true)
                 .value_name("FILE"),
         )
+        .arg(
+            Arg::with_name("repl")
+                .help("Generate a Renode platform description instead of Rust")
+                .short("r")
+                .long("repl"),
+        )
+        .arg(
+            Arg::with_name("accessors")
+                .help("Also generate a struct per peripheral with typed field accessors")
+                .short("a")
+                .long("accessors"),
+        )
         .arg(
             Arg::with_name("output")
                 .help("Output .rs file or crate directory")
@@ -38,7 +50,14 @@ fn main() -> anyhow::Result<()> {
         Some(path) => Box::new(File::open(path).context("Cannot open destination file")?),
     };
 
-    generate::generate(src, &mut dest).context("Cannot generate output file")?;
+    if matches.is_present("repl") {
+        generate::generate_repl(src, &mut dest).context("Cannot generate output file")?;
+    } else if matches.is_present("accessors") {
+        let options = generate::Options { accessors: true };
+        generate::generate_with(src, &mut dest, &options).context("Cannot generate output file")?;
+    } else {
+        generate::generate(src, &mut dest).context("Cannot generate output file")?;
+    }
 
     Ok(())
 }
@@ -0,0 +1,175 @@
+//! Golden-file tests for the generated output.
+//!
+//! Each SVD file in `tests/fixtures` is run through the generator and the
+//! result is compared with the matching file in `tests/snapshots`.  After an
+//! intentional change to the output, run the tests with `SVD2UTRA_BLESS=1`
+//! to rewrite the snapshots, and review the diff before committing it.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use svd2utra::ParseError;
+
+fn test_path(dir: &str, name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join(dir)
+        .join(name)
+}
+
+fn fixture(name: &str) -> Vec<u8> {
+    let path = test_path("fixtures", name);
+    fs::read(&path).unwrap_or_else(|e| panic!("cannot read {}: {}", path.display(), e))
+}
+
+fn assert_snapshot(name: &str, actual: &str) {
+    let path = test_path("snapshots", name);
+    if env::var_os("SVD2UTRA_BLESS").is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, actual).unwrap();
+        return;
+    }
+
+    let expected = fs::read_to_string(&path).unwrap_or_else(|_| {
+        panic!(
+            "there is no snapshot at {}, run with SVD2UTRA_BLESS=1 to create it",
+            path.display()
+        )
+    });
+    if expected == actual {
+        return;
+    }
+
+    // Point at the first line that changed rather than dumping both files.
+    let line = expected
+        .lines()
+        .zip(actual.lines())
+        .position(|(e, a)| e != a)
+        .unwrap_or_else(|| expected.lines().count().min(actual.lines().count()));
+    panic!(
+        "{} differs from the snapshot at line {}:\n    expected: {:?}\n      actual: {:?}\n\
+         run with SVD2UTRA_BLESS=1 if the change is intended",
+        name,
+        line + 1,
+        expected.lines().nth(line),
+        actual.lines().nth(line)
+    );
+}
+
+fn generate(name: &str) -> String {
+    let mut dest = vec![];
+    svd2utra::generate(fixture(name).as_slice(), &mut dest).unwrap();
+    String::from_utf8(dest).unwrap()
+}
+
+fn generate_error(name: &str) -> ParseError {
+    let mut dest = vec![];
+    match svd2utra::generate(fixture(name).as_slice(), &mut dest) {
+        Ok(()) => panic!("{} should not have generated any output", name),
+        Err(e) => e,
+    }
+}
+
+#[test]
+fn litex() {
+    assert_snapshot("litex.rs.snap", &generate("litex.svd"));
+}
+
+#[test]
+fn litex_repl() {
+    let mut dest = vec![];
+    svd2utra::generate_repl(fixture("litex.svd").as_slice(), &mut dest).unwrap();
+    assert_snapshot("litex.repl.snap", &String::from_utf8(dest).unwrap());
+}
+
+#[test]
+fn litex_accessors() {
+    let options = svd2utra::Options { accessors: true };
+    let mut dest = vec![];
+    svd2utra::generate_with(fixture("litex.svd").as_slice(), &mut dest, &options).unwrap();
+    assert_snapshot("litex_accessors.rs.snap", &String::from_utf8(dest).unwrap());
+}
+
+/// Peripherals that use `derivedFrom` don't inherit any registers yet, so
+/// `SPI1` only gets its base address and interrupt.
+#[test]
+fn vendor() {
+    assert_snapshot("vendor.rs.snap", &generate("vendor.svd"));
+}
+
+/// Registers inside (nested) clusters are flattened into the peripheral,
+/// with the cluster names as a prefix and the cluster offsets added.
+#[test]
+fn cluster() {
+    let output = generate("cluster.svd");
+    assert!(output.contains("pub const CH0_CFG_LEN: super::super::Register = super::super::Register::new(6);"));
+    assert!(output.contains("pub const CH0_CFG_LEN_COUNT: super::super::Field = super::super::Field::new(16, 0, CH0_CFG_LEN);"));
+    assert_snapshot("cluster.rs.snap", &output);
+}
+
+/// Fields given as `bitOffset` and `bitWidth` instead of `lsb` and `msb`
+#[test]
+fn missing_value() {
+    let error = generate_error("missing_value.svd");
+    assert!(matches!(
+        error,
+        ParseError::MissingValue(ref element, ref tag, location)
+            if element == "field" && tag == "lsb" && location.line == 18
+    ));
+    assert_snapshot("missing_value.err.snap", &format!("{}\n", error));
+}
+
+#[test]
+fn bad_number() {
+    let error = generate_error("bad_number.svd");
+    assert!(matches!(
+        error,
+        ParseError::ParseIntError(ref value, location) if value == "0x4002_0000" && location.line == 7
+    ));
+    assert_snapshot("bad_number.err.snap", &format!("{}\n", error));
+}
+
+#[test]
+fn invalid() {
+    let error = generate_error("invalid.svd");
+    assert!(matches!(error, ParseError::Invalid(ref diagnostics) if diagnostics.len() == 4));
+    assert_snapshot("invalid.err.snap", &format!("{}\n", error));
+}
+
+#[test]
+fn unexpected_tag() {
+    let error = generate_error("unexpected_tag.svd");
+    assert!(matches!(
+        error,
+        ParseError::UnexpectedTag(ref tag, ref parent, location)
+            if tag == "field" && parent == "registers" && location.line == 15 && location.column == 9
+    ));
+    assert_snapshot("unexpected_tag.err.snap", &format!("{}\n", error));
+}
+
+/// Cluster arrays are refused rather than generating only the first element
+#[test]
+fn cluster_array() {
+    let error = generate_error("cluster_array.svd");
+    assert!(matches!(
+        error,
+        ParseError::Unsupported(ref tag, ref parent, location)
+            if tag == "dim" && parent == "cluster" && location.line == 16 && location.column == 11
+    ));
+    assert_snapshot("cluster_array.err.snap", &format!("{}\n", error));
+}
+
+#[test]
+fn truncated() {
+    let error = generate_error("truncated.svd");
+    assert!(matches!(error, ParseError::UnexpectedEof(ref element, _) if element == "register"));
+    assert_snapshot("truncated.err.snap", &format!("{}\n", error));
+}
+
+#[test]
+fn mismatched() {
+    let error = generate_error("mismatched.svd");
+    assert!(matches!(error, ParseError::Xml(_, location) if location.line == 17));
+    assert_snapshot("mismatched.err.snap", &format!("{}\n", error));
+}
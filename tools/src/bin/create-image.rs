@@ -6,10 +6,15 @@ extern crate crc;
 use std::convert::TryInto;
 use std::fs::File;
 
-use tools::elf::{read_minielf, read_program};
+use tools::elf::{read_minielf, read_program, read_symbols};
+use tools::manifest::Manifest;
 use tools::tags::bflg::Bflg;
+use tools::tags::caps::Capabilities;
 use tools::tags::inie::IniE;
+use tools::tags::meas::Measurement;
 use tools::tags::memory::{MemoryRegion, MemoryRegions};
+use tools::tags::pmp::PmpRanges;
+use tools::tags::symb::SymbolTable;
 use tools::tags::xkrn::XousKernel;
 use tools::utils::{parse_csr_csv, parse_u32};
 use tools::xous_arguments::XousArguments;
@@ -122,6 +127,64 @@ fn main() {
                 .takes_value(false)
                 .help("Reduce kernel-userspace security and enable debugging programs"),
         )
+        .arg(
+            Arg::with_name("no-aslr")
+                .long("no-aslr")
+                .takes_value(false)
+                .help("Place process stacks and memory areas at fixed addresses"),
+        )
+        .arg(
+            Arg::with_name("pmp")
+                .long("pmp")
+                .takes_value(false)
+                .help("Have the loader use the PMP to protect kernel text and memory-mapped regions"),
+        )
+        .arg(
+            Arg::with_name("measure")
+                .long("measure")
+                .takes_value(false)
+                .help("Have the loader hash the kernel and initial programs so the kernel can report what was booted"),
+        )
+        .arg(
+            Arg::with_name("symbols")
+                .long("symbols")
+                .takes_value(false)
+                .help("Embed function names so faults and panics can be symbolized"),
+        )
+        .arg(
+            Arg::with_name("capabilities")
+                .long("capabilities")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("CAPS")
+                .help("Comma-separated capabilities for each --init, in the same order"),
+        )
+        .arg(
+            Arg::with_name("manifest")
+                .long("manifest")
+                .value_name("MANIFEST")
+                .takes_value(true)
+                .help("Write a manifest of the build inputs and image section digests"),
+        )
+        .arg(
+            Arg::with_name("manifest-info")
+                .long("manifest-info")
+                .value_name("KEY=VALUE")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Extra build information to record in the manifest"),
+        )
+        .arg(
+            Arg::with_name("manifest-input")
+                .long("manifest-input")
+                .value_name("FILE")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Extra file, such as Cargo.lock, to hash into the manifest"),
+        )
         .arg(
             Arg::with_name("output")
                 .value_name("OUTPUT")
@@ -171,7 +234,8 @@ fn main() {
 
     if let Some(soc_svd) = matches.value_of("svd") {
         let soc_svd_file = std::fs::File::open(soc_svd).unwrap();
-        let desc = svd2utra::parse_svd(soc_svd_file).unwrap();
+        let desc = svd2utra::parse_svd(soc_svd_file)
+            .unwrap_or_else(|e| panic!("{}: {}", soc_svd, e));
         let mut map = std::collections::BTreeMap::new();
 
         let mut csr_top = 0;
@@ -211,24 +275,81 @@ fn main() {
         args.add(ram_config.regions);
     }
 
-    if matches.is_present("debug") {
-        args.add(Bflg::new().debug());
+    if matches.is_present("debug") || matches.is_present("no-aslr") {
+        let mut flags = Bflg::new();
+        if matches.is_present("debug") {
+            flags = flags.debug();
+        }
+        if matches.is_present("no-aslr") {
+            flags = flags.no_aslr();
+        }
+        args.add(flags);
     }
 
-    let kernel = read_program(
-        matches
-            .value_of("kernel")
-            .expect("kernel was somehow missing"),
-    )
-    .expect("unable to read kernel");
+    let kernel_path = matches
+        .value_of("kernel")
+        .expect("kernel was somehow missing");
+    let kernel = read_program(kernel_path).expect("unable to read kernel");
 
     if let Some(init_paths) = matches.values_of("init") {
         for init_path in init_paths {
             let init = read_minielf(init_path).expect("couldn't parse init file");
+            if let Some(page) = init.write_execute_page() {
+                eprintln!(
+                    "Error: {}: page {:08x} would be both writable and executable -- \
+                     sections must be page-aligned",
+                    init_path, page
+                );
+                std::process::exit(1);
+            }
             args.add(IniE::new(init.entry_point, init.sections, init.program));
         }
     }
 
+    // The kernel is always PID 1, and initial programs are assigned PIDs
+    // in the order they appear on the command line.
+    if let Some(cap_lists) = matches.values_of("capabilities") {
+        let init_count = matches.values_of("init").map(|i| i.len()).unwrap_or(0);
+        if cap_lists.len() != init_count {
+            eprintln!(
+                "Error: {} --capabilities given for {} --init programs",
+                cap_lists.len(),
+                init_count
+            );
+            return;
+        }
+        let mut caps = Capabilities::new();
+        for (pid, list) in (2..).zip(cap_lists) {
+            match Capabilities::parse(list) {
+                Ok(c) => caps.add(pid, c),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    return;
+                }
+            }
+        }
+        args.add(caps);
+    }
+
+    if matches.is_present("pmp") {
+        args.add(PmpRanges::new());
+    }
+
+    if matches.is_present("measure") {
+        args.add(Measurement::new());
+    }
+
+    if matches.is_present("symbols") {
+        let symbols = read_symbols(kernel_path).expect("couldn't read kernel symbols");
+        args.add(SymbolTable::new(1, &symbols));
+        if let Some(init_paths) = matches.values_of("init") {
+            for (pid, init_path) in (2..).zip(init_paths) {
+                let symbols = read_symbols(init_path).expect("couldn't read init symbols");
+                args.add(SymbolTable::new(pid, &symbols));
+            }
+        }
+    }
+
     let xkrn = XousKernel::new(
         kernel.text_offset,
         kernel.text_size,
@@ -258,4 +379,34 @@ fn main() {
         ram_config.memory_required
     );
     println!("Image created in file {}", output_filename);
+
+    if let Some(manifest_filename) = matches.value_of("manifest") {
+        let mut manifest = Manifest::new();
+        if let Some(infos) = matches.values_of("manifest-info") {
+            for info in infos {
+                let mut parts = info.splitn(2, '=');
+                let key = parts.next().unwrap();
+                manifest.info(key, parts.next().unwrap_or(""));
+            }
+        }
+        manifest.input("kernel", kernel_path).expect("couldn't hash kernel");
+        if let Some(init_paths) = matches.values_of("init") {
+            for init_path in init_paths {
+                manifest.input("init", init_path).expect("couldn't hash init");
+            }
+        }
+        for (role, arg) in &[("svd", "svd"), ("csv", "csv"), ("extra", "manifest-input")] {
+            if let Some(paths) = matches.values_of(arg) {
+                for path in paths {
+                    manifest
+                        .input(role, path)
+                        .unwrap_or_else(|e| panic!("couldn't hash {}: {}", path, e));
+                }
+            }
+        }
+        let image = std::fs::read(output_filename).expect("couldn't read back image");
+        manifest.sections(&image).expect("couldn't split image into sections");
+        std::fs::write(manifest_filename, manifest.to_string()).expect("couldn't write manifest");
+        println!("Manifest written to {}", manifest_filename);
+    }
 }
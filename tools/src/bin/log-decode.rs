@@ -0,0 +1,163 @@
+use clap::{crate_version, App, Arg};
+use log_server::frame::{scan_frame, Record, Scanned};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::process;
+
+struct Filter {
+    max_level: log::Level,
+    pid: Option<u32>,
+    target: Option<String>,
+}
+
+impl Filter {
+    fn matches(&self, record: &Record) -> bool {
+        record.level.map(|l| l <= self.max_level).unwrap_or(true)
+            && self.pid.map(|pid| pid == record.pid).unwrap_or(true)
+            && self
+                .target
+                .as_ref()
+                .map(|t| record.target.starts_with(t.as_str()))
+                .unwrap_or(true)
+    }
+}
+
+fn print_record(out: &mut dyn Write, timestamp: u64, record: &Record) -> io::Result<()> {
+    let level = record.level.map(|l| l.as_str()).unwrap_or("?");
+    write!(
+        out,
+        "[{:>12}] {:<5} PID{} {}: {}",
+        timestamp, level, record.pid, record.target, record.message
+    )?;
+    for (key, value) in record.pairs() {
+        write!(out, " {}={}", key, value)?;
+    }
+    writeln!(out)
+}
+
+fn main() {
+    let matches = App::new("Xous Log Decoder")
+        .version(crate_version!())
+        .about("Decode binary log frames written by the Xous log server")
+        .arg(
+            Arg::with_name("input")
+                .value_name("INPUT")
+                .help("File or serial device to read from, or stdin if omitted"),
+        )
+        .arg(
+            Arg::with_name("level")
+                .short("l")
+                .long("level")
+                .takes_value(true)
+                .value_name("LEVEL")
+                .help("Most verbose level to show: error, warn, info, debug, or trace"),
+        )
+        .arg(
+            Arg::with_name("pid")
+                .short("p")
+                .long("pid")
+                .takes_value(true)
+                .value_name("PID")
+                .help("Only show records from this process"),
+        )
+        .arg(
+            Arg::with_name("target")
+                .short("t")
+                .long("target")
+                .takes_value(true)
+                .value_name("TARGET")
+                .help("Only show records whose target starts with this string"),
+        )
+        .arg(
+            Arg::with_name("frames-only")
+                .long("frames-only")
+                .takes_value(false)
+                .help("Drop any text that isn't part of a frame"),
+        )
+        .get_matches();
+
+    let max_level = match matches.value_of("level") {
+        None => log::Level::Trace,
+        Some(name) => match name.parse() {
+            Ok(level) => level,
+            Err(_) => {
+                eprintln!("Error: unrecognized level \"{}\"", name);
+                process::exit(1);
+            }
+        },
+    };
+    let pid = matches.value_of("pid").map(|p| {
+        p.parse().unwrap_or_else(|_| {
+            eprintln!("Error: invalid PID \"{}\"", p);
+            process::exit(1);
+        })
+    });
+    let filter = Filter {
+        max_level,
+        pid,
+        target: matches.value_of("target").map(|t| t.to_owned()),
+    };
+    let passthrough = !matches.is_present("frames-only");
+
+    let mut input: Box<dyn Read> = match matches.value_of("input") {
+        Some(path) => Box::new(File::open(path).unwrap_or_else(|e| {
+            eprintln!("Error: unable to open {}: {}", path, e);
+            process::exit(1);
+        })),
+        None => Box::new(io::stdin()),
+    };
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+
+    // Bytes that have been read but not yet decoded.  Text is passed on as
+    // soon as it's clear it isn't the start of a frame, so this only holds
+    // onto a frame that hasn't fully arrived.
+    let mut pending: Vec<u8> = vec![];
+    let mut chunk = [0u8; 4096];
+    let mut eof = false;
+    loop {
+        let consumed = match scan_frame(&pending) {
+            Scanned::Text(len) => {
+                if passthrough {
+                    out.write_all(&pending[..len]).unwrap();
+                }
+                len
+            }
+            Scanned::Frame {
+                timestamp,
+                record,
+                len,
+            } => {
+                match Record::parse(record) {
+                    Some(record) if filter.matches(&record) => {
+                        print_record(&mut out, timestamp, &record).unwrap()
+                    }
+                    Some(_) => (),
+                    None => eprintln!("log-decode: dropping malformed record"),
+                }
+                len
+            }
+            Scanned::Corrupt(len) => {
+                eprintln!("log-decode: dropping frame with bad CRC");
+                len
+            }
+            Scanned::Incomplete if eof => break,
+            Scanned::Incomplete => 0,
+        };
+        if consumed > 0 {
+            pending.drain(..consumed);
+            continue;
+        }
+
+        out.flush().unwrap();
+        match input.read(&mut chunk) {
+            Ok(0) => eof = true,
+            Ok(len) => pending.extend_from_slice(&chunk[..len]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => {
+                eprintln!("Error: unable to read input: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+}
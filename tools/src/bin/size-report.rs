@@ -0,0 +1,92 @@
+use std::path::{Path, PathBuf};
+
+use tools::elf::{read_minielf, MiniElfFlags};
+
+/// Bytes a program occupies in the image, and in RAM once it is loaded.
+/// Xous copies every program into RAM, so RAM includes the image bytes
+/// as well as .bss.
+#[derive(Clone, Copy, Default)]
+struct Size {
+    flash: i64,
+    ram: i64,
+}
+
+fn program_size(path: &Path) -> Result<Size, String> {
+    let elf = read_minielf(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut size = Size::default();
+    for section in &elf.sections {
+        if !section.flags.contains(MiniElfFlags::NOCOPY) {
+            size.flash += section.size as i64;
+        }
+        size.ram += section.size as i64;
+    }
+    Ok(size)
+}
+
+fn delta(value: i64, base: i64) -> String {
+    if value == base {
+        String::new()
+    } else {
+        format!(" ({:+})", value - base)
+    }
+}
+
+fn main() {
+    let configs: Vec<PathBuf> = std::env::args().skip(1).map(PathBuf::from).collect();
+    if configs.is_empty() {
+        eprintln!("Usage: size-report BASELINE_DIR [OTHER_DIR ...]");
+        eprintln!("Each directory holds the program ELFs of one build configuration.");
+        std::process::exit(1);
+    }
+
+    // Programs are the files in the baseline, in name order.
+    let mut programs: Vec<String> = std::fs::read_dir(&configs[0])
+        .unwrap_or_else(|e| panic!("couldn't read {}: {}", configs[0].display(), e))
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_file())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect();
+    programs.sort();
+
+    print!("{:<20}", "process");
+    for config in &configs {
+        let name = config.file_name().unwrap_or_default().to_string_lossy();
+        print!(" {:>24} {:>24}", format!("{} FLASH", name), format!("{} RAM", name));
+    }
+    println!();
+
+    let mut totals = vec![Size::default(); configs.len()];
+    for program in &programs {
+        print!("{:<20}", program);
+        let mut base = None;
+        for (config, total) in configs.iter().zip(totals.iter_mut()) {
+            match program_size(&config.join(program)) {
+                Ok(size) => {
+                    let b: Size = *base.get_or_insert(size);
+                    total.flash += size.flash;
+                    total.ram += size.ram;
+                    print!(
+                        " {:>24} {:>24}",
+                        format!("{}{}", size.flash, delta(size.flash, b.flash)),
+                        format!("{}{}", size.ram, delta(size.ram, b.ram)),
+                    );
+                }
+                Err(e) => {
+                    eprintln!("{}", e);
+                    print!(" {:>24} {:>24}", "-", "-");
+                }
+            }
+        }
+        println!();
+    }
+
+    print!("{:<20}", "total");
+    for total in &totals {
+        print!(
+            " {:>24} {:>24}",
+            format!("{}{}", total.flash, delta(total.flash, totals[0].flash)),
+            format!("{}{}", total.ram, delta(total.ram, totals[0].ram)),
+        );
+    }
+    println!();
+}
@@ -0,0 +1,73 @@
+#[macro_use]
+extern crate clap;
+
+use clap::{App, Arg};
+use tools::manifest::Manifest;
+use tools::utils::parse_u32;
+
+fn main() {
+    let matches = App::new("Xous Image Verifier")
+        .version(crate_version!())
+        .about("Check an image, such as one read back from a device, against a build manifest")
+        .arg(
+            Arg::with_name("manifest")
+                .value_name("MANIFEST")
+                .required(true)
+                .help("Manifest written by create-image"),
+        )
+        .arg(
+            Arg::with_name("offset")
+                .long("offset")
+                .value_name("OFFSET")
+                .takes_value(true)
+                .help("Offset of the argument image within IMAGE, e.g. 0x10000 for xous.img"),
+        )
+        .arg(
+            Arg::with_name("image")
+                .value_name("IMAGE")
+                .required(true)
+                .help("Argument image to check"),
+        )
+        .get_matches();
+
+    let manifest_filename = matches.value_of("manifest").unwrap();
+    let image_filename = matches.value_of("image").unwrap();
+
+    let text = std::fs::read_to_string(manifest_filename)
+        .unwrap_or_else(|e| panic!("couldn't read {}: {}", manifest_filename, e));
+    let manifest = match Manifest::parse(&text) {
+        Ok(m) => m,
+        Err(e) => {
+            eprintln!("Error: {}: {}", manifest_filename, e);
+            std::process::exit(1);
+        }
+    };
+    let image = std::fs::read(image_filename)
+        .unwrap_or_else(|e| panic!("couldn't read {}: {}", image_filename, e));
+    let offset = match matches.value_of("offset").map(parse_u32) {
+        None => 0,
+        Some(Ok(o)) => o as usize,
+        Some(Err(e)) => {
+            eprintln!("Error: couldn't parse offset: {:?}", e);
+            std::process::exit(1);
+        }
+    };
+    if offset > image.len() {
+        eprintln!("Error: offset is past the end of {}", image_filename);
+        std::process::exit(1);
+    }
+
+    match manifest.verify(&image[offset..]) {
+        Ok(problems) if problems.is_empty() => println!("{} matches the manifest", image_filename),
+        Ok(problems) => {
+            for problem in problems {
+                println!("{}", problem);
+            }
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("Error: {}: {}", image_filename, e);
+            std::process::exit(1);
+        }
+    }
+}
@@ -5,7 +5,8 @@ use std::fs::File;
 use std::io::{Cursor, Read, Seek, SeekFrom, Write};
 use std::path::Path;
 use xmas_elf::program::Type as ProgramType;
-use xmas_elf::sections::ShType;
+use xmas_elf::sections::{SectionData, ShType};
+use xmas_elf::symbol_table::{Entry, Type as SymbolType};
 use xmas_elf::ElfFile;
 
 // Normal ELF flags
@@ -61,6 +62,19 @@ impl fmt::Display for MiniElfSection {
     }
 }
 
+/// A single function symbol, used to resolve addresses at runtime
+#[derive(Debug)]
+pub struct ElfSymbol {
+    /// Demangled name of the function, without the trailing hash
+    pub name: String,
+
+    /// Virtual address of the start of the function
+    pub start: u32,
+
+    /// Size of the function, in bytes
+    pub size: u32,
+}
+
 /// Describes a Mini ELF file, suitable for loading into RAM
 pub struct MiniElf {
     /// Virtual address of the entrypoint
@@ -73,6 +87,29 @@ pub struct MiniElf {
     pub program: Vec<u8>,
 }
 
+impl MiniElf {
+    /// Return the address of the first page that holds both a writable and
+    /// an executable section.  The loader refuses to map such a page, so
+    /// sections must be page-aligned.
+    pub fn write_execute_page(&self) -> Option<u32> {
+        let pages = |s: &MiniElfSection| {
+            let first = s.virt & !0xfff;
+            let last = (s.virt + s.size.max(1) - 1) & !0xfff;
+            first..=last
+        };
+        for w in self.sections.iter().filter(|s| s.flags.contains(MiniElfFlags::WRITE)) {
+            for x in self.sections.iter().filter(|s| s.flags.contains(MiniElfFlags::EXECUTE)) {
+                let (w, x) = (pages(w), pages(x));
+                let first = *w.start().max(x.start());
+                if first <= *w.end().min(x.end()) {
+                    return Some(first);
+                }
+            }
+        }
+        None
+    }
+}
+
 #[derive(Debug)]
 pub enum ElfReadError {
     /// Read an unexpected number of bytes
@@ -357,3 +394,38 @@ pub fn read_minielf<P: AsRef<Path>>(filename: P) -> Result<MiniElf, ElfReadError
         program: program_data.into_inner(),
     })
 }
+
+/// Read all function symbols out of an ELF file, sorted by address. Symbols
+/// that have no size are skipped, since they can't be used to resolve an
+/// address.
+pub fn read_symbols<P: AsRef<Path>>(filename: P) -> Result<Vec<ElfSymbol>, ElfReadError> {
+    let mut b = Vec::new();
+    {
+        let mut fi = File::open(filename).map_err(ElfReadError::OpenElfError)?;
+        fi.read_to_end(&mut b).map_err(ElfReadError::ReadFileError)?;
+    }
+    let elf = ElfFile::new(&b).map_err(ElfReadError::ParseElfError)?;
+
+    let mut symbols = vec![];
+    for s in elf.section_iter() {
+        let entries = match s.get_data(&elf) {
+            Ok(SectionData::SymbolTable32(entries)) => entries,
+            _ => continue,
+        };
+        for entry in entries {
+            if entry.get_type() != Ok(SymbolType::Func) || entry.size() == 0 {
+                continue;
+            }
+            let name = entry.get_name(&elf).unwrap_or("<<error>>");
+            symbols.push(ElfSymbol {
+                name: format!("{:#}", rustc_demangle::demangle(name)),
+                start: entry.value() as u32,
+                size: entry.size() as u32,
+            });
+        }
+    }
+    symbols.sort_by_key(|sym| sym.start);
+    symbols.dedup_by_key(|sym| sym.start);
+    debug!("Found {} function symbols", symbols.len());
+    Ok(symbols)
+}
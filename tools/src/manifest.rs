@@ -0,0 +1,311 @@
+//! Build manifests record every input that went into an image, along with
+//! a digest of each section of the result, so that a flashed image can be
+//! checked against the build that claims to have produced it.
+//!
+//! A manifest is a text file with one entry per line:
+//!
+//! ```text
+//! info rustc rustc 1.48.0 (7eac88abb 2020-11-16)
+//! info rustc-commit 7eac88abb2e57e752f3302f02be5f3ce3d7adfb4
+//! info features-kernel default,debug-print
+//! input kernel target/riscv32imac-unknown-none-elf/release/xous-kernel 9f86d0...
+//! section XArg 5e884898...
+//! ```
+//!
+//! `info` lines are free-form values, while `input` and `section` lines end
+//! in a hex SHA-256 digest.
+
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::io;
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    let digest = Sha256::digest(data);
+    let mut s = String::with_capacity(64);
+    for b in digest.iter() {
+        s.push_str(&format!("{:02x}", b));
+    }
+    s
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Entry {
+    /// A fact about the build environment, such as the compiler version
+    Info(String, String),
+    /// A file that was read to build the image, and its digest
+    Input(String, String, String),
+    /// A section of the finished image, and its digest
+    Section(String, String),
+}
+
+#[derive(Debug, Default)]
+pub struct Manifest {
+    pub entries: Vec<Entry>,
+}
+
+impl Manifest {
+    pub fn new() -> Manifest {
+        Default::default()
+    }
+
+    pub fn info(&mut self, key: &str, value: &str) {
+        self.entries.push(Entry::Info(key.to_owned(), value.to_owned()));
+    }
+
+    /// Hash the file at `path` and record it as the input `role`
+    pub fn input(&mut self, role: &str, path: &str) -> io::Result<()> {
+        let data = std::fs::read(path)?;
+        self.entries
+            .push(Entry::Input(role.to_owned(), path.to_owned(), sha256_hex(&data)));
+        Ok(())
+    }
+
+    /// Record a digest for each section of `image`
+    pub fn sections(&mut self, image: &[u8]) -> Result<(), String> {
+        self.info("image-size", &image.len().to_string());
+        for (name, data) in image_sections(image)? {
+            self.entries.push(Entry::Section(name, sha256_hex(data)));
+        }
+        Ok(())
+    }
+
+    pub fn parse(text: &str) -> Result<Manifest, String> {
+        let mut manifest = Manifest::new();
+        for (line_no, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut words = line.splitn(2, ' ');
+            let kind = words.next().unwrap_or("");
+            let rest = words.next().unwrap_or("");
+            let entry = match kind {
+                "info" => {
+                    let mut parts = rest.splitn(2, ' ');
+                    let key = parts.next().unwrap_or("");
+                    Entry::Info(key.to_owned(), parts.next().unwrap_or("").to_owned())
+                }
+                "input" => {
+                    let parts: Vec<&str> = rest.rsplitn(2, ' ').collect();
+                    let mut role_path = parts.get(1).unwrap_or(&"").splitn(2, ' ');
+                    let role = role_path.next().unwrap_or("");
+                    let path = role_path.next().unwrap_or("");
+                    if parts.len() != 2 || path.is_empty() {
+                        return Err(format!("line {}: malformed input entry", line_no + 1));
+                    }
+                    Entry::Input(role.to_owned(), path.to_owned(), parts[0].to_owned())
+                }
+                "section" => {
+                    let parts: Vec<&str> = rest.split(' ').collect();
+                    if parts.len() != 2 {
+                        return Err(format!("line {}: malformed section entry", line_no + 1));
+                    }
+                    Entry::Section(parts[0].to_owned(), parts[1].to_owned())
+                }
+                _ => return Err(format!("line {}: unknown entry \"{}\"", line_no + 1, kind)),
+            };
+            manifest.entries.push(entry);
+        }
+        Ok(manifest)
+    }
+
+    /// Compare the section digests of `image` against this manifest,
+    /// returning a description of each difference.  Anything past the
+    /// recorded image size, such as erased flash, is ignored.
+    pub fn verify(&self, mut image: &[u8]) -> Result<Vec<String>, String> {
+        let size = self.entries.iter().find_map(|e| match e {
+            Entry::Info(key, value) if key == "image-size" => value.parse::<usize>().ok(),
+            _ => None,
+        });
+        if let Some(size) = size {
+            if image.len() > size {
+                image = &image[..size];
+            }
+        }
+        let actual = image_sections(image)?;
+        let expected: Vec<(&str, &str)> = self
+            .entries
+            .iter()
+            .filter_map(|e| match e {
+                Entry::Section(name, digest) => Some((name.as_str(), digest.as_str())),
+                _ => None,
+            })
+            .collect();
+
+        let mut problems = vec![];
+        for (name, digest) in &expected {
+            match actual.iter().find(|(n, _)| n == name) {
+                None => problems.push(format!("section {} is missing from the image", name)),
+                Some((_, data)) => {
+                    let found = sha256_hex(data);
+                    if found != *digest {
+                        problems.push(format!(
+                            "section {} has digest {}, expected {}",
+                            name, found, digest
+                        ));
+                    }
+                }
+            }
+        }
+        for (name, _) in &actual {
+            if !expected.iter().any(|(n, _)| n == name) {
+                problems.push(format!("section {} is not in the manifest", name));
+            }
+        }
+        Ok(problems)
+    }
+}
+
+impl fmt::Display for Manifest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for entry in &self.entries {
+            match entry {
+                Entry::Info(key, value) => writeln!(f, "info {} {}", key, value)?,
+                Entry::Input(role, path, digest) => {
+                    writeln!(f, "input {} {} {}", role, path, digest)?
+                }
+                Entry::Section(name, digest) => writeln!(f, "section {} {}", name, digest)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Split an argument image into sections: one per tag, named after the tag
+/// and numbered when a tag appears more than once, followed by `payload`
+/// for the program data after the tags.
+pub fn image_sections(image: &[u8]) -> Result<Vec<(String, &[u8])>, String> {
+    fn word(image: &[u8], offset: usize) -> Result<u32, String> {
+        image
+            .get(offset..offset + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .ok_or_else(|| format!("image is truncated at offset {}", offset))
+    }
+
+    if word(image, 0)? != u32::from_le_bytes(*b"XArg") {
+        return Err("image does not start with an XArg tag".to_owned());
+    }
+    let tags_len = word(image, 8)? as usize * 4;
+    if tags_len > image.len() {
+        return Err("image is shorter than its tag list".to_owned());
+    }
+
+    let mut sections: Vec<(String, &[u8])> = vec![];
+    let mut offset = 0;
+    while offset < tags_len {
+        let code = word(image, offset)?.to_le_bytes();
+        let size = (word(image, offset + 4)? >> 16) as usize * 4;
+        let end = offset + 8 + size;
+        if end > tags_len {
+            return Err(format!("tag at offset {} runs past the tag list", offset));
+        }
+        let tag = String::from_utf8_lossy(&code).to_string();
+        let count = sections.iter().filter(|(n, _)| n.starts_with(&tag)).count();
+        let name = if count == 0 { tag } else { format!("{}.{}", tag, count) };
+        sections.push((name, &image[offset..end]));
+        offset = end;
+    }
+    sections.push(("payload".to_owned(), &image[tags_len..]));
+    Ok(sections)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A small argument image: an `XArg` tag, a `Test` tag, and a payload
+    fn sample_image() -> Vec<u8> {
+        let mut image = vec![];
+        image.extend_from_slice(b"XArg");
+        image.extend_from_slice(&(2u32 << 16).to_le_bytes());
+        image.extend_from_slice(&7u32.to_le_bytes());
+        image.extend_from_slice(&1u32.to_le_bytes());
+        image.extend_from_slice(b"Test");
+        image.extend_from_slice(&(1u32 << 16).to_le_bytes());
+        image.extend_from_slice(&0x1234_5678u32.to_le_bytes());
+        image.extend_from_slice(b"payload!");
+        image
+    }
+
+    fn sample_manifest() -> Manifest {
+        let mut manifest = Manifest::new();
+        manifest.info("rustc", "rustc 1.48.0 (7eac88abb 2020-11-16)");
+        manifest.info("features-kernel", "default,debug-print");
+        manifest.entries.push(Entry::Input(
+            "init".to_owned(),
+            "target/release/shell".to_owned(),
+            sha256_hex(b"shell"),
+        ));
+        manifest.sections(&sample_image()).unwrap();
+        manifest
+    }
+
+    #[test]
+    fn sections_are_named_after_tags() {
+        let image = sample_image();
+        let names: Vec<String> = image_sections(&image)
+            .unwrap()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(names, ["XArg", "Test", "payload"]);
+        assert!(image_sections(&image[..20]).is_err());
+        assert!(image_sections(b"not an image").is_err());
+    }
+
+    #[test]
+    fn parse_round_trip() {
+        let manifest = sample_manifest();
+        let text = manifest.to_string();
+        assert!(text.contains("info features-kernel default,debug-print\n"));
+        let parsed = Manifest::parse(&format!("# comment\n\n{}", text)).unwrap();
+        assert_eq!(parsed.entries, manifest.entries);
+    }
+
+    #[test]
+    fn parse_rejects_malformed_lines() {
+        assert!(Manifest::parse("bogus line").is_err());
+        assert!(Manifest::parse("input kernel").is_err());
+        assert!(Manifest::parse("section XArg").is_err());
+        assert_eq!(
+            Manifest::parse("info rustc\nsection XArg 00 extra").unwrap_err(),
+            "line 2: malformed section entry"
+        );
+    }
+
+    #[test]
+    fn verify_matching_image() {
+        let manifest = sample_manifest();
+        let mut image = sample_image();
+        assert_eq!(manifest.verify(&image).unwrap(), Vec::<String>::new());
+
+        // Erased flash after the image is ignored
+        image.extend_from_slice(&[0xff; 64]);
+        assert_eq!(manifest.verify(&image).unwrap(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn verify_reports_differences() {
+        let manifest = sample_manifest();
+
+        let mut image = sample_image();
+        image[24] ^= 1;
+        let problems = manifest.verify(&image).unwrap();
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("section Test has digest"));
+
+        let mut extra = manifest.entries.clone();
+        extra.retain(|e| !matches!(e, Entry::Section(name, _) if name == "Test"));
+        extra.push(Entry::Section("Gone".to_owned(), sha256_hex(b"")));
+        let problems = Manifest { entries: extra }.verify(&sample_image()).unwrap();
+        assert_eq!(
+            problems,
+            [
+                "section Gone is missing from the image",
+                "section Test is not in the manifest"
+            ]
+        );
+
+        assert!(manifest.verify(b"XArg").is_err());
+    }
+}
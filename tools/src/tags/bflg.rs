@@ -12,6 +12,9 @@ pub struct Bflg {
 
     /// Set the SUM bit in $mstatus to allow Supervisor to access User memory
     debug_: bool,
+
+    /// Leave process stacks and memory areas at fixed addresses
+    no_aslr_: bool,
 }
 
 impl fmt::Display for Bflg {
@@ -33,6 +36,12 @@ impl fmt::Display for Bflg {
         } else {
             write!(f, " -debug")?;
         }
+
+        if self.no_aslr_ {
+            write!(f, " +NO_ASLR")?;
+        } else {
+            write!(f, " -no_aslr")?;
+        }
         writeln!(f)
     }
 }
@@ -58,6 +67,10 @@ impl Bflg {
         self.debug_ = true;
         self
     }
+    pub fn no_aslr(mut self) -> Bflg {
+        self.no_aslr_ = true;
+        self
+    }
 }
 
 impl XousArgument for Bflg {
@@ -79,6 +92,9 @@ impl XousArgument for Bflg {
         if self.debug_ {
             val |= 1 << 2;
         }
+        if self.no_aslr_ {
+            val |= 1 << 3;
+        }
         written += output.write(&val.to_le_bytes())?;
         Ok(written)
     }
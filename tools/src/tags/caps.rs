@@ -0,0 +1,86 @@
+use crate::xous_arguments::{XousArgument, XousArgumentCode, XousSize};
+use std::fmt;
+use std::io;
+
+/// Names accepted for each capability bit. These must match the
+/// `Capabilities` flags in the `xous` crate.
+const CAPABILITY_NAMES: &[(&str, u32)] = &[
+    ("map-physical", 1 << 0),
+    ("claim-interrupt", 1 << 1),
+    ("create-server", 1 << 2),
+    ("read-audit-log", 1 << 3),
+    ("debug", 1 << 4),
+    ("set-quota", 1 << 5),
+];
+
+/// A table of the sensitive operations each initial process is allowed to
+/// perform. When the kernel is built with capability enforcement, processes
+/// that are not listed here are denied all of them.
+#[derive(Debug, Default)]
+pub struct Capabilities {
+    /// (pid, capability bits) for each process
+    entries: Vec<(u32, u32)>,
+}
+
+impl fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "    Caps:")?;
+        for (pid, caps) in &self.entries {
+            write!(f, "        PID {}:", pid)?;
+            for (name, bit) in CAPABILITY_NAMES {
+                if caps & bit != 0 {
+                    write!(f, " {}", name)?;
+                }
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl Capabilities {
+    pub fn new() -> Capabilities {
+        Default::default()
+    }
+
+    pub fn add(&mut self, pid: u32, caps: u32) {
+        self.entries.push((pid, caps));
+    }
+
+    /// Parse a comma-separated list of capability names, such as
+    /// `claim-interrupt,create-server`. `all` and `none` are also accepted.
+    pub fn parse(list: &str) -> Result<u32, String> {
+        let mut caps = 0;
+        for name in list.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+            caps |= match name {
+                "all" => CAPABILITY_NAMES.iter().fold(0, |acc, (_, bit)| acc | bit),
+                "none" => 0,
+                _ => CAPABILITY_NAMES
+                    .iter()
+                    .find(|(n, _)| *n == name)
+                    .map(|(_, bit)| *bit)
+                    .ok_or_else(|| format!("unrecognized capability \"{}\"", name))?,
+            };
+        }
+        Ok(caps)
+    }
+}
+
+impl XousArgument for Capabilities {
+    fn code(&self) -> XousArgumentCode {
+        u32::from_le_bytes(*b"Caps")
+    }
+
+    fn length(&self) -> XousSize {
+        (self.entries.len() * 8) as XousSize
+    }
+
+    fn serialize(&self, output: &mut dyn io::Write) -> io::Result<usize> {
+        let mut written = 0;
+        for (pid, caps) in &self.entries {
+            written += output.write(&pid.to_le_bytes())?;
+            written += output.write(&caps.to_le_bytes())?;
+        }
+        Ok(written)
+    }
+}
@@ -0,0 +1,40 @@
+use crate::xous_arguments::{XousArgument, XousArgumentCode, XousSize};
+use std::fmt;
+use std::io;
+
+/// Asks the loader to measure the kernel and every initial process before
+/// starting them. The tag is written out empty; the loader fills in the
+/// number of images it hashed followed by the 32-byte measurement, which
+/// the kernel reports to userspace.
+#[derive(Debug, Default)]
+pub struct Measurement {}
+
+impl fmt::Display for Measurement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "    Meas: measured boot")
+    }
+}
+
+impl Measurement {
+    pub fn new() -> Measurement {
+        Default::default()
+    }
+}
+
+impl XousArgument for Measurement {
+    fn code(&self) -> XousArgumentCode {
+        u32::from_le_bytes(*b"Meas")
+    }
+
+    fn length(&self) -> XousSize {
+        36
+    }
+
+    fn serialize(&self, output: &mut dyn io::Write) -> io::Result<usize> {
+        let mut written = 0;
+        for _ in 0..9 {
+            written += output.write(&0u32.to_le_bytes())?;
+        }
+        Ok(written)
+    }
+}
@@ -1,4 +1,8 @@
 pub mod bflg;
+pub mod caps;
 pub mod inie;
+pub mod meas;
 pub mod memory;
+pub mod pmp;
+pub mod symb;
 pub mod xkrn;
@@ -0,0 +1,43 @@
+use crate::xous_arguments::{XousArgument, XousArgumentCode, XousSize};
+use std::fmt;
+use std::io;
+
+/// The number of address ranges the loader can protect. Each range uses
+/// two PMP entries, and one more entry covers the rest of memory.
+pub const PMP_RANGES: usize = 7;
+
+/// Asks the loader to lock down the kernel's text and the extra memory
+/// regions using the RISC-V PMP. The tag is written out empty; the loader
+/// fills in the ranges it configured so that the kernel can report them.
+#[derive(Debug, Default)]
+pub struct PmpRanges {}
+
+impl fmt::Display for PmpRanges {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "    PMPr: {} ranges", PMP_RANGES)
+    }
+}
+
+impl PmpRanges {
+    pub fn new() -> PmpRanges {
+        Default::default()
+    }
+}
+
+impl XousArgument for PmpRanges {
+    fn code(&self) -> XousArgumentCode {
+        u32::from_le_bytes(*b"PMPr")
+    }
+
+    fn length(&self) -> XousSize {
+        (PMP_RANGES * 12) as XousSize
+    }
+
+    fn serialize(&self, output: &mut dyn io::Write) -> io::Result<usize> {
+        let mut written = 0;
+        for _ in 0..PMP_RANGES * 3 {
+            written += output.write(&0u32.to_le_bytes())?;
+        }
+        Ok(written)
+    }
+}
@@ -0,0 +1,104 @@
+use crate::elf::ElfSymbol;
+use crate::xous_arguments::{XousArgument, XousArgumentCode, XousSize};
+use log::warn;
+use std::fmt;
+use std::io;
+
+/// Names longer than this are truncated to keep the table compact
+const MAX_NAME_LENGTH: usize = 63;
+
+/// Tags are limited to 65535 words, including the two-word header
+const MAX_TAG_SIZE: usize = 65535 * 4;
+
+/// A compact table of function names for a single process, used by the
+/// kernel to resolve raw addresses when printing faults and panics.
+///
+/// The tag is laid out as a header of `pid` and `count`, followed by `count`
+/// entries of `(start, size, name_offset)`, followed by a table of
+/// NUL-terminated names. `name_offset` is relative to the start of the
+/// name table.
+#[derive(Debug)]
+pub struct SymbolTable {
+    /// The process these symbols belong to. The kernel is PID 1, and
+    /// initial programs are numbered in the order they were added.
+    pid: u32,
+
+    /// (start, size, name_offset) for each symbol, sorted by address
+    entries: Vec<(u32, u32, u32)>,
+
+    /// NUL-terminated names, padded to 4 bytes
+    names: Vec<u8>,
+}
+
+impl fmt::Display for SymbolTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "    Symb: {} symbols for PID {} ({} bytes of names)",
+            self.entries.len(),
+            self.pid,
+            self.names.len()
+        )
+    }
+}
+
+impl SymbolTable {
+    pub fn new(pid: u32, symbols: &[ElfSymbol]) -> SymbolTable {
+        let mut entries = vec![];
+        let mut names = vec![];
+        for symbol in symbols {
+            let mut name_len = symbol.name.len().min(MAX_NAME_LENGTH);
+            while !symbol.name.is_char_boundary(name_len) {
+                name_len -= 1;
+            }
+            let name = &symbol.name.as_bytes()[..name_len];
+
+            let new_size = 8 + (entries.len() + 1) * 12 + names.len() + name.len() + 1 + 3;
+            if new_size > MAX_TAG_SIZE - 8 {
+                warn!(
+                    "symbol table for PID {} is full -- dropping {} symbols",
+                    pid,
+                    symbols.len() - entries.len()
+                );
+                break;
+            }
+
+            entries.push((symbol.start, symbol.size, names.len() as u32));
+            names.extend_from_slice(name);
+            names.push(0);
+        }
+
+        // pad the names to 4 bytes
+        while names.len() & 3 != 0 {
+            names.push(0);
+        }
+        SymbolTable {
+            pid,
+            entries,
+            names,
+        }
+    }
+}
+
+impl XousArgument for SymbolTable {
+    fn code(&self) -> XousArgumentCode {
+        u32::from_le_bytes(*b"Symb")
+    }
+
+    fn length(&self) -> XousSize {
+        (4 + 4 + self.entries.len() * 12 + self.names.len()) as XousSize
+    }
+
+    fn serialize(&self, output: &mut dyn io::Write) -> io::Result<usize> {
+        let mut written = 0;
+        written += output.write(&self.pid.to_le_bytes())?;
+        written += output.write(&(self.entries.len() as u32).to_le_bytes())?;
+        for (start, size, name_offset) in &self.entries {
+            written += output.write(&start.to_le_bytes())?;
+            written += output.write(&size.to_le_bytes())?;
+            written += output.write(&name_offset.to_le_bytes())?;
+        }
+        written += output.write(&self.names)?;
+        Ok(written)
+    }
+}
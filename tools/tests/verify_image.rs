@@ -0,0 +1,72 @@
+//! Run `verify-image` against a manifest and image written to a temporary
+//! directory, the way a build would leave them.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tools::manifest::Manifest;
+
+fn sample_image() -> Vec<u8> {
+    let mut image = vec![];
+    image.extend_from_slice(b"XArg");
+    image.extend_from_slice(&(2u32 << 16).to_le_bytes());
+    image.extend_from_slice(&6u32.to_le_bytes());
+    image.extend_from_slice(&1u32.to_le_bytes());
+    image.extend_from_slice(b"Test");
+    image.extend_from_slice(&0u32.to_le_bytes());
+    image.extend_from_slice(b"payload!");
+    image
+}
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("verify-image-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn verify(dir: &Path, image: &[u8], extra_args: &[&str]) -> (bool, String) {
+    let mut manifest = Manifest::new();
+    manifest.sections(&sample_image()).unwrap();
+    std::fs::write(dir.join("manifest.txt"), manifest.to_string()).unwrap();
+    std::fs::write(dir.join("image.bin"), image).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_verify-image"))
+        .arg(dir.join("manifest.txt"))
+        .args(extra_args)
+        .arg(dir.join("image.bin"))
+        .output()
+        .unwrap();
+    (
+        output.status.success(),
+        String::from_utf8_lossy(&output.stdout).into_owned(),
+    )
+}
+
+#[test]
+fn matching_image() {
+    let dir = scratch_dir("match");
+    let (ok, stdout) = verify(&dir, &sample_image(), &[]);
+    assert!(ok, "{}", stdout);
+    assert!(stdout.ends_with("matches the manifest\n"));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn image_at_an_offset() {
+    let dir = scratch_dir("offset");
+    let mut flash = vec![0xff; 0x100];
+    flash.extend_from_slice(&sample_image());
+    let (ok, stdout) = verify(&dir, &flash, &["--offset", "0x100"]);
+    assert!(ok, "{}", stdout);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn changed_payload() {
+    let dir = scratch_dir("changed");
+    let mut image = sample_image();
+    let last = image.len() - 1;
+    image[last] = b'?';
+    let (ok, stdout) = verify(&dir, &image, &[]);
+    assert!(!ok);
+    assert!(stdout.starts_with("section payload has digest"), "{}", stdout);
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -1,14 +1,58 @@
 use std::env;
+use std::path::Path;
 
-fn main() {
-    let svd_filename = env::var("XOUS_SVD_FILE")
-        .expect("Set the environment variable `XOUS_SVD_FILE` to point to an SVD file");
-    println!("cargo:rerun-if-env-changed=XOUS_SVD_FILE");
+/// Register maps that can be selected with a cargo feature. Each entry is
+/// the feature name, the environment variable that overrides its SVD file,
+/// and the SVD file to use if that variable isn't set.
+const TARGETS: &[(&str, &str, Option<&str>)] = &[
+    ("precursor-pvt", "XOUS_PRECURSOR_PVT_SVD", None),
+    ("renode", "XOUS_RENODE_SVD", Some("../emulation/renode.svd")),
+    ("hosted-stub", "XOUS_HOSTED_STUB_SVD", None),
+];
 
-    let svd_file_path = std::path::Path::new(&svd_filename);
+fn generate(svd_filename: &str, dest_filename: &str) {
+    let svd_file_path = Path::new(svd_filename);
     println!("cargo:rerun-if-changed={}", svd_file_path.canonicalize().unwrap().display());
 
     let src_file = std::fs::File::open(svd_filename).expect("couldn't open src file");
-    let mut dest_file = std::fs::File::create("src/generated.rs").expect("couldn't open dest file");
-    svd2utra::generate(src_file, &mut dest_file).unwrap();
+    let mut dest_file = std::fs::File::create(dest_filename).expect("couldn't open dest file");
+    let options = svd2utra::Options {
+        accessors: env::var("CARGO_FEATURE_ACCESSORS").is_ok(),
+    };
+    // Print the error rather than its Debug form, so the failure names the
+    // file and the line of the offending element.
+    if let Err(e) = svd2utra::generate_with(src_file, &mut dest_file, &options) {
+        panic!("{}: {}", svd_filename, e);
+    }
+}
+
+fn main() {
+    let mut feature_selected = false;
+    for (feature, env_var, default_svd) in TARGETS {
+        let cargo_feature = format!("CARGO_FEATURE_{}", feature.to_uppercase().replace('-', "_"));
+        if env::var(cargo_feature).is_err() {
+            continue;
+        }
+        feature_selected = true;
+
+        println!("cargo:rerun-if-env-changed={}", env_var);
+        let svd_filename = env::var(env_var)
+            .ok()
+            .or_else(|| default_svd.map(|s| s.to_owned()))
+            .unwrap_or_else(|| {
+                panic!("Set the environment variable `{}` to point to an SVD file for `{}`", env_var, feature)
+            });
+
+        std::fs::create_dir_all("src/generated").expect("couldn't create src/generated");
+        generate(&svd_filename, &format!("src/generated/{}.rs", feature.replace('-', "_")));
+    }
+
+    // Without a feature, fall back to whatever SVD file the build system
+    // points us at.
+    if !feature_selected {
+        let svd_filename = env::var("XOUS_SVD_FILE")
+            .expect("Set the environment variable `XOUS_SVD_FILE` to point to an SVD file");
+        println!("cargo:rerun-if-env-changed=XOUS_SVD_FILE");
+        generate(&svd_filename, "src/generated.rs");
+    }
 }
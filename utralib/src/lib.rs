@@ -1,3 +1,34 @@
 #![cfg_attr(target_os = "none", no_std)]
+
+// Each SoC gets its own module, since cargo unifies features and more than
+// one may be enabled at once. `generated` points at the one drivers should
+// use, so they can compile against any of them via `utralib::generated::*`.
+
+#[cfg(feature = "precursor-pvt")]
+#[path = "generated/precursor_pvt.rs"]
+pub mod precursor_pvt;
+
+#[cfg(feature = "renode")]
+#[path = "generated/renode.rs"]
+pub mod renode;
+
+#[cfg(feature = "hosted-stub")]
+#[path = "generated/hosted_stub.rs"]
+pub mod hosted_stub;
+
+#[cfg(feature = "precursor-pvt")]
+pub use precursor_pvt as generated;
+
+#[cfg(all(feature = "renode", not(feature = "precursor-pvt")))]
+pub use renode as generated;
+
+#[cfg(all(
+    feature = "hosted-stub",
+    not(any(feature = "precursor-pvt", feature = "renode"))
+))]
+pub use hosted_stub as generated;
+
+#[cfg(not(any(feature = "precursor-pvt", feature = "renode", feature = "hosted-stub")))]
 pub mod generated;
+
 pub use generated::*;
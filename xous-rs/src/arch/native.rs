@@ -174,10 +174,12 @@ where
     T: Send + 'static,
     U: Send + 'static,
 {
+    // The kernel turns the lowest page of the stack into a guard page,
+    // so allocate one extra page to make up for it.
     let stack = crate::map_memory(
         None,
         None,
-        131_072,
+        131_072 + 4096,
         crate::MemoryFlags::R | crate::MemoryFlags::W | crate::MemoryFlags::RESERVE,
     )?;
     let start = unsafe { core::mem::transmute(*f) };
@@ -0,0 +1,246 @@
+//! Sending one event to many servers.
+//!
+//! A server that publishes events -- link state, suspend, key presses --
+//! keeps a `Broadcaster`.  Each subscriber hands the publisher a connection
+//! to one of its own servers and the message id it wants events to arrive
+//! with, and `broadcast()` sends a scalar message to each of them.
+//!
+//! Subscribers are notified in the order they subscribed.  A subscriber whose
+//! server has gone away is dropped from the list the next time an event is
+//! sent, and its connection is disconnected, so a process that exits without
+//! unsubscribing doesn't need any special handling.  A subscriber whose queue
+//! is full misses that event but stays subscribed.
+
+use crate::{Error, Message, ScalarMessage, CID};
+
+/// How many subscribers one `Broadcaster` can hold
+pub const MAX_SUBSCRIBERS: usize = 16;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Subscriber {
+    cid: CID,
+    opcode: usize,
+}
+
+#[derive(Debug)]
+pub struct Broadcaster {
+    subscribers: [Option<Subscriber>; MAX_SUBSCRIBERS],
+    count: usize,
+}
+
+impl Broadcaster {
+    pub const fn new() -> Broadcaster {
+        Broadcaster {
+            subscribers: [None; MAX_SUBSCRIBERS],
+            count: 0,
+        }
+    }
+
+    /// Send future events to `cid` as scalar messages with the id `opcode`.
+    /// Subscribing a connection that is already subscribed just changes its
+    /// opcode.  If the subscriber's server goes away, `broadcast()`
+    /// disconnects `cid`, which gives up one reference to the connection.
+    ///
+    /// # Errors
+    ///
+    /// * **OutOfMemory**: There are already `MAX_SUBSCRIBERS` subscribers
+    pub fn subscribe(&mut self, cid: CID, opcode: usize) -> Result<(), Error> {
+        if let Some(existing) = self.subscribers[..self.count]
+            .iter_mut()
+            .flatten()
+            .find(|s| s.cid == cid)
+        {
+            existing.opcode = opcode;
+            return Ok(());
+        }
+        if self.count >= MAX_SUBSCRIBERS {
+            return Err(Error::OutOfMemory);
+        }
+        self.subscribers[self.count] = Some(Subscriber { cid, opcode });
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Stop sending events to `cid`.  Returns `false` if it wasn't
+    /// subscribed.
+    pub fn unsubscribe(&mut self, cid: CID) -> bool {
+        match self.subscribers[..self.count]
+            .iter()
+            .position(|s| matches!(s, Some(s) if s.cid == cid))
+        {
+            Some(idx) => {
+                self.remove(idx);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Send an event to every subscriber, and return how many of them it
+    /// reached.  This never blocks.
+    pub fn broadcast(&mut self, arg1: usize, arg2: usize, arg3: usize, arg4: usize) -> usize {
+        self.broadcast_with(
+            [arg1, arg2, arg3, arg4],
+            |cid, message| crate::try_send_message(cid, message).map(|_| ()),
+            // The connection's reference is counted, so this only gives up
+            // the one the publisher took for this subscriber.
+            |cid| {
+                crate::disconnect(cid).ok();
+            },
+        )
+    }
+
+    /// The body of `broadcast()`, with the syscalls passed in so the list
+    /// handling can be tested without a kernel.
+    fn broadcast_with(
+        &mut self,
+        args: [usize; 4],
+        mut send: impl FnMut(CID, Message) -> Result<(), Error>,
+        mut release: impl FnMut(CID),
+    ) -> usize {
+        let mut delivered = 0;
+        let mut idx = 0;
+        while idx < self.count {
+            let subscriber = self.subscribers[idx].expect("subscriber list has a hole");
+            let message = Message::Scalar(ScalarMessage {
+                id: subscriber.opcode,
+                arg1: args[0],
+                arg2: args[1],
+                arg3: args[2],
+                arg4: args[3],
+            });
+            match send(subscriber.cid, message) {
+                Ok(()) => delivered += 1,
+                // The subscriber is gone.  Removing it moves the next
+                // subscriber into this slot, so don't advance.
+                Err(Error::ServerNotFound) | Err(Error::ProcessTerminated) => {
+                    self.remove(idx);
+                    release(subscriber.cid);
+                    continue;
+                }
+                Err(_) => (),
+            }
+            idx += 1;
+        }
+        delivered
+    }
+
+    /// Remove the subscriber at `idx`, keeping the rest in order.
+    fn remove(&mut self, idx: usize) {
+        self.subscribers.copy_within(idx + 1..self.count, idx);
+        self.count -= 1;
+        self.subscribers[self.count] = None;
+    }
+}
+
+impl Default for Broadcaster {
+    fn default() -> Self {
+        Broadcaster::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cids(broadcaster: &Broadcaster) -> Vec<(CID, usize)> {
+        broadcaster.subscribers[..broadcaster.count]
+            .iter()
+            .map(|s| {
+                s.map(|s| (s.cid, s.opcode))
+                    .expect("subscriber list has a hole")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn subscribe_keeps_order() {
+        let mut broadcaster = Broadcaster::new();
+        broadcaster.subscribe(2, 10).unwrap();
+        broadcaster.subscribe(3, 11).unwrap();
+        broadcaster.subscribe(4, 12).unwrap();
+
+        // Subscribing again changes the opcode in place.
+        broadcaster.subscribe(2, 20).unwrap();
+        assert_eq!(cids(&broadcaster), [(2, 20), (3, 11), (4, 12)]);
+
+        assert!(broadcaster.unsubscribe(3));
+        assert!(!broadcaster.unsubscribe(3));
+        assert_eq!(cids(&broadcaster), [(2, 20), (4, 12)]);
+
+        broadcaster.subscribe(3, 13).unwrap();
+        assert_eq!(cids(&broadcaster), [(2, 20), (4, 12), (3, 13)]);
+    }
+
+    #[test]
+    fn subscribers_are_limited() {
+        let mut broadcaster = Broadcaster::new();
+        for cid in 0..MAX_SUBSCRIBERS {
+            broadcaster.subscribe(cid, 0).unwrap();
+        }
+        assert_eq!(
+            broadcaster.subscribe(MAX_SUBSCRIBERS, 0),
+            Err(Error::OutOfMemory)
+        );
+        // An existing subscriber can still change its opcode.
+        broadcaster.subscribe(0, 1).unwrap();
+
+        assert!(broadcaster.unsubscribe(5));
+        broadcaster.subscribe(MAX_SUBSCRIBERS, 0).unwrap();
+        assert_eq!(broadcaster.len(), MAX_SUBSCRIBERS);
+    }
+
+    #[test]
+    fn broadcast_prunes_dead_subscribers() {
+        let mut broadcaster = Broadcaster::new();
+        for cid in 2..7 {
+            broadcaster.subscribe(cid, cid * 10).unwrap();
+        }
+
+        let mut sent = vec![];
+        let mut released = vec![];
+        let delivered = broadcaster.broadcast_with(
+            [1, 2, 3, 4],
+            |cid, message| {
+                sent.push((cid, message));
+                match cid {
+                    3 => Err(Error::ServerNotFound),
+                    4 => Err(Error::ProcessTerminated),
+                    5 => Err(Error::ServerQueueFull),
+                    _ => Ok(()),
+                }
+            },
+            |cid| released.push(cid),
+        );
+
+        assert_eq!(delivered, 2);
+        // Every subscriber is tried once, in order, including the one
+        // moved into a pruned slot.
+        assert_eq!(
+            sent.iter().map(|(cid, _)| *cid).collect::<Vec<_>>(),
+            [2, 3, 4, 5, 6]
+        );
+        assert_eq!(
+            sent[4].1,
+            Message::Scalar(ScalarMessage {
+                id: 60,
+                arg1: 1,
+                arg2: 2,
+                arg3: 3,
+                arg4: 4
+            })
+        );
+        // Dead subscribers are dropped and their connections released; a
+        // full queue only misses this event.
+        assert_eq!(released, [3, 4]);
+        assert_eq!(cids(&broadcaster), [(2, 20), (5, 50), (6, 60)]);
+    }
+}
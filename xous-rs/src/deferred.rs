@@ -0,0 +1,171 @@
+//! Deferred interrupt handling.
+//!
+//! An interrupt handler runs with every other interrupt masked, so it should
+//! do as little as possible: acknowledge the hardware, then hand the rest of
+//! the work to the server's main loop.  A `Deferred` is that hand-off.  The
+//! handler calls `post()`, which sends a scalar message to the server, and
+//! the server calls `begin()` as soon as it receives that message.
+//!
+//! Posts are coalesced.  If the server hasn't picked up the last message
+//! yet, no new one is sent, because the main loop has to look at the state
+//! of the hardware anyway.  This also means a burst of interrupts can't fill
+//! the server's queue.
+//!
+//! The time from a message being sent to the server calling `begin()` is
+//! kept as a histogram, measured with `timestamp()`.
+
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use crate::{Message, ScalarMessage, CID, IRQ_LATENCY_BUCKETS};
+
+/// Counters kept by a `Deferred`
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct DeferredStats {
+    /// Messages sent to the server
+    pub posted: u32,
+
+    /// Interrupts that arrived while a message was already waiting
+    pub coalesced: u32,
+
+    /// Messages that couldn't be sent because the queue was full
+    pub dropped: u32,
+
+    /// The most interrupts that arrived before the server got around to a
+    /// message.  This is a measure of how far the main loop lags behind.
+    pub max_backlog: u32,
+
+    /// How long messages waited before the server called `begin()`, in
+    /// the units of `timestamp()`.  The buckets are the same as those of
+    /// `InterruptLatency`.
+    pub latency: [u32; IRQ_LATENCY_BUCKETS],
+
+    /// The longest any message waited
+    pub max_latency: u32,
+}
+
+pub struct Deferred {
+    cid: CID,
+    opcode: usize,
+    pending: AtomicBool,
+    backlog: AtomicU32,
+    posted: AtomicU32,
+    coalesced: AtomicU32,
+    dropped: AtomicU32,
+    max_backlog: AtomicU32,
+    /// Low word of `timestamp()` when the pending message was sent
+    posted_at: AtomicU32,
+    latency: [AtomicU32; IRQ_LATENCY_BUCKETS],
+    max_latency: AtomicU32,
+}
+
+// Only used to initialise `Deferred::latency`, which is how arrays of
+// atomics are built
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicU32 = AtomicU32::new(0);
+
+/// Only the low word of the timestamp is kept, because 64-bit atomics
+/// aren't available everywhere.  A message would have to wait for the
+/// counter to wrap for this to be wrong.
+fn now() -> u32 {
+    crate::timestamp().unwrap_or(0) as u32
+}
+
+fn bucket(latency: u32) -> usize {
+    let log2 = 31 - (latency | 1).leading_zeros() as usize;
+    log2.min(IRQ_LATENCY_BUCKETS - 1)
+}
+
+impl Deferred {
+    /// Post scalar messages with the id `opcode` to `cid`.  The first
+    /// argument of each message is the number of the interrupt.
+    pub const fn new(cid: CID, opcode: usize) -> Deferred {
+        Deferred {
+            cid,
+            opcode,
+            pending: AtomicBool::new(false),
+            backlog: AtomicU32::new(0),
+            posted: AtomicU32::new(0),
+            coalesced: AtomicU32::new(0),
+            dropped: AtomicU32::new(0),
+            max_backlog: AtomicU32::new(0),
+            posted_at: AtomicU32::new(0),
+            latency: [ZERO; IRQ_LATENCY_BUCKETS],
+            max_latency: AtomicU32::new(0),
+        }
+    }
+
+    /// Ask the main loop to finish handling `irq_no`.  Call this from the
+    /// interrupt handler, after acknowledging the hardware.
+    pub fn post(&self, irq_no: usize) {
+        self.backlog.fetch_add(1, Ordering::Relaxed);
+        if self.pending.swap(true, Ordering::AcqRel) {
+            self.coalesced.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        let message = Message::Scalar(ScalarMessage {
+            id: self.opcode,
+            arg1: irq_no,
+            arg2: 0,
+            arg3: 0,
+            arg4: 0,
+        });
+        self.posted_at.store(now(), Ordering::Relaxed);
+        if crate::try_send_message(self.cid, message).is_ok() {
+            self.posted.fetch_add(1, Ordering::Relaxed);
+        } else {
+            // Let the next interrupt try again.
+            self.pending.store(false, Ordering::Release);
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Mark the posted message as received.  Call this when the message
+    /// arrives and before looking at the hardware, so that an interrupt
+    /// during the work posts a fresh message instead of being lost.
+    pub fn begin(&self) {
+        if self.pending.swap(false, Ordering::AcqRel) {
+            let latency = now().wrapping_sub(self.posted_at.load(Ordering::Relaxed));
+            self.latency[bucket(latency)].fetch_add(1, Ordering::Relaxed);
+            self.max_latency.fetch_max(latency, Ordering::Relaxed);
+        }
+        let backlog = self.backlog.swap(0, Ordering::Relaxed);
+        self.max_backlog.fetch_max(backlog, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> DeferredStats {
+        let mut latency = [0; IRQ_LATENCY_BUCKETS];
+        for (count, bucket) in latency.iter_mut().zip(self.latency.iter()) {
+            *count = bucket.load(Ordering::Relaxed);
+        }
+        DeferredStats {
+            posted: self.posted.load(Ordering::Relaxed),
+            coalesced: self.coalesced.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            max_backlog: self.max_backlog.load(Ordering::Relaxed),
+            latency,
+            max_latency: self.max_latency.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latency_buckets() {
+        assert_eq!(bucket(0), 0);
+        assert_eq!(bucket(1), 0);
+        assert_eq!(bucket(2), 1);
+        assert_eq!(bucket(3), 1);
+        assert_eq!(bucket(1 << 15), IRQ_LATENCY_BUCKETS - 1);
+        assert_eq!(bucket(u32::MAX), IRQ_LATENCY_BUCKETS - 1);
+    }
+
+    #[test]
+    fn begin_without_a_post_records_nothing() {
+        let deferred = Deferred::new(1, 0);
+        deferred.begin();
+        assert_eq!(deferred.stats(), DeferredStats::default());
+    }
+}
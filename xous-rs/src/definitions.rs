@@ -66,6 +66,9 @@ pub struct MemoryRange {
     pub size: MemorySize,
 }
 
+/// The most ranges that can be lent together with `lend_pages()`
+pub const MAX_PAGE_LIST: usize = 16;
+
 bitflags! {
     /// Flags to be passed to the MapMemory struct.
     /// Note that it is an error to have memory be
@@ -90,6 +93,35 @@ bitflags! {
     }
 }
 
+bitflags! {
+    /// Sensitive operations that a process must be granted in the boot
+    /// image before it may perform them. Most are only enforced when the
+    /// kernel is built with the `capabilities` feature and the image
+    /// contains a capability table. `SET_QUOTA` and `DEBUG` are always
+    /// enforced, and an image without a table grants them to nobody.
+    pub struct Capabilities: usize {
+        /// Map a specific physical address, such as a block of device
+        /// registers, with `MapMemory`.
+        const MAP_PHYSICAL      = 0b0000_0001;
+
+        /// Claim a hardware interrupt.
+        const CLAIM_INTERRUPT   = 0b0000_0010;
+
+        /// Create a new server.
+        const CREATE_SERVER     = 0b0000_0100;
+
+        /// Read the log of operations the kernel has denied.
+        const READ_AUDIT_LOG    = 0b0000_1000;
+
+        /// Pause every other process and read their registers.
+        const DEBUG             = 0b0001_0000;
+
+        /// Set the CPU quota of any process the kernel schedules
+        /// directly, not just its own children.
+        const SET_QUOTA         = 0b0010_0000;
+    }
+}
+
 pub fn pid_from_usize(src: usize) -> core::result::Result<PID, Error> {
     if src > u8::MAX as _ {
         return Err(Error::InvalidPID);
@@ -122,7 +154,9 @@ pub enum Error {
     ShareViolation = 19,
     InvalidThread = 20,
     InvalidPID = 21,
-    UnknownError = 22,
+    AccessDenied = 22,
+    VersionMismatch = 23,
+    UnknownError = 24,
 }
 
 impl Error {
@@ -151,6 +185,8 @@ impl Error {
             19 => ShareViolation,
             20 => InvalidThread,
             21 => InvalidPID,
+            22 => AccessDenied,
+            23 => VersionMismatch,
             _ => UnknownError,
         }
     }
@@ -179,6 +215,8 @@ impl Error {
             ShareViolation => 19,
             InvalidThread => 20,
             InvalidPID => 21,
+            AccessDenied => 22,
+            VersionMismatch => 23,
             UnknownError => usize::MAX,
         }
     }
@@ -236,6 +274,40 @@ impl MemoryMessage {
             valid,
         })
     }
+    /// The part of `buf` that holds data, in bytes from the start of the
+    /// buffer.  It starts at `offset`, or at the start of the buffer if
+    /// that's `None`, and is `valid` bytes long, or runs to the end of the
+    /// buffer if that's `None`.
+    ///
+    /// # Errors
+    ///
+    /// * **BadAddress**: The range doesn't fit inside `buf`.  The kernel
+    ///   refuses to send messages like this, so a server never sees one.
+    pub fn valid_range(&self) -> core::result::Result<core::ops::Range<usize>, Error> {
+        let start = self.offset.map(|x| x.get()).unwrap_or(0);
+        let len = match self.valid {
+            Some(valid) => valid.get(),
+            None => self.buf.len().checked_sub(start).ok_or(Error::BadAddress)?,
+        };
+        let end = start.checked_add(len).ok_or(Error::BadAddress)?;
+        if end > self.buf.len() {
+            return Err(Error::BadAddress);
+        }
+        Ok(start..end)
+    }
+
+    /// The bytes described by `valid_range()`, or an empty slice if the
+    /// range is out of bounds.
+    ///
+    /// # Safety
+    ///
+    /// `buf` must be mapped into this process, as it is for a message that
+    /// was just received.
+    pub unsafe fn valid_bytes(&self) -> &[u8] {
+        let range = self.valid_range().unwrap_or(0..0);
+        core::slice::from_raw_parts(self.buf.as_ptr().add(range.start), range.len())
+    }
+
     pub fn to_usize(&self) -> [usize; 5] {
         [
             self.id,
@@ -373,12 +445,8 @@ impl Drop for MessageEnvelope {
 }
 
 impl MemoryRange {
+    /// Returns `Error::BadAddress` if either `addr` or `size` is zero.
     pub fn new(addr: usize, size: usize) -> core::result::Result<MemoryRange, Error> {
-        assert!(
-            addr != 0,
-            "tried to construct a memory range with a null pointer"
-        );
-        assert!(size != 0, "tried to construct a zero-length memory range");
         Ok(MemoryRange {
             addr: MemoryAddress::new(addr).ok_or(Error::BadAddress)?,
             size: MemorySize::new(size).ok_or(Error::BadAddress)?,
@@ -471,6 +539,9 @@ pub enum Result {
     /// A scalar with two values
     Scalar2(usize, usize),
 
+    /// A scalar with five values
+    Scalar5(usize, usize, usize, usize, usize),
+
     /// The syscall should be attempted again. This is returned when calling
     /// functions such as `try_connect()` and `try_send()` that may block.
     WouldBlock,
@@ -511,6 +582,7 @@ impl Result {
                 [15, s.0 as _, s.1 as _, s.2 as _, s.3 as _, *cid, 0, 0]
             }
             Result::WouldBlock => [16, 0, 0, 0, 0, 0, 0, 0],
+            Result::Scalar5(a, b, c, d, e) => [17, *a, *b, *c, *d, *e, 0, 0],
             Result::UnknownResult(arg1, arg2, arg3, arg4, arg5, arg6, arg7) => {
                 [usize::MAX, *arg1, *arg2, *arg3, *arg4, *arg5, *arg6, *arg7]
             }
@@ -585,6 +657,7 @@ impl Result {
                 src[5] as _,
             ),
             16 => Result::WouldBlock,
+            17 => Result::Scalar5(src[1], src[2], src[3], src[4], src[5]),
             _ => Result::UnknownResult(src[0], src[1], src[2], src[3], src[4], src[5], src[6]),
         }
     }
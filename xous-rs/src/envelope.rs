@@ -0,0 +1,280 @@
+//! Typed records in lent memory.
+//!
+//! Servers that take a struct in a memory message all do the same thing: map
+//! a page, encode the struct into it by hand, lend it, decode the reply and
+//! unmap the page.  Nothing checks that both ends agree on the layout, so a
+//! client built against an older definition silently gets garbage back.
+//!
+//! A type that implements `Record` says how to encode itself, and names a tag
+//! and a version.  An `Envelope` owns a page holding one record behind an
+//! eight-byte header.  All integers are little-endian:
+//!
+//! | Size | Contents                        |
+//! |------|---------------------------------|
+//! | 4    | `Record::TAG`                   |
+//! | 2    | `Record::VERSION`               |
+//! | 2    | Length of the encoded record    |
+//!
+//! Servers read a lent record with `open_record()` and write their reply
+//! with `seal_record()`.  A record with the wrong tag or version is rejected
+//! with `Error::VersionMismatch` instead of being decoded.
+
+use core::marker::PhantomData;
+
+use crate::{Error, MemoryFlags, MemoryMessage, MemoryRange, Message, CID};
+
+/// Bytes in front of every record
+pub const ENVELOPE_HEADER_SIZE: usize = 8;
+
+/// A type that can be sent in an `Envelope`
+pub trait Record: Sized {
+    /// Identifies the type, usually four ASCII characters
+    const TAG: u32;
+
+    /// Change this whenever the encoding changes
+    const VERSION: u16;
+
+    /// The most bytes `encode()` will write.  This must be less than 64 KiB,
+    /// or `seal_record()` refuses to encode the type.
+    const MAX_SIZE: usize;
+
+    /// Encode the record into `buf`, which is at least `MAX_SIZE` bytes
+    /// long, and return how many bytes were used.
+    fn encode(&self, buf: &mut [u8]) -> usize;
+
+    /// Decode a record written by `encode()`
+    fn decode(buf: &[u8]) -> Option<Self>;
+}
+
+/// Decode the record in `buf`, making sure it is a `T` of the same version.
+///
+/// # Errors
+///
+/// * **VersionMismatch**: The buffer holds a different type or version
+/// * **InternalError**: The record is truncated or couldn't be decoded
+pub fn open_record<T: Record>(buf: &MemoryRange) -> Result<T, Error> {
+    let data = unsafe { core::slice::from_raw_parts(buf.as_ptr(), buf.len()) };
+    if data.len() < ENVELOPE_HEADER_SIZE {
+        return Err(Error::InternalError);
+    }
+    let tag = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+    let version = u16::from_le_bytes([data[4], data[5]]);
+    let len = u16::from_le_bytes([data[6], data[7]]) as usize;
+    if tag != T::TAG || version != T::VERSION {
+        return Err(Error::VersionMismatch);
+    }
+    data.get(ENVELOPE_HEADER_SIZE..ENVELOPE_HEADER_SIZE + len)
+        .and_then(T::decode)
+        .ok_or(Error::InternalError)
+}
+
+/// Replace the contents of `buf` with `record`.  Servers use this to answer
+/// a `MutableBorrow`.
+///
+/// # Errors
+///
+/// * **OutOfMemory**: The buffer is too small to hold a `T`
+/// * **InternalError**: `T::MAX_SIZE` doesn't fit in the 16-bit length
+///   field, or `encode()` claimed to write more than `T::MAX_SIZE` bytes
+pub fn seal_record<T: Record>(buf: &MemoryRange, record: &T) -> Result<(), Error> {
+    if T::MAX_SIZE > u16::MAX as usize {
+        return Err(Error::InternalError);
+    }
+    let data = unsafe { core::slice::from_raw_parts_mut(buf.as_mut_ptr(), buf.len()) };
+    if data.len() < ENVELOPE_HEADER_SIZE + T::MAX_SIZE {
+        return Err(Error::OutOfMemory);
+    }
+    let len = record.encode(&mut data[ENVELOPE_HEADER_SIZE..ENVELOPE_HEADER_SIZE + T::MAX_SIZE]);
+    if len > T::MAX_SIZE {
+        return Err(Error::InternalError);
+    }
+    data[0..4].copy_from_slice(&T::TAG.to_le_bytes());
+    data[4..6].copy_from_slice(&T::VERSION.to_le_bytes());
+    data[6..8].copy_from_slice(&(len as u16).to_le_bytes());
+    Ok(())
+}
+
+/// A page of memory holding one `T`, ready to be lent to a server
+#[derive(Debug)]
+pub struct Envelope<T: Record> {
+    buf: MemoryRange,
+    _record: PhantomData<T>,
+}
+
+impl<T: Record> Envelope<T> {
+    /// Map enough memory to hold a `T` and write `record` into it.
+    pub fn new(record: &T) -> Result<Envelope<T>, Error> {
+        let size = (ENVELOPE_HEADER_SIZE + T::MAX_SIZE + 4095) & !4095;
+        let envelope = Envelope {
+            buf: crate::map_memory(None, None, size, MemoryFlags::R | MemoryFlags::W)?,
+            _record: PhantomData,
+        };
+        seal_record(&envelope.buf, record)?;
+        Ok(envelope)
+    }
+
+    /// Decode the record currently in the envelope.  After `lend_mut()`,
+    /// this is whatever the server wrote back.
+    pub fn record(&self) -> Result<T, Error> {
+        open_record(&self.buf)
+    }
+
+    /// Lend the record to a server, blocking until it is returned.
+    pub fn lend(&self, connection: CID, id: usize) -> Result<crate::Result, Error> {
+        crate::send_message(connection, Message::Borrow(self.message(id)))
+    }
+
+    /// Lend the record to a server that may replace it, blocking until it
+    /// is returned.
+    pub fn lend_mut(&mut self, connection: CID, id: usize) -> Result<crate::Result, Error> {
+        crate::send_message(connection, Message::MutableBorrow(self.message(id)))
+    }
+
+    fn message(&self, id: usize) -> MemoryMessage {
+        MemoryMessage {
+            id,
+            buf: self.buf,
+            offset: None,
+            valid: None,
+        }
+    }
+}
+
+impl<T: Record> Drop for Envelope<T> {
+    fn drop(&mut self) {
+        crate::unmap_memory(self.buf).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Point {
+        x: u32,
+        y: u32,
+    }
+
+    impl Record for Point {
+        const TAG: u32 = u32::from_le_bytes(*b"pnt ");
+        const VERSION: u16 = 2;
+        const MAX_SIZE: usize = 8;
+
+        fn encode(&self, buf: &mut [u8]) -> usize {
+            buf[0..4].copy_from_slice(&self.x.to_le_bytes());
+            buf[4..8].copy_from_slice(&self.y.to_le_bytes());
+            8
+        }
+
+        fn decode(buf: &[u8]) -> Option<Point> {
+            if buf.len() != 8 {
+                return None;
+            }
+            Some(Point {
+                x: u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]),
+                y: u32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]),
+            })
+        }
+    }
+
+    /// The same encoding as `Point`, from before a change to the layout
+    struct OldPoint;
+
+    impl Record for OldPoint {
+        const TAG: u32 = Point::TAG;
+        const VERSION: u16 = 1;
+        const MAX_SIZE: usize = 8;
+
+        fn encode(&self, _buf: &mut [u8]) -> usize {
+            8
+        }
+
+        fn decode(_buf: &[u8]) -> Option<OldPoint> {
+            Some(OldPoint)
+        }
+    }
+
+    /// Claims more than a 16-bit length can describe
+    struct Huge;
+
+    impl Record for Huge {
+        const TAG: u32 = u32::from_le_bytes(*b"huge");
+        const VERSION: u16 = 1;
+        const MAX_SIZE: usize = 65536;
+
+        fn encode(&self, _buf: &mut [u8]) -> usize {
+            0
+        }
+
+        fn decode(_buf: &[u8]) -> Option<Huge> {
+            Some(Huge)
+        }
+    }
+
+    fn range(data: &mut [u8]) -> MemoryRange {
+        MemoryRange::new(data.as_mut_ptr() as usize, data.len()).unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut page = vec![0u8; 4096];
+        let buf = range(&mut page);
+        let point = Point { x: 3, y: 0xdead_beef };
+        seal_record(&buf, &point).unwrap();
+        assert_eq!(&page[0..4], b"pnt ");
+        assert_eq!(&page[4..8], &[2, 0, 8, 0]);
+        assert_eq!(open_record::<Point>(&range(&mut page)), Ok(point));
+    }
+
+    #[test]
+    fn version_mismatch() {
+        let mut page = vec![0u8; 4096];
+        seal_record(&range(&mut page), &Point { x: 1, y: 2 }).unwrap();
+        assert_eq!(
+            open_record::<OldPoint>(&range(&mut page)).err(),
+            Some(Error::VersionMismatch)
+        );
+
+        seal_record(&range(&mut page), &OldPoint).unwrap();
+        assert_eq!(
+            open_record::<Point>(&range(&mut page)),
+            Err(Error::VersionMismatch)
+        );
+    }
+
+    #[test]
+    fn truncated() {
+        let mut page = vec![0u8; 4096];
+        seal_record(&range(&mut page), &Point { x: 1, y: 2 }).unwrap();
+
+        // A length that is too short for the record
+        page[6] = 4;
+        assert_eq!(open_record::<Point>(&range(&mut page)), Err(Error::InternalError));
+
+        // A length that runs off the end of the buffer
+        page[6..8].copy_from_slice(&0xffffu16.to_le_bytes());
+        assert_eq!(open_record::<Point>(&range(&mut page)), Err(Error::InternalError));
+
+        // Not even a whole header
+        assert_eq!(
+            open_record::<Point>(&range(&mut page[..ENVELOPE_HEADER_SIZE - 1])),
+            Err(Error::InternalError)
+        );
+    }
+
+    #[test]
+    fn buffer_too_small() {
+        let mut page = vec![0u8; ENVELOPE_HEADER_SIZE + Point::MAX_SIZE - 1];
+        assert_eq!(
+            seal_record(&range(&mut page), &Point { x: 1, y: 2 }),
+            Err(Error::OutOfMemory)
+        );
+    }
+
+    #[test]
+    fn max_size_must_fit_length() {
+        let mut page = vec![0u8; ENVELOPE_HEADER_SIZE + Huge::MAX_SIZE];
+        assert_eq!(seal_record(&range(&mut page), &Huge), Err(Error::InternalError));
+    }
+}
@@ -9,15 +9,23 @@ pub use macros::xous_main;
 
 pub mod arch;
 
+mod broadcast;
 pub mod carton;
+mod deferred;
 pub mod definitions;
+mod envelope;
 mod messages;
+mod protocol;
 pub mod syscall;
 pub mod string;
 
 pub use arch::{ProcessArgs, ProcessInit, ProcessKey, ThreadInit};
+pub use broadcast::*;
+pub use deferred::*;
 pub use definitions::*;
+pub use envelope::*;
 pub use messages::*;
+pub use protocol::*;
 pub use syscall::*;
 pub use string::*;
 
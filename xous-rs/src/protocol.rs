@@ -0,0 +1,119 @@
+//! Checking that a client and a server speak the same protocol.
+//!
+//! Images are not always built from a single tree, so a client can end up
+//! talking to a server whose opcodes or message layouts have changed under
+//! it.  Rather than have that show up as garbled replies, a client can ask
+//! the server for its `Protocol` when it connects.
+//!
+//! The client lends an `Envelope` holding a `Protocol` using `MutableBorrow`
+//! with the id `GET_PROTOCOL`, and the server replaces it with its own.  A
+//! server that predates the handshake returns the page untouched, so the
+//! client sees the all-zero record it sent and treats it as version 0.
+//!
+//! Clients usually just call `connect_checked()`.  Servers answer with
+//! `answer_protocol()`.
+
+use crate::{Envelope, Error, MemoryRange, Record, CID, SID};
+
+/// Message id reserved in every server for the protocol handshake.  Like
+/// `health::GET_HEALTH`, it is far above the ids servers use for their own
+/// opcodes.
+pub const GET_PROTOCOL: usize = 0x5052_4f54;
+
+/// Size of an encoded `Protocol` record
+const RECORD_SIZE: usize = 8;
+
+/// The version of a server's protocol, and the optional features it offers
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct Protocol {
+    /// Bumped whenever an existing opcode or message layout changes.  `0`
+    /// means the server doesn't answer the handshake.
+    pub version: u32,
+
+    /// Server-defined bits for things that were added without breaking
+    /// existing clients
+    pub features: u32,
+}
+
+impl Protocol {
+    pub const fn new(version: u32, features: u32) -> Protocol {
+        Protocol { version, features }
+    }
+
+    /// Returns `true` if a client that wants `self` can talk to a server
+    /// that offers `server`: the versions must match, and the server must
+    /// have every feature the client asks for.
+    pub fn accepts(&self, server: &Protocol) -> bool {
+        self.version == server.version && (server.features & self.features) == self.features
+    }
+
+    /// Returns `true` if the server offers every bit in `features`
+    pub fn has(&self, features: u32) -> bool {
+        self.features & features == features
+    }
+}
+
+impl Record for Protocol {
+    const TAG: u32 = u32::from_le_bytes(*b"Prot");
+    const VERSION: u16 = 1;
+    const MAX_SIZE: usize = RECORD_SIZE;
+
+    fn encode(&self, buf: &mut [u8]) -> usize {
+        buf[0..4].copy_from_slice(&self.version.to_le_bytes());
+        buf[4..8].copy_from_slice(&self.features.to_le_bytes());
+        RECORD_SIZE
+    }
+
+    fn decode(buf: &[u8]) -> Option<Protocol> {
+        if buf.len() < RECORD_SIZE {
+            return None;
+        }
+        Some(Protocol {
+            version: u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]),
+            features: u32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]),
+        })
+    }
+}
+
+/// Ask the server on the other end of `connection` which protocol it speaks.
+pub fn query_protocol(connection: CID) -> Result<Protocol, Error> {
+    let mut envelope = Envelope::new(&Protocol::default())?;
+    envelope.lend_mut(connection, GET_PROTOCOL)?;
+    envelope.record()
+}
+
+/// Connect to `server` and make sure it speaks the protocol a client built
+/// against `expected` understands.  The server's `Protocol` is returned
+/// along with the connection, so the client can check for optional
+/// features.
+///
+/// The connection is given back if the check fails.  Connections made
+/// elsewhere in the process to the same server share its `CID` but keep
+/// working.
+///
+/// # Errors
+///
+/// * **VersionMismatch**: The server speaks another version or lacks a feature
+pub fn connect_checked(server: SID, expected: Protocol) -> Result<(CID, Protocol), Error> {
+    let connection = crate::connect(server)?;
+    let offered = match query_protocol(connection) {
+        Ok(offered) => offered,
+        Err(e) => {
+            crate::disconnect(connection).ok();
+            return Err(e);
+        }
+    };
+    if expected.accepts(&offered) {
+        Ok((connection, offered))
+    } else {
+        crate::disconnect(connection)?;
+        Err(Error::VersionMismatch)
+    }
+}
+
+/// Answer a protocol handshake by filling in the lent buffer.  The buffer
+/// is returned to the client when the message envelope is dropped.
+pub fn answer_protocol(buf: MemoryRange, protocol: Protocol) {
+    // A buffer too small to hold the answer is the client's problem
+    crate::seal_record(&buf, &protocol).ok();
+}
@@ -1,7 +1,7 @@
 use crate::{
     pid_from_usize, CpuID, Error, MemoryAddress, MemoryFlags, MemoryMessage, MemoryRange,
     MemorySize, MemoryType, Message, MessageEnvelope, MessageSender, ProcessArgs, ProcessInit,
-    Result, ScalarMessage, SysCallResult, ThreadInit, CID, PID, SID,
+    Result, ScalarMessage, SysCallResult, ThreadInit, CID, MAX_PAGE_LIST, PID, SID, TID,
 };
 // use num_derive::FromPrimitive;
 // use num_traits::FromPrimitive;
@@ -268,6 +268,256 @@ pub enum SysCall {
     /// Shut down the entire system
     Shutdown,
 
+    /// Return CPU idle accounting gathered by the kernel since boot. The
+    /// result is a `Scalar5` of the idle time (low, high), the total time
+    /// (low, high), and the number of times the CPU has woken from idle.
+    /// Times are in units of the platform timer.
+    ///
+    /// # Errors
+    ///
+    /// None
+    GetIdleStats,
+
+    /// Return one bucket of the latency histograms for the given IRQ. The
+    /// result is a `Scalar5` of the dispatch count and handler count for that
+    /// bucket, followed by the worst-case dispatch time, the worst-case
+    /// handler time, and the total number of times the handler has run.
+    /// Only available if the kernel was built with `irq-latency`.
+    ///
+    /// # Errors
+    ///
+    /// * **InterruptNotFound**: The IRQ number is out of range
+    /// * **InvalidSyscall**: The bucket number is out of range
+    /// * **UnhandledSyscall**: Latency tracking is not enabled
+    GetInterruptLatency(usize /* IRQ number */, usize /* bucket */),
+
+    /// Read an entry from the kernel's log of denied operations. Returns the
+    /// oldest entry still held whose sequence number is at least the one
+    /// given, as a `Scalar5` of the sequence number, the offending PID, the
+    /// syscall number, and its first two arguments. Returns `Ok` if there
+    /// are no newer entries.
+    ///
+    /// # Errors
+    ///
+    /// * **AccessDenied**: The caller lacks `READ_AUDIT_LOG`
+    /// * **UnhandledSyscall**: The kernel does not enforce capabilities
+    GetAuditEntry(usize /* sequence number */),
+
+    /// Look up the permissions of the page containing the given address in
+    /// the current process. Returns a `Scalar2` of the `MemoryFlags` bits
+    /// and the state of the page: 0 for unmapped, 1 for reserved but not yet
+    /// allocated, 2 for mapped, and 3 for a guard page.
+    ///
+    /// # Errors
+    ///
+    /// None
+    GetMemoryFlags(usize /* address */),
+
+    /// Get one of the address ranges that the loader protected using the
+    /// RISC-V PMP. Returns a `Scalar5` of the start and end addresses and
+    /// the `MemoryFlags` bits that the range is limited to. Slots that are
+    /// not in use have a start and end of 0.
+    ///
+    /// # Errors
+    ///
+    /// * **InvalidSyscall**: The index is past the end of the table, or the
+    ///                       image did not ask for PMP protection
+    GetPmpRange(usize /* index */),
+
+    /// Return the ID of the calling process as a `Scalar1`.
+    ///
+    /// # Errors
+    ///
+    /// None
+    GetProcessId,
+
+    /// Measure how much of a thread's stack has been used, by scanning for
+    /// the deepest word that no longer holds the fill pattern the kernel
+    /// wrote when the stack was created. Pass `0` for the calling thread.
+    /// Returns a `Scalar2` of the bytes used and the size of the stack.
+    /// Only available if the kernel was built with `stack-watermark`.
+    ///
+    /// # Errors
+    ///
+    /// * **ThreadNotAvailable**: The thread does not exist in this process
+    /// * **UnhandledSyscall**: Stack watermarks are not enabled
+    GetStackUsage(TID),
+
+    /// Like `ClaimInterrupt`, but lets other processes claim the same line
+    /// as long as they also use `ClaimSharedInterrupt`.  When the interrupt
+    /// fires, every handler is called in the order they were claimed, so
+    /// each one must check whether its own device raised it.
+    ///
+    /// # Errors
+    ///
+    /// * **InterruptNotFound**: The specified interrupt isn't valid on this
+    ///   system
+    /// * **InterruptInUse**: The interrupt has been claimed exclusively, this
+    ///   process has already claimed it, or too many processes share it
+    ClaimSharedInterrupt(
+        usize,                 /* IRQ number */
+        MemoryAddress,         /* function pointer */
+        Option<MemoryAddress>, /* argument */
+    ),
+
+    /// Limit how many messages can wait in the queue of one of this
+    /// process' servers.  Once the limit is reached, `TrySendMessage` fails
+    /// with `ServerQueueFull` and `SendMessage` blocks until the server
+    /// receives a message.  The limit is clamped to the size of the queue,
+    /// which is also the default, and the limit that was set is returned as
+    /// a `Scalar1`.
+    ///
+    /// # Errors
+    ///
+    /// * **ServerNotFound**: This process doesn't own a server with that ID
+    SetQueueLimit(SID, usize),
+
+    /// Run a process in sandboxed mode.  A sandboxed process may only manage
+    /// its own memory and threads, create processes, and send and receive
+    /// messages.  Every other syscall, such as creating servers, mapping
+    /// physical memory, claiming interrupts or shutting down the system,
+    /// fails with `AccessDenied`.  It may only connect to servers that were
+    /// added with `SandboxAllowConnection`.
+    /// Processes it creates are sandboxed in the same way.  This must be
+    /// called by the process that created the target, before it has started
+    /// running, and can't be undone.
+    ///
+    /// # Errors
+    ///
+    /// * **ProcessNotFound**: The target process doesn't exist
+    /// * **ProcessNotChild**: The target process wasn't created by this process
+    /// * **AccessDenied**: The target process has already started running
+    SandboxProcess(PID),
+
+    /// Allow a sandboxed process to connect to the given server.  The same
+    /// rules apply as for `SandboxProcess`, and a sandboxed process can only
+    /// pass on servers that it may connect to itself.
+    ///
+    /// # Errors
+    ///
+    /// * **ProcessNotFound**: The target process doesn't exist
+    /// * **ProcessNotChild**: The target process wasn't created by this process
+    /// * **AccessDenied**: The target has already started running, or this process may not connect to the server
+    /// * **InvalidSyscall**: The target process isn't sandboxed
+    /// * **OutOfMemory**: The target's allow-list is full
+    SandboxAllowConnection(PID, SID),
+
+    /// Limit a process to a percentage of the CPU whenever other processes
+    /// are waiting to run.  It may still use more when the CPU would
+    /// otherwise be idle.  100 removes the limit.  The process that created
+    /// the target may set its quota.  The kernel itself schedules the
+    /// processes in the boot image, so their quotas may be set by any
+    /// process granted `Capabilities::SET_QUOTA`.
+    ///
+    /// # Errors
+    ///
+    /// * **ProcessNotFound**: The target process doesn't exist
+    /// * **ProcessNotChild**: The target process wasn't created by this
+    ///   process, and this process may not set the quota of processes the
+    ///   kernel schedules
+    /// * **InvalidSyscall**: The percentage is 0 or more than 100
+    SetCpuQuota(PID, usize /* percent */),
+
+    /// Return the CPU accounting for a process.  The result is a `Scalar5`
+    /// of its quota in percent, the total time it has run (low, high), the
+    /// time it has run in the current accounting window, and how many times
+    /// the scheduler passed it over because it was over its quota.  Times
+    /// are in units of the platform timer.
+    ///
+    /// # Errors
+    ///
+    /// * **ProcessNotFound**: The target process doesn't exist
+    GetCpuStats(PID),
+
+    /// Read the CPU cycle counter.  The result is a `Scalar2` of the low and
+    /// high words of a count of cycles since boot.  Hosted kernels don't
+    /// keep time and always return 0.
+    ///
+    /// # Errors
+    ///
+    /// None
+    GetTimestamp,
+
+    /// Read the measurement the loader took of the kernel and initial
+    /// processes.  `half` selects the first (0) or second (1) 16 bytes of
+    /// the 32-byte measurement, which is returned as a `Scalar5` of four
+    /// little-endian words followed by the number of images measured.
+    ///
+    /// # Errors
+    ///
+    /// * **InvalidSyscall**: `half` is not 0 or 1, or there is no measurement
+    GetBootMeasurement(usize /* half */),
+
+    /// Stop scheduling every process except the caller, so that a debugger
+    /// or crash dumper can look at them without them changing underneath
+    /// it.  Servers still run to handle messages the caller sends them, and
+    /// interrupt handlers still run on their own thread.  Hosted kernels
+    /// don't schedule processes, so this has no effect there.
+    ///
+    /// # Errors
+    ///
+    /// * **AccessDenied**: Another process has paused the world, or the caller lacks `DEBUG`
+    /// * **ProcessNotChild**: The caller wasn't started by the kernel, so nothing would run it
+    PauseWorld,
+
+    /// Let every process run again after a `PauseWorld`.
+    ///
+    /// # Errors
+    ///
+    /// * **AccessDenied**: The caller did not pause the world
+    ResumeWorld,
+
+    /// Read four of the saved registers of a thread in another process while
+    /// the world is paused.  Register 0 is the program counter and registers
+    /// 1 through 31 are `x1` through `x31`.  Returns a `Scalar5` of the
+    /// registers starting at `first`, followed by the number of registers.
+    ///
+    /// # Errors
+    ///
+    /// * **AccessDenied**: The caller has not paused the world
+    /// * **ProcessNotFound**: The process doesn't exist
+    /// * **ThreadNotAvailable**: The thread doesn't exist
+    /// * **InvalidSyscall**: `first` is past the last group of four
+    ReadThreadRegisters(PID, TID, usize /* first */),
+
+    /// Lend several page-aligned ranges to a server as a single buffer.
+    /// The message must be a `Borrow` or `MutableBorrow` whose buffer holds
+    /// up to `MAX_PAGE_LIST` pairs of words, each the address and length of
+    /// a range in the caller.  The server receives an ordinary message whose
+    /// buffer is those ranges placed back to back, and `offset` and `valid`
+    /// are relative to that buffer.  Each page goes back to its own range
+    /// when the server returns the memory.
+    ///
+    /// # Errors
+    ///
+    /// * **BadAddress**: The list is empty, too long or unreadable, a range is empty, or the valid part doesn't fit
+    /// * **BadAlignment**: A range is not page-aligned
+    /// * **OutOfMemory**: Too many page lists are lent out already
+    /// * **ServerNotFound**: The server does not exist so the connection is now invalid
+    /// * **ShareViolation**: Part of a range is already lent
+    /// * **InvalidSyscall**: The message is not a `Borrow` or `MutableBorrow`
+    /// * **UnhandledSyscall**: Hosted kernels don't own the memory, so `lend_pages()` copies instead
+    LendPages(CID, Message),
+
+    /// Give back a connection made with `Connect` or `TryConnect`.
+    /// Connecting to the same server again hands out the same `CID`, so the
+    /// kernel counts how many times it was handed out and only frees the
+    /// slot once each of them has been given back.  A connection to a
+    /// server that has since exited can be given back as well.
+    ///
+    /// # Errors
+    ///
+    /// * **ServerNotFound**: The `CID` isn't a connection
+    Disconnect(CID),
+
+    /// Count the messages waiting in the queue of one of this process'
+    /// servers that have not been received yet, returned as a `Scalar1`.
+    ///
+    /// # Errors
+    ///
+    /// * **ServerNotFound**: This process doesn't own a server with that ID
+    GetQueueDepth(SID),
+
     /// This syscall does not exist. It captures all possible
     /// arguments so detailed analysis can be performed.
     Invalid(usize, usize, usize, usize, usize, usize, usize),
@@ -301,6 +551,27 @@ pub enum SysCallNumber {
     TryConnect = 25,
     ReturnScalar1 = 26,
     ReturnScalar2 = 27,
+    GetIdleStats = 28,
+    GetInterruptLatency = 29,
+    GetAuditEntry = 30,
+    GetMemoryFlags = 31,
+    GetPmpRange = 32,
+    GetProcessId = 33,
+    GetStackUsage = 34,
+    ClaimSharedInterrupt = 35,
+    SetQueueLimit = 36,
+    SandboxProcess = 37,
+    SandboxAllowConnection = 38,
+    SetCpuQuota = 39,
+    GetCpuStats = 40,
+    GetTimestamp = 41,
+    GetBootMeasurement = 42,
+    PauseWorld = 43,
+    ResumeWorld = 44,
+    ReadThreadRegisters = 45,
+    LendPages = 46,
+    Disconnect = 47,
+    GetQueueDepth = 48,
     Invalid,
 }
 
@@ -334,6 +605,27 @@ impl SysCallNumber {
             25 => TryConnect,
             26 => ReturnScalar1,
             27 => ReturnScalar2,
+            28 => GetIdleStats,
+            29 => GetInterruptLatency,
+            30 => GetAuditEntry,
+            31 => GetMemoryFlags,
+            32 => GetPmpRange,
+            33 => GetProcessId,
+            34 => GetStackUsage,
+            35 => ClaimSharedInterrupt,
+            36 => SetQueueLimit,
+            37 => SandboxProcess,
+            38 => SandboxAllowConnection,
+            39 => SetCpuQuota,
+            40 => GetCpuStats,
+            41 => GetTimestamp,
+            42 => GetBootMeasurement,
+            43 => PauseWorld,
+            44 => ResumeWorld,
+            45 => ReadThreadRegisters,
+            46 => LendPages,
+            47 => Disconnect,
+            48 => GetQueueDepth,
             _ => Invalid,
         }
     }
@@ -600,6 +892,192 @@ impl SysCall {
                 0,
                 0,
             ],
+            SysCall::GetIdleStats => [SysCallNumber::GetIdleStats as usize, 0, 0, 0, 0, 0, 0, 0],
+            SysCall::GetInterruptLatency(irq_no, bucket) => [
+                SysCallNumber::GetInterruptLatency as usize,
+                *irq_no,
+                *bucket,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::GetAuditEntry(sequence) => [
+                SysCallNumber::GetAuditEntry as usize,
+                *sequence,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::GetMemoryFlags(addr) => [
+                SysCallNumber::GetMemoryFlags as usize,
+                *addr,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::GetPmpRange(index) => [
+                SysCallNumber::GetPmpRange as usize,
+                *index,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::GetProcessId => [SysCallNumber::GetProcessId as usize, 0, 0, 0, 0, 0, 0, 0],
+            SysCall::GetStackUsage(tid) => [
+                SysCallNumber::GetStackUsage as usize,
+                *tid,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::ClaimSharedInterrupt(a1, a2, a3) => [
+                SysCallNumber::ClaimSharedInterrupt as usize,
+                *a1,
+                a2.get(),
+                a3.map(|x| x.get()).unwrap_or_default(),
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::SetQueueLimit(sid, limit) => {
+                let s = sid.to_u32();
+                [
+                    SysCallNumber::SetQueueLimit as usize,
+                    s.0 as _,
+                    s.1 as _,
+                    s.2 as _,
+                    s.3 as _,
+                    *limit,
+                    0,
+                    0,
+                ]
+            }
+            SysCall::SandboxProcess(pid) => [
+                SysCallNumber::SandboxProcess as usize,
+                pid.get() as usize,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::SandboxAllowConnection(pid, sid) => {
+                let s = sid.to_u32();
+                [
+                    SysCallNumber::SandboxAllowConnection as usize,
+                    pid.get() as usize,
+                    s.0 as _,
+                    s.1 as _,
+                    s.2 as _,
+                    s.3 as _,
+                    0,
+                    0,
+                ]
+            }
+            SysCall::SetCpuQuota(pid, percent) => [
+                SysCallNumber::SetCpuQuota as usize,
+                pid.get() as usize,
+                *percent,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::GetCpuStats(pid) => [
+                SysCallNumber::GetCpuStats as usize,
+                pid.get() as usize,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::GetTimestamp => [SysCallNumber::GetTimestamp as usize, 0, 0, 0, 0, 0, 0, 0],
+            SysCall::GetBootMeasurement(half) => [
+                SysCallNumber::GetBootMeasurement as usize,
+                *half,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::PauseWorld => [SysCallNumber::PauseWorld as usize, 0, 0, 0, 0, 0, 0, 0],
+            SysCall::ResumeWorld => [SysCallNumber::ResumeWorld as usize, 0, 0, 0, 0, 0, 0, 0],
+            SysCall::ReadThreadRegisters(pid, tid, first) => [
+                SysCallNumber::ReadThreadRegisters as usize,
+                pid.get() as usize,
+                *tid,
+                *first,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::LendPages(a1, ref a2) => match a2 {
+                Message::MutableBorrow(mm) | Message::Borrow(mm) | Message::Move(mm) => [
+                    SysCallNumber::LendPages as usize,
+                    *a1,
+                    a2.message_type(),
+                    mm.id as usize,
+                    mm.buf.as_ptr() as usize,
+                    mm.buf.len(),
+                    mm.offset.map(|x| x.get()).unwrap_or(0) as usize,
+                    mm.valid.map(|x| x.get()).unwrap_or(0) as usize,
+                ],
+                Message::Scalar(sc) | Message::BlockingScalar(sc) => [
+                    SysCallNumber::LendPages as usize,
+                    *a1,
+                    a2.message_type(),
+                    sc.id as usize,
+                    sc.arg1,
+                    sc.arg2,
+                    sc.arg3,
+                    sc.arg4,
+                ],
+            },
+            SysCall::Disconnect(cid) => [
+                SysCallNumber::Disconnect as usize,
+                *cid,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::GetQueueDepth(sid) => {
+                let s = sid.to_u32();
+                [
+                    SysCallNumber::GetQueueDepth as usize,
+                    s.0 as _,
+                    s.1 as _,
+                    s.2 as _,
+                    s.3 as _,
+                    0,
+                    0,
+                    0,
+                ]
+            }
             SysCall::Invalid(a1, a2, a3, a4, a5, a6, a7) => [
                 SysCallNumber::Invalid as usize,
                 *a1,
@@ -784,6 +1262,60 @@ impl SysCall {
             },
             SysCallNumber::ReturnScalar1 => SysCall::ReturnScalar1(a1, a2),
             SysCallNumber::ReturnScalar2 => SysCall::ReturnScalar2(a1, a2, a3),
+            SysCallNumber::GetIdleStats => SysCall::GetIdleStats,
+            SysCallNumber::GetInterruptLatency => SysCall::GetInterruptLatency(a1, a2),
+            SysCallNumber::GetAuditEntry => SysCall::GetAuditEntry(a1),
+            SysCallNumber::GetMemoryFlags => SysCall::GetMemoryFlags(a1),
+            SysCallNumber::GetPmpRange => SysCall::GetPmpRange(a1),
+            SysCallNumber::GetProcessId => SysCall::GetProcessId,
+            SysCallNumber::GetStackUsage => SysCall::GetStackUsage(a1),
+            SysCallNumber::ClaimSharedInterrupt => SysCall::ClaimSharedInterrupt(
+                a1,
+                MemoryAddress::new(a2).ok_or(Error::InvalidSyscall)?,
+                MemoryAddress::new(a3),
+            ),
+            SysCallNumber::SetQueueLimit => {
+                SysCall::SetQueueLimit(SID::from_u32(a1 as _, a2 as _, a3 as _, a4 as _), a5)
+            }
+            SysCallNumber::SandboxProcess => SysCall::SandboxProcess(pid_from_usize(a1)?),
+            SysCallNumber::SandboxAllowConnection => SysCall::SandboxAllowConnection(
+                pid_from_usize(a1)?,
+                SID::from_u32(a2 as _, a3 as _, a4 as _, a5 as _),
+            ),
+            SysCallNumber::SetCpuQuota => SysCall::SetCpuQuota(pid_from_usize(a1)?, a2),
+            SysCallNumber::GetCpuStats => SysCall::GetCpuStats(pid_from_usize(a1)?),
+            SysCallNumber::GetTimestamp => SysCall::GetTimestamp,
+            SysCallNumber::GetBootMeasurement => SysCall::GetBootMeasurement(a1),
+            SysCallNumber::PauseWorld => SysCall::PauseWorld,
+            SysCallNumber::ResumeWorld => SysCall::ResumeWorld,
+            SysCallNumber::ReadThreadRegisters => {
+                SysCall::ReadThreadRegisters(pid_from_usize(a1)?, a2, a3)
+            }
+            SysCallNumber::LendPages => match a2 {
+                1 => SysCall::LendPages(
+                    a1,
+                    Message::MutableBorrow(MemoryMessage {
+                        id: a3,
+                        buf: MemoryRange::new(a4, a5)?,
+                        offset: MemoryAddress::new(a6),
+                        valid: MemorySize::new(a7),
+                    }),
+                ),
+                2 => SysCall::LendPages(
+                    a1,
+                    Message::Borrow(MemoryMessage {
+                        id: a3,
+                        buf: MemoryRange::new(a4, a5)?,
+                        offset: MemoryAddress::new(a6),
+                        valid: MemorySize::new(a7),
+                    }),
+                ),
+                _ => SysCall::Invalid(a1, a2, a3, a4, a5, a6, a7),
+            },
+            SysCallNumber::Disconnect => SysCall::Disconnect(a1),
+            SysCallNumber::GetQueueDepth => {
+                SysCall::GetQueueDepth(SID::from_u32(a1 as _, a2 as _, a3 as _, a4 as _))
+            }
             SysCallNumber::Invalid => SysCall::Invalid(a1, a2, a3, a4, a5, a6, a7),
         })
     }
@@ -968,6 +1500,371 @@ pub fn return_scalar2(
     }
 }
 
+/// CPU idle accounting, as reported by the kernel.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct IdleStats {
+    /// Time spent waiting for an interrupt with nothing to run
+    pub idle: u64,
+
+    /// Time elapsed since the kernel started
+    pub total: u64,
+
+    /// Number of times the CPU was woken up from idle
+    pub wakeups: usize,
+}
+
+impl IdleStats {
+    /// The percentage of time the CPU has spent idle since boot
+    pub fn idle_percent(&self) -> u32 {
+        if self.total == 0 {
+            return 0;
+        }
+        (self.idle.min(self.total) * 100 / self.total) as u32
+    }
+}
+
+/// Get CPU idle statistics. Times are in units of the platform timer, so
+/// compare two samples to compute the idle percentage over an interval.
+pub fn idle_stats() -> core::result::Result<IdleStats, Error> {
+    let result = rsyscall(SysCall::GetIdleStats)?;
+    if let Result::Scalar5(idle_lo, idle_hi, total_lo, total_hi, wakeups) = result {
+        Ok(IdleStats {
+            idle: (idle_lo as u32 as u64) | ((idle_hi as u32 as u64) << 32),
+            total: (total_lo as u32 as u64) | ((total_hi as u32 as u64) << 32),
+            wakeups,
+        })
+    } else if let Result::Error(e) = result {
+        Err(e)
+    } else {
+        Err(Error::InternalError)
+    }
+}
+
+/// CPU accounting for one process, as returned by `cpu_stats()`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CpuStats {
+    /// Percentage of the CPU the process may use while others are waiting
+    pub quota: usize,
+
+    /// Time the process has run since it was created
+    pub total: u64,
+
+    /// Time the process has run in the current accounting window
+    pub window: u32,
+
+    /// Number of times the scheduler passed the process over because it was
+    /// over its quota
+    pub throttled: usize,
+}
+
+/// Limit `pid` to `percent` of the CPU whenever other processes are waiting
+/// to run.  `pid` must be a process created by this one, or this process
+/// must hold `Capabilities::SET_QUOTA` and `pid` must be scheduled by the
+/// kernel.
+pub fn set_cpu_quota(pid: PID, percent: usize) -> core::result::Result<(), Error> {
+    let result = rsyscall(SysCall::SetCpuQuota(pid, percent))?;
+    if let Result::Ok = result {
+        Ok(())
+    } else if let Result::Error(e) = result {
+        Err(e)
+    } else {
+        Err(Error::InternalError)
+    }
+}
+
+/// Get the CPU accounting for `pid`.  Times are in units of the platform
+/// timer.
+pub fn cpu_stats(pid: PID) -> core::result::Result<CpuStats, Error> {
+    let result = rsyscall(SysCall::GetCpuStats(pid))?;
+    if let Result::Scalar5(quota, total_lo, total_hi, window, throttled) = result {
+        Ok(CpuStats {
+            quota,
+            total: (total_lo as u32 as u64) | ((total_hi as u32 as u64) << 32),
+            window: window as u32,
+            throttled,
+        })
+    } else if let Result::Error(e) = result {
+        Err(e)
+    } else {
+        Err(Error::InternalError)
+    }
+}
+
+/// Read the CPU cycle counter, which counts up from boot.  This is a
+/// syscall but doesn't involve any other process, so it is much cheaper
+/// than asking the ticktimer server for the time.  Hosted kernels always
+/// return 0.
+pub fn timestamp() -> core::result::Result<u64, Error> {
+    let result = rsyscall(SysCall::GetTimestamp)?;
+    if let Result::Scalar2(lo, hi) = result {
+        Ok((lo as u32 as u64) | ((hi as u32 as u64) << 32))
+    } else if let Result::Error(e) = result {
+        Err(e)
+    } else {
+        Err(Error::InternalError)
+    }
+}
+
+/// What the loader measured before starting the kernel
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BootMeasurement {
+    /// How many images went into the measurement: the kernel plus each
+    /// initial process
+    pub images: usize,
+
+    /// The running SHA-256 measurement of those images
+    pub digest: [u8; 32],
+}
+
+/// Get the measurement the loader took of the kernel and initial processes,
+/// so it can be included in an attestation or compared against a known-good
+/// value.  Returns `Error::InvalidSyscall` if the image was not built with a
+/// measured boot.
+pub fn boot_measurement() -> core::result::Result<BootMeasurement, Error> {
+    let mut measurement = BootMeasurement {
+        images: 0,
+        digest: [0; 32],
+    };
+    for (half, digest) in measurement.digest.chunks_exact_mut(16).enumerate() {
+        match rsyscall(SysCall::GetBootMeasurement(half))? {
+            Result::Scalar5(w0, w1, w2, w3, images) => {
+                for (bytes, word) in digest.chunks_exact_mut(4).zip([w0, w1, w2, w3].iter()) {
+                    bytes.copy_from_slice(&(*word as u32).to_le_bytes());
+                }
+                measurement.images = images;
+            }
+            Result::Error(e) => return Err(e),
+            _ => return Err(Error::InternalError),
+        }
+    }
+    Ok(measurement)
+}
+
+/// Number of registers saved for each thread: the program counter followed
+/// by `x1` through `x31`
+pub const THREAD_REGISTER_COUNT: usize = 32;
+
+/// Stop every other process from being scheduled until `resume_world()` is
+/// called.  See `SysCall::PauseWorld`.
+pub fn pause_world() -> core::result::Result<(), Error> {
+    rsyscall(SysCall::PauseWorld).map(|_| ())
+}
+
+/// Let every process run again after `pause_world()`
+pub fn resume_world() -> core::result::Result<(), Error> {
+    rsyscall(SysCall::ResumeWorld).map(|_| ())
+}
+
+/// Read the saved registers of thread `tid` in `pid`.  The world must have
+/// been paused by the caller.  Element 0 is the program counter and the
+/// rest are `x1` through `x31`.
+pub fn read_thread_registers(
+    pid: PID,
+    tid: TID,
+) -> core::result::Result<[usize; THREAD_REGISTER_COUNT], Error> {
+    let mut registers = [0usize; THREAD_REGISTER_COUNT];
+    for (group, chunk) in registers.chunks_exact_mut(4).enumerate() {
+        match rsyscall(SysCall::ReadThreadRegisters(pid, tid, group * 4))? {
+            Result::Scalar5(r0, r1, r2, r3, _) => chunk.copy_from_slice(&[r0, r1, r2, r3]),
+            Result::Error(e) => return Err(e),
+            _ => return Err(Error::InternalError),
+        }
+    }
+    Ok(registers)
+}
+
+/// Number of buckets in each interrupt latency histogram
+pub const IRQ_LATENCY_BUCKETS: usize = 16;
+
+/// Interrupt latency histograms for a single IRQ, in CPU cycles. Bucket `n`
+/// counts samples that took less than `2^(n+1)` cycles, and the final bucket
+/// also holds every sample longer than that.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct InterruptLatency {
+    /// Time from the kernel trap entry to the handler being called
+    pub dispatch: [u32; IRQ_LATENCY_BUCKETS],
+
+    /// Time spent running the handler
+    pub handler: [u32; IRQ_LATENCY_BUCKETS],
+
+    /// The longest dispatch time seen
+    pub max_dispatch: u32,
+
+    /// The longest handler time seen
+    pub max_handler: u32,
+
+    /// The number of times the handler has completed
+    pub count: u32,
+}
+
+/// Get the latency histograms for the given IRQ. This requires the kernel
+/// to be built with the `irq-latency` feature.
+pub fn interrupt_latency(irq_no: usize) -> core::result::Result<InterruptLatency, Error> {
+    let mut latency = InterruptLatency::default();
+    for bucket in 0..IRQ_LATENCY_BUCKETS {
+        let result = rsyscall(SysCall::GetInterruptLatency(irq_no, bucket))?;
+        if let Result::Scalar5(dispatch, handler, max_dispatch, max_handler, count) = result {
+            latency.dispatch[bucket] = dispatch as u32;
+            latency.handler[bucket] = handler as u32;
+            latency.max_dispatch = max_dispatch as u32;
+            latency.max_handler = max_handler as u32;
+            latency.count = count as u32;
+        } else if let Result::Error(e) = result {
+            return Err(e);
+        } else {
+            return Err(Error::InternalError);
+        }
+    }
+    Ok(latency)
+}
+
+/// An operation that the kernel refused because the process lacked the
+/// required capability.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct AuditEntry {
+    /// Increments with every denial, so readers can tell if they missed any
+    pub sequence: usize,
+
+    /// The process that made the call
+    pub pid: PID,
+
+    /// The `SysCallNumber` of the denied call
+    pub syscall: usize,
+
+    /// The first two arguments to the denied call
+    pub args: [usize; 2],
+}
+
+/// Read the first entry in the kernel's audit log with a sequence number of
+/// at least `sequence`, or `None` if there are no newer entries. Entries are
+/// held in a ring, so the returned sequence number may be later than the
+/// one requested if older entries have been overwritten.
+pub fn audit_entry(sequence: usize) -> core::result::Result<Option<AuditEntry>, Error> {
+    let result = rsyscall(SysCall::GetAuditEntry(sequence))?;
+    if let Result::Scalar5(sequence, pid, syscall, arg1, arg2) = result {
+        Ok(Some(AuditEntry {
+            sequence,
+            pid: PID::new(pid as _).ok_or(Error::InternalError)?,
+            syscall,
+            args: [arg1, arg2],
+        }))
+    } else if let Result::Ok = result {
+        Ok(None)
+    } else if let Result::Error(e) = result {
+        Err(e)
+    } else {
+        Err(Error::InternalError)
+    }
+}
+
+/// The state of a page in the current process's address space
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PageState {
+    /// Nothing is mapped here
+    Unmapped,
+
+    /// The page has been reserved and will be allocated when first touched
+    Reserved,
+
+    /// The page is backed by memory
+    Mapped,
+
+    /// The page is a guard page, and any access to it will fault
+    Guard,
+}
+
+/// Get the permissions and state of the page containing `addr` in the
+/// current process, for example to verify that no page is both writable
+/// and executable.
+pub fn memory_flags(addr: usize) -> core::result::Result<(MemoryFlags, PageState), Error> {
+    let result = rsyscall(SysCall::GetMemoryFlags(addr))?;
+    if let Result::Scalar2(flags, state) = result {
+        let state = match state {
+            0 => PageState::Unmapped,
+            1 => PageState::Reserved,
+            2 => PageState::Mapped,
+            3 => PageState::Guard,
+            _ => return Err(Error::InternalError),
+        };
+        Ok((MemoryFlags::from_bits_truncate(flags), state))
+    } else if let Result::Error(e) = result {
+        Err(e)
+    } else {
+        Err(Error::InternalError)
+    }
+}
+
+/// An address range that the loader locked down with the RISC-V PMP.
+/// Accesses from the kernel and from userspace are limited to `flags`,
+/// regardless of how the range is mapped.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PmpRange {
+    /// First address in the range
+    pub start: usize,
+
+    /// Address just past the end of the range
+    pub end: usize,
+
+    /// The accesses that are permitted
+    pub flags: MemoryFlags,
+}
+
+/// Get the PMP-protected range at `index`, or `None` if that slot is unused.
+/// Indices past the end of the table return `Error::InvalidSyscall`.
+pub fn pmp_range(index: usize) -> core::result::Result<Option<PmpRange>, Error> {
+    let result = rsyscall(SysCall::GetPmpRange(index))?;
+    if let Result::Scalar5(start, end, flags, _, _) = result {
+        if start == end {
+            return Ok(None);
+        }
+        Ok(Some(PmpRange {
+            start,
+            end,
+            flags: MemoryFlags::from_bits_truncate(flags),
+        }))
+    } else if let Result::Error(e) = result {
+        Err(e)
+    } else {
+        Err(Error::InternalError)
+    }
+}
+
+/// How much of a thread's stack has been touched since it was created
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct StackUsage {
+    /// The deepest the stack has grown, in bytes
+    pub used: usize,
+
+    /// The size of the stack, in bytes
+    pub size: usize,
+}
+
+/// Get the stack high-water mark of thread `tid` in the current process, or
+/// of the calling thread if `tid` is `0`. This requires the kernel to be
+/// built with the `stack-watermark` feature.
+pub fn stack_usage(tid: TID) -> core::result::Result<StackUsage, Error> {
+    let result = rsyscall(SysCall::GetStackUsage(tid))?;
+    if let Result::Scalar2(used, size) = result {
+        Ok(StackUsage { used, size })
+    } else if let Result::Error(e) = result {
+        Err(e)
+    } else {
+        Err(Error::InternalError)
+    }
+}
+
+/// Return the ID of the current process.
+pub fn process_id() -> core::result::Result<PID, Error> {
+    let result = rsyscall(SysCall::GetProcessId)?;
+    if let Result::Scalar1(pid) = result {
+        PID::new(pid as _).ok_or(Error::InternalError)
+    } else if let Result::Error(e) = result {
+        Err(e)
+    } else {
+        Err(Error::InternalError)
+    }
+}
+
 /// Claim a hardware interrupt for this process.
 pub fn claim_interrupt(
     irq_no: usize,
@@ -988,6 +1885,94 @@ pub fn claim_interrupt(
     }
 }
 
+/// Claim a hardware interrupt that other processes may also claim with
+/// `claim_shared_interrupt()`.  The callback is run every time the line
+/// fires, whichever device raised it.
+pub fn claim_shared_interrupt(
+    irq_no: usize,
+    callback: fn(irq_no: usize, arg: *mut usize),
+    arg: *mut usize,
+) -> core::result::Result<(), Error> {
+    let result = rsyscall(SysCall::ClaimSharedInterrupt(
+        irq_no,
+        MemoryAddress::new(callback as *mut usize as usize).ok_or(Error::InvalidSyscall)?,
+        MemoryAddress::new(arg as *mut usize as usize),
+    ))?;
+    if let crate::Result::Ok = result {
+        Ok(())
+    } else if let Result::Error(e) = result {
+        Err(e)
+    } else {
+        Err(Error::InternalError)
+    }
+}
+
+/// Give up an interrupt claimed by this process.  The interrupt is masked
+/// once nobody is left to handle it.
+pub fn free_interrupt(irq_no: usize) -> core::result::Result<(), Error> {
+    let result = rsyscall(SysCall::FreeInterrupt(irq_no))?;
+    if let crate::Result::Ok = result {
+        Ok(())
+    } else if let Result::Error(e) = result {
+        Err(e)
+    } else {
+        Err(Error::InternalError)
+    }
+}
+
+/// Let at most `limit` messages wait in `server`'s queue, and return the
+/// limit that was actually set.  Senders get backpressure once the queue
+/// holds that many messages.
+pub fn set_queue_limit(server: SID, limit: usize) -> core::result::Result<usize, Error> {
+    let result = rsyscall(SysCall::SetQueueLimit(server, limit))?;
+    if let Result::Scalar1(limit) = result {
+        Ok(limit)
+    } else if let Result::Error(e) = result {
+        Err(e)
+    } else {
+        Err(Error::InternalError)
+    }
+}
+
+/// Return how many messages are waiting in `server`'s queue without
+/// having been received yet
+pub fn queue_depth(server: SID) -> core::result::Result<usize, Error> {
+    let result = rsyscall(SysCall::GetQueueDepth(server))?;
+    if let Result::Scalar1(depth) = result {
+        Ok(depth)
+    } else if let Result::Error(e) = result {
+        Err(e)
+    } else {
+        Err(Error::InternalError)
+    }
+}
+
+/// Start `pid`, a process that was just created, in sandboxed mode.  It will
+/// not be able to create servers, map physical memory, claim interrupts or
+/// connect to any server not allowed with `sandbox_allow_connection()`.
+pub fn sandbox_process(pid: PID) -> core::result::Result<(), Error> {
+    let result = rsyscall(SysCall::SandboxProcess(pid))?;
+    if let Result::Ok = result {
+        Ok(())
+    } else if let Result::Error(e) = result {
+        Err(e)
+    } else {
+        Err(Error::InternalError)
+    }
+}
+
+/// Let the sandboxed process `pid` connect to `server`.
+pub fn sandbox_allow_connection(pid: PID, server: SID) -> core::result::Result<(), Error> {
+    let result = rsyscall(SysCall::SandboxAllowConnection(pid, server))?;
+    if let Result::Ok = result {
+        Ok(())
+    } else if let Result::Error(e) = result {
+        Err(e)
+    } else {
+        Err(Error::InternalError)
+    }
+}
+
 /// Create a new server with the given name.  This enables other processes to
 /// connect to this server to send messages.  The name is a UTF-8 token that
 /// will be mixed with other random data that is unique to each process.
@@ -1035,6 +2020,21 @@ pub fn try_connect(server: SID) -> core::result::Result<CID, Error> {
     }
 }
 
+/// Give back a connection made with `connect()` or `try_connect()`.  The
+/// same `CID` is handed out each time a process connects to a server, so
+/// the slot is only freed once every one of those connections has been
+/// given back.
+pub fn disconnect(connection: CID) -> core::result::Result<(), Error> {
+    let result = rsyscall(SysCall::Disconnect(connection))?;
+    if let Result::Ok = result {
+        Ok(())
+    } else if let Result::Error(e) = result {
+        Err(e)
+    } else {
+        Err(Error::InternalError)
+    }
+}
+
 /// Suspend the current process until a message is received.  This thread will
 /// block until a message is received.
 ///
@@ -1096,6 +2096,115 @@ pub fn send_message(connection: CID, message: Message) -> core::result::Result<R
     }
 }
 
+/// Lend `pages`, a list of page-aligned ranges, to a server as if they
+/// were a single buffer.  This lets a large buffer that was built up in
+/// pieces go to a server without first being copied somewhere contiguous.
+/// The server sees the ranges back to back, and `offset` and `valid` are
+/// relative to that.  Blocks until the server returns the memory.
+///
+/// Hosted kernels can't move pages between processes, so there the ranges
+/// are copied into one buffer, and copied back if `mutable` is set.
+///
+/// # Errors
+///
+/// * **BadAddress**: There are no ranges or more than `MAX_PAGE_LIST`, or the valid part doesn't fit
+/// * **BadAlignment**: A range is not page-aligned
+/// * **OutOfMemory**: Too many page lists are lent out already
+/// * **ServerNotFound**: The server does not exist so the connection is now invalid
+/// * **ShareViolation**: Part of a range is already lent
+pub fn lend_pages(
+    connection: CID,
+    id: usize,
+    pages: &[MemoryRange],
+    mutable: bool,
+    offset: Option<MemoryAddress>,
+    valid: Option<MemorySize>,
+) -> core::result::Result<Result, Error> {
+    if pages.is_empty() || pages.len() > MAX_PAGE_LIST {
+        return Err(Error::BadAddress);
+    }
+    lend_pages_inner(connection, id, pages, mutable, offset, valid)
+}
+
+#[cfg(target_os = "none")]
+fn lend_pages_inner(
+    connection: CID,
+    id: usize,
+    pages: &[MemoryRange],
+    mutable: bool,
+    offset: Option<MemoryAddress>,
+    valid: Option<MemorySize>,
+) -> core::result::Result<Result, Error> {
+    // The kernel copies the list before this returns, so it can live on
+    // the stack.
+    let mut list = [0usize; MAX_PAGE_LIST * 2];
+    for (pair, range) in list.chunks_exact_mut(2).zip(pages) {
+        pair[0] = range.as_ptr() as usize;
+        pair[1] = range.len();
+    }
+    let message = MemoryMessage {
+        id,
+        buf: MemoryRange::new(
+            list.as_ptr() as usize,
+            pages.len() * 2 * core::mem::size_of::<usize>(),
+        )?,
+        offset,
+        valid,
+    };
+    let message = if mutable {
+        Message::MutableBorrow(message)
+    } else {
+        Message::Borrow(message)
+    };
+    match rsyscall(SysCall::LendPages(connection, message)) {
+        Ok(Result::Ok) => Ok(Result::Ok),
+        Ok(Result::Scalar1(a)) => Ok(Result::Scalar1(a)),
+        Ok(Result::Scalar2(a, b)) => Ok(Result::Scalar2(a, b)),
+        Err(e) => Err(e),
+        v => panic!("Unexpected return value: {:?}", v),
+    }
+}
+
+#[cfg(not(target_os = "none"))]
+fn lend_pages_inner(
+    connection: CID,
+    id: usize,
+    pages: &[MemoryRange],
+    mutable: bool,
+    offset: Option<MemoryAddress>,
+    valid: Option<MemorySize>,
+) -> core::result::Result<Result, Error> {
+    let mut gathered = vec![];
+    for range in pages {
+        if range.as_ptr() as usize & 0xfff != 0 || range.len() & 0xfff != 0 {
+            return Err(Error::BadAlignment);
+        }
+        gathered.extend_from_slice(unsafe { core::slice::from_raw_parts(range.as_ptr(), range.len()) });
+    }
+    let message = MemoryMessage {
+        id,
+        buf: MemoryRange::new(gathered.as_mut_ptr() as usize, gathered.len())?,
+        offset,
+        valid,
+    };
+    message.valid_range()?;
+    let result = if mutable {
+        send_message(connection, Message::MutableBorrow(message))
+    } else {
+        send_message(connection, Message::Borrow(message))
+    };
+    if mutable && result.is_ok() {
+        let mut chunks = &gathered[..];
+        for range in pages {
+            let (chunk, rest) = chunks.split_at(range.len());
+            unsafe { core::slice::from_raw_parts_mut(range.as_mut_ptr(), range.len()) }
+                .copy_from_slice(chunk);
+            chunks = rest;
+        }
+    }
+    result
+}
+
 pub fn terminate_process() {
     rsyscall(SysCall::TerminateProcess).expect("terminate_process returned an error");
 }
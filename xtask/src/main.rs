@@ -9,6 +9,20 @@ type DynError = Box<dyn std::error::Error>;
 
 const TARGET: &str = "riscv32imac-unknown-none-elf";
 
+/// Kernel feature sets compared by `size-matrix` when none are given
+const SIZE_MATRIX: &[&str] = &[
+    "",
+    "debug-print",
+    "capabilities",
+    "irq-latency",
+    "multicore",
+    "stack-watermark",
+];
+
+/// Programs that make up an image, in addition to the kernel.  Every image
+/// and hosted run starts these, in this order.
+const IMAGE_PROGRAMS: &[&str] = &["shell", "log-server", "graphics-server", "ticktimer-server"];
+
 enum MemorySpec {
     SvdFile(String),
 }
@@ -40,9 +54,12 @@ fn try_main() -> Result<(), DynError> {
     match task.as_deref() {
         Some("renode-image") => renode_image(false)?,
         Some("renode-image-debug") => renode_image(true)?,
-        Some("run") => run(false)?,
+        Some("run") => run(false, false)?,
+        Some("run-bench") => run(false, true)?,
         Some("hw-image") => build_hw_image(false, env::args().nth(2))?,
-        Some("debug") => run(true)?,
+        Some("debug") => run(true, false)?,
+        Some("verify-image") => verify_image(env::args().skip(2).collect())?,
+        Some("size-matrix") => size_matrix(env::args().skip(2).collect())?,
         _ => print_help(),
     }
     Ok(())
@@ -55,7 +72,10 @@ renode-image            builds a test image for renode
 renode-image-debug      builds a test image for renode in debug mode
 hw-image [soc.svd]      builds an image for real hardware
 run                     runs a release build using a hosted environment
+run-bench               runs the benchmark suite using a hosted environment
 debug                   runs a debug build using a hosted environment
+verify-image [args]     checks an image against the manifest written by a build
+size-matrix [features]  compares program sizes across kernel feature sets
 "
     )
 }
@@ -76,12 +96,12 @@ fn build_hw_image(debug: bool, svd: Option<String>) -> Result<(), DynError> {
 
     let kernel = build_kernel(debug)?;
     let mut init = vec![];
-    for pkg in &["shell", "graphics-server", "ticktimer-server", "log-server"] {
+    for pkg in IMAGE_PROGRAMS {
         init.push(build(pkg, debug, Some(TARGET), None)?);
     }
     let loader = build("loader", debug, Some(TARGET), Some("loader".into()))?;
 
-    let output_bundle = create_image(&kernel, &init, debug, MemorySpec::SvdFile(svd_file))?;
+    let output_bundle = create_image(&kernel, &init, &[], debug, MemorySpec::SvdFile(svd_file))?;
     println!();
     println!(
         "Kernel+Init bundle is available at {}",
@@ -145,7 +165,7 @@ fn renode_image(debug: bool) -> Result<(), DynError> {
     std::env::set_var("XOUS_SVD_FILE", path.canonicalize().unwrap());
     let kernel = build_kernel(debug)?;
     let mut init = vec![];
-    for pkg in &["shell", "log-server", "graphics-server", "ticktimer-server"] {
+    for pkg in IMAGE_PROGRAMS {
         init.push(build(pkg, debug, Some(TARGET), None)?);
     }
     build("loader", debug, Some(TARGET), Some("loader".into()))?;
@@ -153,6 +173,7 @@ fn renode_image(debug: bool) -> Result<(), DynError> {
     create_image(
         &kernel,
         &init,
+        &[],
         debug,
         MemorySpec::SvdFile("emulation/renode.svd".into()),
     )?;
@@ -160,13 +181,17 @@ fn renode_image(debug: bool) -> Result<(), DynError> {
     Ok(())
 }
 
-fn run(debug: bool) -> Result<(), DynError> {
+fn run(debug: bool, bench: bool) -> Result<(), DynError> {
     let stream = if debug { "debug" } else { "release" };
-    let init = ["shell", "log-server", "graphics-server", "ticktimer-server"];
+    let mut init = IMAGE_PROGRAMS.to_vec();
+    if bench {
+        init.push("bench");
+    }
 
     // let mut init_paths = vec![];
     for pkg in &init {
-        build(pkg, debug, None, None)?;
+        let features: &[&str] = if bench && *pkg == "shell" { &["bench"] } else { &[] };
+        build_features(pkg, debug, None, None, features)?;
     }
     // println!("Built packages: {:?}", init_paths);
 
@@ -217,10 +242,25 @@ fn build(
     debug: bool,
     target: Option<&str>,
     directory: Option<PathBuf>,
+) -> Result<PathBuf, DynError> {
+    build_features(project, debug, target, directory, &[])
+}
+
+fn build_features(
+    project: &str,
+    debug: bool,
+    target: Option<&str>,
+    directory: Option<PathBuf>,
+    features: &[&str],
 ) -> Result<PathBuf, DynError> {
     println!("Building {}...", project);
     let stream = if debug { "debug" } else { "release" };
     let mut args = vec!["build", "--package", project];
+    let feature_list = features.join(",");
+    if !features.is_empty() {
+        args.push("--features");
+        args.push(&feature_list);
+    }
     let mut target_path = "".to_owned();
     if let Some(t) = target {
         args.push("--target");
@@ -259,9 +299,13 @@ fn build(
     }
 }
 
+/// Bundle `kernel` and `init` into an argument image.  `features` lists
+/// the packages that were built with more than their default features,
+/// so the manifest can record them.
 fn create_image(
     kernel: &Path,
     init: &[PathBuf],
+    features: &[(&str, &[&str])],
     debug: bool,
     memory_spec: MemorySpec,
 ) -> Result<PathBuf, DynError> {
@@ -271,6 +315,50 @@ fn create_image(
     let output_file = format!("target/{}/{}/args.bin", TARGET, stream);
     args.push(&output_file);
 
+    // Record what went into the image, so it can be checked later.
+    let manifest_file = format!("target/{}/{}/manifest.txt", TARGET, stream);
+    args.push("--manifest");
+    args.push(&manifest_file);
+    let (rustc_release, rustc_commit) = rustc_version()?;
+    let rustc_info = format!("rustc={}", rustc_release);
+    let rustc_commit_info = format!("rustc-commit={}", rustc_commit);
+    let debug_info = format!("debug={}", debug);
+    args.push("--manifest-info");
+    args.push(&rustc_info);
+    args.push("--manifest-info");
+    args.push(&rustc_commit_info);
+    args.push("--manifest-info");
+    args.push(&debug_info);
+    let mut feature_infos = vec![];
+    for program in std::iter::once(kernel).chain(init.iter().map(|p| p.as_path())) {
+        let name = program
+            .file_name()
+            .and_then(|n| n.to_str())
+            .ok_or(BuildError::PathConversionError)?;
+        let mut enabled = vec!["default"];
+        if let Some((_, extra)) = features.iter().find(|(pkg, _)| *pkg == name) {
+            enabled.extend_from_slice(extra);
+        }
+        feature_infos.push(format!("features-{}={}", name, enabled.join(",")));
+    }
+    for info in &feature_infos {
+        args.push("--manifest-info");
+        args.push(info);
+    }
+    let svd_env_info;
+    if let Ok(svd) = env::var("XOUS_SVD_FILE") {
+        svd_env_info = format!("xous-svd-file={}", svd);
+        args.push("--manifest-info");
+        args.push(&svd_env_info);
+    }
+    let lock_files = ["Cargo.lock", "kernel/Cargo.lock", "loader/Cargo.lock"];
+    for lock_file in &lock_files {
+        if project_root().join(lock_file).exists() {
+            args.push("--manifest-input");
+            args.push(lock_file);
+        }
+    }
+
     args.push("--kernel");
     args.push(kernel.to_str().ok_or(BuildError::PathConversionError)?);
 
@@ -297,6 +385,105 @@ fn create_image(
     Ok(project_root().join(&format!("target/{}/{}/args.bin", TARGET, stream)))
 }
 
+/// Build the full image once for each kernel feature set, then print a
+/// table of per-process sizes relative to the first set.  Each argument is
+/// a comma-separated list of features, and an empty string means the
+/// default.  The image and manifest of each set are kept next to its ELFs.
+fn size_matrix(feature_sets: Vec<String>) -> Result<(), DynError> {
+    let feature_sets = if feature_sets.is_empty() {
+        SIZE_MATRIX.iter().map(|s| s.to_string()).collect()
+    } else {
+        feature_sets
+    };
+
+    let path = std::path::Path::new("emulation/renode.svd");
+    std::env::set_var("XOUS_SVD_FILE", path.canonicalize().unwrap());
+
+    let matrix_dir = project_root().join("target").join("size-matrix");
+    let mut report_dirs = vec![];
+    for features in &feature_sets {
+        let feature_list: Vec<&str> = features.split(',').filter(|f| !f.is_empty()).collect();
+        let label = if feature_list.is_empty() {
+            "default".to_string()
+        } else {
+            feature_list.join("+")
+        };
+        // Build everything for each set, exactly as `renode-image` would,
+        // so nothing is reported from a stale build.
+        let kernel = build_features("kernel", false, Some(TARGET), Some("kernel".into()), &feature_list)?;
+        let mut programs = vec![];
+        for pkg in IMAGE_PROGRAMS {
+            programs.push(build(pkg, false, Some(TARGET), None)?);
+        }
+        let image = create_image(
+            &kernel,
+            &programs,
+            &[("kernel", feature_list.as_slice())],
+            false,
+            MemorySpec::SvdFile("emulation/renode.svd".into()),
+        )?;
+
+        // The report reads every file in the directory as an ELF, so the
+        // image and manifest go alongside it.
+        let dir = matrix_dir.join(&label);
+        std::fs::create_dir_all(&dir)?;
+        std::fs::copy(&kernel, dir.join("kernel"))?;
+        for program in &programs {
+            std::fs::copy(program, dir.join(program.file_name().unwrap()))?;
+        }
+        std::fs::copy(&image, matrix_dir.join(format!("{}.bin", label)))?;
+        std::fs::copy(
+            image.with_file_name("manifest.txt"),
+            matrix_dir.join(format!("{}.manifest.txt", label)),
+        )?;
+        report_dirs.push(dir);
+    }
+
+    let mut args = vec!["run", "--package", "tools", "--bin", "size-report", "--"];
+    for dir in &report_dirs {
+        args.push(dir.to_str().ok_or(BuildError::PathConversionError)?);
+    }
+    let status = Command::new(cargo())
+        .current_dir(project_root())
+        .args(&args)
+        .status()?;
+    if !status.success() {
+        return Err("couldn't generate size report".into());
+    }
+    Ok(())
+}
+
+fn verify_image(extra_args: Vec<String>) -> Result<(), DynError> {
+    let mut args = vec!["run", "--package", "tools", "--bin", "verify-image", "--"];
+    for arg in &extra_args {
+        args.push(arg);
+    }
+    let status = Command::new(cargo())
+        .current_dir(project_root())
+        .args(&args)
+        .status()?;
+    if !status.success() {
+        return Err("image does not match manifest".into());
+    }
+    Ok(())
+}
+
+/// The compiler's version line, and the full hash of the commit it was
+/// built from.  `rustc --version` only gives a short hash, so this asks for
+/// the verbose version.
+fn rustc_version() -> Result<(String, String), DynError> {
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let output = Command::new(rustc).arg("-vV").output()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let version = text.lines().next().unwrap_or("").trim().to_string();
+    let commit = text
+        .lines()
+        .find_map(|line| line.strip_prefix("commit-hash:"))
+        .map(|hash| hash.trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    Ok((version, commit))
+}
+
 fn cargo() -> String {
     env::var("CARGO").unwrap_or_else(|_| "cargo".to_string())
 }